@@ -0,0 +1,231 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{ quote, format_ident };
+use syn::{ parse_macro_input, FnArg, Item, ItemFn, ItemStruct, LitStr, Visibility };
+
+/// Declares a worker entry point.
+///
+/// The function takes either `(args, Receiver<O>, WorkerSender<I, C>)` to be spawned with
+/// `Worker::new` or `(args, Requests<P, C>)` to be spawned with `ProtocolWorker::new`, and may
+/// be `async`. Its name becomes a type which is passed to those constructors:
+/// ```ignore
+/// #[webutil::worker]
+/// async fn squarer(_: (), incoming: Receiver<u32>, outgoing: WorkerSender<u32>) {
+///     while let Some(v) = incoming.recv().await {
+///         outgoing.send(&(v * v));
+///     }
+/// }
+///
+/// let worker = Worker::new::<squarer>("./worker.js", &()).await?;
+/// ```
+///
+/// The entry point is registered under an ID derived from its name, so the main thread and
+/// the worker find it even if they aren't running the exact same build. Entry point names must
+/// be unique across the whole application.
+///
+/// Spawning fails if the worker's entry point has different message types than the main
+/// thread's. Changes the type names don't show can be flagged by bumping a version:
+/// `#[webutil::worker(version = 2)]`.
+#[proc_macro_attribute]
+pub fn worker(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut version = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("version") {
+            version = Some(meta.value()?.parse::<syn::Expr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported #[worker] argument"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let f = parse_macro_input!(item as ItemFn);
+    match expand(f, version) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into()
+    }
+}
+
+fn expand(f: ItemFn, version: Option<syn::Expr>) -> syn::Result<TokenStream2> {
+    let name = &f.sig.ident;
+    let vis = &f.vis;
+    let attrs = &f.attrs;
+    let id = LitStr::new(&format!("__webutil_worker_{}", name), name.span());
+    let export = format_ident!("__webutil_worker_{}", name);
+
+    let types = f.sig.inputs.iter().map(|arg| match arg {
+        FnArg::Typed(arg) => Ok(&*arg.ty),
+        FnArg::Receiver(arg) => Err(syn::Error::new_spanned(
+            arg, "worker entry points can't take self"
+        ))
+    }).collect::<syn::Result<Vec<_>>>()?;
+    let args = (0..types.len()).map(|i| format_ident!("arg{}", i)).collect::<Vec<_>>();
+
+    let mut entry = f.clone();
+    entry.attrs.clear();
+    entry.vis = Visibility::Inherited;
+    entry.sig.ident = format_ident!("entry");
+    let run = if f.sig.asyncness.is_some() {
+        quote! { Some(Box::pin(entry(#(#args),*))) }
+    } else {
+        quote! { entry(#(#args),*); None }
+    };
+
+    let version = version.map(|v| quote!(const VERSION: u32 = #v;));
+
+    let (entry_impl, runner) = match &*types {
+        [args_ty, receiver, sender] => (quote! {
+            impl ::webutil::worker::Entry for #name {
+                const ID: &'static str = #id;
+                #version
+                type Args = #args_ty;
+                type Incoming = <#receiver as ::webutil::worker::__EntryReceiver>::Message;
+                type Outgoing = <#sender as ::webutil::worker::__EntrySender>::Message;
+                type Codec = <#sender as ::webutil::worker::__EntrySender>::Codec;
+                fn run(
+                    #(#args: #types),*
+                ) -> Option<::webutil::worker::WorkerFuture> {
+                    #entry
+                    #run
+                }
+            }
+        }, quote!(__run_entry)),
+        [args_ty, requests] => (quote! {
+            impl ::webutil::worker::ProtocolEntry for #name {
+                const ID: &'static str = #id;
+                #version
+                type Args = #args_ty;
+                type Protocol = <#requests as ::webutil::worker::__EntryRequests>::Protocol;
+                type Codec = <#requests as ::webutil::worker::__EntryRequests>::Codec;
+                fn run(
+                    #(#args: #types),*
+                ) -> Option<::webutil::worker::WorkerFuture> {
+                    #entry
+                    #run
+                }
+            }
+        }, quote!(__run_protocol_entry)),
+        _ => return Err(syn::Error::new_spanned(
+            &f.sig.inputs,
+            "worker entry points take (args, Receiver<_>, WorkerSender<_>) or (args, Requests<_>)"
+        ))
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #[allow(non_camel_case_types)]
+        #vis struct #name;
+
+        #entry_impl
+
+        #[doc(hidden)]
+        #[::webutil::__private::wasm_bindgen::prelude::wasm_bindgen(
+            wasm_bindgen = ::webutil::__private::wasm_bindgen,
+            js_name = #id
+        )]
+        pub fn #export(
+            scope: ::webutil::__private::web_sys::DedicatedWorkerGlobalScope,
+            data: ::std::vec::Vec<u8>
+        ) {
+            ::webutil::worker::#runner::<#name>(scope, data)
+        }
+    })
+}
+
+/// Declares an audio worklet processor, making it loadable with
+/// `webutil::worker::worklet::register_audio`.
+///
+/// Goes on the processor's type, which must implement `AudioProcessor`, or on a function
+/// taking `(feed: &[f32], outputs: &mut Buses)` to declare a `webutil::audio::SampleFn` for
+/// `RustProcessorNode`. The function's name becomes the type passed to
+/// `RustProcessorNode::new`. Like worker entry points, processor names must be unique across
+/// the whole application.
+#[proc_macro_attribute]
+pub fn audio_processor(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new_spanned(
+            TokenStream2::from(attr), "#[audio_processor] does not take arguments"
+        ).to_compile_error().into();
+    }
+    let result = match parse_macro_input!(item as Item) {
+        Item::Struct(item) => expand_audio_processor(item),
+        Item::Fn(f) => expand_sample_fn(f),
+        item => Err(syn::Error::new_spanned(
+            item, "#[audio_processor] goes on a processor type or a sample function"
+        ))
+    };
+    match result {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into()
+    }
+}
+
+fn expand_audio_processor(item: ItemStruct) -> syn::Result<TokenStream2> {
+    let name = &item.ident;
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(&item.generics, "audio processors can't be generic"));
+    }
+    let id = LitStr::new(&format!("__webutil_audio_{}", name), name.span());
+    let export = audio_export(name, &id, quote!(#name));
+
+    Ok(quote! {
+        #item
+
+        impl ::webutil::worker::worklet::__AudioExport for #name {
+            const ID: &'static str = #id;
+        }
+
+        #export
+    })
+}
+
+fn expand_sample_fn(f: ItemFn) -> syn::Result<TokenStream2> {
+    let name = &f.sig.ident;
+    let vis = &f.vis;
+    let attrs = &f.attrs;
+    if f.sig.asyncness.is_some() || !f.sig.generics.params.is_empty() || f.sig.inputs.len() != 2 {
+        return Err(syn::Error::new_spanned(
+            &f.sig, "sample functions take (feed: &[f32], outputs: &mut Buses)"
+        ));
+    }
+    let id = LitStr::new(&format!("__webutil_audio_{}", name), name.span());
+
+    let mut entry = f.clone();
+    entry.attrs.clear();
+    entry.vis = Visibility::Inherited;
+    entry.sig.ident = format_ident!("entry");
+
+    let export = audio_export(name, &id, quote!(::webutil::audio::__SampleProcessor<#name>));
+    Ok(quote! {
+        #(#attrs)*
+        #[allow(non_camel_case_types)]
+        #vis struct #name;
+
+        impl ::webutil::audio::SampleFn for #name {
+            const ID: &'static str = #id;
+            fn process(feed: &[f32], outputs: &mut ::webutil::worker::worklet::Buses) {
+                #entry
+                entry(feed, outputs)
+            }
+        }
+
+        #export
+    })
+}
+
+/// The function the worklet calls to create an instance of `processor`.
+fn audio_export(name: &syn::Ident, id: &LitStr, processor: TokenStream2) -> TokenStream2 {
+    let export = format_ident!("__webutil_audio_{}", name);
+    quote! {
+        #[doc(hidden)]
+        #[::webutil::__private::wasm_bindgen::prelude::wasm_bindgen(
+            wasm_bindgen = ::webutil::__private::wasm_bindgen,
+            js_name = #id
+        )]
+        #[allow(non_snake_case)]
+        pub fn #export(
+            port: ::webutil::__private::web_sys::MessagePort
+        ) -> ::webutil::worker::worklet::AudioProcessorHandle {
+            ::webutil::worker::worklet::__new_audio_processor::<#processor>(port)
+        }
+    }
+}
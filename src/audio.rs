@@ -0,0 +1,329 @@
+//! Sound with the Web Audio API.
+//!
+//! Every page shares one [`context`], created when it is first used. Browsers only let it
+//! start after the user has interacted with the page, so it resumes on the first key press,
+//! click or touch.
+//!
+//! ```ignore
+//! let jump = audio::load("sounds/jump.ogg").await?;
+//! ...
+//! Sound::new(&jump).gain(0.8).pan(-0.5).rate(1.1).play()?;
+//! ```
+
+use crate::prelude::*;
+use crate::event::{ self, ListenerHandle };
+use crate::global::GlobalScope;
+use crate::http;
+use std::cell::{ Cell, RefCell };
+use std::rc::Rc;
+use wasm_bindgen_futures::JsFuture;
+
+mod processor;
+pub use processor::*;
+
+thread_local! {
+    static CONTEXT: RefCell<Option<web_sys::AudioContext>> = const { RefCell::new(None) };
+    static UNLOCK: RefCell<Vec<ListenerHandle>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The page's audio context, which is created the first time this is called.
+///
+/// The context starts suspended if the user hasn't interacted with the page yet, and resumes
+/// on their first key press, click or touch.
+pub fn context() -> Result<web_sys::AudioContext, GeneralError> {
+    if let Some(context) = CONTEXT.with(|c| c.borrow().clone()) {
+        return Ok(context);
+    }
+    let context = web_sys::AudioContext::new()?;
+    if context.state() != web_sys::AudioContextState::Running {
+        resume_on_gesture(&context);
+    }
+    CONTEXT.with(|c| *c.borrow_mut() = Some(context.clone()));
+    Ok(context)
+}
+
+fn resume_on_gesture(context: &web_sys::AudioContext) {
+    let window = match GlobalScope::current() {
+        GlobalScope::Window(window) => window,
+        _ => return
+    };
+    let resume = {
+        let context = context.clone();
+        move || {
+            let _ = context.resume();
+        }
+    };
+    let listeners = vec![
+        window.add_event_listener({
+            let resume = resume.clone();
+            move |_: event::KeyDown| resume()
+        }),
+        window.add_event_listener({
+            let resume = resume.clone();
+            move |_: event::MouseDown| resume()
+        }),
+        window.add_event_listener({
+            let resume = resume.clone();
+            move |_: event::PointerUp| resume()
+        }),
+        window.add_event_listener(move |_: event::TouchEnd| resume()),
+        context.add_event_listener({
+            let context = context.clone();
+            move |_: event::AudioStateChange| {
+                if context.state() == web_sys::AudioContextState::Running {
+                    // removing the listeners from inside one of them is left until after it
+                    spawn_local(async { UNLOCK.with(|l| l.borrow_mut().clear()) });
+                }
+            }
+        })
+    ];
+    UNLOCK.with(|l| *l.borrow_mut() = listeners);
+}
+
+/// Whether the [`context`] is playing sound, rather than waiting for the user to interact
+/// with the page.
+pub fn is_running() -> bool {
+    CONTEXT.with(|c| match &*c.borrow() {
+        Some(context) => context.state() == web_sys::AudioContextState::Running,
+        None => false
+    })
+}
+
+/// Waits until the [`context`] is playing sound.
+pub async fn running() -> Result<(), GeneralError> {
+    let context = context()?;
+    while context.state() != web_sys::AudioContextState::Running {
+        context.once::<event::AudioStateChange>().await;
+    }
+    Ok(())
+}
+
+/// Decodes a sound file, such as Ogg Vorbis or MP3 data. Browsers support different formats.
+pub async fn decode(bytes: &[u8]) -> Result<web_sys::AudioBuffer, GeneralError> {
+    let data = js_sys::Uint8Array::from(bytes);
+    let decoded = context()?.decode_audio_data(&data.buffer())?;
+    Ok(web_sys::AudioBuffer::from(JsFuture::from(decoded).await?))
+}
+
+/// Downloads and decodes a sound file.
+pub async fn load(url: &str) -> Result<web_sys::AudioBuffer, GeneralError> {
+    let bytes = http::get(url).send().await?.error_for_status()?.bytes().await?;
+    decode(&bytes).await
+}
+
+/// Plays `buffer` once through the speakers.
+pub fn play(buffer: &web_sys::AudioBuffer) -> Result<Playing, GeneralError> {
+    Sound::new(buffer).play()
+}
+
+/// Builds playback of a sound.
+pub struct Sound<'a> {
+    buffer: &'a web_sys::AudioBuffer,
+    gain: f32,
+    pan: f32,
+    rate: f32,
+    looping: bool,
+    destination: Option<web_sys::AudioNode>
+}
+
+impl<'a> Sound<'a> {
+    pub fn new(buffer: &'a web_sys::AudioBuffer) -> Self {
+        Sound { buffer, gain: 1.0, pan: 0.0, rate: 1.0, looping: false, destination: None }
+    }
+
+    /// Volume, where `1.0` is the volume of the sound itself.
+    pub fn gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Stereo position, from `-1.0` for the left speaker to `1.0` for the right.
+    pub fn pan(mut self, pan: f32) -> Self {
+        self.pan = pan;
+        self
+    }
+
+    /// Playback speed, which also changes the pitch.
+    pub fn rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Plays the sound over and over until stopped.
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Plays into `node` instead of the speakers, such as a shared gain node for all sound
+    /// effects.
+    pub fn to(mut self, node: &web_sys::AudioNode) -> Self {
+        self.destination = Some(node.clone());
+        self
+    }
+
+    pub fn play(self) -> Result<Playing, GeneralError> {
+        let context = context()?;
+        let source = context.create_buffer_source()?;
+        source.set_buffer(Some(self.buffer));
+        source.set_loop(self.looping);
+        source.playback_rate().set_value(self.rate);
+
+        let destination = match self.destination {
+            Some(node) => node,
+            None => context.destination().into()
+        };
+        let mut output: web_sys::AudioNode = source.clone().into();
+        if self.gain != 1.0 {
+            let node = gain(self.gain)?;
+            output.connect_with_audio_node(&node)?;
+            output = node.into();
+        }
+        if self.pan != 0.0 {
+            let node = stereo_panner(self.pan)?;
+            output.connect_with_audio_node(&node)?;
+            output = node.into();
+        }
+        output.connect_with_audio_node(&destination)?;
+        web_sys::AudioScheduledSourceNode::start(&source)?;
+        let ended = Rc::new(Cell::new(false));
+        let listener = source.add_event_listener_once({
+            let ended = ended.clone();
+            move |_: event::Ended| ended.set(true)
+        });
+        Ok(Playing { source, ended, _listener: listener })
+    }
+}
+
+/// A sound being played, from [`Sound::play`]. It keeps playing if this is dropped.
+pub struct Playing {
+    source: web_sys::AudioBufferSourceNode,
+    ended: Rc<Cell<bool>>,
+    _listener: ListenerHandle
+}
+
+impl Playing {
+    pub fn stop(&self) {
+        let _ = web_sys::AudioScheduledSourceNode::stop(&self.source);
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.ended.get()
+    }
+
+    /// Waits for the sound to finish or be stopped.
+    pub async fn ended(&self) {
+        if !self.ended.get() {
+            self.source.once::<event::Ended>().await;
+        }
+    }
+
+    /// The underlying source node, such as for changing its playback rate.
+    pub fn source(&self) -> &web_sys::AudioBufferSourceNode {
+        &self.source
+    }
+}
+
+/// A gain node set to `value`, for changing the volume of everything played into it.
+pub fn gain(value: f32) -> Result<web_sys::GainNode, GeneralError> {
+    let node = context()?.create_gain()?;
+    node.gain().set_value(value);
+    Ok(node)
+}
+
+/// A stereo panner node set to `pan`, from `-1.0` for the left speaker to `1.0` for the right.
+pub fn stereo_panner(pan: f32) -> Result<web_sys::StereoPannerNode, GeneralError> {
+    let node = context()?.create_stereo_panner()?;
+    node.pan().set_value(pan);
+    Ok(node)
+}
+
+/// How a [`Panner`] gets quieter with distance from the listener.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Distance {
+    Linear { reference: f64, max: f64, rolloff: f64 },
+    Inverse { reference: f64, rolloff: f64 },
+    Exponential { reference: f64, rolloff: f64 }
+}
+
+/// Builds a panner node, for positioning sounds in 3D space around the listener.
+#[derive(Clone, Debug)]
+pub struct Panner {
+    position: [f32; 3],
+    orientation: Option<[f32; 3]>,
+    hrtf: bool,
+    distance: Distance
+}
+
+impl Default for Panner {
+    fn default() -> Self {
+        Panner {
+            position: [0.0; 3],
+            orientation: None,
+            hrtf: false,
+            distance: Distance::Inverse { reference: 1.0, rolloff: 1.0 }
+        }
+    }
+}
+
+impl Panner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn position(mut self, x: f32, y: f32, z: f32) -> Self {
+        self.position = [x, y, z];
+        self
+    }
+
+    /// The direction the sound is facing, for directional sounds.
+    pub fn orientation(mut self, x: f32, y: f32, z: f32) -> Self {
+        self.orientation = Some([x, y, z]);
+        self
+    }
+
+    /// Uses head-related transfer functions, which sound more realistic on headphones but
+    /// cost more to process.
+    pub fn hrtf(mut self, hrtf: bool) -> Self {
+        self.hrtf = hrtf;
+        self
+    }
+
+    pub fn distance(mut self, distance: Distance) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    pub fn build(&self) -> Result<web_sys::PannerNode, GeneralError> {
+        let node = context()?.create_panner()?;
+        node.set_panning_model(match self.hrtf {
+            true => web_sys::PanningModelType::Hrtf,
+            false => web_sys::PanningModelType::Equalpower
+        });
+        let [x, y, z] = self.position;
+        node.position_x().set_value(x);
+        node.position_y().set_value(y);
+        node.position_z().set_value(z);
+        if let Some([x, y, z]) = self.orientation {
+            node.orientation_x().set_value(x);
+            node.orientation_y().set_value(y);
+            node.orientation_z().set_value(z);
+        }
+        let (model, reference, rolloff) = match self.distance {
+            Distance::Linear { reference, max, rolloff } => {
+                node.set_max_distance(max);
+                (web_sys::DistanceModelType::Linear, reference, rolloff)
+            }
+            Distance::Inverse { reference, rolloff } => {
+                (web_sys::DistanceModelType::Inverse, reference, rolloff)
+            }
+            Distance::Exponential { reference, rolloff } => {
+                (web_sys::DistanceModelType::Exponential, reference, rolloff)
+            }
+        };
+        node.set_distance_model(model);
+        node.set_ref_distance(reference);
+        node.set_rolloff_factor(rolloff);
+        Ok(node)
+    }
+}
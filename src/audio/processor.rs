@@ -0,0 +1,177 @@
+use crate::prelude::*;
+use crate::codec::Bincode;
+use crate::shared::RingBuffer;
+use crate::worker::Transfer;
+use crate::worker::worklet::{ self, AudioPort, AudioProcessor, AudioProcessorNode, Buses };
+use serde::{ Deserialize, Serialize };
+use std::cell::RefCell;
+use std::marker::PhantomData;
+
+thread_local! {
+    static REGISTERED: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A function run on the audio thread for each render quantum of a [`RustProcessorNode`].
+///
+/// Declare it with [`#[webutil::audio_processor]`](crate::audio_processor) on a function
+/// taking `(feed: &[f32], outputs: &mut Buses)`. `feed` holds the interleaved samples taken
+/// from the node's feed for the quantum, with silence in place of any which weren't fed in
+/// time, and `outputs` the channels to fill. The function's name becomes a type which is
+/// passed to [`RustProcessorNode::new`]:
+/// ```ignore
+/// #[webutil::audio_processor]
+/// fn quieter(feed: &[f32], outputs: &mut Buses) { ... }
+///
+/// let node = RustProcessorNode::new::<quieter>("./pkg/my_app", 2, 8192).await?;
+/// ```
+pub trait SampleFn: 'static {
+    /// Name under which the function's processor is exported to JS and registered with the
+    /// audio worklet.
+    const ID: &'static str;
+
+    fn process(feed: &[f32], outputs: &mut Buses);
+}
+
+/// The default [`SampleFn`], which plays the fed samples as they are.
+pub struct Deinterleave;
+
+impl SampleFn for Deinterleave {
+    const ID: &'static str = "__webutil_audio_Deinterleave";
+
+    fn process(feed: &[f32], outputs: &mut Buses) {
+        let channels = outputs.len();
+        for (c, samples) in outputs.iter_mut().enumerate() {
+            for (i, sample) in samples.iter_mut().enumerate() {
+                *sample = feed[i * channels + c];
+            }
+        }
+    }
+}
+
+#[doc(hidden)]
+#[wasm_bindgen(js_name = "__webutil_audio_Deinterleave")]
+pub fn __webutil_audio_deinterleave(
+    port: web_sys::MessagePort
+) -> worklet::AudioProcessorHandle {
+    worklet::__new_audio_processor::<__SampleProcessor<Deinterleave>>(port)
+}
+
+/// A source node playing samples fed through a ring buffer, such as from an emulator or
+/// synthesizer running in a worker, after passing them through a Rust function on the audio
+/// thread.
+///
+/// Samples are interleaved, and only one thread may feed them, either the main thread
+/// through [`push`](Self::push) or a worker through the ring buffer from
+/// [`feed_buffer`](Self::feed_buffer). Like [`RingBuffer`], this needs the page to be
+/// cross-origin isolated.
+/// ```ignore
+/// let node = RustProcessorNode::new::<audio::Deinterleave>("./pkg/my_app", 2, 8192).await?;
+/// node.node().connect_with_audio_node(&audio::context()?.destination())?;
+/// emulator.send(&Transfer(node.feed_buffer().clone()))?;
+/// ```
+pub struct RustProcessorNode {
+    node: web_sys::AudioWorkletNode,
+    feed: RingBuffer<f32>,
+    channels: u32
+}
+
+impl RustProcessorNode {
+    /// Creates a node in the [`context`](super::context) with `channels` output channels,
+    /// which buffers up to `capacity` samples and processes them with `S`.
+    ///
+    /// `app` is the path to the wasm-bindgen output without extension, like for
+    /// [`worklet::register_audio`], which the application must be built for with
+    /// `--target web`.
+    pub async fn new<S: SampleFn>(
+        app: &str, channels: u32, capacity: u32
+    ) -> Result<Self, GeneralError> {
+        let context = super::context()?;
+        if !REGISTERED.with(|r| r.borrow().contains(&S::ID)) {
+            worklet::register_audio::<__SampleProcessor<S>>(&context, app, S::ID).await?;
+            REGISTERED.with(|r| r.borrow_mut().push(S::ID));
+        }
+
+        let options = web_sys::AudioWorkletNodeOptions::new();
+        options.set_number_of_inputs(0);
+        options.set_number_of_outputs(1);
+        options.set_output_channel_count(&js_sys::Array::of1(&channels.into()));
+        let node = AudioProcessorNode::<__SampleProcessor<S>>::new_with_options(
+            &context, S::ID, &options
+        )?;
+
+        let feed = RingBuffer::new(capacity)?;
+        node.send(&Setup { feed: Transfer(feed.buffer().clone()), channels })?;
+        Ok(RustProcessorNode { node: node.node().clone(), feed, channels })
+    }
+
+    /// The underlying node, for connecting it to the audio graph.
+    pub fn node(&self) -> &web_sys::AudioWorkletNode {
+        &self.node
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    /// Feeds interleaved samples, returning how many fit in the buffer.
+    pub fn push(&self, samples: &[f32]) -> usize {
+        self.feed.push_slice(samples)
+    }
+
+    /// How many samples are waiting to be played.
+    pub fn buffered(&self) -> u32 {
+        self.feed.len()
+    }
+
+    /// The shared buffer samples are fed through, for sending to a worker which attaches to
+    /// it with [`RingBuffer::from_buffer`].
+    pub fn feed_buffer(&self) -> &js_sys::SharedArrayBuffer {
+        self.feed.buffer()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[doc(hidden)]
+pub struct Setup {
+    feed: Transfer<js_sys::SharedArrayBuffer>,
+    channels: u32
+}
+
+/// The processor behind [`RustProcessorNode`], exported for each [`SampleFn`].
+#[doc(hidden)]
+pub struct __SampleProcessor<S: SampleFn> {
+    port: AudioPort<Self>,
+    feed: Option<RingBuffer<f32>>,
+    samples: Vec<f32>,
+    _phantom: PhantomData<fn() -> S>
+}
+
+impl<S: SampleFn> AudioProcessor for __SampleProcessor<S> {
+    type Incoming = Setup;
+    type Outgoing = ();
+    type Codec = Bincode;
+
+    fn new(port: AudioPort<Self>) -> Self {
+        __SampleProcessor { port, feed: None, samples: vec![], _phantom: PhantomData }
+    }
+
+    fn process(&mut self, _: &[Buses], outputs: &mut [Buses]) -> bool {
+        if let Some(setup) = self.port.try_recv() {
+            let channels = setup.channels as usize;
+            self.samples.resize(channels * outputs[0].first().map_or(0, Vec::len), 0.0);
+            self.feed = RingBuffer::from_buffer(setup.feed.0).ok();
+        }
+        let feed = match &self.feed {
+            Some(feed) => feed,
+            None => return true
+        };
+        let read = feed.pop_slice(&mut self.samples);
+        self.samples[read..].iter_mut().for_each(|s| *s = 0.0);
+        S::process(&self.samples, &mut outputs[0]);
+        true
+    }
+}
+
+impl<S: SampleFn> worklet::__AudioExport for __SampleProcessor<S> {
+    const ID: &'static str = S::ID;
+}
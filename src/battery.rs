@@ -0,0 +1,126 @@
+//! The device's battery, from the Battery Status API.
+//!
+//! Only Chromium-based browsers support it, so anything depending on it should have a
+//! fallback.
+//!
+//! ```ignore
+//! if let Ok(battery) = battery::status().await {
+//!     spawn(async move {
+//!         loop {
+//!             sync.set_interval(if battery.get().is_low() { 600_000 } else { 60_000 });
+//!             battery.changed().await;
+//!         }
+//!     });
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::global;
+use crate::channel::{ WatchReceiver, WatchSender, watch };
+use crate::event;
+use std::cell::RefCell;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+thread_local! {
+    static STATUS: RefCell<Option<WatchSender<BatteryStatus>>> = const { RefCell::new(None) };
+}
+
+/// Errors from getting the battery status.
+#[derive(Debug)]
+pub enum BatteryError {
+    /// The API isn't supported, or isn't allowed here, such as in a cross-origin frame.
+    Unavailable,
+    Other(JsValue)
+}
+
+impl From<JsValue> for BatteryError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotAllowedError") | Some("SecurityError") => BatteryError::Unavailable,
+            _ => BatteryError::Other(e)
+        }
+    }
+}
+
+impl From<BatteryError> for GeneralError {
+    fn from(e: BatteryError) -> Self {
+        match e {
+            BatteryError::Unavailable => GeneralError::message("battery status is unavailable"),
+            BatteryError::Other(e) => GeneralError::WebSys(e)
+        }
+    }
+}
+
+/// The state of the battery. Times are in seconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BatteryStatus {
+    /// How full the battery is, from `0.0` to `1.0`.
+    pub level: f64,
+    pub charging: bool,
+    /// How long until the battery is full, if it is charging and the browser can tell.
+    pub charging_time: Option<f64>,
+    /// How long until the battery is empty, if it isn't charging and the browser can tell.
+    pub discharging_time: Option<f64>
+}
+
+impl BatteryStatus {
+    /// Whether the battery is running low, so that work which isn't needed should be put off.
+    pub fn is_low(&self) -> bool {
+        !self.charging && self.level <= 0.2
+    }
+}
+
+impl From<&web_sys::BatteryManager> for BatteryStatus {
+    fn from(battery: &web_sys::BatteryManager) -> Self {
+        // unknown times are reported as infinite
+        let time = |t: f64| Some(t).filter(|t| t.is_finite());
+        BatteryStatus {
+            level: battery.level(),
+            charging: battery.charging(),
+            charging_time: time(battery.charging_time()),
+            discharging_time: time(battery.discharging_time())
+        }
+    }
+}
+
+/// The state of the battery, which changes as it charges and discharges.
+///
+/// Devices without a battery report one which is full and charging.
+pub async fn status() -> Result<WatchReceiver<BatteryStatus>, BatteryError> {
+    if let Some(status) = STATUS.with(|s| s.borrow().as_ref().map(WatchSender::subscribe)) {
+        return Ok(status);
+    }
+
+    let get_battery = global::navigator_feature::<JsValue>("getBattery")
+        .filter(JsValue::is_function)
+        .ok_or(BatteryError::Unavailable)?;
+    let get_battery: js_sys::Function = get_battery.unchecked_into();
+    let battery: web_sys::BatteryManager =
+        JsFuture::from(js_sys::Promise::from(get_battery.call0(&global::navigator())?)).await?
+            .unchecked_into();
+
+    Ok(STATUS.with(|s| {
+        let mut s = s.borrow_mut();
+        // another call may have finished first while this one was waiting
+        let sender = s.get_or_insert_with(|| {
+            // the battery is watched for as long as the page lives
+            let update = {
+                let battery = battery.clone();
+                move || STATUS.with(|s| if let Some(s) = &*s.borrow() {
+                    s.set_if_changed((&battery).into());
+                })
+            };
+            let upd = update.clone();
+            battery.add_event_listener(move |_: event::ChargingChange| upd()).forget();
+            let upd = update.clone();
+            battery.add_event_listener(move |_: event::LevelChange| upd()).forget();
+            let upd = update.clone();
+            battery.add_event_listener(move |_: event::ChargingTimeChange| upd()).forget();
+            let upd = update;
+            battery.add_event_listener(move |_: event::DischargingTimeChange| upd()).forget();
+            watch((&battery).into()).0
+        });
+        sender.subscribe()
+    }))
+}
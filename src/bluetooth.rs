@@ -0,0 +1,551 @@
+//! Bluetooth Low Energy devices, from the Web Bluetooth API.
+//!
+//! Only Chromium-based browsers support Web Bluetooth, and only for pages served securely.
+//! Devices are chosen by the user with [`request_device`], which needs to be called while
+//! handling a click or key press. Services are only available if they were asked for when
+//! requesting the device.
+//!
+//! ```ignore
+//! let device = bluetooth::request_device(
+//!     &RequestOptions::new().filter(BluetoothFilter::new().service("heart_rate"))
+//! ).await?;
+//! let gatt = device.connect().await?;
+//! let measurement = gatt.service("heart_rate").await?
+//!     .characteristic("heart_rate_measurement").await?;
+//! let notifications = measurement.notifications().await?;
+//! while let Some(value) = notifications.next().await {
+//!     heart_rate.set(value[1]);
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::global;
+use crate::promise::done;
+use crate::channel::{ Receiver, channel };
+use crate::event::{ self, ListenerHandle };
+use std::cell::RefCell;
+use wasm_bindgen::JsCast;
+
+#[wasm_bindgen]
+extern "C" {
+    // web-sys only binds Web Bluetooth as unstable
+    #[wasm_bindgen(extends = web_sys::EventTarget)]
+    type Bluetooth;
+    #[wasm_bindgen(method, catch, js_name = requestDevice)]
+    fn request_device(this: &Bluetooth, options: &JsValue) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = getDevices)]
+    fn get_devices(this: &Bluetooth) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = requestLEScan)]
+    fn request_le_scan(this: &Bluetooth, options: &JsValue) -> Result<js_sys::Promise, JsValue>;
+
+    #[derive(Clone, Debug, PartialEq)]
+    #[wasm_bindgen(extends = web_sys::EventTarget, js_name = BluetoothDevice)]
+    type RawDevice;
+    #[wasm_bindgen(method, getter)]
+    fn id(this: &RawDevice) -> String;
+    #[wasm_bindgen(method, getter)]
+    fn name(this: &RawDevice) -> Option<String>;
+    #[wasm_bindgen(method, getter)]
+    fn gatt(this: &RawDevice) -> Option<RawServer>;
+    #[wasm_bindgen(method, catch)]
+    fn forget(this: &RawDevice) -> Result<js_sys::Promise, JsValue>;
+
+    #[derive(Clone, Debug)]
+    type RawServer;
+    #[wasm_bindgen(method, getter)]
+    fn connected(this: &RawServer) -> bool;
+    #[wasm_bindgen(method, catch)]
+    fn connect(this: &RawServer) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method)]
+    fn disconnect(this: &RawServer);
+    #[wasm_bindgen(method, catch, js_name = getPrimaryService)]
+    fn get_primary_service(this: &RawServer, uuid: &str) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = getPrimaryServices)]
+    fn get_primary_services(this: &RawServer) -> Result<js_sys::Promise, JsValue>;
+
+    #[derive(Clone, Debug)]
+    type RawService;
+    #[wasm_bindgen(method, getter)]
+    fn uuid(this: &RawService) -> String;
+    #[wasm_bindgen(method, catch, js_name = getCharacteristic)]
+    fn get_characteristic(this: &RawService, uuid: &str) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = getCharacteristics)]
+    fn get_characteristics(this: &RawService) -> Result<js_sys::Promise, JsValue>;
+
+    #[derive(Clone, Debug)]
+    #[wasm_bindgen(extends = web_sys::EventTarget)]
+    type RawCharacteristic;
+    #[wasm_bindgen(method, getter, js_name = uuid)]
+    fn characteristic_uuid(this: &RawCharacteristic) -> String;
+    #[wasm_bindgen(method, getter)]
+    fn properties(this: &RawCharacteristic) -> JsValue;
+    #[wasm_bindgen(method, getter)]
+    fn value(this: &RawCharacteristic) -> Option<js_sys::DataView>;
+    #[wasm_bindgen(method, catch, js_name = readValue)]
+    fn read_value(this: &RawCharacteristic) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = writeValueWithResponse)]
+    fn write_value_with_response(
+        this: &RawCharacteristic, value: &[u8]
+    ) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = writeValueWithoutResponse)]
+    fn write_value_without_response(
+        this: &RawCharacteristic, value: &[u8]
+    ) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = startNotifications)]
+    fn start_notifications(this: &RawCharacteristic) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = stopNotifications)]
+    fn stop_notifications(this: &RawCharacteristic) -> Result<js_sys::Promise, JsValue>;
+
+    #[derive(Clone, Debug)]
+    type RawScan;
+    #[wasm_bindgen(method)]
+    fn stop(this: &RawScan);
+}
+
+/// Errors from using Bluetooth devices.
+#[derive(Debug)]
+pub enum BluetoothError {
+    /// Web Bluetooth isn't supported, such as because the page isn't served securely, or the
+    /// device has no Bluetooth adapter.
+    Unavailable,
+    /// The user didn't choose a device, or the device doesn't have the service or
+    /// characteristic.
+    NotFound,
+    /// The browser didn't allow using the device, such as because [`request_device`] wasn't
+    /// called while handling user input, or the service wasn't asked for.
+    PermissionDenied,
+    /// The device isn't connected, or disconnected during the operation.
+    Disconnected,
+    /// The characteristic doesn't support the operation, such as writing to a read-only one.
+    NotSupported,
+    Other(JsValue)
+}
+
+impl From<JsValue> for BluetoothError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotFoundError") => BluetoothError::NotFound,
+            Some("SecurityError") | Some("NotAllowedError") => BluetoothError::PermissionDenied,
+            Some("NetworkError") | Some("InvalidStateError") => BluetoothError::Disconnected,
+            Some("NotSupportedError") => BluetoothError::NotSupported,
+            _ => BluetoothError::Other(e)
+        }
+    }
+}
+
+impl From<BluetoothError> for GeneralError {
+    fn from(e: BluetoothError) -> Self {
+        let msg = match e {
+            BluetoothError::Unavailable => "Web Bluetooth is unavailable",
+            BluetoothError::NotFound => "Bluetooth device, service or characteristic not found",
+            BluetoothError::PermissionDenied => "Bluetooth permission denied",
+            BluetoothError::Disconnected => "Bluetooth device is disconnected",
+            BluetoothError::NotSupported => "Bluetooth operation is not supported",
+            BluetoothError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(msg)
+    }
+}
+
+fn bluetooth() -> Result<Bluetooth, BluetoothError> {
+    global::navigator_feature("bluetooth").ok_or(BluetoothError::Unavailable)
+}
+
+fn to_bytes(data: &js_sys::DataView) -> Vec<u8> {
+    js_sys::Uint8Array::new_with_byte_offset_and_length(
+        &data.buffer(), data.byte_offset() as u32, data.byte_length() as u32
+    ).to_vec()
+}
+
+fn strings(values: &[String]) -> js_sys::Array {
+    values.iter().map(|v| JsValue::from(v.as_str())).collect()
+}
+
+/// The full UUID of a standard service or characteristic with a 16 or 32 bit alias, such as
+/// `0x180d` for the heart rate service.
+///
+/// Services and characteristics can also be named by their full UUID, or by their name in the
+/// Bluetooth assigned numbers, such as `heart_rate`.
+pub fn alias(alias: u32) -> String {
+    format!("{:08x}-0000-1000-8000-00805f9b34fb", alias)
+}
+
+/// Which devices the user can choose from. A device matches if it matches everything set.
+#[derive(Clone, Debug, Default)]
+pub struct BluetoothFilter {
+    services: Vec<String>,
+    name: Option<String>,
+    name_prefix: Option<String>
+}
+
+impl BluetoothFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches devices advertising the service, which can then be used.
+    pub fn service(mut self, uuid: &str) -> Self {
+        self.services.push(uuid.to_owned());
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    pub fn name_prefix(mut self, prefix: &str) -> Self {
+        self.name_prefix = Some(prefix.to_owned());
+        self
+    }
+
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        let filter = js_sys::Object::new();
+        if !self.services.is_empty() {
+            js_sys::Reflect::set(&filter, &"services".into(), &strings(&self.services))?;
+        }
+        if let Some(name) = &self.name {
+            js_sys::Reflect::set(&filter, &"name".into(), &name.as_str().into())?;
+        }
+        if let Some(prefix) = &self.name_prefix {
+            js_sys::Reflect::set(&filter, &"namePrefix".into(), &prefix.as_str().into())?;
+        }
+        Ok(filter.into())
+    }
+}
+
+/// Which devices the user can choose from in [`request_device`] or scan for with [`scan`],
+/// and which services the page can use.
+#[derive(Clone, Debug, Default)]
+pub struct RequestOptions {
+    filters: Vec<BluetoothFilter>,
+    accept_all: bool,
+    optional_services: Vec<String>
+}
+
+impl RequestOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets the user choose devices matching `filter`, along with those of other filters.
+    pub fn filter(mut self, filter: BluetoothFilter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Lets the user choose any device, instead of using filters. Services still need to be
+    /// asked for with [`optional_service`](Self::optional_service).
+    pub fn accept_all(mut self) -> Self {
+        self.accept_all = true;
+        self
+    }
+
+    /// Asks for a service which isn't in the filters, so that it can be used if the device has
+    /// it.
+    pub fn optional_service(mut self, uuid: &str) -> Self {
+        self.optional_services.push(uuid.to_owned());
+        self
+    }
+
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        let options = js_sys::Object::new();
+        if self.accept_all {
+            js_sys::Reflect::set(&options, &"acceptAllDevices".into(), &true.into())?;
+        } else {
+            let filters = self.filters.iter()
+                .map(BluetoothFilter::to_js)
+                .collect::<Result<js_sys::Array, _>>()?;
+            js_sys::Reflect::set(&options, &"filters".into(), &filters)?;
+        }
+        let services = strings(&self.optional_services);
+        js_sys::Reflect::set(&options, &"optionalServices".into(), &services)?;
+        Ok(options.into())
+    }
+}
+
+/// Asks the user to choose a device, allowing the page to use it.
+pub async fn request_device(options: &RequestOptions) -> Result<BluetoothDevice, BluetoothError> {
+    let device = done(bluetooth()?.request_device(&options.to_js()?)).await?;
+    Ok(BluetoothDevice(device.unchecked_into()))
+}
+
+/// The devices which the user has allowed the page to use before. Chrome only supports this
+/// with the experimental web platform features flag.
+pub async fn devices() -> Result<Vec<BluetoothDevice>, BluetoothError> {
+    let devices = done(bluetooth()?.get_devices()).await?;
+    Ok(js_sys::Array::from(&devices).iter()
+        .map(|d| BluetoothDevice(d.unchecked_into()))
+        .collect())
+}
+
+/// A Bluetooth device the page may use.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BluetoothDevice(RawDevice);
+
+impl BluetoothDevice {
+    /// An identifier for the device, which is the same each time the page uses it but differs
+    /// from its address.
+    pub fn id(&self) -> String {
+        self.0.id()
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.0.name()
+    }
+
+    fn server(&self) -> Result<RawServer, BluetoothError> {
+        self.0.gatt().ok_or(BluetoothError::Unavailable)
+    }
+
+    /// Connects to the device's GATT server, for using its services.
+    pub async fn connect(&self) -> Result<Gatt, BluetoothError> {
+        let server = done(self.server()?.connect()).await?;
+        Ok(Gatt(server.unchecked_into()))
+    }
+
+    pub fn disconnect(&self) {
+        if let Ok(server) = self.server() {
+            server.disconnect();
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.server().map(|s| s.connected()).unwrap_or(false)
+    }
+
+    /// Waits for the device to disconnect, such as by going out of range.
+    pub async fn disconnected(&self) {
+        if self.is_connected() {
+            self.0.once::<event::GattServerDisconnected>().await;
+        }
+    }
+
+    /// Revokes the page's permission to use the device, so that the user has to choose it
+    /// again.
+    pub async fn forget(&self) -> Result<(), BluetoothError> {
+        done(self.0.forget()).await?;
+        Ok(())
+    }
+}
+
+/// The GATT server of a connected device, from [`BluetoothDevice::connect`].
+#[derive(Clone, Debug)]
+pub struct Gatt(RawServer);
+
+impl Gatt {
+    /// The primary service with the UUID, which must have been asked for when requesting the
+    /// device.
+    pub async fn service(&self, uuid: &str) -> Result<Service, BluetoothError> {
+        let service = done(self.0.get_primary_service(uuid)).await?;
+        Ok(Service(service.unchecked_into()))
+    }
+
+    /// The primary services the page may use.
+    pub async fn services(&self) -> Result<Vec<Service>, BluetoothError> {
+        let services = done(self.0.get_primary_services()).await?;
+        Ok(js_sys::Array::from(&services).iter().map(|s| Service(s.unchecked_into())).collect())
+    }
+}
+
+/// A service of a device, from [`Gatt::service`].
+#[derive(Clone, Debug)]
+pub struct Service(RawService);
+
+impl Service {
+    pub fn uuid(&self) -> String {
+        self.0.uuid()
+    }
+
+    pub async fn characteristic(&self, uuid: &str) -> Result<Characteristic, BluetoothError> {
+        let characteristic = done(self.0.get_characteristic(uuid)).await?;
+        Ok(Characteristic(characteristic.unchecked_into()))
+    }
+
+    pub async fn characteristics(&self) -> Result<Vec<Characteristic>, BluetoothError> {
+        let characteristics = done(self.0.get_characteristics()).await?;
+        Ok(js_sys::Array::from(&characteristics).iter()
+            .map(|c| Characteristic(c.unchecked_into()))
+            .collect())
+    }
+}
+
+/// What a [`Characteristic`] supports.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Properties {
+    pub read: bool,
+    pub write: bool,
+    pub write_without_response: bool,
+    pub notify: bool,
+    pub indicate: bool
+}
+
+/// A value of a service, from [`Service::characteristic`].
+#[derive(Clone, Debug)]
+pub struct Characteristic(RawCharacteristic);
+
+impl Characteristic {
+    pub fn uuid(&self) -> String {
+        self.0.characteristic_uuid()
+    }
+
+    pub fn properties(&self) -> Properties {
+        let properties = self.0.properties();
+        let flag = |key: &str| {
+            js_sys::Reflect::get(&properties, &key.into()).ok().is_some_and(|v| v.is_truthy())
+        };
+        Properties {
+            read: flag("read"),
+            write: flag("write"),
+            write_without_response: flag("writeWithoutResponse"),
+            notify: flag("notify"),
+            indicate: flag("indicate")
+        }
+    }
+
+    pub async fn read(&self) -> Result<Vec<u8>, BluetoothError> {
+        let value = done(self.0.read_value()).await?;
+        Ok(to_bytes(value.unchecked_ref()))
+    }
+
+    /// Writes the value, waiting for the device to acknowledge it.
+    pub async fn write(&self, value: &[u8]) -> Result<(), BluetoothError> {
+        done(self.0.write_value_with_response(value)).await?;
+        Ok(())
+    }
+
+    /// Writes the value without waiting for the device, which is faster but may be lost.
+    pub async fn write_without_response(&self, value: &[u8]) -> Result<(), BluetoothError> {
+        done(self.0.write_value_without_response(value)).await?;
+        Ok(())
+    }
+
+    /// Subscribes to changes to the value, from notifications or indications. Each
+    /// subscription gets every change, and the device keeps sending them until every
+    /// subscription to the characteristic is dropped.
+    pub async fn notifications(&self) -> Result<Notifications, BluetoothError> {
+        let (sender, values) = channel();
+        let listener = self.0.add_event_listener({
+            let characteristic = self.0.clone();
+            move |_: event::CharacteristicValueChanged| {
+                if let Some(value) = characteristic.value() {
+                    let _ = sender.send(to_bytes(&value));
+                }
+            }
+        });
+        // counted before starting, so that a subscription dropped meanwhile doesn't stop them
+        SUBSCRIPTIONS.with(|s| {
+            let mut s = s.borrow_mut();
+            match s.iter_mut().find(|(c, _)| js_sys::Object::is(c, &self.0)) {
+                Some((_, count)) => *count += 1,
+                None => s.push((self.0.clone(), 1))
+            }
+        });
+        let notifications = Notifications {
+            characteristic: self.0.clone(), values, _listener: listener
+        };
+        // starting again while already started does nothing
+        done(self.0.start_notifications()).await?;
+        Ok(notifications)
+    }
+}
+
+thread_local! {
+    /// How many [`Notifications`] there are for each characteristic.
+    static SUBSCRIPTIONS: RefCell<Vec<(RawCharacteristic, u32)>> = const {
+        RefCell::new(vec![])
+    };
+}
+
+/// Changes to a characteristic's value, from [`Characteristic::notifications`]. The
+/// subscription ends when this is dropped.
+pub struct Notifications {
+    characteristic: RawCharacteristic,
+    values: Receiver<Vec<u8>>,
+    _listener: ListenerHandle
+}
+
+impl Notifications {
+    pub fn try_next(&self) -> Option<Vec<u8>> {
+        self.values.try_recv().ok()
+    }
+
+    /// Waits for the value to change. This waits forever if the device disconnects, which can
+    /// be watched for with [`BluetoothDevice::disconnected`].
+    pub async fn next(&self) -> Option<Vec<u8>> {
+        self.values.recv().await
+    }
+}
+
+impl Drop for Notifications {
+    fn drop(&mut self) {
+        let last = SUBSCRIPTIONS.with(|s| {
+            let mut s = s.borrow_mut();
+            let i = s.iter().position(|(c, _)| js_sys::Object::is(c, &self.characteristic))?;
+            s[i].1 -= 1;
+            (s[i].1 == 0).then(|| s.swap_remove(i))
+        });
+        if last.is_some() {
+            let _ = self.characteristic.stop_notifications();
+        }
+    }
+}
+
+/// An advertisement from a nearby device, from [`Scan`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Advertisement {
+    pub device: BluetoothDevice,
+    pub name: Option<String>,
+    /// Received signal strength in dBm.
+    pub rssi: Option<i16>,
+    /// Transmission power in dBm, which together with the RSSI tells how far away the device
+    /// is.
+    pub tx_power: Option<i16>
+}
+
+/// Scans for advertisements from nearby devices matching `options`. Services can't be used
+/// through scanned devices until they are requested.
+///
+/// Chrome only supports this with the experimental web platform features flag.
+pub async fn scan(options: &RequestOptions) -> Result<Scan, BluetoothError> {
+    let bluetooth = bluetooth()?;
+    let (sender, advertisements) = channel();
+    let listener = bluetooth.add_event_listener(move |e: event::AdvertisementReceived| {
+        let get = |key: &str| js_sys::Reflect::get(&e, &key.into()).ok();
+        let device = match get("device") {
+            Some(device) if !device.is_undefined() => BluetoothDevice(device.unchecked_into()),
+            _ => return
+        };
+        let _ = sender.send(Advertisement {
+            device,
+            name: get("name").and_then(|n| n.as_string()),
+            rssi: get("rssi").and_then(|r| r.as_f64()).map(|r| r as i16),
+            tx_power: get("txPower").and_then(|t| t.as_f64()).map(|t| t as i16)
+        });
+    });
+    let scan = done(bluetooth.request_le_scan(&options.to_js()?)).await?;
+    Ok(Scan { scan: scan.unchecked_into(), advertisements, _listener: listener })
+}
+
+/// Advertisements being scanned for, from [`scan`]. Scanning stops when this is dropped.
+pub struct Scan {
+    scan: RawScan,
+    advertisements: Receiver<Advertisement>,
+    _listener: ListenerHandle
+}
+
+impl Scan {
+    pub fn try_next(&self) -> Option<Advertisement> {
+        self.advertisements.try_recv().ok()
+    }
+
+    pub async fn next(&self) -> Advertisement {
+        self.advertisements.recv().await.unwrap()
+    }
+}
+
+impl Drop for Scan {
+    fn drop(&mut self) {
+        self.scan.stop();
+    }
+}
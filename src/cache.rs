@@ -0,0 +1,192 @@
+//! The Cache API, for storing responses to requests, such as in service workers or to keep
+//! downloaded assets around.
+//!
+//! ```ignore
+//! let cache = cache::open("assets-v1").await?;
+//! cache.add_all(&["/level1.json", "/atlas.png"]).await?;
+//! let level = match cache.match_request("/level1.json").await? {
+//!     Some(response) => response,
+//!     None => http::get("/level1.json").send().await?
+//! };
+//! ```
+
+use crate::prelude::*;
+use crate::http::Response;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Errors from using caches.
+#[derive(Debug)]
+pub enum CacheError {
+    /// The Cache API can't be used, because the page isn't served securely or the browser has
+    /// disabled it.
+    Unavailable,
+    /// There isn't enough space left to store the response.
+    QuotaExceeded,
+    /// Fetching a request to add failed, or its response was not successful.
+    Fetch(JsValue),
+    Other(JsValue)
+}
+
+impl From<JsValue> for CacheError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("QuotaExceededError") => CacheError::QuotaExceeded,
+            Some("SecurityError") => CacheError::Unavailable,
+            _ => CacheError::Other(e)
+        }
+    }
+}
+
+impl From<CacheError> for GeneralError {
+    fn from(e: CacheError) -> Self {
+        match e {
+            CacheError::Unavailable => GeneralError::message("caches are unavailable"),
+            CacheError::QuotaExceeded => GeneralError::message("storage quota exceeded"),
+            CacheError::Fetch(e) | CacheError::Other(e) => GeneralError::WebSys(e)
+        }
+    }
+}
+
+/// What cache entries are stored and looked up by: a URL, or a `web_sys::Request` to match
+/// on more than the URL.
+pub trait CacheRequest {
+    #[doc(hidden)]
+    fn to_request(&self) -> Result<web_sys::Request, JsValue>;
+}
+
+impl CacheRequest for str {
+    fn to_request(&self) -> Result<web_sys::Request, JsValue> {
+        web_sys::Request::new_with_str(self)
+    }
+}
+
+impl CacheRequest for String {
+    fn to_request(&self) -> Result<web_sys::Request, JsValue> {
+        web_sys::Request::new_with_str(self)
+    }
+}
+
+impl CacheRequest for web_sys::Request {
+    fn to_request(&self) -> Result<web_sys::Request, JsValue> {
+        Ok(Clone::clone(self))
+    }
+}
+
+/// The origin's caches.
+pub fn caches() -> Result<web_sys::CacheStorage, CacheError> {
+    let caches = js_sys::Reflect::get(&js_sys::global(), &"caches".into())
+        .map_err(|_| CacheError::Unavailable)?;
+    if caches.is_undefined() {
+        return Err(CacheError::Unavailable);
+    }
+    Ok(caches.unchecked_into())
+}
+
+/// Opens the cache `name`, creating it if it doesn't exist.
+pub async fn open(name: &str) -> Result<Cache, CacheError> {
+    let cache = JsFuture::from(caches()?.open(name)).await?;
+    Ok(Cache(cache.unchecked_into()))
+}
+
+/// Whether the cache `name` exists.
+pub async fn has(name: &str) -> Result<bool, CacheError> {
+    let has = JsFuture::from(caches()?.has(name)).await?;
+    Ok(has.is_truthy())
+}
+
+/// Deletes the cache `name`, returning whether it existed.
+pub async fn delete(name: &str) -> Result<bool, CacheError> {
+    let deleted = JsFuture::from(caches()?.delete(name)).await?;
+    Ok(deleted.is_truthy())
+}
+
+/// The names of the caches, in the order they were created.
+pub async fn names() -> Result<Vec<String>, CacheError> {
+    let names: js_sys::Array = JsFuture::from(caches()?.keys()).await?.unchecked_into();
+    Ok(names.iter().filter_map(|name| name.as_string()).collect())
+}
+
+/// Looks up `request` in every cache, in the order they were created.
+pub async fn match_any(
+    request: &(impl CacheRequest + ?Sized)
+) -> Result<Option<Response>, CacheError> {
+    let response = JsFuture::from(caches()?.match_with_request(&request.to_request()?)).await?;
+    Ok(to_response(response))
+}
+
+/// A cache of responses.
+#[derive(Clone, Debug)]
+pub struct Cache(web_sys::Cache);
+
+impl Cache {
+    /// Stores `response` as the response to `request`, replacing any stored already.
+    ///
+    /// This reads the response's body, so use [`Response::try_clone`] to keep using it.
+    pub async fn put(
+        &self, request: &(impl CacheRequest + ?Sized), response: &Response
+    ) -> Result<(), CacheError> {
+        let put = self.0.put_with_request(&request.to_request()?, response.raw());
+        JsFuture::from(put).await?;
+        Ok(())
+    }
+
+    /// Fetches `request` and stores the response. Fails without storing anything if the
+    /// response isn't successful.
+    pub async fn add(&self, request: &(impl CacheRequest + ?Sized)) -> Result<(), CacheError> {
+        let added = self.0.add_with_request(&request.to_request()?);
+        JsFuture::from(added).await.map_err(fetch_error)?;
+        Ok(())
+    }
+
+    /// Fetches every request and stores the responses. Fails without storing anything if any
+    /// response isn't successful.
+    pub async fn add_all<R: CacheRequest + ?Sized>(
+        &self, requests: &[&R]
+    ) -> Result<(), CacheError> {
+        let requests = requests.iter()
+            .map(|request| request.to_request().map(JsValue::from))
+            .collect::<Result<js_sys::Array, _>>()?;
+        let added = self.0.add_all_with_request_sequence(&requests);
+        JsFuture::from(added).await.map_err(fetch_error)?;
+        Ok(())
+    }
+
+    /// Looks up the stored response to `request`.
+    pub async fn match_request(
+        &self, request: &(impl CacheRequest + ?Sized)
+    ) -> Result<Option<Response>, CacheError> {
+        let response = JsFuture::from(self.0.match_with_request(&request.to_request()?)).await?;
+        Ok(to_response(response))
+    }
+
+    /// Deletes the stored response to `request`, returning whether there was one.
+    pub async fn delete(&self, request: &(impl CacheRequest + ?Sized)) -> Result<bool, CacheError> {
+        let deleted = JsFuture::from(self.0.delete_with_request(&request.to_request()?)).await?;
+        Ok(deleted.is_truthy())
+    }
+
+    /// The requests which have stored responses.
+    pub async fn keys(&self) -> Result<Vec<web_sys::Request>, CacheError> {
+        let keys: js_sys::Array = JsFuture::from(self.0.keys()).await?.unchecked_into();
+        Ok(keys.iter().map(JsCast::unchecked_into).collect())
+    }
+
+    /// The underlying cache.
+    pub fn raw(&self) -> &web_sys::Cache {
+        &self.0
+    }
+}
+
+fn to_response(response: JsValue) -> Option<Response> {
+    response.dyn_into::<web_sys::Response>().ok().map(Response::from)
+}
+
+/// Fetches fail with a `TypeError`, unlike the other reasons adding fails.
+fn fetch_error(e: JsValue) -> CacheError {
+    if e.is_instance_of::<js_sys::TypeError>() {
+        CacheError::Fetch(e)
+    } else {
+        e.into()
+    }
+}
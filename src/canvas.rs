@@ -0,0 +1,331 @@
+//! Setting up 2D canvases, so that drawing is sharp on high DPI screens and the canvas follows
+//! its size in the page.
+//!
+//! ```ignore
+//! let canvas = Canvas2d::new(&element)?;
+//! let resizes = canvas.auto_resize()?;
+//! loop {
+//!     global::animation_frame().await;
+//!     canvas.clear();
+//!     {
+//!         let ctx = canvas.save();
+//!         ctx.translate(canvas.width() / 2.0, canvas.height() / 2.0)?;
+//!         ctx.rotate(angle)?;
+//!         canvas.path().rect(-10.0, -10.0, 20.0, 20.0).fill();
+//!     }
+//!     for (i, line) in canvas.wrap_text(&message, canvas.width()).iter().enumerate() {
+//!         canvas.context().fill_text(line, 0.0, 20.0 * (i + 1) as f64)?;
+//!     }
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, channel };
+use std::cell::Cell;
+use std::ops::Deref;
+use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+
+/// A canvas with a 2D context, drawn to in CSS pixels while its backing store matches the
+/// device's pixels.
+#[derive(Clone, Debug)]
+pub struct Canvas2d(Rc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    canvas: web_sys::HtmlCanvasElement,
+    context: web_sys::CanvasRenderingContext2d,
+    width: Cell<f64>,
+    height: Cell<f64>,
+    pixel_ratio: Cell<f64>
+}
+
+impl Canvas2d {
+    /// Gets the 2D context of `canvas` and sizes it to fit its current size in the page.
+    pub fn new(canvas: &web_sys::HtmlCanvasElement) -> Result<Self, GeneralError> {
+        let context = canvas.get_context("2d")?.ok_or_else(|| {
+            GeneralError::message("canvas already has another kind of context")
+        })?;
+        let this = Canvas2d(Rc::new(Inner {
+            canvas: canvas.clone(),
+            context: context.unchecked_into(),
+            width: Cell::new(0.0),
+            height: Cell::new(0.0),
+            pixel_ratio: Cell::new(1.0)
+        }));
+        this.fit();
+        Ok(this)
+    }
+
+    pub fn canvas(&self) -> &web_sys::HtmlCanvasElement {
+        &self.0.canvas
+    }
+
+    pub fn context(&self) -> &web_sys::CanvasRenderingContext2d {
+        &self.0.context
+    }
+
+    /// Width in CSS pixels, which drawing is measured in.
+    pub fn width(&self) -> f64 {
+        self.0.width.get()
+    }
+
+    /// Height in CSS pixels, which drawing is measured in.
+    pub fn height(&self) -> f64 {
+        self.0.height.get()
+    }
+
+    /// Device pixels per CSS pixel.
+    pub fn pixel_ratio(&self) -> f64 {
+        self.0.pixel_ratio.get()
+    }
+
+    /// Resizes the backing store to the canvas's current size in the page, returning whether
+    /// it changed. Resizing clears the canvas and resets the context's state.
+    ///
+    /// [`auto_resize`](Self::auto_resize) does this whenever the size changes.
+    pub fn fit(&self) -> bool {
+        let ratio = web_sys::window().map_or(1.0, |w| w.device_pixel_ratio());
+        let width = self.0.canvas.client_width() as f64;
+        let height = self.0.canvas.client_height() as f64;
+        self.resize(width, height, (width * ratio).round(), (height * ratio).round())
+    }
+
+    fn resize(&self, width: f64, height: f64, pixel_width: f64, pixel_height: f64) -> bool {
+        let ratio = match width > 0.0 {
+            true => pixel_width / width,
+            false => 1.0
+        };
+        let changed = width != self.0.width.get() || height != self.0.height.get()
+            || ratio != self.0.pixel_ratio.get();
+        self.0.width.set(width);
+        self.0.height.set(height);
+        self.0.pixel_ratio.set(ratio);
+
+        // setting the size clears the canvas even when it is the same, so only set it when it
+        // differs, such as when the `width` and `height` attributes already match
+        let canvas = &self.0.canvas;
+        let (pixel_width, pixel_height) = (pixel_width as u32, pixel_height as u32);
+        let resized = canvas.width() != pixel_width || canvas.height() != pixel_height;
+        if resized {
+            canvas.set_width(pixel_width);
+            canvas.set_height(pixel_height);
+        }
+        if resized || changed {
+            let _ = self.0.context.set_transform(ratio, 0.0, 0.0, ratio, 0.0, 0.0);
+        }
+        resized || changed
+    }
+
+    /// Keeps the backing store sized to the canvas as it changes size in the page, or moves
+    /// to a screen with a different pixel ratio, until the returned handle is dropped.
+    ///
+    /// The handle receives the new size in CSS pixels after each resize, since the canvas
+    /// needs redrawing.
+    pub fn auto_resize(&self) -> Result<AutoResize, GeneralError> {
+        let (sender, resizes) = channel();
+        let this = self.clone();
+        let callback = Closure::<dyn FnMut(js_sys::Array)>::new(move |entries: js_sys::Array| {
+            let entry: web_sys::ResizeObserverEntry = match entries.get(0).dyn_into() {
+                Ok(entry) => entry,
+                Err(_) => return
+            };
+            let rect = entry.content_rect();
+            let (width, height) = (rect.width(), rect.height());
+            // the exact device pixel size is only reported by some browsers
+            let resized = match entry.device_pixel_content_box_size().get(0).dyn_into() {
+                Ok(size) => {
+                    let size: web_sys::ResizeObserverSize = size;
+                    this.resize(width, height, size.inline_size(), size.block_size())
+                }
+                Err(_) => {
+                    let ratio = web_sys::window().map_or(1.0, |w| w.device_pixel_ratio());
+                    this.resize(width, height, (width * ratio).round(), (height * ratio).round())
+                }
+            };
+            if resized {
+                let _ = sender.send((width, height));
+            }
+        });
+        let observer = web_sys::ResizeObserver::new(callback.as_ref().unchecked_ref())?;
+        let options = web_sys::ResizeObserverOptions::new();
+        options.set_box(web_sys::ResizeObserverBoxOptions::DevicePixelContentBox);
+        observer.observe_with_options(&self.0.canvas, &options);
+        Ok(AutoResize { resizes, observer, _callback: callback })
+    }
+
+    /// Clears the whole canvas.
+    pub fn clear(&self) {
+        self.0.context.clear_rect(0.0, 0.0, self.width(), self.height());
+    }
+
+    /// Saves the context's state, such as its transform and styles, restoring it when the
+    /// returned guard is dropped. The guard gives access to the context.
+    pub fn save(&self) -> Saved<'_> {
+        self.0.context.save();
+        Saved(&self.0.context)
+    }
+
+    /// Starts a new path, discarding any earlier one.
+    pub fn path(&self) -> Path<'_> {
+        self.0.context.begin_path();
+        Path(&self.0.context)
+    }
+
+    /// Measures `text` in the context's current font.
+    pub fn measure_text(&self, text: &str) -> TextSize {
+        match self.0.context.measure_text(text) {
+            Ok(metrics) => TextSize {
+                width: metrics.width(),
+                ascent: metrics.font_bounding_box_ascent(),
+                descent: metrics.font_bounding_box_descent()
+            },
+            Err(_) => TextSize { width: 0.0, ascent: 0.0, descent: 0.0 }
+        }
+    }
+
+    /// Splits `text` into lines no wider than `max_width` in the context's current font,
+    /// breaking between words. Words wider than `max_width` get a line of their own. Newlines
+    /// in `text` always break.
+    pub fn wrap_text(&self, text: &str, max_width: f64) -> Vec<String> {
+        let mut lines = vec![];
+        for paragraph in text.split('\n') {
+            let mut line = String::new();
+            for word in paragraph.split_whitespace() {
+                let candidate = match line.is_empty() {
+                    true => word.to_owned(),
+                    false => format!("{} {}", line, word)
+                };
+                if line.is_empty() || self.measure_text(&candidate).width <= max_width {
+                    line = candidate;
+                } else {
+                    lines.push(std::mem::replace(&mut line, word.to_owned()));
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+/// Keeps a [`Canvas2d`] sized to fit, from [`Canvas2d::auto_resize`]. Resizing stops when
+/// this is dropped.
+pub struct AutoResize {
+    resizes: Receiver<(f64, f64)>,
+    observer: web_sys::ResizeObserver,
+    _callback: Closure<dyn FnMut(js_sys::Array)>
+}
+
+impl AutoResize {
+    pub fn try_next(&self) -> Option<(f64, f64)> {
+        self.resizes.try_recv().ok()
+    }
+
+    /// Waits for the canvas to be resized, returning its new size in CSS pixels.
+    pub async fn next(&self) -> (f64, f64) {
+        self.resizes.recv().await.unwrap()
+    }
+}
+
+impl Drop for AutoResize {
+    fn drop(&mut self) {
+        self.observer.disconnect();
+    }
+}
+
+/// Restores the context's state when dropped, from [`Canvas2d::save`].
+pub struct Saved<'a>(&'a web_sys::CanvasRenderingContext2d);
+
+impl Deref for Saved<'_> {
+    type Target = web_sys::CanvasRenderingContext2d;
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl Drop for Saved<'_> {
+    fn drop(&mut self) {
+        self.0.restore();
+    }
+}
+
+/// A path being built, from [`Canvas2d::path`]. Dropping it without filling or stroking it
+/// draws nothing.
+pub struct Path<'a>(&'a web_sys::CanvasRenderingContext2d);
+
+impl Path<'_> {
+    pub fn move_to(self, x: f64, y: f64) -> Self {
+        self.0.move_to(x, y);
+        self
+    }
+
+    pub fn line_to(self, x: f64, y: f64) -> Self {
+        self.0.line_to(x, y);
+        self
+    }
+
+    pub fn quadratic_to(self, cx: f64, cy: f64, x: f64, y: f64) -> Self {
+        self.0.quadratic_curve_to(cx, cy, x, y);
+        self
+    }
+
+    pub fn bezier_to(self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64) -> Self {
+        self.0.bezier_curve_to(c1x, c1y, c2x, c2y, x, y);
+        self
+    }
+
+    /// Adds an arc clockwise from `start` to `end`, in radians.
+    pub fn arc(self, x: f64, y: f64, radius: f64, start: f64, end: f64) -> Self {
+        let _ = self.0.arc(x, y, radius, start, end);
+        self
+    }
+
+    pub fn circle(self, x: f64, y: f64, radius: f64) -> Self {
+        self.0.move_to(x + radius, y);
+        self.arc(x, y, radius, 0.0, std::f64::consts::TAU)
+    }
+
+    pub fn rect(self, x: f64, y: f64, width: f64, height: f64) -> Self {
+        self.0.rect(x, y, width, height);
+        self
+    }
+
+    pub fn close(self) -> Self {
+        self.0.close_path();
+        self
+    }
+
+    /// Fills the path with the context's fill style.
+    pub fn fill(self) {
+        self.0.fill();
+    }
+
+    /// Strokes the path with the context's stroke style.
+    pub fn stroke(self) {
+        self.0.stroke();
+    }
+
+    /// Restricts drawing to inside the path, until the state is restored. Usually used with
+    /// [`Canvas2d::save`].
+    pub fn clip(self) {
+        self.0.clip();
+    }
+}
+
+/// The size of some text, in CSS pixels, from [`Canvas2d::measure_text`]. The ascent and
+/// descent are of the font rather than the text, so that lines are spaced evenly.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TextSize {
+    pub width: f64,
+    /// Distance from the baseline to the top of the font's tallest glyphs.
+    pub ascent: f64,
+    /// Distance from the baseline to the bottom of the font's lowest glyphs.
+    pub descent: f64
+}
+
+impl TextSize {
+    pub fn height(&self) -> f64 {
+        self.ascent + self.descent
+    }
+}
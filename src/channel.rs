@@ -2,16 +2,20 @@ use std::future::Future;
 use std::task::{ Poll, Context, Waker };
 use std::rc::Rc;
 use std::pin::Pin;
-use std::cell::RefCell;
+use std::cell::{ Cell, Ref, RefCell };
 use std::collections::VecDeque;
+use crate::global::{ self, IntoDelay };
 
 pub struct Sender<T>(Rc<RefCell<ChannelState<T>>>);
 
-pub struct Receiver<T>(Rc<RefCell<ChannelState<T>>>);
+/// Receiving side of a [`channel`]. The `u64` identifies its waker slot.
+pub struct Receiver<T>(Rc<RefCell<ChannelState<T>>>, u64);
 
 struct ChannelState<T> {
     recvs: u32,
-    waker: Option<Waker>,
+    /// Every receiver waiting for a value, since clones of a receiver can wait at once.
+    wakers: Wakers,
+    close_waker: Option<Waker>,
     senders: u32,
     queue: VecDeque<T>
 }
@@ -21,14 +25,46 @@ impl<T> Sender<T> {
         let mut state = self.0.borrow_mut();
         if state.recvs > 0 {
             state.queue.push_back(v);
-            if let Some(waker) = state.waker.take() {
-                waker.wake()
-            }
+            state.wake();
             Ok(())
         } else {
             Err(v)
         }
     }
+
+    /// Creates a new receiver for this channel, unless every receiver has already been dropped.
+    pub fn subscribe(&self) -> Option<Receiver<T>> {
+        let mut state = self.0.borrow_mut();
+        if state.recvs > 0 {
+            state.recvs += 1;
+            let id = state.wakers.id();
+            Some(Receiver(self.0.clone(), id))
+        } else {
+            None
+        }
+    }
+
+    /// Drops this sender, returning a future which resolves once every receiver has been
+    /// dropped.
+    pub fn close(self) -> Closed<T> {
+        Closed(self.0.clone())
+    }
+}
+
+/// Future returned by [`Sender::close`].
+pub struct Closed<T>(Rc<RefCell<ChannelState<T>>>);
+
+impl<T> Future for Closed<T> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()> {
+        let mut state = self.0.borrow_mut();
+        if state.recvs == 0 {
+            Poll::Ready(())
+        } else {
+            state.close_waker.replace(ctx.waker().clone());
+            Poll::Pending
+        }
+    }
 }
 
 impl<T> Drop for Sender<T> {
@@ -36,9 +72,7 @@ impl<T> Drop for Sender<T> {
         let mut state = self.0.borrow_mut();
         state.senders -= 1;
         if state.senders == 0 {
-            if let Some(waker) = state.waker.take() {
-                waker.wake();
-            }
+            state.wake();
         }
     }
 }
@@ -58,16 +92,73 @@ impl<T> Future for RecvFuture<'_, T> {
             Ok(v) => Poll::Ready(Some(v)),
             Err(TryRecvError::Closed) => Poll::Ready(None),
             Err(TryRecvError::Empty) => {
-                self.0 .0.borrow_mut().waker.replace(ctx.waker().clone());
+                self.0 .0.borrow_mut().wakers.register(self.0 .1, ctx.waker());
                 Poll::Pending
             }
         }
     }
 }
 
+impl<T> ChannelState<T> {
+    /// Wakes every waiting receiver. Those which find the queue empty again go back to waiting.
+    fn wake(&mut self) {
+        self.wakers.wake();
+    }
+}
+
+/// Wakers of the receivers waiting on a channel. Each receiver has one slot, so one which is
+/// polled over and over doesn't pile up wakers.
+#[derive(Default)]
+struct Wakers {
+    slots: Vec<(u64, Waker)>,
+    next_id: u64
+}
+
+impl Wakers {
+    /// A slot for a new receiver.
+    fn id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn register(&mut self, id: u64, waker: &Waker) {
+        match self.slots.iter_mut().find(|(i, _)| *i == id) {
+            Some((_, w)) => if !w.will_wake(waker) {
+                *w = waker.clone();
+            }
+            None => self.slots.push((id, waker.clone()))
+        }
+    }
+
+    fn remove(&mut self, id: u64) {
+        self.slots.retain(|(i, _)| *i != id);
+    }
+
+    fn wake(&mut self) {
+        for (_, waker) in self.slots.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
 impl<T> Receiver<T> {
     pub async fn recv(&self) -> Option<T> {
-        RecvFuture(&self).await
+        RecvFuture(self).await
+    }
+
+    /// Receives a value, giving up after `timeout`.
+    pub async fn recv_timeout(&self, timeout: impl IntoDelay) -> Result<T, RecvTimeoutError> {
+        let mut recv = RecvFuture(self);
+        let mut sleep = global::sleep(timeout);
+        std::future::poll_fn(|cx| {
+            if let Poll::Ready(v) = Pin::new(&mut recv).poll(cx) {
+                Poll::Ready(v.ok_or(RecvTimeoutError::Closed))
+            } else if Pin::new(&mut sleep).poll(cx).is_ready() {
+                Poll::Ready(Err(RecvTimeoutError::Timeout))
+            } else {
+                Poll::Pending
+            }
+        }).await
     }
 
     pub fn try_recv(&self) -> Result<T, TryRecvError> {
@@ -81,18 +172,57 @@ impl<T> Receiver<T> {
             }
         }
     }
+
+    /// Returns a receiver which only gets a value once `delay` milliseconds pass without
+    /// another one arriving here. See [`global::debounce`].
+    ///
+    /// A value still waiting out the delay when this receiver closes is dropped.
+    pub fn debounce(self, delay: impl IntoDelay) -> Receiver<T> where T: 'static {
+        let delay = delay.into_millis();
+        self.adapt(|s| global::debounce(delay, move |v| { let _ = s.send(v); }))
+    }
+
+    /// Returns a receiver which gets at most one value every `period` milliseconds, the latest
+    /// one to arrive here. See [`global::throttle`].
+    pub fn throttle(self, period: impl IntoDelay) -> Receiver<T> where T: 'static {
+        let period = period.into_millis();
+        self.adapt(|s| global::throttle(period, move |v| { let _ = s.send(v); }))
+    }
+
+    fn adapt<F: FnMut(T) + 'static>(self, f: impl FnOnce(Sender<T>) -> F) -> Receiver<T>
+    where
+        T: 'static
+    {
+        let (s, r) = channel();
+        let mut f = f(s);
+        wasm_bindgen_futures::spawn_local(async move {
+            while let Some(v) = self.recv().await {
+                f(v);
+            }
+        });
+        r
+    }
 }
 
 impl<T> Drop for Receiver<T> {
     fn drop(&mut self) {
-        self.0.borrow_mut().recvs -= 1;
+        let mut state = self.0.borrow_mut();
+        state.recvs -= 1;
+        state.wakers.remove(self.1);
+        if state.recvs == 0 {
+            if let Some(waker) = state.close_waker.take() {
+                waker.wake();
+            }
+        }
     }
 }
 
 impl<T> Clone for Receiver<T> {
     fn clone(&self) -> Self {
-        self.0.borrow_mut().recvs += 1;
-        Receiver(self.0.clone())
+        let mut state = self.0.borrow_mut();
+        state.recvs += 1;
+        let id = state.wakers.id();
+        Receiver(self.0.clone(), id)
     }
 }
 
@@ -100,10 +230,12 @@ pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
     let state = Rc::new(RefCell::new(ChannelState {
         recvs: 1,
         senders: 1,
-        waker: None,
+        wakers: Wakers::default(),
+        close_waker: None,
         queue: VecDeque::new()
     }));
-    (Sender(state.clone()), Receiver(state))
+    let id = state.borrow_mut().wakers.id();
+    (Sender(state.clone()), Receiver(state, id))
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -112,6 +244,12 @@ pub enum TryRecvError {
     Closed
 }
 
+#[derive(Debug, Eq, PartialEq)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Closed
+}
+
 struct OneshotState<T> {
     v: Option<T>,
     waker: Option<Waker>,
@@ -119,6 +257,10 @@ struct OneshotState<T> {
     send_exists: bool
 }
 
+/// Creates a channel for sending a single value.
+///
+/// If the [`Oneshot`] is dropped without resolving, such as when it was moved into a JS
+/// callback which gets dropped without ever firing, the [`Once`] resolves to `None`.
 pub fn oneshot<T>() -> (Oneshot<T>, Once<T>) {
     let state = Rc::new(RefCell::new(OneshotState {
         v: None,
@@ -146,6 +288,22 @@ impl<T> Oneshot<T> {
             Err(v)
         }
     }
+
+    /// Whether the receiving side was dropped, in which case there is no point resolving.
+    pub fn is_closed(&self) -> bool {
+        !self.0.borrow().recv_exists
+    }
+}
+
+impl<T, E> Oneshot<Result<T, E>> {
+    /// Resolves with an error. The receiver sees `Some(Err(e))`.
+    pub fn fail(self, e: E) -> Result<(), E> {
+        match self.resolve(Err(e)) {
+            Ok(()) => Ok(()),
+            Err(Err(e)) => Err(e),
+            Err(Ok(_)) => unreachable!()
+        }
+    }
 }
 
 impl<T> Once<T> {
@@ -187,4 +345,127 @@ impl<T> Future for Once<T> {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Creates a channel holding a single value which can be changed and watched for changes.
+///
+/// Receivers only see the latest value; intermediate values set between two looks are skipped.
+pub fn watch<T>(initial: T) -> (WatchSender<T>, WatchReceiver<T>) {
+    let state = Rc::new(RefCell::new(WatchState {
+        value: initial,
+        version: 0,
+        wakers: Wakers::default(),
+        sender_exists: true
+    }));
+    let id = state.borrow_mut().wakers.id();
+    (WatchSender(state.clone()), WatchReceiver(state, Cell::new(0), id))
+}
+
+struct WatchState<T> {
+    value: T,
+    version: u64,
+    wakers: Wakers,
+    sender_exists: bool
+}
+
+pub struct WatchSender<T>(Rc<RefCell<WatchState<T>>>);
+
+/// Receiver of a [`watch`] channel. Each clone tracks which value it has seen separately.
+pub struct WatchReceiver<T>(Rc<RefCell<WatchState<T>>>, Cell<u64>, u64);
+
+impl<T> WatchSender<T> {
+    pub fn set(&self, v: T) {
+        self.update(|value| *value = v);
+    }
+
+    /// Sets the value if it differs from the current one, so receivers aren't woken for
+    /// nothing.
+    pub fn set_if_changed(&self, v: T) where T: PartialEq {
+        if self.0.borrow().value != v {
+            self.set(v);
+        }
+    }
+
+    /// Modifies the value in place and notifies receivers.
+    pub fn update(&self, f: impl FnOnce(&mut T)) {
+        let mut state = self.0.borrow_mut();
+        f(&mut state.value);
+        state.version += 1;
+        state.wakers.wake();
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref::map(self.0.borrow(), |s| &s.value)
+    }
+
+    /// Creates a new receiver, which considers the current value seen.
+    pub fn subscribe(&self) -> WatchReceiver<T> {
+        let mut state = self.0.borrow_mut();
+        let id = state.wakers.id();
+        WatchReceiver(self.0.clone(), Cell::new(state.version), id)
+    }
+}
+
+impl<T> Drop for WatchSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.0.borrow_mut();
+        state.sender_exists = false;
+        state.wakers.wake();
+    }
+}
+
+impl<T> WatchReceiver<T> {
+    /// Borrows the current value, marking it seen.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        let state = self.0.borrow();
+        self.1.set(state.version);
+        Ref::map(state, |s| &s.value)
+    }
+
+    /// Returns a copy of the current value, marking it seen.
+    pub fn get(&self) -> T where T: Clone {
+        self.borrow().clone()
+    }
+
+    /// Whether the value has changed since it was last seen by this receiver.
+    pub fn has_changed(&self) -> bool {
+        self.0.borrow().version != self.1.get()
+    }
+
+    /// Waits until the value has changed since it was last seen by this receiver. Returns
+    /// `None` if the sender has been dropped, after which it can't change anymore.
+    pub async fn changed(&self) -> Option<()> {
+        WatchChanged(self).await
+    }
+}
+
+impl<T> Clone for WatchReceiver<T> {
+    fn clone(&self) -> Self {
+        let id = self.0.borrow_mut().wakers.id();
+        WatchReceiver(self.0.clone(), Cell::new(self.1.get()), id)
+    }
+}
+
+impl<T> Drop for WatchReceiver<T> {
+    fn drop(&mut self) {
+        self.0.borrow_mut().wakers.remove(self.2);
+    }
+}
+
+struct WatchChanged<'a, T>(&'a WatchReceiver<T>);
+
+impl<T> Future for WatchChanged<'_, T> {
+    type Output = Option<()>;
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<()>> {
+        let mut state = self.0 .0.borrow_mut();
+        if state.version != self.0 .1.get() {
+            self.0 .1.set(state.version);
+            Poll::Ready(Some(()))
+        } else if !state.sender_exists {
+            Poll::Ready(None)
+        } else {
+            state.wakers.register(self.0 .2, ctx.waker());
+            Poll::Pending
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! The asynchronous clipboard, with a fallback to `execCommand` for copying text where it
+//! isn't supported.
+//!
+//! Browsers only allow using the clipboard in response to user input, such as in a click
+//! handler, and may ask the user for permission to read it.
+//!
+//! ```ignore
+//! button.add_event_listener(move |_: event::Click| spawn_local(async move {
+//!     clipboard::write_text(&invite_link).await.ok();
+//! })).forget();
+//! ```
+
+use crate::prelude::*;
+use crate::global::GlobalScope;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Errors from using the clipboard.
+#[derive(Debug)]
+pub enum ClipboardError {
+    /// The clipboard can't be used, because the page isn't served securely, this isn't a
+    /// window, or the browser doesn't support what was asked.
+    Unavailable,
+    /// The user or browser didn't allow using the clipboard, such as because it wasn't in
+    /// response to user input.
+    PermissionDenied,
+    Other(JsValue)
+}
+
+impl From<JsValue> for ClipboardError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotAllowedError") | Some("SecurityError") => ClipboardError::PermissionDenied,
+            _ => ClipboardError::Other(e)
+        }
+    }
+}
+
+impl From<ClipboardError> for GeneralError {
+    fn from(e: ClipboardError) -> Self {
+        match e {
+            ClipboardError::Unavailable => GeneralError::message("clipboard is unavailable"),
+            ClipboardError::PermissionDenied => {
+                GeneralError::message("clipboard permission denied")
+            }
+            ClipboardError::Other(e) => GeneralError::WebSys(e)
+        }
+    }
+}
+
+/// Copies `text` to the clipboard.
+pub async fn write_text(text: &str) -> Result<(), ClipboardError> {
+    match clipboard() {
+        Some(clipboard) => {
+            JsFuture::from(clipboard.write_text(text)).await?;
+            Ok(())
+        }
+        None => exec_copy(text)
+    }
+}
+
+/// Reads the text on the clipboard, which is empty if there isn't any.
+pub async fn read_text() -> Result<String, ClipboardError> {
+    let clipboard = clipboard().ok_or(ClipboardError::Unavailable)?;
+    let text = JsFuture::from(clipboard.read_text()).await?;
+    Ok(text.as_string().unwrap_or_default())
+}
+
+/// Copies `blob` to the clipboard as its MIME type. Browsers only support a few types, such
+/// as `image/png`.
+pub async fn write_blob(blob: &web_sys::Blob) -> Result<(), ClipboardError> {
+    let clipboard = clipboard().ok_or(ClipboardError::Unavailable)?;
+    if blob.type_().is_empty() {
+        return Err(ClipboardError::Other(js_sys::Error::new("blob has no type").into()));
+    }
+    let items = js_sys::Object::new();
+    js_sys::Reflect::set(&items, &blob.type_().into(), blob)?;
+    let item = web_sys::ClipboardItem::new_with_record_from_str_to_blob_promise(&items)
+        .map_err(|_| ClipboardError::Unavailable)?;
+    JsFuture::from(clipboard.write(&js_sys::Array::of1(&item))).await?;
+    Ok(())
+}
+
+/// Copies a PNG image to the clipboard.
+pub async fn write_image(png: &[u8]) -> Result<(), ClipboardError> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(png));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type("image/png");
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+    write_blob(&blob).await
+}
+
+/// Reads the first item on the clipboard of the MIME type `ty`.
+pub async fn read_blob(ty: &str) -> Result<Option<web_sys::Blob>, ClipboardError> {
+    read_matching(|item_ty| item_ty == ty).await
+}
+
+/// Reads the first image on the clipboard, of whichever type it is.
+pub async fn read_image() -> Result<Option<web_sys::Blob>, ClipboardError> {
+    read_matching(|ty| ty.starts_with("image/")).await
+}
+
+async fn read_matching(
+    matches: impl Fn(&str) -> bool
+) -> Result<Option<web_sys::Blob>, ClipboardError> {
+    let clipboard = clipboard().ok_or(ClipboardError::Unavailable)?;
+    let items: js_sys::Array = JsFuture::from(clipboard.read()).await?.unchecked_into();
+    for item in items.iter() {
+        let item: web_sys::ClipboardItem = item.unchecked_into();
+        let ty = item.types().iter().filter_map(|ty| ty.as_string()).find(|ty| matches(ty));
+        if let Some(ty) = ty {
+            let blob = JsFuture::from(item.get_type(&ty)).await?;
+            return Ok(Some(blob.unchecked_into()));
+        }
+    }
+    Ok(None)
+}
+
+/// `navigator.clipboard`, which is missing in insecure contexts and older browsers.
+fn clipboard() -> Option<web_sys::Clipboard> {
+    let window = match GlobalScope::current() {
+        GlobalScope::Window(window) => window,
+        _ => return None
+    };
+    let clipboard = js_sys::Reflect::get(&window.navigator(), &"clipboard".into()).ok()?;
+    if clipboard.is_undefined() {
+        return None;
+    }
+    Some(clipboard.unchecked_into())
+}
+
+/// Copies `text` by selecting it in a hidden text area and running the copy command.
+fn exec_copy(text: &str) -> Result<(), ClipboardError> {
+    let document = match GlobalScope::current() {
+        GlobalScope::Window(window) => window.document(),
+        _ => None
+    };
+    let document: web_sys::HtmlDocument = document
+        .and_then(|document| document.dyn_into().ok())
+        .ok_or(ClipboardError::Unavailable)?;
+    let body = document.body().ok_or(ClipboardError::Unavailable)?;
+
+    let area: web_sys::HtmlTextAreaElement = document.create_element("textarea")?.unchecked_into();
+    area.set_value(text);
+    area.set_attribute("readonly", "")?;
+    area.set_attribute("style", "position: fixed; top: 0; left: 0; opacity: 0;")?;
+    body.append_child(&area)?;
+    area.select();
+    let copied = document.exec_command("copy");
+    area.remove();
+
+    match copied {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ClipboardError::PermissionDenied),
+        Err(e) => Err(e.into())
+    }
+}
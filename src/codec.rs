@@ -0,0 +1,213 @@
+use crate::prelude::*;
+use crate::worker;
+use serde::{ Serialize, de::DeserializeOwned };
+use wasm_bindgen::JsCast;
+use std::cell::RefCell;
+
+/// Wire format used to turn messages into JS values and back.
+pub trait Codec: 'static {
+    /// Encodes a value, returning the payload and an optional object to transfer along with it.
+    fn encode<T: Serialize>(v: &T) -> Result<(JsValue, Option<JsValue>), GeneralError>;
+    fn decode<T: DeserializeOwned>(v: JsValue) -> Result<T, GeneralError>;
+}
+
+/// Compact binary encoding. The payload is a `Uint8Array` whose buffer is transferred.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn encode<T: Serialize>(v: &T) -> Result<(JsValue, Option<JsValue>), GeneralError> {
+        let buf = with_staging(|data| {
+            bincode::serialize_into(&mut *data, v)?;
+            Ok::<_, GeneralError>(js_sys::Uint8Array::from(&**data))
+        })?;
+        let transfer = buf.buffer().into();
+        Ok((buf.into(), Some(transfer)))
+    }
+
+    fn decode<T: DeserializeOwned>(v: JsValue) -> Result<T, GeneralError> {
+        let buf = v.dyn_into::<js_sys::Uint8Array>()?;
+        with_staging(|data| {
+            data.resize(buf.length() as usize, 0);
+            buf.copy_to(data);
+            Ok(bincode::deserialize(data)?)
+        })
+    }
+}
+
+thread_local! {
+    static STAGING: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The most capacity the staging buffer keeps between messages, so one large message doesn't
+/// pin its size in memory for the life of the thread.
+const MAX_STAGING: usize = 64 * 1024;
+
+/// Runs `f` with an empty staging buffer, reusing its allocation across messages.
+fn with_staging<R>(f: impl FnOnce(&mut Vec<u8>) -> R) -> R {
+    // fall back to a fresh buffer if a message is encoded while another one is
+    let mut data = STAGING.with(|s| s.try_borrow_mut().map(|mut s| std::mem::take(&mut *s)))
+        .unwrap_or_default();
+    data.clear();
+    let result = f(&mut data);
+    data.clear();
+    data.shrink_to(MAX_STAGING);
+    STAGING.with(|s| if let Ok(mut s) = s.try_borrow_mut() {
+        if s.capacity() < data.capacity() {
+            *s = data;
+        }
+    });
+    result
+}
+
+/// [`Bincode`] encoding, except that messages which encode to at most `THRESHOLD` bytes are
+/// sent as plain JS values like [`StructuredClone`].
+///
+/// Small messages such as numbers and flags then skip allocating and transferring a buffer,
+/// which dominates the cost of sending them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Adaptive<const THRESHOLD: usize = 16>;
+
+const SMALL: serde_wasm_bindgen::Serializer = serde_wasm_bindgen::Serializer::new()
+    // keeps small messages from being mistaken for encoded ones
+    .serialize_bytes_as_arrays(true);
+
+impl<const THRESHOLD: usize> Codec for Adaptive<THRESHOLD> {
+    fn encode<T: Serialize>(v: &T) -> Result<(JsValue, Option<JsValue>), GeneralError> {
+        // serializing twice would capture any transferred objects twice, so the small
+        // representation replaces the objects captured while measuring
+        let mark = worker::capture_mark();
+        with_staging(|data| {
+            bincode::serialize_into(&mut *data, v)?;
+            if data.len() <= THRESHOLD {
+                if let Ok(value) = worker::reserialize(mark, || v.serialize(&SMALL)) {
+                    return Ok((value, None));
+                }
+            }
+            let buf = js_sys::Uint8Array::from(&**data);
+            let transfer = buf.buffer().into();
+            Ok((buf.into(), Some(transfer)))
+        })
+    }
+
+    fn decode<T: DeserializeOwned>(v: JsValue) -> Result<T, GeneralError> {
+        if v.is_instance_of::<js_sys::Uint8Array>() {
+            Bincode::decode(v)
+        } else {
+            Ok(serde_wasm_bindgen::from_value(v)?)
+        }
+    }
+}
+
+/// [`Bincode`] encoding with messages larger than `THRESHOLD` bytes compressed using deflate.
+///
+/// Worthwhile for large, compressible messages such as state snapshots, where the time saved
+/// copying the message outweighs the time spent compressing it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Deflate<const THRESHOLD: usize = 4096>;
+
+const UNCOMPRESSED: u8 = 0;
+const COMPRESSED: u8 = 1;
+
+impl<const THRESHOLD: usize> Codec for Deflate<THRESHOLD> {
+    fn encode<T: Serialize>(v: &T) -> Result<(JsValue, Option<JsValue>), GeneralError> {
+        let buf = js_sys::Uint8Array::from(&*Self::to_bytes(v)?);
+        let transfer = buf.buffer().into();
+        Ok((buf.into(), Some(transfer)))
+    }
+
+    fn decode<T: DeserializeOwned>(v: JsValue) -> Result<T, GeneralError> {
+        Self::from_bytes(&v.dyn_into::<js_sys::Uint8Array>()?.to_vec())
+    }
+}
+
+impl<const THRESHOLD: usize> Deflate<THRESHOLD> {
+    fn to_bytes<T: Serialize>(v: &T) -> Result<Vec<u8>, GeneralError> {
+        let mut data = vec![UNCOMPRESSED];
+        bincode::serialize_into(&mut data, v)?;
+        if data.len() - 1 > THRESHOLD {
+            let mut compressed = vec![COMPRESSED];
+            compressed.extend(miniz_oxide::deflate::compress_to_vec(&data[1..], 6));
+            data = compressed;
+        }
+        Ok(data)
+    }
+
+    fn from_bytes<T: DeserializeOwned>(data: &[u8]) -> Result<T, GeneralError> {
+        match data.split_first() {
+            Some((&UNCOMPRESSED, data)) => Ok(bincode::deserialize(data)?),
+            Some((&COMPRESSED, data)) => {
+                let data = miniz_oxide::inflate::decompress_to_vec(data).map_err(|e| {
+                    bincode::Error::from(bincode::ErrorKind::Custom(e.to_string()))
+                })?;
+                Ok(bincode::deserialize(&data)?)
+            }
+            _ => Err(bincode::Error::from(
+                bincode::ErrorKind::Custom("invalid compression header".to_owned())
+            ).into())
+        }
+    }
+}
+
+/// JSON encoding. The payload is a JS string, which is convenient for debugging and for JS
+/// code listening to the same messages.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize>(v: &T) -> Result<(JsValue, Option<JsValue>), GeneralError> {
+        Ok((serde_json::to_string(v)?.into(), None))
+    }
+
+    fn decode<T: DeserializeOwned>(v: JsValue) -> Result<T, GeneralError> {
+        let s = v.as_string().ok_or(v)?;
+        Ok(serde_json::from_str(&s)?)
+    }
+}
+
+/// Encodes messages as plain JS objects using `serde_wasm_bindgen`, which are then
+/// structured-cloned by `postMessage`.
+///
+/// Fields annotated with `#[serde(with = "serde_wasm_bindgen::preserve")]` can hold arbitrary
+/// `JsValue`s, which pass through untouched.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StructuredClone;
+
+impl Codec for StructuredClone {
+    fn encode<T: Serialize>(v: &T) -> Result<(JsValue, Option<JsValue>), GeneralError> {
+        Ok((serde_wasm_bindgen::to_value(v)?, None))
+    }
+
+    fn decode<T: DeserializeOwned>(v: JsValue) -> Result<T, GeneralError> {
+        Ok(serde_wasm_bindgen::from_value(v)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deflate_round_trips_small_messages_uncompressed() {
+        let v = (1u32, "small".to_owned());
+        let data = Deflate::<4096>::to_bytes(&v).unwrap();
+        assert_eq!(data[0], UNCOMPRESSED);
+        assert_eq!(Deflate::<4096>::from_bytes::<(u32, String)>(&data).unwrap(), v);
+    }
+
+    #[test]
+    fn deflate_round_trips_large_messages_compressed() {
+        let v = vec![7u8; 10_000];
+        let data = Deflate::<4096>::to_bytes(&v).unwrap();
+        assert_eq!(data[0], COMPRESSED);
+        assert!(data.len() < 1000);
+        assert_eq!(Deflate::<4096>::from_bytes::<Vec<u8>>(&data).unwrap(), v);
+    }
+
+    #[test]
+    fn deflate_rejects_invalid_data() {
+        assert!(Deflate::<4096>::from_bytes::<u32>(&[]).is_err());
+        assert!(Deflate::<4096>::from_bytes::<u32>(&[2, 0, 0, 0, 0]).is_err());
+        assert!(Deflate::<4096>::from_bytes::<u32>(&[COMPRESSED, 0xFF, 0xFF]).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Gzip and deflate compression using the browser's own implementation, from
+//! `CompressionStream` and `DecompressionStream`.
+//!
+//! ```ignore
+//! let response = http::get("/level.json.gz").send().await?.error_for_status()?;
+//! let level = compress::gunzip(response.body_stream()?)?.read_all().await?;
+//!
+//! let payload = compress::compress_bytes(Format::Gzip, &save_data).await?;
+//! ```
+
+use crate::prelude::*;
+use crate::channel::channel;
+use crate::streams::ByteStream;
+use wasm_bindgen::JsCast;
+
+#[wasm_bindgen]
+extern "C" {
+    // web-sys only binds compression streams as unstable
+    #[wasm_bindgen(js_name = CompressionStream)]
+    type RawCompressionStream;
+    #[wasm_bindgen(catch, constructor, js_class = CompressionStream)]
+    fn new(format: &str) -> Result<RawCompressionStream, JsValue>;
+
+    #[wasm_bindgen(js_name = DecompressionStream)]
+    type RawDecompressionStream;
+    #[wasm_bindgen(catch, constructor, js_class = DecompressionStream)]
+    fn new(format: &str) -> Result<RawDecompressionStream, JsValue>;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    Gzip,
+    /// Deflate with a zlib header, as in the HTTP `deflate` content encoding.
+    Deflate,
+    /// Deflate without a header.
+    DeflateRaw
+}
+
+impl Format {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Gzip => "gzip",
+            Format::Deflate => "deflate",
+            Format::DeflateRaw => "deflate-raw"
+        }
+    }
+}
+
+fn unavailable(e: JsValue) -> GeneralError {
+    // the constructors are missing entirely where unsupported
+    let e: JsValue = match e.is_instance_of::<js_sys::ReferenceError>() {
+        true => js_sys::Error::new("compression streams are unavailable").into(),
+        false => e
+    };
+    e.into()
+}
+
+/// A JS stream which compresses bytes written to it, such as for
+/// [`ByteStream::pipe_through`].
+pub fn compressor(format: Format) -> Result<web_sys::TransformStream, GeneralError> {
+    Ok(RawCompressionStream::new(format.as_str()).map_err(unavailable)?.unchecked_into())
+}
+
+/// A JS stream which decompresses bytes written to it. Reading its output fails if the input
+/// isn't validly compressed.
+pub fn decompressor(format: Format) -> Result<web_sys::TransformStream, GeneralError> {
+    Ok(RawDecompressionStream::new(format.as_str()).map_err(unavailable)?.unchecked_into())
+}
+
+/// `stream`, compressed as it is read.
+pub fn compress(
+    format: Format, stream: impl Into<ByteStream>
+) -> Result<ByteStream, GeneralError> {
+    stream.into().pipe_through(&compressor(format)?)
+}
+
+/// `stream`, decompressed as it is read.
+pub fn decompress(
+    format: Format, stream: impl Into<ByteStream>
+) -> Result<ByteStream, GeneralError> {
+    stream.into().pipe_through(&decompressor(format)?)
+}
+
+pub fn gzip(stream: impl Into<ByteStream>) -> Result<ByteStream, GeneralError> {
+    compress(Format::Gzip, stream)
+}
+
+pub fn gunzip(stream: impl Into<ByteStream>) -> Result<ByteStream, GeneralError> {
+    decompress(Format::Gzip, stream)
+}
+
+/// Compresses `data` all at once, such as before sending it to a worker or server.
+pub async fn compress_bytes(format: Format, data: &[u8]) -> Result<Vec<u8>, GeneralError> {
+    compress(format, once(data))?.read_all().await
+}
+
+pub async fn decompress_bytes(format: Format, data: &[u8]) -> Result<Vec<u8>, GeneralError> {
+    decompress(format, once(data))?.read_all().await
+}
+
+fn once(data: &[u8]) -> ByteStream {
+    let (sender, chunks) = channel();
+    let _ = sender.send(data.to_vec());
+    ByteStream::from(chunks)
+}
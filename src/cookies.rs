@@ -0,0 +1,399 @@
+//! Cookies, through `document.cookie` and the asynchronous `CookieStore` API.
+//!
+//! Names and values are percent-encoded when set, and decoded when read.
+//!
+//! ```ignore
+//! Cookie::new("theme", "dark").max_age(Duration::from_secs(60 * 60 * 24 * 365)).set()?;
+//! let theme = cookies::get("theme")?;
+//!
+//! if let Some(store) = CookieStore::current() {
+//!     let changes = store.changes();
+//!     while let CookieChange::Changed { name, value } = changes.next().await { ... }
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, channel };
+use crate::event::{ self, ListenerHandle };
+use crate::global::{ GlobalScope, IntoDelay };
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use std::fmt;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    /// `CookieStore`, which web-sys only binds as an unstable API.
+    #[wasm_bindgen(js_name = CookieStore, extends = web_sys::EventTarget)]
+    #[derive(Clone, Debug)]
+    type RawCookieStore;
+    #[wasm_bindgen(method)]
+    fn get(this: &RawCookieStore, name: &str) -> js_sys::Promise;
+    #[wasm_bindgen(method, js_name = getAll)]
+    fn get_all(this: &RawCookieStore) -> js_sys::Promise;
+    #[wasm_bindgen(method)]
+    fn set(this: &RawCookieStore, options: &JsValue) -> js_sys::Promise;
+    #[wasm_bindgen(method)]
+    fn delete(this: &RawCookieStore, options: &JsValue) -> js_sys::Promise;
+
+    #[wasm_bindgen(extends = web_sys::Event)]
+    type CookieChangeEvent;
+    #[wasm_bindgen(method, getter)]
+    fn changed(this: &CookieChangeEvent) -> js_sys::Array;
+    #[wasm_bindgen(method, getter)]
+    fn deleted(this: &CookieChangeEvent) -> js_sys::Array;
+}
+
+/// Errors from accessing cookies.
+#[derive(Debug)]
+pub enum CookieError {
+    /// Cookies can't be used, because this isn't a window or the document is sandboxed.
+    Unavailable,
+    Other(JsValue)
+}
+
+impl From<JsValue> for CookieError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("SecurityError") => CookieError::Unavailable,
+            _ => CookieError::Other(e)
+        }
+    }
+}
+
+impl From<serde_wasm_bindgen::Error> for CookieError {
+    fn from(e: serde_wasm_bindgen::Error) -> Self {
+        CookieError::Other(e.into())
+    }
+}
+
+impl From<CookieError> for GeneralError {
+    fn from(e: CookieError) -> Self {
+        match e {
+            CookieError::Unavailable => GeneralError::message("cookies are unavailable"),
+            CookieError::Other(e) => GeneralError::WebSys(e)
+        }
+    }
+}
+
+/// Whether a cookie is sent with requests coming from other sites.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SameSite {
+    /// Only sent with requests from the same site.
+    Strict,
+    /// Also sent when navigating to the site from elsewhere. Browsers default to this.
+    Lax,
+    /// Always sent, which requires the cookie to be secure.
+    None
+}
+
+impl SameSite {
+    fn as_str(self) -> &'static str {
+        match self {
+            SameSite::Strict => "strict",
+            SameSite::Lax => "lax",
+            SameSite::None => "none"
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Expiry {
+    /// Milliseconds from when the cookie is set.
+    MaxAge(f64),
+    /// Milliseconds since the Unix epoch.
+    At(f64)
+}
+
+/// A cookie to set. Without an expiry, it is a session cookie.
+#[derive(Clone, Debug)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    expiry: Option<Expiry>,
+    path: Option<String>,
+    domain: Option<String>,
+    same_site: Option<SameSite>,
+    secure: bool
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Cookie {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            expiry: None,
+            path: None,
+            domain: None,
+            same_site: None,
+            secure: false
+        }
+    }
+
+    /// Expires the cookie `age` after it is set.
+    pub fn max_age(mut self, age: impl IntoDelay) -> Self {
+        self.expiry = Some(Expiry::MaxAge(age.into_millis()));
+        self
+    }
+
+    /// Expires the cookie at `date`.
+    pub fn expires(mut self, date: &js_sys::Date) -> Self {
+        self.expiry = Some(Expiry::At(date.get_time()));
+        self
+    }
+
+    /// Only sends the cookie with requests under `path`. Defaults to the current directory.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_owned());
+        self
+    }
+
+    /// Also sends the cookie to subdomains of `domain`. Defaults to only the current host.
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_owned());
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Only sends the cookie over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Sets the cookie through `document.cookie`.
+    pub fn set(&self) -> Result<(), CookieError> {
+        document()?.set_cookie(&self.to_string())?;
+        Ok(())
+    }
+
+    /// Removes the cookie through `document.cookie`. The path and domain must match the ones it
+    /// was set with.
+    pub fn remove(&self) -> Result<(), CookieError> {
+        let mut expired = self.clone();
+        expired.value.clear();
+        expired.expiry = Some(Expiry::MaxAge(0.0));
+        expired.set()
+    }
+
+    fn expires_at(&self) -> Option<f64> {
+        match self.expiry? {
+            Expiry::MaxAge(ms) => Some(js_sys::Date::now() + ms),
+            Expiry::At(ms) => Some(ms)
+        }
+    }
+}
+
+/// Formats the cookie as assigned to `document.cookie`.
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", encode(&self.name), encode(&self.value))?;
+        match self.expiry {
+            Some(Expiry::MaxAge(ms)) => write!(f, "; max-age={}", (ms / 1000.0).ceil())?,
+            Some(Expiry::At(ms)) => {
+                let date = js_sys::Date::new(&ms.into());
+                write!(f, "; expires={}", String::from(date.to_utc_string()))?
+            }
+            None => {}
+        }
+        if let Some(path) = &self.path {
+            write!(f, "; path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; domain={}", domain)?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; samesite={}", same_site.as_str())?;
+        }
+        if self.secure {
+            write!(f, "; secure")?;
+        }
+        Ok(())
+    }
+}
+
+/// The cookies visible to the page, by name.
+pub fn all() -> Result<HashMap<String, String>, CookieError> {
+    Ok(parse(&document()?.cookie()?))
+}
+
+/// The value of the cookie `name`, if it is set.
+pub fn get(name: &str) -> Result<Option<String>, CookieError> {
+    Ok(all()?.remove(name))
+}
+
+/// Parses a `document.cookie` or `Cookie` header string. Pairs without a `=` are skipped.
+pub fn parse(cookies: &str) -> HashMap<String, String> {
+    cookies.split(';')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            Some((decode(name.trim()), decode(value.trim())))
+        })
+        .collect()
+}
+
+/// The asynchronous cookie API, which can be used from service workers and reports changes.
+#[derive(Clone, Debug)]
+pub struct CookieStore(RawCookieStore);
+
+/// A change to a cookie reported by [`CookieStore::changes`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CookieChange {
+    Changed { name: String, value: String },
+    Deleted { name: String }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SetOptions<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    domain: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    same_site: Option<&'static str>
+}
+
+#[derive(Deserialize)]
+struct ListItem {
+    name: String,
+    value: Option<String>
+}
+
+impl CookieStore {
+    /// The global `cookieStore`, if the browser supports it.
+    pub fn current() -> Option<Self> {
+        let store = js_sys::Reflect::get(&js_sys::global(), &"cookieStore".into()).ok()?;
+        if store.is_undefined() {
+            return None;
+        }
+        Some(CookieStore(store.unchecked_into()))
+    }
+
+    /// The value of the cookie `name`, if it is set.
+    pub async fn get(&self, name: &str) -> Result<Option<String>, CookieError> {
+        let item = JsFuture::from(self.0.get(name)).await?;
+        if item.is_null() || item.is_undefined() {
+            return Ok(None);
+        }
+        let item: ListItem = serde_wasm_bindgen::from_value(item)?;
+        Ok(item.value)
+    }
+
+    /// The cookies visible to the page or worker, by name.
+    pub async fn all(&self) -> Result<HashMap<String, String>, CookieError> {
+        let items = JsFuture::from(self.0.get_all()).await?;
+        let items: Vec<ListItem> = serde_wasm_bindgen::from_value(items)?;
+        Ok(items.into_iter().map(|item| (item.name, item.value.unwrap_or_default())).collect())
+    }
+
+    /// Sets `cookie`. Cookies set this way are always secure.
+    pub async fn set(&self, cookie: &Cookie) -> Result<(), CookieError> {
+        let options = SetOptions {
+            name: &cookie.name,
+            value: Some(&cookie.value),
+            expires: cookie.expires_at(),
+            path: cookie.path.as_deref(),
+            domain: cookie.domain.as_deref(),
+            same_site: cookie.same_site.map(SameSite::as_str)
+        };
+        JsFuture::from(self.0.set(&serde_wasm_bindgen::to_value(&options)?)).await?;
+        Ok(())
+    }
+
+    /// Removes `cookie`, which is matched by its name, path and domain.
+    pub async fn remove(&self, cookie: &Cookie) -> Result<(), CookieError> {
+        let options = SetOptions {
+            name: &cookie.name,
+            value: None,
+            expires: None,
+            path: cookie.path.as_deref(),
+            domain: cookie.domain.as_deref(),
+            same_site: None
+        };
+        JsFuture::from(self.0.delete(&serde_wasm_bindgen::to_value(&options)?)).await?;
+        Ok(())
+    }
+
+    /// Watches for cookies changing, whether by this page, other pages or the server.
+    pub fn changes(&self) -> CookieChanges {
+        let (sender, changes) = channel();
+        let listener = self.0.add_event_listener(move |e: event::Change| {
+            let e: &CookieChangeEvent = e.unchecked_ref();
+            let items = e.changed().into_iter().map(|item| (item, true))
+                .chain(e.deleted().into_iter().map(|item| (item, false)));
+            for (item, changed) in items {
+                let item: ListItem = match serde_wasm_bindgen::from_value(item) {
+                    Ok(item) => item,
+                    Err(_) => continue
+                };
+                let change = match changed {
+                    true => CookieChange::Changed {
+                        name: item.name,
+                        value: item.value.unwrap_or_default()
+                    },
+                    false => CookieChange::Deleted { name: item.name }
+                };
+                let _ = sender.send(change);
+            }
+        });
+        CookieChanges { changes, _listener: listener }
+    }
+
+    /// The underlying cookie store.
+    pub fn raw(&self) -> &JsValue {
+        &self.0
+    }
+}
+
+/// Changes to cookies, from [`CookieStore::changes`].
+pub struct CookieChanges {
+    changes: Receiver<CookieChange>,
+    _listener: ListenerHandle
+}
+
+impl CookieChanges {
+    pub fn try_next(&self) -> Option<CookieChange> {
+        self.changes.try_recv().ok()
+    }
+
+    pub async fn next(&self) -> CookieChange {
+        self.changes.recv().await.unwrap()
+    }
+}
+
+fn document() -> Result<web_sys::HtmlDocument, CookieError> {
+    match GlobalScope::current() {
+        GlobalScope::Window(window) => window.document()
+            .and_then(|document| document.dyn_into().ok())
+            .ok_or(CookieError::Unavailable),
+        _ => Err(CookieError::Unavailable)
+    }
+}
+
+fn encode(s: &str) -> String {
+    js_sys::encode_uri_component(s).into()
+}
+
+/// Cookies set by servers aren't necessarily encoded, so those are read as they are.
+fn decode(s: &str) -> String {
+    js_sys::decode_uri_component(s).map(String::from).unwrap_or_else(|_| s.to_owned())
+}
@@ -0,0 +1,251 @@
+//! Hashing, signing and encryption, from the Web Crypto API.
+//!
+//! Everything except [`random_bytes`] needs the page to be served securely.
+//!
+//! ```ignore
+//! let key = AesGcmKey::generate(256).await?;
+//! let sealed = key.encrypt(b"save data").await?;
+//! assert_eq!(key.decrypt(&sealed).await?, b"save data");
+//!
+//! let hash = crypto::digest(Hash::Sha256, &file_bytes).await?;
+//! ```
+
+use crate::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Length of the nonce [`AesGcmKey::encrypt`] generates, in bytes.
+const NONCE_LEN: usize = 12;
+
+/// Errors from cryptographic operations.
+#[derive(Debug)]
+pub enum CryptoError {
+    /// The Web Crypto API isn't available, such as because the page isn't served securely.
+    Unavailable,
+    /// The key or its data isn't valid for the operation, such as imported key data of the
+    /// wrong length.
+    InvalidKey,
+    /// The data couldn't be decrypted, because it was modified or encrypted with another key.
+    DecryptFailed,
+    Other(JsValue)
+}
+
+impl From<JsValue> for CryptoError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("InvalidAccessError") | Some("DataError") => CryptoError::InvalidKey,
+            Some("OperationError") => CryptoError::DecryptFailed,
+            _ => CryptoError::Other(e)
+        }
+    }
+}
+
+impl From<CryptoError> for GeneralError {
+    fn from(e: CryptoError) -> Self {
+        let msg = match e {
+            CryptoError::Unavailable => "web crypto is unavailable",
+            CryptoError::InvalidKey => "invalid key",
+            CryptoError::DecryptFailed => "decryption failed",
+            CryptoError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(msg)
+    }
+}
+
+fn crypto() -> Result<web_sys::Crypto, CryptoError> {
+    let crypto = js_sys::Reflect::get(&js_sys::global(), &"crypto".into())?;
+    match crypto.is_undefined() {
+        true => Err(CryptoError::Unavailable),
+        false => Ok(crypto.unchecked_into())
+    }
+}
+
+fn subtle() -> Result<web_sys::SubtleCrypto, CryptoError> {
+    // only secure contexts have crypto.subtle
+    let crypto = crypto()?;
+    let subtle = js_sys::Reflect::get(&crypto, &"subtle".into())?;
+    match subtle.is_undefined() {
+        true => Err(CryptoError::Unavailable),
+        false => Ok(subtle.unchecked_into())
+    }
+}
+
+async fn bytes(promise: js_sys::Promise) -> Result<Vec<u8>, CryptoError> {
+    Ok(js_sys::Uint8Array::new(&JsFuture::from(promise).await?).to_vec())
+}
+
+async fn key(promise: js_sys::Promise) -> Result<web_sys::CryptoKey, CryptoError> {
+    Ok(JsFuture::from(promise).await?.unchecked_into())
+}
+
+fn algorithm(props: &[(&str, &JsValue)]) -> Result<js_sys::Object, CryptoError> {
+    let algorithm = js_sys::Object::new();
+    for (key, value) in props {
+        js_sys::Reflect::set(&algorithm, &(*key).into(), value)?;
+    }
+    Ok(algorithm)
+}
+
+fn usages(usages: &[&str]) -> JsValue {
+    usages.iter().map(|&u| JsValue::from(u)).collect::<js_sys::Array>().into()
+}
+
+/// `n` cryptographically secure random bytes, such as for salts or tokens.
+pub fn random_bytes(n: usize) -> Result<Vec<u8>, CryptoError> {
+    let crypto = crypto()?;
+    let mut bytes = vec![0; n];
+    // getRandomValues fills at most 64 KiB at a time
+    for chunk in bytes.chunks_mut(65536) {
+        crypto.get_random_values_with_u8_array(chunk)?;
+    }
+    Ok(bytes)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Hash {
+    /// Broken for collision resistance; only for compatibility with existing formats.
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512
+}
+
+impl Hash {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Hash::Sha1 => "SHA-1",
+            Hash::Sha256 => "SHA-256",
+            Hash::Sha384 => "SHA-384",
+            Hash::Sha512 => "SHA-512"
+        }
+    }
+}
+
+/// Hashes `data`.
+pub async fn digest(hash: Hash, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    bytes(subtle()?.digest_with_str_and_u8_array(hash.as_str(), data)?).await
+}
+
+/// A secret key for signing messages with HMAC, so that changes to them can be detected.
+#[derive(Clone, Debug)]
+pub struct HmacKey(web_sys::CryptoKey);
+
+impl HmacKey {
+    /// Generates a random key as long as the output of `hash`.
+    pub async fn generate(hash: Hash) -> Result<Self, CryptoError> {
+        let params = algorithm(&[("name", &"HMAC".into()), ("hash", &hash.as_str().into())])?;
+        let usages = usages(&["sign", "verify"]);
+        let promise = subtle()?.generate_key_with_object(&params, true, &usages)?;
+        Ok(HmacKey(key(promise).await?))
+    }
+
+    /// Imports a key from its raw bytes, such as from [`export`](Self::export).
+    pub async fn import(hash: Hash, raw: &[u8]) -> Result<Self, CryptoError> {
+        let params = algorithm(&[("name", &"HMAC".into()), ("hash", &hash.as_str().into())])?;
+        let data = js_sys::Uint8Array::from(raw);
+        let usages = usages(&["sign", "verify"]);
+        let promise = subtle()?.import_key_with_object("raw", &data, &params, true, &usages)?;
+        Ok(HmacKey(key(promise).await?))
+    }
+
+    /// The raw bytes of the key.
+    pub async fn export(&self) -> Result<Vec<u8>, CryptoError> {
+        bytes(subtle()?.export_key("raw", &self.0)?).await
+    }
+
+    /// The signature of `data`.
+    pub async fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        bytes(subtle()?.sign_with_str_and_u8_array("HMAC", &self.0, data)?).await
+    }
+
+    /// Whether `signature` is the signature of `data`, checked in constant time.
+    pub async fn verify(&self, signature: &[u8], data: &[u8]) -> Result<bool, CryptoError> {
+        let subtle = subtle()?;
+        let valid = subtle.verify_with_str_and_u8_array_and_u8_array(
+            "HMAC", &self.0, signature, data
+        )?;
+        Ok(JsFuture::from(valid).await?.is_truthy())
+    }
+
+    /// The underlying key, such as for storing in IndexedDB.
+    pub fn raw(&self) -> &web_sys::CryptoKey {
+        &self.0
+    }
+}
+
+/// A secret key for encrypting data with AES-GCM, which also detects changes to it.
+#[derive(Clone, Debug)]
+pub struct AesGcmKey(web_sys::CryptoKey);
+
+impl AesGcmKey {
+    /// Generates a random key of 128 or 256 bits.
+    pub async fn generate(bits: u32) -> Result<Self, CryptoError> {
+        let params = algorithm(&[("name", &"AES-GCM".into()), ("length", &bits.into())])?;
+        let usages = usages(&["encrypt", "decrypt"]);
+        let promise = subtle()?.generate_key_with_object(&params, true, &usages)?;
+        Ok(AesGcmKey(key(promise).await?))
+    }
+
+    /// Imports a key from its raw 16 or 32 bytes, such as from [`export`](Self::export).
+    pub async fn import(raw: &[u8]) -> Result<Self, CryptoError> {
+        let params = algorithm(&[("name", &"AES-GCM".into())])?;
+        let data = js_sys::Uint8Array::from(raw);
+        let usages = usages(&["encrypt", "decrypt"]);
+        let promise = subtle()?.import_key_with_object("raw", &data, &params, true, &usages)?;
+        Ok(AesGcmKey(key(promise).await?))
+    }
+
+    /// The raw bytes of the key.
+    pub async fn export(&self) -> Result<Vec<u8>, CryptoError> {
+        bytes(subtle()?.export_key("raw", &self.0)?).await
+    }
+
+    /// Encrypts `data` with a random nonce, which is put before the ciphertext so that
+    /// [`decrypt`](Self::decrypt) can find it.
+    pub async fn encrypt(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let nonce = random_bytes(NONCE_LEN)?;
+        let mut sealed = nonce.clone();
+        sealed.extend(self.encrypt_with(&nonce, &[], data).await?);
+        Ok(sealed)
+    }
+
+    /// Decrypts data from [`encrypt`](Self::encrypt).
+    pub async fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if sealed.len() < NONCE_LEN {
+            return Err(CryptoError::DecryptFailed);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.decrypt_with(nonce, &[], ciphertext).await
+    }
+
+    /// Encrypts `data` with `nonce`, for formats which keep the nonce separately. A nonce must
+    /// never be used twice with the same key. `additional` is data which isn't encrypted, but
+    /// which decrypting checks is unchanged.
+    pub async fn encrypt_with(
+        &self, nonce: &[u8], additional: &[u8], data: &[u8]
+    ) -> Result<Vec<u8>, CryptoError> {
+        let params = gcm_params(nonce, additional)?;
+        bytes(subtle()?.encrypt_with_object_and_u8_array(&params, &self.0, data)?).await
+    }
+
+    /// Decrypts `data` encrypted with [`encrypt_with`](Self::encrypt_with).
+    pub async fn decrypt_with(
+        &self, nonce: &[u8], additional: &[u8], data: &[u8]
+    ) -> Result<Vec<u8>, CryptoError> {
+        let params = gcm_params(nonce, additional)?;
+        bytes(subtle()?.decrypt_with_object_and_u8_array(&params, &self.0, data)?).await
+    }
+
+    /// The underlying key, such as for storing in IndexedDB.
+    pub fn raw(&self) -> &web_sys::CryptoKey {
+        &self.0
+    }
+}
+
+fn gcm_params(nonce: &[u8], additional: &[u8]) -> Result<js_sys::Object, CryptoError> {
+    algorithm(&[
+        ("name", &"AES-GCM".into()),
+        ("iv", &js_sys::Uint8Array::from(nonce)),
+        ("additionalData", &js_sys::Uint8Array::from(additional))
+    ])
+}
@@ -0,0 +1,59 @@
+//! Saving data as a file the user downloads.
+//!
+//! ```ignore
+//! download::save_bytes("replay.bin", "application/octet-stream", &replay)?;
+//! ```
+
+use crate::prelude::*;
+use crate::file;
+use crate::global::GlobalScope;
+use crate::url::ObjectUrl;
+use wasm_bindgen::JsCast;
+
+/// How long object URLs are kept after starting a download. Revoking right away can cancel
+/// it, and some browsers only start reading the blob a while after the click.
+const REVOKE_DELAY: u32 = 40_000;
+
+/// Downloads `bytes` as a file called `name`, with the MIME type `mime`.
+pub fn save_bytes(name: &str, mime: &str, bytes: &[u8]) -> Result<(), GeneralError> {
+    save_blob(name, &file::to_blob(bytes, mime)?)
+}
+
+/// Downloads text as a file called `name`, with the MIME type `mime`.
+pub fn save_text(name: &str, mime: &str, text: &str) -> Result<(), GeneralError> {
+    save_bytes(name, mime, text.as_bytes())
+}
+
+/// Downloads `blob` as a file called `name`.
+///
+/// Browsers which don't support naming downloads open the blob in a new tab instead.
+pub fn save_blob(name: &str, blob: &web_sys::Blob) -> Result<(), GeneralError> {
+    let document = match GlobalScope::current() {
+        GlobalScope::Window(window) => window.document(),
+        _ => None
+    };
+    let document = document.ok_or_else(|| {
+        GeneralError::message("downloads are only available in windows")
+    })?;
+    let body = document.body()
+        .ok_or_else(|| GeneralError::message("document has no body"))?;
+
+    let url = ObjectUrl::new(blob)?;
+    let link: web_sys::HtmlElement = document.create_element("a")?.unchecked_into();
+    link.set_attribute("href", &url)?;
+    if js_sys::Reflect::has(&link, &"download".into())? {
+        link.set_attribute("download", name)?;
+    } else {
+        // older Safari ignores the attribute and would navigate away from the page
+        link.set_attribute("target", "_blank")?;
+    }
+    link.set_attribute("rel", "noopener")?;
+    link.set_attribute("style", "display: none;")?;
+    // Firefox only follows clicks on links in the document
+    body.append_child(&link)?;
+    link.click();
+    link.remove();
+
+    url.revoke_after(REVOKE_DELAY);
+    Ok(())
+}
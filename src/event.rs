@@ -4,6 +4,7 @@ use std::future::Future;
 use std::task::{ Poll, Context };
 use std::pin::Pin;
 use crate::channel::{ Receiver, channel, Once, oneshot };
+use crate::global::IntoDelay;
 
 pub trait EventTargetExt {
     fn add_event_listener<E: Event>(&self, f: impl FnMut(E) + 'static) -> ListenerHandle;
@@ -28,10 +29,12 @@ impl EventTargetExt for web_sys::EventTarget {
 
     fn add_event_listener_once<E: Event>(&self, f: impl FnOnce(E) + 'static) -> ListenerHandle {
         let closure = Closure::once(move |e| f(E::from_event(e)));
+        let options = web_sys::AddEventListenerOptions::new();
+        options.set_once(true);
         self.add_event_listener_with_callback_and_add_event_listener_options(
             E::NAME,
             closure.as_ref().unchecked_ref(),
-            web_sys::AddEventListenerOptions::new().once(true)
+            &options
         ).unwrap();
         ListenerHandle {
             target: self.clone(),
@@ -84,7 +87,7 @@ pub trait Event: 'static {
     fn from_event(e: web_sys::Event) -> Self;
 }
 
-pub struct EventStream<E>(Receiver<E>, ListenerHandle);
+pub struct EventStream<E>(Receiver<E>, #[allow(dead_code)] ListenerHandle);
 
 impl<E> EventStream<E> {
     pub fn try_next(&self) -> Option<E> {
@@ -94,9 +97,19 @@ impl<E> EventStream<E> {
     pub async fn next(&self) -> E {
         self.0.recv().await.unwrap()
     }
+
+    /// See [`Receiver::debounce`].
+    pub fn debounce(self, delay: impl IntoDelay) -> EventStream<E> where E: 'static {
+        EventStream(self.0.debounce(delay), self.1)
+    }
+
+    /// See [`Receiver::throttle`].
+    pub fn throttle(self, period: impl IntoDelay) -> EventStream<E> where E: 'static {
+        EventStream(self.0.throttle(period), self.1)
+    }
 }
 
-pub struct EventOnce<E>(Once<E>, ListenerHandle);
+pub struct EventOnce<E>(Once<E>, #[allow(dead_code)] ListenerHandle);
 
 impl<E> EventOnce<E> {
     pub fn try_next(&self) -> Option<E> {
@@ -155,15 +168,38 @@ event! {
     FocusOut FocusEvent "focusout";
 
     // WebSocket events
-    Open    Event        "open";
-    Message MessageEvent "message";
-    Close   CloseEvent   "close";
+    Open         Event        "open";
+    Message      MessageEvent "message";
+    MessageError MessageEvent "messageerror";
+    Close        CloseEvent   "close";
+
+    // WebRTC events
+    IceCandidate          RtcPeerConnectionIceEvent "icecandidate";
+    DataChannel           RtcDataChannelEvent       "datachannel";
+    ConnectionStateChange Event                     "connectionstatechange";
+    BufferedAmountLow     Event                     "bufferedamountlow";
+
+    // Database events
+    Success             Event                 "success";
+    UpgradeNeeded       IdbVersionChangeEvent "upgradeneeded";
+    Blocked             IdbVersionChangeEvent "blocked";
+    VersionChange       IdbVersionChangeEvent "versionchange";
+    TransactionComplete Event                 "complete";
 
     // Session History events
     PageHide PageTransitionEvent "pagehide";
     PageShow PageTransitionEvent "pageshow";
     PopState PopStateEvent       "popstate";
 
+    // Font loading events
+    Loading      FontFaceSetLoadEvent "loading";
+    LoadingDone  FontFaceSetLoadEvent "loadingdone";
+    LoadingError FontFaceSetLoadEvent "loadingerror";
+
+    // Page Lifecycle events
+    Freeze Event "freeze";
+    Resume Event "resume";
+
     // Form events
     Reset  Event "reset";
     Submit Event "submit"; // should be a SubmitEvent but that doesn't seem to be in web-sys?
@@ -173,6 +209,7 @@ event! {
     FullscreenError  Event   "fullscreenerror";
     Resize           UiEvent "resize";
     Scroll           Event   "scroll";
+    VisibilityChange Event   "visibilitychange";
 
     // Keyboard events
     KeyDown    KeyboardEvent "keydown";
@@ -226,6 +263,62 @@ event! {
     VolumeChange   Event                       "volumechange";
     Waiting        Event                       "waiting";
 
+    // Web Audio events
+    AudioStateChange Event "statechange";
+
+    // Touch events
+    TouchStart  TouchEvent "touchstart";
+    TouchEnd    TouchEvent "touchend";
+    TouchMove   TouchEvent "touchmove";
+    TouchCancel TouchEvent "touchcancel";
+
+    // Pointer events
+    PointerDown        PointerEvent "pointerdown";
+    PointerUp          PointerEvent "pointerup";
+    PointerMove        PointerEvent "pointermove";
+    PointerOver        PointerEvent "pointerover";
+    PointerOut         PointerEvent "pointerout";
+    PointerEnter       PointerEvent "pointerenter";
+    PointerLeave       PointerEvent "pointerleave";
+    PointerCancel      PointerEvent "pointercancel";
+    GotPointerCapture  PointerEvent "gotpointercapture";
+    LostPointerCapture PointerEvent "lostpointercapture";
+
+    // Media device events
+    DeviceChange Event "devicechange";
+
+    // Device connection events, such as for USB devices
+    DeviceConnect    Event "connect";
+    DeviceDisconnect Event "disconnect";
+
+    // Bluetooth events
+    GattServerDisconnected     Event "gattserverdisconnected";
+    CharacteristicValueChanged Event "characteristicvaluechanged";
+    AdvertisementReceived      Event "advertisementreceived";
+
+    // MIDI events
+    MidiMessage     MidiMessageEvent    "midimessage";
+    MidiStateChange MidiConnectionEvent "statechange";
+
+    // Media recording events
+    DataAvailable BlobEvent "dataavailable";
+    RecorderStart Event     "start";
+    RecorderStop  Event     "stop";
+
+    // Speech events
+    VoicesChanged     Event                     "voiceschanged";
+    SynthesisEnd      SpeechSynthesisEvent      "end";
+    SynthesisError    SpeechSynthesisErrorEvent "error";
+    RecognitionResult SpeechRecognitionEvent    "result";
+    RecognitionError  SpeechRecognitionError    "error";
+    RecognitionEnd    Event                     "end";
+
+    // Battery events
+    ChargingChange        Event "chargingchange";
+    ChargingTimeChange    Event "chargingtimechange";
+    DischargingTimeChange Event "dischargingtimechange";
+    LevelChange           Event "levelchange";
+
     // Progress events
     ProgressAbort     ProgressEvent "abort";
     ProgressLoad      ProgressEvent "load";
@@ -243,19 +336,27 @@ event! {
     Input            InputEvent      "input";
     ReadyStateChange Event           "readystatechange";
 
+    // Notification events
+    NotificationClick       Event             "click";
+    NotificationClose       Event             "close";
+    WorkerNotificationClick NotificationEvent "notificationclick";
+    WorkerNotificationClose NotificationEvent "notificationclose";
+
+    // Push events
+    Push                   PushEvent "push";
+    PushSubscriptionChange Event     "pushsubscriptionchange";
+
     // Uncategorized events
     Invalid Event "invalid";
+    Cancel  Event "cancel";
 
     // TODO Abortable Fetch events
     // TODO WebVR events
     // TODO SVG events
-    // TODO Database events
     // TODO Tab events
     // TODO Sensor events
     // TODO Smartcard events
     // TODO DOM mutation events
-    // TODO Touch events
-    // TODO Pointer events
     // TODO Printing events
     // TODO Text Composition events
     // TODO CSS Animation events
@@ -0,0 +1,102 @@
+//! Reading blobs and files, such as from file inputs or dropped on the page.
+//!
+//! ```ignore
+//! let e: event::DragDrop = target.once().await;
+//! for file in file::files(&e.data_transfer().unwrap().files().unwrap()) {
+//!     let save: Save = serde_json::from_str(&file::read_text(&file).await?)?;
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::event;
+use wasm_bindgen_futures::JsFuture;
+use std::cell::Cell;
+
+/// The files in a list, such as from `HtmlInputElement::files`.
+pub fn files(list: &web_sys::FileList) -> Vec<web_sys::File> {
+    (0..list.length()).filter_map(|i| list.get(i)).collect()
+}
+
+/// Reads the whole blob.
+pub async fn read_bytes(blob: &web_sys::Blob) -> Result<Vec<u8>, GeneralError> {
+    let buffer = JsFuture::from(blob.array_buffer()).await?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+/// Reads the whole blob as UTF-8 text. Invalid UTF-8 is replaced with `U+FFFD`.
+pub async fn read_text(blob: &web_sys::Blob) -> Result<String, GeneralError> {
+    let text = JsFuture::from(blob.text()).await?;
+    Ok(text.as_string().unwrap_or_default())
+}
+
+/// Reads the blob as a `data:` URL, which can be used as the source of an image.
+///
+/// For large blobs, an [`ObjectUrl`](crate::url::ObjectUrl) avoids copying the data.
+pub async fn read_data_url(blob: &web_sys::Blob) -> Result<String, GeneralError> {
+    let reader = web_sys::FileReader::new()?;
+    let load = reader.once::<event::Load>();
+    let error = reader.once::<event::Error>();
+    reader.read_as_data_url(blob)?;
+    let ok = async { load.await; true }.race(async { error.await; false }).await;
+    if ok {
+        Ok(reader.result()?.as_string().unwrap_or_default())
+    } else {
+        Err(GeneralError::WebSys(reader.error().into()))
+    }
+}
+
+/// Reads the blob in chunks of `chunk_size` bytes, so that large files don't have to fit in
+/// memory at once. The last chunk may be shorter.
+///
+/// Each chunk is only read when it is asked for, so at most one is in memory at a time.
+pub fn read_stream(blob: &web_sys::Blob, chunk_size: usize) -> Chunks {
+    assert!(chunk_size > 0, "chunk size must be positive");
+    Chunks {
+        blob: blob.clone(),
+        chunk_size: chunk_size as f64,
+        start: Cell::new(0.0)
+    }
+}
+
+/// The chunks of a blob, read on demand, from [`read_stream`].
+pub struct Chunks {
+    blob: web_sys::Blob,
+    chunk_size: f64,
+    start: Cell<f64>
+}
+
+impl Chunks {
+    /// Reads the next chunk, or returns `None` once the whole blob has been read. Reading
+    /// stops after an error.
+    pub async fn next(&self) -> Option<Result<Vec<u8>, GeneralError>> {
+        let size = self.blob.size();
+        let start = self.start.get();
+        if start >= size {
+            return None;
+        }
+        let end = (start + self.chunk_size).min(size);
+        // claimed before reading, so that reads waiting at the same time get later chunks
+        self.start.set(end);
+        let chunk = match self.blob.slice_with_f64_and_f64(start, end) {
+            Ok(chunk) => read_bytes(&chunk).await,
+            Err(e) => Err(e.into())
+        };
+        if chunk.is_err() {
+            self.start.set(size);
+        }
+        Some(chunk)
+    }
+
+    /// The offset in bytes of the next chunk.
+    pub fn position(&self) -> u64 {
+        self.start.get() as u64
+    }
+}
+
+/// Makes a blob out of bytes, with the MIME type `ty`.
+pub fn to_blob(bytes: &[u8], ty: &str) -> Result<web_sys::Blob, GeneralError> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(ty);
+    Ok(web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?)
+}
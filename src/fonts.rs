@@ -0,0 +1,58 @@
+//! Waiting for webfonts, so canvas text isn't measured and drawn with a fallback font.
+//!
+//! ```ignore
+//! fonts::load("16px MyFont").await?;
+//! context.set_font("16px MyFont");
+//! ```
+
+use crate::prelude::*;
+use crate::event::{ self, EventStream, EventTargetExt };
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// The font set of the current window or worker, `document.fonts` or `self.fonts`.
+pub fn font_set() -> web_sys::FontFaceSet {
+    let fonts = js_sys::Reflect::get(&js_sys::global(), &"fonts".into())
+        .ok()
+        .filter(|fonts| !fonts.is_undefined())
+        .expect("font loading is not available in this scope");
+    fonts.unchecked_into()
+}
+
+/// Loads the fonts needed to render text in `font`, a CSS font shorthand like `"16px MyFont"`.
+///
+/// Returns the loaded font faces, which is empty if no declared font face matches. Fails if
+/// `font` can't be parsed or a font fails to load.
+pub async fn load(font: &str) -> Result<Vec<web_sys::FontFace>, GeneralError> {
+    Ok(faces(JsFuture::from(font_set().load(font)).await?))
+}
+
+/// Like [`load`], but only loads the faces needed to render the characters in `text`.
+pub async fn load_with_text(
+    font: &str, text: &str
+) -> Result<Vec<web_sys::FontFace>, GeneralError> {
+    Ok(faces(JsFuture::from(font_set().load_with_text(font, text)).await?))
+}
+
+fn faces(loaded: JsValue) -> Vec<web_sys::FontFace> {
+    loaded.unchecked_into::<js_sys::Array>().iter().map(JsCast::unchecked_into).collect()
+}
+
+/// Waits until the document has finished loading fonts, including any which start loading
+/// while waiting.
+pub async fn ready() {
+    if let Ok(ready) = font_set().ready() {
+        let _ = JsFuture::from(ready).await;
+    }
+}
+
+/// Whether text in `font` can be rendered without loading any more fonts.
+pub fn check(font: &str) -> bool {
+    font_set().check(font).unwrap_or(false)
+}
+
+/// Returns a stream of `loadingdone` events, sent each time a batch of font loads finishes.
+/// The event lists the faces which loaded successfully.
+pub fn loading_done() -> EventStream<event::LoadingDone> {
+    font_set().on()
+}
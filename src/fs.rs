@@ -0,0 +1,445 @@
+//! The File System Access API, for editing files and directories the user picks.
+//!
+//! Where the pickers aren't supported, opening files falls back to a file input, and writing
+//! falls back to downloading the new contents, so that apps keep working as upload and
+//! download. Directories can't be picked without the API.
+//!
+//! ```ignore
+//! let types = [FileType::new("Levels", "application/json", &[".json"])];
+//! if let Some(file) = fs::pick_open_file(&types).await? {
+//!     let level: Level = serde_json::from_str(&file.read_text().await?)?;
+//!     ...
+//!     file.write(serde_json::to_string(&level)?.as_bytes()).await?;
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::download;
+use crate::file;
+use crate::global::GlobalScope;
+use crate::streams::{ self, ByteSink };
+use crate::upload;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    // the pickers are only bound by web-sys as unstable APIs
+    #[wasm_bindgen(catch, js_name = showOpenFilePicker)]
+    fn show_open_file_picker(options: &JsValue) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(catch, js_name = showSaveFilePicker)]
+    fn show_save_file_picker(options: &JsValue) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(catch, js_name = showDirectoryPicker)]
+    fn show_directory_picker() -> Result<js_sys::Promise, JsValue>;
+}
+
+/// Errors from using the file system.
+#[derive(Debug)]
+pub enum FsError {
+    /// The API isn't supported by the browser, or can't be used in this context.
+    Unavailable,
+    /// The user didn't allow access to the file or directory.
+    PermissionDenied,
+    /// The file or directory doesn't exist, or is the other kind of entry.
+    NotFound,
+    /// There isn't enough space left to write the file.
+    QuotaExceeded,
+    Other(JsValue)
+}
+
+impl From<JsValue> for FsError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotAllowedError") | Some("SecurityError") => FsError::PermissionDenied,
+            Some("NotFoundError") | Some("TypeMismatchError") => FsError::NotFound,
+            Some("QuotaExceededError") => FsError::QuotaExceeded,
+            _ => FsError::Other(e)
+        }
+    }
+}
+
+impl From<GeneralError> for FsError {
+    fn from(e: GeneralError) -> Self {
+        FsError::Other(e.into())
+    }
+}
+
+impl From<FsError> for GeneralError {
+    fn from(e: FsError) -> Self {
+        let msg = match e {
+            FsError::Unavailable => "file system access is unavailable",
+            FsError::PermissionDenied => "file system permission denied",
+            FsError::NotFound => "file or directory not found",
+            FsError::QuotaExceeded => "storage quota exceeded",
+            FsError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(msg)
+    }
+}
+
+/// A kind of file the pickers offer, such as `FileType::new("Images", "image/png", &[".png"])`.
+#[derive(Clone, Debug)]
+pub struct FileType {
+    pub description: String,
+    pub mime: String,
+    /// Extensions including the leading `.`.
+    pub extensions: Vec<String>
+}
+
+impl FileType {
+    pub fn new(description: &str, mime: &str, extensions: &[&str]) -> Self {
+        FileType {
+            description: description.to_owned(),
+            mime: mime.to_owned(),
+            extensions: extensions.iter().map(|&ext| ext.to_owned()).collect()
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PickerOptions<'a> {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    types: Vec<AcceptType<'a>>,
+    multiple: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggested_name: Option<&'a str>
+}
+
+#[derive(Serialize)]
+struct AcceptType<'a> {
+    description: &'a str,
+    accept: HashMap<&'a str, &'a [String]>
+}
+
+impl<'a> PickerOptions<'a> {
+    fn new(types: &'a [FileType]) -> Self {
+        PickerOptions {
+            types: types.iter().map(|ty| AcceptType {
+                description: &ty.description,
+                accept: std::iter::once((&*ty.mime, &*ty.extensions)).collect()
+            }).collect(),
+            multiple: false,
+            suggested_name: None
+        }
+    }
+
+    fn to_js(&self) -> Result<JsValue, FsError> {
+        self.serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+            .map_err(|e| FsError::Other(e.into()))
+    }
+}
+
+/// Whether the browser supports the pickers, rather than falling back to upload and download.
+pub fn is_supported() -> bool {
+    js_sys::Reflect::has(&js_sys::global(), &"showOpenFilePicker".into()).unwrap_or(false)
+}
+
+/// Asks the user to pick a file to open, returning `None` if they cancel.
+///
+/// Some browsers without the API don't report cancelling the file input, in which case this
+/// never finishes.
+pub async fn pick_open_file(types: &[FileType]) -> Result<Option<FileHandle>, FsError> {
+    Ok(pick_open(types, false).await?.pop())
+}
+
+/// Asks the user to pick files to open, returning none if they cancel.
+pub async fn pick_open_files(types: &[FileType]) -> Result<Vec<FileHandle>, FsError> {
+    pick_open(types, true).await
+}
+
+/// Asks the user where to save a file, returning `None` if they cancel.
+///
+/// Without the API, this doesn't ask, and instead the file is downloaded as `suggested_name`
+/// whenever it is written.
+pub async fn pick_save_file(
+    suggested_name: &str, types: &[FileType]
+) -> Result<Option<FileHandle>, FsError> {
+    if !is_supported() {
+        let parts = js_sys::Array::new();
+        let empty = web_sys::File::new_with_u8_array_sequence(&parts, suggested_name)?;
+        return Ok(Some(FileHandle::local(empty)));
+    }
+    let mut options = PickerOptions::new(types);
+    options.suggested_name = Some(suggested_name);
+    let handle = match picked(show_save_file_picker(&options.to_js()?)).await? {
+        Some(handle) => handle,
+        None => return Ok(None)
+    };
+    Ok(Some(FileHandle(Inner::Native(handle.unchecked_into()))))
+}
+
+/// Asks the user to pick a directory, returning `None` if they cancel.
+pub async fn pick_directory() -> Result<Option<DirectoryHandle>, FsError> {
+    if !is_supported() {
+        return Err(FsError::Unavailable);
+    }
+    let handle = match picked(show_directory_picker()).await? {
+        Some(handle) => handle,
+        None => return Ok(None)
+    };
+    Ok(Some(DirectoryHandle(handle.unchecked_into())))
+}
+
+async fn pick_open(types: &[FileType], multiple: bool) -> Result<Vec<FileHandle>, FsError> {
+    if !is_supported() {
+        return pick_upload(types, multiple).await;
+    }
+    let mut options = PickerOptions::new(types);
+    options.multiple = multiple;
+    let handles: js_sys::Array = match picked(show_open_file_picker(&options.to_js()?)).await? {
+        Some(handles) => handles.unchecked_into(),
+        None => return Ok(vec![])
+    };
+    Ok(handles.iter().map(|handle| FileHandle(Inner::Native(handle.unchecked_into()))).collect())
+}
+
+/// Waits for a picker, which rejects with an `AbortError` if the user cancels.
+async fn picked(picker: Result<js_sys::Promise, JsValue>) -> Result<Option<JsValue>, FsError> {
+    match JsFuture::from(picker?).await {
+        Ok(picked) => Ok(Some(picked)),
+        Err(e) => {
+            match crate::error_name(&e).as_deref() {
+                Some("AbortError") => Ok(None),
+                _ => Err(e.into())
+            }
+        }
+    }
+}
+
+/// Picks files with a file input.
+async fn pick_upload(types: &[FileType], multiple: bool) -> Result<Vec<FileHandle>, FsError> {
+    if !GlobalScope::current().is_window() {
+        return Err(FsError::Unavailable);
+    }
+    let accept = types.iter()
+        .flat_map(|ty| std::iter::once(&ty.mime).chain(&ty.extensions))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let files = upload::pick_files(&accept.join(","), multiple).await?;
+    Ok(files.into_iter().map(FileHandle::local).collect())
+}
+
+#[derive(Clone, Debug)]
+enum Inner {
+    Native(web_sys::FileSystemFileHandle),
+    /// Without the API, the file is kept in memory, and downloaded whenever it is written.
+    Local(Rc<RefCell<web_sys::File>>)
+}
+
+/// A file picked by the user or in a directory.
+#[derive(Clone, Debug)]
+pub struct FileHandle(Inner);
+
+impl FileHandle {
+    fn local(file: web_sys::File) -> Self {
+        FileHandle(Inner::Local(Rc::new(RefCell::new(file))))
+    }
+
+    pub fn name(&self) -> String {
+        match &self.0 {
+            Inner::Native(handle) => handle.name(),
+            Inner::Local(file) => file.borrow().name()
+        }
+    }
+
+    /// Whether writing saves the file in place, rather than downloading it.
+    pub fn is_native(&self) -> bool {
+        matches!(self.0, Inner::Native(_))
+    }
+
+    /// A snapshot of the file's current contents.
+    pub async fn file(&self) -> Result<web_sys::File, FsError> {
+        match &self.0 {
+            Inner::Native(handle) => Ok(JsFuture::from(handle.get_file()).await?.unchecked_into()),
+            Inner::Local(file) => Ok(file.borrow().clone())
+        }
+    }
+
+    pub async fn read(&self) -> Result<Vec<u8>, FsError> {
+        let file = self.file().await?;
+        Ok(file::read_bytes(&file).await?)
+    }
+
+    /// Reads the file as UTF-8 text. Invalid UTF-8 is replaced with `U+FFFD`.
+    pub async fn read_text(&self) -> Result<String, FsError> {
+        let file = self.file().await?;
+        Ok(file::read_text(&file).await?)
+    }
+
+    /// Replaces the contents of the file with `data`. The file is only changed once all of it
+    /// has been written.
+    pub async fn write(&self, data: &[u8]) -> Result<(), FsError> {
+        match &self.0 {
+            Inner::Native(handle) => {
+                let writable = JsFuture::from(handle.create_writable()).await?;
+                write_at(writable.unchecked_into(), None, data).await
+            }
+            Inner::Local(file) => {
+                let name = file.borrow().name();
+                let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+                let written = web_sys::File::new_with_u8_array_sequence(&parts, &name)?;
+                download::save_blob(&name, &written)?;
+                *file.borrow_mut() = written;
+                Ok(())
+            }
+        }
+    }
+
+    /// Replaces the contents of the file with what is written to the returned sink, once it
+    /// is closed, without needing all of it at once. Only supported with the File System
+    /// Access API.
+    pub async fn write_stream(&self) -> Result<ByteSink, FsError> {
+        match &self.0 {
+            Inner::Native(handle) => {
+                let writable = JsFuture::from(handle.create_writable()).await?;
+                Ok(streams::from_writable(writable.unchecked_ref()).map_err(JsValue::from)?)
+            }
+            Inner::Local(_) => Err(FsError::Unavailable)
+        }
+    }
+
+    /// Adds `data` to the end of the file.
+    pub async fn append(&self, data: &[u8]) -> Result<(), FsError> {
+        match &self.0 {
+            Inner::Native(handle) => {
+                let size = self.file().await?.size();
+                let options = web_sys::FileSystemCreateWritableOptions::new();
+                options.set_keep_existing_data(true);
+                let writable = handle.create_writable_with_options(&options);
+                let writable = JsFuture::from(writable).await?;
+                write_at(writable.unchecked_into(), Some(size), data).await
+            }
+            Inner::Local(_) => {
+                let mut contents = self.read().await?;
+                contents.extend_from_slice(data);
+                self.write(&contents).await
+            }
+        }
+    }
+
+    /// The underlying handle, unless the API isn't supported.
+    pub fn raw(&self) -> Option<&web_sys::FileSystemFileHandle> {
+        match &self.0 {
+            Inner::Native(handle) => Some(handle),
+            Inner::Local(_) => None
+        }
+    }
+}
+
+impl From<web_sys::FileSystemFileHandle> for FileHandle {
+    fn from(handle: web_sys::FileSystemFileHandle) -> Self {
+        FileHandle(Inner::Native(handle))
+    }
+}
+
+/// Writes `data` at `position`, committing it only if all of it was written.
+async fn write_at(
+    writable: web_sys::FileSystemWritableFileStream, position: Option<f64>, data: &[u8]
+) -> Result<(), FsError> {
+    let result = async {
+        if let Some(position) = position {
+            JsFuture::from(writable.seek_with_f64(position)?).await?;
+        }
+        JsFuture::from(writable.write_with_u8_array(data)?).await
+    }.await;
+    match result {
+        Ok(_) => {
+            JsFuture::from(writable.close()).await?;
+            Ok(())
+        }
+        Err(e) => {
+            JsFuture::from(writable.abort()).await.ok();
+            Err(e.into())
+        }
+    }
+}
+
+/// A directory picked by the user or in another directory.
+#[derive(Clone, Debug)]
+pub struct DirectoryHandle(web_sys::FileSystemDirectoryHandle);
+
+/// An entry in a directory.
+#[derive(Clone, Debug)]
+pub enum Entry {
+    File(FileHandle),
+    Directory(DirectoryHandle)
+}
+
+impl DirectoryHandle {
+    pub fn name(&self) -> String {
+        self.0.name()
+    }
+
+    /// The file `name` in this directory, creating it if it doesn't exist and `create` is
+    /// set.
+    pub async fn file(&self, name: &str, create: bool) -> Result<FileHandle, FsError> {
+        let options = web_sys::FileSystemGetFileOptions::new();
+        options.set_create(create);
+        let handle = JsFuture::from(self.0.get_file_handle_with_options(name, &options)).await?;
+        Ok(FileHandle(Inner::Native(handle.unchecked_into())))
+    }
+
+    /// The directory `name` in this directory, creating it if it doesn't exist and `create`
+    /// is set.
+    pub async fn directory(&self, name: &str, create: bool) -> Result<DirectoryHandle, FsError> {
+        let options = web_sys::FileSystemGetDirectoryOptions::new();
+        options.set_create(create);
+        let handle = self.0.get_directory_handle_with_options(name, &options);
+        Ok(DirectoryHandle(JsFuture::from(handle).await?.unchecked_into()))
+    }
+
+    /// Removes the entry `name`. Directories which aren't empty are only removed if
+    /// `recursive` is set.
+    pub async fn remove(&self, name: &str, recursive: bool) -> Result<(), FsError> {
+        let options = web_sys::FileSystemRemoveOptions::new();
+        options.set_recursive(recursive);
+        JsFuture::from(self.0.remove_entry_with_options(name, &options)).await?;
+        Ok(())
+    }
+
+    /// The entries of the directory, in no particular order.
+    pub fn entries(&self) -> Entries {
+        Entries(self.0.values())
+    }
+
+    /// The underlying handle.
+    pub fn raw(&self) -> &web_sys::FileSystemDirectoryHandle {
+        &self.0
+    }
+}
+
+impl From<web_sys::FileSystemDirectoryHandle> for DirectoryHandle {
+    fn from(handle: web_sys::FileSystemDirectoryHandle) -> Self {
+        DirectoryHandle(handle)
+    }
+}
+
+/// The entries of a directory, from [`DirectoryHandle::entries`].
+pub struct Entries(js_sys::AsyncIterator);
+
+impl Entries {
+    /// The next entry, or `None` once all of them have been listed.
+    pub async fn next(&self) -> Option<Result<Entry, FsError>> {
+        let next = match self.0.next() {
+            Ok(next) => JsFuture::from(next).await,
+            Err(e) => Err(e)
+        };
+        let next: js_sys::IteratorNext = match next {
+            Ok(next) => next.unchecked_into(),
+            Err(e) => return Some(Err(e.into()))
+        };
+        if next.done() {
+            return None;
+        }
+        let handle = next.value();
+        Some(Ok(match handle.dyn_into::<web_sys::FileSystemFileHandle>() {
+            Ok(file) => Entry::File(FileHandle(Inner::Native(file))),
+            Err(handle) => Entry::Directory(DirectoryHandle(handle.unchecked_into()))
+        }))
+    }
+}
@@ -0,0 +1,220 @@
+use crate::prelude::*;
+use crate::event::{ self, ListenerHandle };
+use crate::global::{ self, IntoDelay };
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Runs a game loop, calling `update` every `fixed_dt` of game time and `render` every
+/// animation frame, until the returned handle is dropped.
+///
+/// `render` is passed how far the game time is between the last update and the next one, from
+/// 0 to 1, for interpolating positions. If updates fall behind, at most
+/// [`max_updates_per_frame`](LoopHandle::set_max_updates_per_frame) are run per frame and the
+/// rest of the time is skipped, so the game slows down rather than freezing. The loop also
+/// pauses while the page is hidden instead of catching up afterwards.
+///
+/// Panics if `fixed_dt` is not positive.
+/// ```ignore
+/// let _game_loop = game_loop::run(
+///     Duration::from_secs_f64(1.0 / 60.0),
+///     move || world.borrow_mut().step(),
+///     move |alpha| world.borrow().draw(&ctx, alpha)
+/// );
+/// ```
+pub fn run(
+    fixed_dt: impl IntoDelay,
+    mut update: impl FnMut() + 'static,
+    mut render: impl FnMut(f64) + 'static
+) -> LoopHandle {
+    let fixed_dt = fixed_dt.into_millis();
+    assert!(fixed_dt > 0.0, "fixed_dt must be positive");
+    let state = Rc::new(LoopState {
+        running: Cell::new(true),
+        paused: Cell::new(false),
+        resumed: Cell::new(false),
+        max_updates: Cell::new(5)
+    });
+
+    // animation frames stop while the page is hidden, and the first frame after that would
+    // otherwise account for all the time the page was hidden
+    let visibility = web_sys::window().and_then(|w| w.document()).map(|document| {
+        let st = state.clone();
+        let doc = document.clone();
+        document.add_event_listener(move |_: event::VisibilityChange| {
+            if !doc.hidden() {
+                st.resumed.set(true);
+            }
+        })
+    });
+
+    let st = state.clone();
+    spawn_local(async move {
+        let frames = global::animation_frames();
+        let mut accumulator = 0.0;
+        while st.running.get() {
+            let frame = frames.next().await;
+            if !st.running.get() {
+                break;
+            }
+            if st.paused.get() || st.resumed.replace(false) {
+                continue;
+            }
+
+            accumulator += frame.delta;
+            let mut updates = 0;
+            while accumulator >= fixed_dt {
+                if updates == st.max_updates.get() {
+                    accumulator %= fixed_dt;
+                    break;
+                }
+                update();
+                accumulator -= fixed_dt;
+                updates += 1;
+            }
+            render(accumulator / fixed_dt);
+        }
+    });
+
+    LoopHandle { state, _visibility: visibility }
+}
+
+struct LoopState {
+    running: Cell<bool>,
+    paused: Cell<bool>,
+    resumed: Cell<bool>,
+    max_updates: Cell<u32>
+}
+
+/// Handle to a loop started with [`run`]. Dropping it stops the loop.
+pub struct LoopHandle {
+    state: Rc<LoopState>,
+    _visibility: Option<ListenerHandle>
+}
+
+impl LoopHandle {
+    /// Stops calling `update` and `render` until [`resume`](Self::resume) is called. Time
+    /// doesn't pass for the game while paused.
+    pub fn pause(&self) {
+        self.state.paused.set(true);
+    }
+
+    pub fn resume(&self) {
+        if self.state.paused.replace(false) {
+            self.state.resumed.set(true);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.get()
+    }
+
+    /// Sets how many updates may be run per frame to catch up. Defaults to 5.
+    pub fn set_max_updates_per_frame(&self, max: u32) {
+        self.state.max_updates.set(max.max(1));
+    }
+}
+
+impl Drop for LoopHandle {
+    fn drop(&mut self) {
+        self.state.running.set(false);
+    }
+}
+
+/// Paces a render loop to a target frame rate below the display's refresh rate by skipping
+/// animation frames, to save battery or leave time for CPU-bound work.
+/// ```ignore
+/// let mut pacer = FramePacer::new(30.0);
+/// loop {
+///     let frame = pacer.next().await;
+///     draw(frame.delta);
+/// }
+/// ```
+pub struct FramePacer {
+    frames: global::AnimationFrameStream,
+    interval: f64,
+    refresh_interval: f64,
+    next_target: Option<f64>,
+    last: Option<f64>,
+    stats: PacingStats
+}
+
+/// Statistics about the intervals between paced frames, in milliseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PacingStats {
+    pub frames: u64,
+    pub mean_interval: f64,
+    /// Standard deviation of the intervals.
+    pub jitter: f64,
+    pub max_interval: f64,
+    sum_squares: f64
+}
+
+impl FramePacer {
+    pub fn new(fps: f64) -> Self {
+        FramePacer {
+            frames: global::animation_frames(),
+            interval: 1000.0 / fps,
+            refresh_interval: 1000.0 / 60.0,
+            next_target: None,
+            last: None,
+            stats: PacingStats::default()
+        }
+    }
+
+    pub fn set_target_fps(&mut self, fps: f64) {
+        self.interval = 1000.0 / fps;
+        self.next_target = None;
+    }
+
+    /// Waits for the next frame at the target frame rate. The delta is the time since the
+    /// previous frame returned by this.
+    pub async fn next(&mut self) -> global::FrameTiming {
+        loop {
+            let frame = self.frames.next().await;
+            if frame.delta > 0.0 {
+                // smoothed estimate of the display's refresh interval
+                self.refresh_interval += (frame.delta.min(100.0) - self.refresh_interval) * 0.1;
+            }
+
+            let target = *self.next_target.get_or_insert(frame.timestamp);
+            // take the frame closest to the target, so the average rate is exact even when
+            // the target isn't a divisor of the refresh rate
+            if frame.timestamp + self.refresh_interval / 2.0 < target {
+                continue;
+            }
+            self.next_target = Some(if frame.timestamp > target + self.interval {
+                // fell behind, so don't try to catch up
+                frame.timestamp + self.interval
+            } else {
+                target + self.interval
+            });
+
+            let delta = self.last.replace(frame.timestamp)
+                .map_or(0.0, |last| frame.timestamp - last);
+            if delta > 0.0 {
+                self.stats.record(delta);
+            }
+            return global::FrameTiming { timestamp: frame.timestamp, delta };
+        }
+    }
+
+    pub fn stats(&self) -> PacingStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = PacingStats::default();
+    }
+}
+
+impl PacingStats {
+    fn record(&mut self, interval: f64) {
+        self.frames += 1;
+        let n = self.frames as f64;
+        self.mean_interval += (interval - self.mean_interval) / n;
+        self.sum_squares += interval * interval;
+        let variance = self.sum_squares / n - self.mean_interval * self.mean_interval;
+        self.jitter = variance.max(0.0).sqrt();
+        self.max_interval = self.max_interval.max(interval);
+    }
+}
@@ -0,0 +1,249 @@
+//! The device's location, from the Geolocation API.
+//!
+//! ```ignore
+//! let here = geo::current_position(&GeoOptions::new().timeout(10_000)).await?;
+//! map.center(here.latitude, here.longitude);
+//!
+//! let watch = geo::watch(&GeoOptions::new().high_accuracy(true));
+//! loop {
+//!     match watch.next().await {
+//!         Ok(position) => map.move_marker(position.latitude, position.longitude),
+//!         Err(GeoError::PermissionDenied) => break,
+//!         Err(_) => {}
+//!     }
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, channel, oneshot };
+use crate::global::{ GlobalScope, IntoDelay };
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+
+/// Errors from getting the location.
+#[derive(Debug)]
+pub enum GeoError {
+    /// The location can't be determined, such as because the API isn't supported, this isn't
+    /// a window, or there is no signal.
+    Unavailable,
+    /// The user or browser didn't allow getting the location, such as because the page isn't
+    /// served securely.
+    PermissionDenied,
+    /// The location wasn't found within the [`GeoOptions::timeout`].
+    Timeout,
+    Other(JsValue)
+}
+
+impl From<JsValue> for GeoError {
+    fn from(e: JsValue) -> Self {
+        // position errors aren't DOMExceptions and have a code instead of a name
+        let code = js_sys::Reflect::get(&e, &"code".into()).ok().and_then(|c| c.as_f64());
+        match code.map(|c| c as u16) {
+            Some(1) => GeoError::PermissionDenied,
+            Some(2) => GeoError::Unavailable,
+            Some(3) => GeoError::Timeout,
+            _ => GeoError::Other(e)
+        }
+    }
+}
+
+impl From<GeoError> for GeneralError {
+    fn from(e: GeoError) -> Self {
+        match e {
+            GeoError::Unavailable => GeneralError::message("location is unavailable"),
+            GeoError::PermissionDenied => GeneralError::message("location permission denied"),
+            GeoError::Timeout => GeneralError::message("timed out getting location"),
+            GeoError::Other(e) => GeneralError::WebSys(e)
+        }
+    }
+}
+
+/// A location, with distances in meters and angles in degrees.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// How far the actual location may be from the latitude and longitude.
+    pub accuracy: f64,
+    /// Height above the WGS84 ellipsoid, if the device can tell.
+    pub altitude: Option<f64>,
+    pub altitude_accuracy: Option<f64>,
+    /// Direction of travel clockwise from true north, if the device is moving.
+    pub heading: Option<f64>,
+    /// Speed in meters per second, if the device can tell.
+    pub speed: Option<f64>,
+    /// When the location was determined, in milliseconds since the Unix epoch.
+    pub timestamp: f64
+}
+
+impl From<web_sys::Position> for Position {
+    fn from(position: web_sys::Position) -> Self {
+        let coords = position.coords();
+        Position {
+            latitude: coords.latitude(),
+            longitude: coords.longitude(),
+            accuracy: coords.accuracy(),
+            altitude: coords.altitude(),
+            altitude_accuracy: coords.altitude_accuracy(),
+            heading: coords.heading().filter(|h| !h.is_nan()),
+            speed: coords.speed(),
+            timestamp: position.timestamp()
+        }
+    }
+}
+
+/// How to get the location.
+#[derive(Clone, Debug, Default)]
+pub struct GeoOptions {
+    high_accuracy: bool,
+    timeout: Option<f64>,
+    maximum_age: Option<f64>
+}
+
+impl GeoOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks for the most accurate location, such as from GPS, which can be slower and use more
+    /// power.
+    pub fn high_accuracy(mut self, high_accuracy: bool) -> Self {
+        self.high_accuracy = high_accuracy;
+        self
+    }
+
+    /// Fails with [`GeoError::Timeout`] if the location takes longer than `timeout` to find,
+    /// not counting the time waiting for the user to allow it.
+    pub fn timeout(mut self, timeout: impl IntoDelay) -> Self {
+        self.timeout = Some(timeout.into_millis());
+        self
+    }
+
+    /// Accepts a location found up to `age` ago instead of finding a new one.
+    pub fn maximum_age(mut self, age: impl IntoDelay) -> Self {
+        self.maximum_age = Some(age.into_millis());
+        self
+    }
+
+    fn to_raw(&self) -> web_sys::PositionOptions {
+        let options = web_sys::PositionOptions::new();
+        options.set_enable_high_accuracy(self.high_accuracy);
+        if let Some(timeout) = self.timeout {
+            options.set_timeout(timeout.min(u32::MAX as f64) as u32);
+        }
+        if let Some(age) = self.maximum_age {
+            options.set_maximum_age(age.min(u32::MAX as f64) as u32);
+        }
+        options
+    }
+}
+
+fn geolocation() -> Result<web_sys::Geolocation, GeoError> {
+    match GlobalScope::current() {
+        GlobalScope::Window(window) => {
+            window.navigator().geolocation().map_err(|_| GeoError::Unavailable)
+        }
+        _ => Err(GeoError::Unavailable)
+    }
+}
+
+/// Finds the current location. Browsers ask the user for permission the first time.
+pub async fn current_position(options: &GeoOptions) -> Result<Position, GeoError> {
+    let geolocation = geolocation()?;
+    let (sender, result) = oneshot();
+    // only one of the callbacks is called, but both need the sender
+    let sender = Rc::new(Cell::new(Some(sender)));
+    let success = Closure::once({
+        let sender = sender.clone();
+        move |position: web_sys::Position| {
+            if let Some(sender) = sender.take() {
+                let _ = sender.resolve(Ok(position.into()));
+            }
+        }
+    });
+    let error = Closure::once(move |e: JsValue| {
+        if let Some(sender) = sender.take() {
+            let _ = sender.resolve(Err(GeoError::from(e)));
+        }
+    });
+    geolocation.get_current_position_with_error_callback_and_options(
+        success.as_ref().unchecked_ref(),
+        Some(error.as_ref().unchecked_ref()),
+        &options.to_raw()
+    )?;
+    result.await.unwrap()
+}
+
+/// Watches the location, which is reported whenever it changes.
+pub fn watch(options: &GeoOptions) -> PositionWatch {
+    let (sender, positions) = channel();
+    let geolocation = match geolocation() {
+        Ok(geolocation) => geolocation,
+        Err(e) => {
+            let _ = sender.send(Err(e));
+            return PositionWatch { positions, _watch: None };
+        }
+    };
+
+    let success = Closure::wrap(Box::new({
+        let sender = sender.clone();
+        move |position: web_sys::Position| {
+            let _ = sender.send(Ok(position.into()));
+        }
+    }) as Box<dyn FnMut(web_sys::Position)>);
+    let error = Closure::wrap(Box::new({
+        let sender = sender.clone();
+        move |e: JsValue| {
+            let _ = sender.send(Err(GeoError::from(e)));
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+    let id = geolocation.watch_position_with_error_callback_and_options(
+        success.as_ref().unchecked_ref(),
+        Some(error.as_ref().unchecked_ref()),
+        &options.to_raw()
+    );
+    let watch = match id {
+        Ok(id) => Some(Watch { geolocation, id, _success: success, _error: error }),
+        Err(e) => {
+            let _ = sender.send(Err(e.into()));
+            None
+        }
+    };
+    PositionWatch { positions, _watch: watch }
+}
+
+/// Locations from [`watch`]. Watching stops when this is dropped.
+pub struct PositionWatch {
+    positions: Receiver<Result<Position, GeoError>>,
+    _watch: Option<Watch>
+}
+
+struct Watch {
+    geolocation: web_sys::Geolocation,
+    id: i32,
+    _success: Closure<dyn FnMut(web_sys::Position)>,
+    _error: Closure<dyn FnMut(JsValue)>
+}
+
+impl PositionWatch {
+    pub fn try_next(&self) -> Option<Result<Position, GeoError>> {
+        self.positions.try_recv().ok()
+    }
+
+    /// Waits for the location to change. Errors don't stop the watch, except for
+    /// [`GeoError::PermissionDenied`] and failing to start it, after which this waits forever.
+    pub async fn next(&self) -> Result<Position, GeoError> {
+        match self.positions.recv().await {
+            Some(position) => position,
+            None => std::future::pending().await
+        }
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        self.geolocation.clear_watch(self.id);
+    }
+}
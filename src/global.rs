@@ -1,45 +1,419 @@
 use crate::prelude::*;
-use crate::channel::{ oneshot, Receiver, channel };
+use crate::channel::{ oneshot, Receiver, WatchReceiver, channel, watch };
 use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
+use std::cell::{ Cell, OnceCell, RefCell };
+use std::rc::Rc;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ Context, Poll, Wake, Waker };
+use std::collections::{ HashMap, VecDeque };
+use std::sync::Arc;
 
 #[wasm_bindgen]
 extern "C" {
-    fn setInterval(closure: &Closure<dyn FnMut()>, period: u32) -> i32;
+    fn setInterval(closure: &Closure<dyn FnMut()>, period: f64) -> i32;
     fn clearInterval(handle: i32);
-    fn setTimeout(closure: &Closure<dyn FnMut()>, delay: u32) -> i32;
+    fn setTimeout(closure: &js_sys::Function, delay: f64) -> i32;
+    fn requestIdleCallback(closure: &Closure<dyn FnMut(web_sys::IdleDeadline)>) -> u32;
+    fn cancelIdleCallback(handle: u32);
+    fn queueMicrotask(closure: &js_sys::Function);
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
 }
 
-pub fn set_interval(period: u32, f: impl FnMut() + 'static) -> IntervalHandle {
+/// High resolution time in milliseconds since the page or worker started, from
+/// `performance.now()`. See [`PerfInstant`](crate::perf::PerfInstant) for measuring durations.
+pub fn now() -> f64 {
+    performance_now()
+}
+
+/// The global object of the context the code is running in.
+///
+/// The timer helpers in this module work in any context, while animation frames are only
+/// available in windows and dedicated workers.
+#[derive(Clone, Debug)]
+pub enum GlobalScope {
+    Window(web_sys::Window),
+    Worker(web_sys::DedicatedWorkerGlobalScope),
+    /// Any other global scope, such as a shared worker or a worklet.
+    Other(js_sys::Object)
+}
+
+impl GlobalScope {
+    pub fn current() -> Self {
+        let global = js_sys::global();
+        if let Some(window) = global.dyn_ref::<web_sys::Window>() {
+            GlobalScope::Window(window.clone())
+        } else if let Some(scope) = global.dyn_ref::<web_sys::DedicatedWorkerGlobalScope>() {
+            GlobalScope::Worker(scope.clone())
+        } else {
+            GlobalScope::Other(global)
+        }
+    }
+
+    pub fn is_window(&self) -> bool {
+        matches!(self, GlobalScope::Window(_))
+    }
+
+    pub fn is_worker(&self) -> bool {
+        matches!(self, GlobalScope::Worker(_))
+    }
+
+    /// The global object as an event target, for listening to global events.
+    pub fn event_target(&self) -> &web_sys::EventTarget {
+        match self {
+            GlobalScope::Window(window) => window,
+            GlobalScope::Worker(scope) => scope,
+            GlobalScope::Other(global) => global.unchecked_ref()
+        }
+    }
+
+    /// The URL of the page or worker script, if the scope has one.
+    pub fn location_href(&self) -> Option<String> {
+        match self {
+            GlobalScope::Window(window) => window.location().href().ok(),
+            GlobalScope::Worker(scope) => Some(scope.location().href()),
+            GlobalScope::Other(_) => None
+        }
+    }
+
+    fn request_animation_frame(&self, f: &js_sys::Function) -> i32 {
+        match self {
+            GlobalScope::Window(window) => window.request_animation_frame(f).unwrap(),
+            GlobalScope::Worker(scope) => scope.request_animation_frame(f).unwrap(),
+            GlobalScope::Other(_) => panic!("animation frames are not available in this scope")
+        }
+    }
+
+    fn cancel_animation_frame(&self, id: i32) {
+        match self {
+            GlobalScope::Window(window) => window.cancel_animation_frame(id).unwrap(),
+            GlobalScope::Worker(scope) => scope.cancel_animation_frame(id).unwrap(),
+            GlobalScope::Other(_) => {}
+        }
+    }
+}
+
+/// The `navigator` of the current scope, which is undefined in worklets.
+pub(crate) fn navigator() -> JsValue {
+    js_sys::Reflect::get(&js_sys::global(), &"navigator".into()).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// The API at `navigator[name]`, such as `usb`, or `None` where it isn't supported.
+///
+/// This is looked up dynamically since web-sys assumes these APIs exist, and calling them
+/// where they don't throws.
+pub(crate) fn navigator_feature<T: JsCast>(name: &str) -> Option<T> {
+    let feature = js_sys::Reflect::get(&navigator(), &name.into()).ok()?;
+    match feature.is_undefined() || feature.is_null() {
+        true => None,
+        false => Some(feature.unchecked_into())
+    }
+}
+
+/// The page's `navigator.serviceWorker`, where service workers are supported.
+pub(crate) fn service_worker_container() -> Option<web_sys::ServiceWorkerContainer> {
+    navigator_feature("serviceWorker")
+}
+
+/// The service worker registration for the page, or of the service worker this runs in.
+pub(crate) async fn service_worker_registration(
+) -> Result<Option<web_sys::ServiceWorkerRegistration>, JsValue> {
+    if let Some(scope) = js_sys::global().dyn_ref::<web_sys::ServiceWorkerGlobalScope>() {
+        return Ok(Some(scope.registration()));
+    }
+    let container = match service_worker_container() {
+        Some(container) => container,
+        None => return Ok(None)
+    };
+    // unlike `ready`, this finishes when there is no service worker
+    let registration = wasm_bindgen_futures::JsFuture::from(container.get_registration()).await?;
+    Ok(registration.dyn_into().ok())
+}
+
+/// A delay accepted by the timer functions: either milliseconds or a `Duration`.
+pub trait IntoDelay {
+    fn into_millis(self) -> f64;
+}
+
+impl IntoDelay for u32 {
+    fn into_millis(self) -> f64 {
+        self as f64
+    }
+}
+
+impl IntoDelay for f64 {
+    fn into_millis(self) -> f64 {
+        self
+    }
+}
+
+impl IntoDelay for std::time::Duration {
+    fn into_millis(self) -> f64 {
+        self.as_secs_f64() * 1000.0
+    }
+}
+
+/// The longest delay browsers support for a single timeout, about 24.8 days. Longer delays
+/// are split into a chain of timeouts.
+const MAX_DELAY: f64 = i32::MAX as f64;
+
+pub fn set_interval(period: impl IntoDelay, mut f: impl FnMut() + 'static) -> IntervalHandle {
+    let period = period.into_millis();
+    if period > MAX_DELAY {
+        return chain_timeouts(period, move || {
+            f();
+            Some(period)
+        });
+    }
     let closure = Closure::wrap(Box::new(f) as Box<dyn FnMut()>);
     let id = setInterval(&closure, period);
-    IntervalHandle(id, Some(closure))
+    IntervalHandle(Rc::new(Cell::new(id)), Some(closure))
 }
 
-pub fn set_timeout(delay: u32, f: impl FnOnce() + 'static) -> IntervalHandle {
-    let closure = Closure::once(f);
-    let id = setTimeout(&closure, delay);
+pub fn set_timeout(delay: impl IntoDelay, f: impl FnOnce() + 'static) -> IntervalHandle {
+    let mut f = Some(f);
+    chain_timeouts(delay.into_millis(), move || {
+        f.take().unwrap()();
+        None
+    })
+}
+
+/// Calls `fire` after `delay` milliseconds, and again after the delay it returns, if any.
+fn chain_timeouts(delay: f64, mut fire: impl FnMut() -> Option<f64> + 'static) -> IntervalHandle {
+    let id = Rc::new(Cell::new(0));
+    let function = Rc::new(OnceCell::<js_sys::Function>::new());
+    let remaining = Rc::new(Cell::new(delay));
+    let arm = {
+        let id = id.clone();
+        let function = function.clone();
+        let remaining = remaining.clone();
+        move || {
+            let delay = remaining.get().min(MAX_DELAY);
+            remaining.set(remaining.get() - delay);
+            id.set(setTimeout(function.get().unwrap(), delay));
+        }
+    };
+    let rearm = arm.clone();
+    let closure = Closure::wrap(Box::new(move || {
+        if remaining.get() <= 0.0 {
+            match fire() {
+                Some(delay) => remaining.set(delay),
+                None => return
+            }
+        }
+        rearm();
+    }) as Box<dyn FnMut()>);
+    let _ = function.set(closure.as_ref().unchecked_ref::<js_sys::Function>().clone());
+    arm();
     IntervalHandle(id, Some(closure))
 }
 
 pub fn request_animation_frame(f: impl FnOnce(f64) + 'static) -> AnimationFrameHandle {
     let closure = Closure::once(f);
-    let id = web_sys::window().unwrap()
-        .request_animation_frame(closure.as_ref().unchecked_ref())
-        .unwrap();
-    AnimationFrameHandle(id, Some(closure))
+    let scope = GlobalScope::current();
+    let id = scope.request_animation_frame(closure.as_ref().unchecked_ref());
+    AnimationFrameHandle(id, scope, Some(closure))
+}
+
+/// Calls `f` when the browser is idle. The deadline tells how long `f` may take before it
+/// delays more important work.
+///
+/// See [`idle::Queue`](crate::idle::Queue) for spreading work across idle periods.
+///
+/// Where idle callbacks aren't supported, such as in Safari, `f` is called after a short
+/// timeout instead, with a deadline 50 milliseconds away.
+pub fn request_idle_callback(
+    f: impl FnOnce(web_sys::IdleDeadline) + 'static
+) -> IdleCallbackHandle {
+    let closure = Closure::once(f);
+    if js_sys::Reflect::has(&js_sys::global(), &"requestIdleCallback".into()).unwrap_or(false) {
+        let id = requestIdleCallback(&closure);
+        return IdleCallbackHandle(IdleId::Idle(id), Some(closure));
+    }
+    let callback = closure.as_ref().unchecked_ref::<js_sys::Function>().clone();
+    let timeout = set_timeout(1, move || {
+        let _ = callback.call1(&JsValue::UNDEFINED, &fallback_deadline(now() + 50.0));
+    });
+    IdleCallbackHandle(IdleId::Timeout(timeout), Some(closure))
 }
 
-pub fn interval(period: u32) -> IntervalStream {
+thread_local! {
+    /// Shared by every fallback deadline, which binds its end time to it.
+    static TIME_REMAINING: js_sys::Function = Closure::<dyn Fn(f64) -> f64>::new(
+        |end: f64| (end - now()).max(0.0)
+    ).into_js_value().unchecked_into();
+}
+
+/// An object acting like an `IdleDeadline` which ends at `end`.
+fn fallback_deadline(end: f64) -> web_sys::IdleDeadline {
+    let deadline = js_sys::Object::new();
+    let time_remaining = TIME_REMAINING.with(|f| f.bind1(&JsValue::UNDEFINED, &end.into()));
+    let _ = js_sys::Reflect::set(&deadline, &"timeRemaining".into(), &time_remaining);
+    let _ = js_sys::Reflect::set(&deadline, &"didTimeout".into(), &false.into());
+    deadline.unchecked_into()
+}
+
+/// Runs `f` once the current task finishes, before the browser handles any events or
+/// renders.
+pub fn micro_task(f: impl FnOnce() + 'static) {
+    queueMicrotask(&Closure::once_into_js(f).unchecked_into());
+}
+
+/// Wraps `f` so that it is only called once `delay` milliseconds pass without another call,
+/// with the value of the last call. Useful for handlers of bursty events like resizing.
+pub fn debounce<T: 'static>(
+    delay: impl IntoDelay, f: impl FnMut(T) + 'static
+) -> impl FnMut(T) {
+    let delay = delay.into_millis();
+    let f = Rc::new(RefCell::new(f));
+    let mut timer = None;
+    move |v| {
+        let f = f.clone();
+        // replacing the timer cancels the previous call
+        timer.replace(set_timeout(delay, move || (f.borrow_mut())(v)));
+    }
+}
+
+/// Wraps `f` so that it is called at most once every `period` milliseconds.
+///
+/// The first call goes through immediately. Calls made during the following `period` are
+/// collapsed into one call with the latest value at its end.
+pub fn throttle<T: 'static>(
+    period: impl IntoDelay, f: impl FnMut(T) + 'static
+) -> impl FnMut(T) {
+    let period = period.into_millis();
+    let state = Rc::new(RefCell::new(Throttle {
+        f: Rc::new(RefCell::new(f)),
+        pending: None,
+        cooling_down: false,
+        timer: None
+    }));
+    move |v| {
+        let mut st = state.borrow_mut();
+        if st.cooling_down {
+            st.pending = Some(v);
+        } else {
+            drop(st);
+            throttled_call(&state, period, v);
+        }
+    }
+}
+
+type ThrottledFn<T> = Rc<RefCell<dyn FnMut(T)>>;
+
+struct Throttle<T> {
+    f: ThrottledFn<T>,
+    pending: Option<T>,
+    cooling_down: bool,
+    timer: Option<IntervalHandle>
+}
+
+fn throttled_call<T: 'static>(state: &Rc<RefCell<Throttle<T>>>, period: f64, v: T) {
+    let f = {
+        let mut st = state.borrow_mut();
+        st.cooling_down = true;
+        let state = state.clone();
+        st.timer = Some(set_timeout(period, move || {
+            let pending = state.borrow_mut().pending.take();
+            match pending {
+                Some(v) => throttled_call(&state, period, v),
+                None => state.borrow_mut().cooling_down = false
+            }
+        }));
+        st.f.clone()
+    };
+    // the state isn't borrowed while `f` runs, so `f` may call the throttled function, which
+    // only queues the value since it is cooling down
+    (f.borrow_mut())(v);
+}
+
+pub fn interval(period: impl IntoDelay) -> IntervalStream {
     let (s, r) = channel();
     let handle = set_interval(period, move || s.send(()).ok().unwrap());
     IntervalStream(r, handle)
 }
 
-pub async fn later(delay: u32) {
-    let (s, r) = oneshot();
-    set_timeout(delay, || s.resolve(()).ok().unwrap()).forget();
-    r.await.unwrap()
+/// Returns a stream of animation frames for driving render loops.
+pub fn animation_frames() -> AnimationFrameStream {
+    let (s, r) = watch(0.0);
+    let frame_loop = Rc::new(FrameLoop {
+        scope: GlobalScope::current(),
+        id: Cell::new(0),
+        closure: OnceCell::new()
+    });
+    let weak = Rc::downgrade(&frame_loop);
+    let closure = Closure::wrap(Box::new(move |timestamp| {
+        s.set(timestamp);
+        if let Some(frame_loop) = weak.upgrade() {
+            frame_loop.request();
+        }
+    }) as Box<dyn FnMut(f64)>);
+    let _ = frame_loop.closure.set(closure);
+    frame_loop.request();
+    AnimationFrameStream(r, frame_loop, Cell::new(None))
+}
+
+pub async fn later(delay: impl IntoDelay) {
+    sleep(delay).await
+}
+
+/// Returns a future which resolves after `delay` milliseconds. Dropping it cancels the
+/// underlying timeout.
+pub fn sleep(delay: impl IntoDelay) -> Sleep {
+    let state = Rc::new(RefCell::new(SleepState { done: false, waker: None }));
+    let timer = sleep_timer(&state, delay);
+    Sleep { state, timer }
+}
+
+/// Future returned by [`sleep`].
+pub struct Sleep {
+    state: Rc<RefCell<SleepState>>,
+    timer: IntervalHandle
+}
+
+struct SleepState {
+    done: bool,
+    waker: Option<Waker>
+}
+
+impl Sleep {
+    /// Restarts the sleep so that it resolves `delay` milliseconds from now, even if it
+    /// already has. Handy for inactivity timers.
+    pub fn reset(&mut self, delay: impl IntoDelay) {
+        self.state.borrow_mut().done = false;
+        self.timer = sleep_timer(&self.state, delay);
+    }
+
+    pub fn is_elapsed(&self) -> bool {
+        self.state.borrow().done
+    }
+}
+
+fn sleep_timer(state: &Rc<RefCell<SleepState>>, delay: impl IntoDelay) -> IntervalHandle {
+    let state = state.clone();
+    set_timeout(delay, move || {
+        let mut state = state.borrow_mut();
+        state.done = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    })
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let mut state = self.state.borrow_mut();
+        if state.done {
+            Poll::Ready(())
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
 }
 
 pub async fn animation_frame() -> f64 {
@@ -48,39 +422,182 @@ pub async fn animation_frame() -> f64 {
     r.await.unwrap()
 }
 
-pub struct IntervalHandle(i32, Option<Closure<dyn FnMut()>>);
+/// Lets the browser handle events and render before continuing, so long computations can
+/// yield cooperatively.
+///
+/// Unlike `later(0)`, this is not subject to the 4ms timer clamping. It goes through
+/// [`defer`] rather than [`micro_task`], since microtasks run before the browser gets a chance
+/// to do anything.
+pub async fn yield_now() {
+    let (s, r) = oneshot();
+    defer(|| s.resolve(()).ok().unwrap());
+    r.await.unwrap()
+}
+
+/// Runs `f` as a new task, after the browser has had a chance to handle events and render.
+///
+/// Like `setTimeout(f, 0)` but without the 4ms clamping, since it's posted through a
+/// `MessageChannel` which is reused for every call.
+pub fn defer(f: impl FnOnce() + 'static) {
+    DEFERRER.with(|d| {
+        d.queue.borrow_mut().push_back(Box::new(f));
+        d.channel.port2().post_message(&JsValue::UNDEFINED).unwrap();
+    });
+}
+
+/// Spawns `f` on the current thread, polling it in a new task via [`defer`] each time it is
+/// woken instead of in a microtask like `spawn_local`.
+///
+/// Compute-heavy futures which yield often then let the browser stay responsive. Wakers of
+/// these futures must only be used on the current thread.
+pub fn spawn_deferred(f: impl Future<Output = ()> + 'static) {
+    let id = DEFERRED_TASKS.with(|tasks| {
+        let mut tasks = tasks.borrow_mut();
+        let id = tasks.next_id;
+        tasks.next_id += 1;
+        tasks.tasks.insert(id, Box::pin(f));
+        id
+    });
+    defer(move || poll_deferred(id));
+}
+
+type DeferQueue = Rc<RefCell<VecDeque<Box<dyn FnOnce()>>>>;
+
+struct Deferrer {
+    channel: web_sys::MessageChannel,
+    queue: DeferQueue,
+    _closure: Closure<dyn FnMut()>
+}
+
+impl Deferrer {
+    fn new() -> Self {
+        let channel = web_sys::MessageChannel::new().unwrap();
+        let queue: DeferQueue = Default::default();
+        let q = queue.clone();
+        let closure = Closure::wrap(Box::new(move || {
+            // one message is posted per call, so run one call per message
+            let f = q.borrow_mut().pop_front();
+            if let Some(f) = f {
+                f();
+            }
+        }) as Box<dyn FnMut()>);
+        channel.port1().set_onmessage(Some(closure.as_ref().unchecked_ref()));
+        Deferrer { channel, queue, _closure: closure }
+    }
+}
+
+#[derive(Default)]
+struct DeferredTasks {
+    tasks: HashMap<u64, Pin<Box<dyn Future<Output = ()>>>>,
+    next_id: u64
+}
+
+thread_local! {
+    static DEFERRER: Deferrer = Deferrer::new();
+    static DEFERRED_TASKS: RefCell<DeferredTasks> = RefCell::default();
+}
+
+fn poll_deferred(id: u64) {
+    // take the task out while polling it, so it can spawn and wake tasks
+    let task = DEFERRED_TASKS.with(|tasks| tasks.borrow_mut().tasks.remove(&id));
+    if let Some(mut task) = task {
+        let waker = Waker::from(Arc::new(DeferredWaker(id)));
+        if task.as_mut().poll(&mut Context::from_waker(&waker)).is_pending() {
+            DEFERRED_TASKS.with(|tasks| tasks.borrow_mut().tasks.insert(id, task));
+        }
+    }
+}
+
+struct DeferredWaker(u64);
+
+impl Wake for DeferredWaker {
+    fn wake(self: Arc<Self>) {
+        let id = self.0;
+        defer(move || poll_deferred(id));
+    }
+}
+
+pub async fn idle() -> web_sys::IdleDeadline {
+    let (s, r) = oneshot();
+    request_idle_callback(|deadline| {
+        let _ = s.resolve(deadline);
+    }).forget();
+    r.await.unwrap()
+}
+
+pub struct IntervalHandle(Rc<Cell<i32>>, Option<Closure<dyn FnMut()>>);
 
 impl IntervalHandle {
     pub fn forget(mut self) {
         self.1.take().unwrap().forget();
     }
+
+    /// Drops the handle of a timeout which has already fired, freeing its closure without
+    /// clearing the timeout.
+    pub(crate) fn fired(mut self) {
+        self.1 = None;
+    }
 }
 
 impl Drop for IntervalHandle {
     fn drop(&mut self) {
         if self.1.is_some() {
-            clearInterval(self.0);
+            clearInterval(self.0.get());
         }
     }
 }
 
-pub struct AnimationFrameHandle(i32, Option<Closure<dyn FnMut(f64)>>);
+pub struct AnimationFrameHandle(i32, GlobalScope, Option<Closure<dyn FnMut(f64)>>);
 
 impl AnimationFrameHandle {
     pub fn forget(mut self) {
-        self.1.take().unwrap().forget();
+        self.2.take().unwrap().forget();
     }
 }
 
 impl Drop for AnimationFrameHandle {
     fn drop(&mut self) {
-        if self.1.is_some() {
-            web_sys::window().unwrap().cancel_animation_frame(self.0).unwrap();
+        if self.2.is_some() {
+            self.1.cancel_animation_frame(self.0);
+        }
+    }
+}
+
+pub struct IdleCallbackHandle(IdleId, Option<Closure<dyn FnMut(web_sys::IdleDeadline)>>);
+
+enum IdleId {
+    Idle(u32),
+    Timeout(IntervalHandle)
+}
+
+impl IdleCallbackHandle {
+    pub fn forget(mut self) {
+        self.1.take().unwrap().forget();
+        if let IdleId::Timeout(timeout) = std::mem::replace(&mut self.0, IdleId::Idle(0)) {
+            timeout.forget();
+        }
+    }
+
+    /// Drops the handle of a callback which has already been called, freeing its closure
+    /// without cancelling anything.
+    pub(crate) fn fired(mut self) {
+        self.1 = None;
+        if let IdleId::Timeout(timeout) = std::mem::replace(&mut self.0, IdleId::Idle(0)) {
+            timeout.fired();
         }
     }
 }
 
-pub struct IntervalStream(Receiver<()>, IntervalHandle);
+impl Drop for IdleCallbackHandle {
+    fn drop(&mut self) {
+        // a fallback timeout is cleared when its handle is dropped
+        if let (IdleId::Idle(id), Some(_)) = (&self.0, &self.1) {
+            cancelIdleCallback(*id);
+        }
+    }
+}
+
+pub struct IntervalStream(Receiver<()>, #[allow(dead_code)] IntervalHandle);
 
 impl IntervalStream {
     pub fn try_next(&self) -> Option<()> {
@@ -90,4 +607,59 @@ impl IntervalStream {
     pub async fn next(&self) {
         self.0.recv().await.unwrap()
     }
-}
\ No newline at end of file
+}
+
+/// Timing of an animation frame, in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameTiming {
+    /// The `requestAnimationFrame` timestamp.
+    pub timestamp: f64,
+    /// Time since the previous frame returned by the stream, or 0 for the first one.
+    pub delta: f64
+}
+
+/// Stream of animation frames returned by [`animation_frames`].
+///
+/// Frames which pass while the stream isn't being polled are coalesced into the latest one,
+/// so a slow iteration of a render loop doesn't cause a backlog of frames.
+pub struct AnimationFrameStream(WatchReceiver<f64>, Rc<FrameLoop>, Cell<Option<f64>>);
+
+struct FrameLoop {
+    scope: GlobalScope,
+    id: Cell<i32>,
+    closure: OnceCell<Closure<dyn FnMut(f64)>>
+}
+
+impl FrameLoop {
+    fn request(&self) {
+        if let Some(closure) = self.closure.get() {
+            self.id.set(self.scope.request_animation_frame(closure.as_ref().unchecked_ref()));
+        }
+    }
+}
+
+impl AnimationFrameStream {
+    pub fn try_next(&self) -> Option<FrameTiming> {
+        match self.0.has_changed() {
+            true => Some(self.timing(self.0.get())),
+            false => None
+        }
+    }
+
+    pub async fn next(&self) -> FrameTiming {
+        // the sender lives in the frame loop, which lives as long as the stream
+        self.0.changed().await.unwrap();
+        self.timing(self.0.get())
+    }
+
+    fn timing(&self, timestamp: f64) -> FrameTiming {
+        let delta = self.2.replace(Some(timestamp)).map_or(0.0, |last| timestamp - last);
+        FrameTiming { timestamp, delta }
+    }
+}
+
+impl Drop for AnimationFrameStream {
+    fn drop(&mut self) {
+        self.1.scope.cancel_animation_frame(self.1.id.get());
+    }
+}
@@ -0,0 +1,91 @@
+//! Vibrating the device, from the Vibration API.
+//!
+//! Only some mobile browsers support vibration, and nothing happens elsewhere, so it is only
+//! suitable for feedback which isn't needed to use the page.
+//!
+//! ```ignore
+//! // two short buzzes when the player is hit
+//! haptics::vibrate(&Pattern::new().vibrate(50).pause(50).vibrate(50));
+//! ```
+
+use crate::prelude::*;
+use crate::global::{ GlobalScope, IntoDelay };
+
+/// Alternating times of vibrating and pausing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pattern(Vec<u32>);
+
+impl Pattern {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn vibrate(self, duration: impl IntoDelay) -> Self {
+        self.push(true, duration)
+    }
+
+    pub fn pause(self, duration: impl IntoDelay) -> Self {
+        self.push(false, duration)
+    }
+
+    /// Plays the pattern so far `times` times in a row.
+    pub fn repeat(mut self, times: u32) -> Self {
+        let once = std::mem::take(&mut self.0);
+        for _ in 0..times {
+            for (i, &ms) in once.iter().enumerate() {
+                self = self.push(i.is_multiple_of(2), ms);
+            }
+        }
+        self
+    }
+
+    fn push(mut self, vibrating: bool, duration: impl IntoDelay) -> Self {
+        let ms = duration.into_millis().clamp(0.0, u32::MAX as f64) as u32;
+        // even entries are vibrations and odd ones are pauses, so one of the same kind as the
+        // last is merged into it
+        let next_vibrating = self.0.len().is_multiple_of(2);
+        if vibrating == next_vibrating {
+            self.0.push(ms);
+        } else {
+            match self.0.last_mut() {
+                Some(last) => *last = last.saturating_add(ms),
+                None => self.0.extend([0, ms])
+            }
+        }
+        self
+    }
+}
+
+fn navigator() -> Option<web_sys::Navigator> {
+    let navigator = match GlobalScope::current() {
+        GlobalScope::Window(window) => window.navigator(),
+        _ => return None
+    };
+    match js_sys::Reflect::has(&navigator, &"vibrate".into()) {
+        Ok(true) => Some(navigator),
+        _ => None
+    }
+}
+
+pub fn is_supported() -> bool {
+    navigator().is_some()
+}
+
+/// Vibrates the device in `pattern`, replacing any pattern already playing. Returns whether
+/// the device is vibrating, which it isn't where unsupported, or before the user has
+/// interacted with the page.
+pub fn vibrate(pattern: &Pattern) -> bool {
+    let navigator = match navigator() {
+        Some(navigator) => navigator,
+        None => return false
+    };
+    let pattern: js_sys::Array = pattern.0.iter().map(|&ms| JsValue::from(ms)).collect();
+    navigator.vibrate_with_pattern(&pattern)
+}
+
+/// Stops any pattern playing.
+pub fn cancel() {
+    if let Some(navigator) = navigator() {
+        navigator.vibrate_with_duration(0);
+    }
+}
@@ -0,0 +1,581 @@
+//! Typed wrappers around `fetch`.
+//!
+//! ```ignore
+//! let user: User = http::get("/api/user").json().await?;
+//! http::post("/api/score").body_json(&score).send().await?.error_for_status()?;
+//! ```
+//!
+//! App-wide policy like authentication and retries can be set up once on a [`Client`].
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, Sender, channel };
+use crate::event;
+use crate::global::{ self, IntoDelay };
+use crate::streams::{ self, ByteStream };
+use crate::task::{ self, CancelToken, RetryPolicy };
+use serde::{ Serialize, de::DeserializeOwned };
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = fetch)]
+    fn fetch_with_request(request: &web_sys::Request) -> js_sys::Promise;
+}
+
+pub fn get(url: &str) -> RequestBuilder {
+    RequestBuilder::new("GET", url)
+}
+
+pub fn post(url: &str) -> RequestBuilder {
+    RequestBuilder::new("POST", url)
+}
+
+pub fn put(url: &str) -> RequestBuilder {
+    RequestBuilder::new("PUT", url)
+}
+
+pub fn patch(url: &str) -> RequestBuilder {
+    RequestBuilder::new("PATCH", url)
+}
+
+pub fn delete(url: &str) -> RequestBuilder {
+    RequestBuilder::new("DELETE", url)
+}
+
+pub fn head(url: &str) -> RequestBuilder {
+    RequestBuilder::new("HEAD", url)
+}
+
+/// A request being built. Nothing is sent until [`send`](Self::send) or one of the shorthands
+/// for reading the response is awaited.
+pub struct RequestBuilder {
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Result<Option<Body>, GeneralError>,
+    timeout: Option<f64>,
+    cancel: Option<CancelToken>,
+    client: Option<Client>,
+    upload: Option<Sender<UploadProgress>>
+}
+
+/// Progress of sending a request body, in bytes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UploadProgress {
+    pub loaded: u64,
+    /// The size of the body, if known.
+    pub total: Option<u64>
+}
+
+#[derive(Clone, Debug)]
+enum Body {
+    Text(String),
+    Bytes(Vec<u8>)
+}
+
+impl RequestBuilder {
+    pub fn new(method: &str, url: &str) -> Self {
+        RequestBuilder {
+            method: method.to_owned(),
+            url: url.to_owned(),
+            headers: vec![],
+            body: Ok(None),
+            timeout: None,
+            cancel: None,
+            client: None,
+            upload: None
+        }
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Copies the request, for sending it more than once. Returns `None` if the body failed to
+    /// serialize.
+    pub fn try_clone(&self) -> Option<Self> {
+        Some(RequestBuilder {
+            method: self.method.clone(),
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            body: Ok(self.body.as_ref().ok()?.clone()),
+            timeout: self.timeout,
+            cancel: self.cancel.clone(),
+            client: self.client.clone(),
+            upload: self.upload.clone()
+        })
+    }
+
+    /// Adds a header. Repeated headers are all sent.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Adds an `Authorization: Bearer` header.
+    pub fn bearer(self, token: &str) -> Self {
+        self.header("Authorization", &format!("Bearer {}", token))
+    }
+
+    /// Appends URL-encoded query parameters to the URL.
+    pub fn query(mut self, params: &[(&str, &str)]) -> Self {
+        for (name, value) in params {
+            let separator = if self.url.contains('?') { '&' } else { '?' };
+            self.url.push(separator);
+            self.url.push_str(&String::from(js_sys::encode_uri_component(name)));
+            self.url.push('=');
+            self.url.push_str(&String::from(js_sys::encode_uri_component(value)));
+        }
+        self
+    }
+
+    /// Sends `body` serialized as JSON, setting `Content-Type` accordingly. Serialization
+    /// errors are reported when the request is sent.
+    pub fn body_json<T: Serialize + ?Sized>(self, body: &T) -> Self {
+        let mut this = self.header("Content-Type", "application/json");
+        this.body = serde_json::to_string(body)
+            .map(|json| Some(Body::Text(json)))
+            .map_err(GeneralError::from);
+        this
+    }
+
+    pub fn body_text(mut self, body: impl Into<String>) -> Self {
+        self.body = Ok(Some(Body::Text(body.into())));
+        self
+    }
+
+    pub fn body_bytes(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Ok(Some(Body::Bytes(body.into())));
+        self
+    }
+
+    /// Aborts the request if the response headers haven't arrived within `delay`. The request
+    /// then fails with a `TimeoutError` `DOMException`.
+    pub fn timeout(mut self, delay: impl IntoDelay) -> Self {
+        self.timeout = Some(delay.into_millis());
+        self
+    }
+
+    /// Aborts the request when `token` is cancelled, including while the body is being read.
+    pub fn with_cancel(mut self, token: &CancelToken) -> Self {
+        self.cancel = Some(token.clone());
+        self
+    }
+
+    /// Reports the progress of sending the body on the returned receiver, which `fetch` can't
+    /// do. The request is then sent with `XMLHttpRequest` instead, which buffers the whole
+    /// response body.
+    ///
+    /// If the request is retried, the progress starts over for each attempt.
+    pub fn upload_progress(mut self) -> (Self, Receiver<UploadProgress>) {
+        let (sender, receiver) = channel();
+        self.upload = Some(sender);
+        (self, receiver)
+    }
+
+    /// Sends the request. Responses with error statuses are returned successfully; see
+    /// [`Response::error_for_status`].
+    ///
+    /// Dropping the returned future before the response arrives aborts the request.
+    pub async fn send(mut self) -> Result<Response, GeneralError> {
+        match self.client.take() {
+            Some(client) => client.execute(self).await,
+            None => self.fetch().await
+        }
+    }
+
+    async fn fetch(self) -> Result<Response, GeneralError> {
+        let token = match &self.cancel {
+            Some(token) => token.child(),
+            None => CancelToken::new()
+        };
+        let _timer = self.timeout.map(|delay| {
+            let token = token.clone();
+            global::set_timeout(delay, move || {
+                let reason = web_sys::DomException::new_with_message_and_name(
+                    "request timed out", "TimeoutError"
+                );
+                match reason {
+                    Ok(reason) => token.cancel_with_reason(&reason),
+                    Err(_) => token.cancel()
+                }
+            })
+        });
+        let mut guard = AbortOnDrop(Some(token.clone()));
+        let response = match self.upload.clone() {
+            Some(progress) => self.send_xhr(&token, progress).await?,
+            None => {
+                let request = self.build(token.signal())?;
+                JsFuture::from(fetch_with_request(&request)).await?.into()
+            }
+        };
+        guard.0 = None;
+        Ok(Response(response, Some(token)))
+    }
+
+    /// Sends the request with `XMLHttpRequest`, failing the same ways `fetch` would.
+    async fn send_xhr(
+        self, token: &CancelToken, progress: Sender<UploadProgress>
+    ) -> Result<web_sys::Response, GeneralError> {
+        let xhr = web_sys::XmlHttpRequest::new()?;
+        xhr.open_with_async(&self.method, &self.url, true)?;
+        for (name, value) in &self.headers {
+            xhr.set_request_header(name, value)?;
+        }
+        xhr.set_response_type(web_sys::XmlHttpRequestResponseType::Arraybuffer);
+
+        let _progress = xhr.upload()?.add_event_listener(move |e: event::Progress| {
+            let _ = progress.send(UploadProgress {
+                loaded: e.loaded() as u64,
+                total: if e.length_computable() { Some(e.total() as u64) } else { None }
+            });
+        });
+        let x = xhr.clone();
+        let _abort = token.signal().add_event_listener_once(move |_: event::Abort| {
+            let _ = x.abort();
+        });
+        // fires after the request either loads, fails or is aborted
+        let done = xhr.once::<event::ProgressLoadEnd>();
+        match self.body? {
+            Some(Body::Text(text)) => xhr.send_with_opt_str(Some(&text))?,
+            Some(Body::Bytes(bytes)) => xhr.send_with_opt_u8_array(Some(&bytes))?,
+            None => xhr.send()?
+        }
+        done.await;
+
+        if let Some(reason) = token.reason() {
+            return Err(reason.into());
+        }
+        let status = xhr.status()?;
+        if status == 0 {
+            return Err(GeneralError::WebSys(js_sys::TypeError::new("network error").into()));
+        }
+        let init = web_sys::ResponseInit::new();
+        init.set_status(status);
+        init.set_status_text(&xhr.status_text()?);
+        let headers = web_sys::Headers::new()?;
+        for line in xhr.get_all_response_headers()?.split("\r\n") {
+            if let Some((name, value)) = line.split_once(": ") {
+                headers.append(name, value)?;
+            }
+        }
+        init.set_headers(&headers);
+        // these statuses can't have a body, even an empty one
+        let body = match status {
+            101 | 103 | 204 | 205 | 304 => None,
+            _ => Some(xhr.response()?.unchecked_into::<js_sys::Object>())
+        };
+        Ok(web_sys::Response::new_with_opt_buffer_source_and_init(body.as_ref(), &init)?)
+    }
+
+    /// Sends the request and deserializes the JSON response, failing on error statuses.
+    pub async fn json<T: DeserializeOwned>(self) -> Result<T, GeneralError> {
+        self.send().await?.error_for_status()?.json().await
+    }
+
+    /// Sends the request and returns the response text, failing on error statuses.
+    pub async fn text(self) -> Result<String, GeneralError> {
+        self.send().await?.error_for_status()?.text().await
+    }
+
+    /// Sends the request and returns the response body, failing on error statuses.
+    pub async fn bytes(self) -> Result<Vec<u8>, GeneralError> {
+        self.send().await?.error_for_status()?.bytes().await
+    }
+
+    fn build(self, signal: &web_sys::AbortSignal) -> Result<web_sys::Request, GeneralError> {
+        let init = web_sys::RequestInit::new();
+        init.set_method(&self.method);
+        init.set_signal(Some(signal));
+        let headers = web_sys::Headers::new()?;
+        for (name, value) in &self.headers {
+            headers.append(name, value)?;
+        }
+        init.set_headers(&headers);
+        match self.body? {
+            Some(Body::Text(text)) => init.set_body(&text.into()),
+            Some(Body::Bytes(bytes)) => init.set_body(&js_sys::Uint8Array::from(&bytes[..])),
+            None => {}
+        }
+        Ok(web_sys::Request::new_with_str_and_init(&self.url, &init)?)
+    }
+}
+
+impl std::fmt::Debug for RequestBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("RequestBuilder")
+            .field("method", &self.method)
+            .field("url", &self.url)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+struct AbortOnDrop(Option<CancelToken>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = &self.0 {
+            token.cancel();
+        }
+    }
+}
+
+/// Sends requests through a chain of [`Middleware`], with default headers and an optional base
+/// URL. Clones share the same configuration.
+///
+/// ```ignore
+/// let api = Client::new()
+///     .base_url("https://example.com/api")
+///     .bearer(&token)
+///     .with(Retry::new(RetryPolicy::new()))
+///     .with(Logging);
+/// let user: User = api.get("user").json().await?;
+/// ```
+#[derive(Clone, Default)]
+pub struct Client(Rc<ClientConfig>);
+
+#[derive(Clone, Default)]
+struct ClientConfig {
+    base_url: Option<String>,
+    headers: Vec<(String, String)>,
+    middleware: Vec<Rc<dyn Middleware>>
+}
+
+impl Client {
+    pub fn new() -> Self {
+        Client::default()
+    }
+
+    /// Resolves request URLs without a scheme relative to `url`.
+    pub fn base_url(mut self, url: &str) -> Self {
+        Rc::make_mut(&mut self.0).base_url = Some(url.trim_end_matches('/').to_owned());
+        self
+    }
+
+    /// Adds a header to every request, before any middleware runs.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        Rc::make_mut(&mut self.0).headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Adds an `Authorization: Bearer` header to every request.
+    pub fn bearer(self, token: &str) -> Self {
+        self.header("Authorization", &format!("Bearer {}", token))
+    }
+
+    /// Adds a middleware. Middleware added first sees requests first and responses last.
+    pub fn with(mut self, middleware: impl Middleware + 'static) -> Self {
+        Rc::make_mut(&mut self.0).middleware.push(Rc::new(middleware));
+        self
+    }
+
+    pub fn request(&self, method: &str, url: &str) -> RequestBuilder {
+        let url = match &self.0.base_url {
+            Some(base) if !url.contains("://") => {
+                format!("{}/{}", base, url.trim_start_matches('/'))
+            }
+            _ => url.to_owned()
+        };
+        let mut request = RequestBuilder::new(method, &url);
+        request.headers = self.0.headers.clone();
+        request.client = Some(self.clone());
+        request
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.request("GET", url)
+    }
+
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.request("POST", url)
+    }
+
+    pub fn put(&self, url: &str) -> RequestBuilder {
+        self.request("PUT", url)
+    }
+
+    pub fn patch(&self, url: &str) -> RequestBuilder {
+        self.request("PATCH", url)
+    }
+
+    pub fn delete(&self, url: &str) -> RequestBuilder {
+        self.request("DELETE", url)
+    }
+
+    pub fn head(&self, url: &str) -> RequestBuilder {
+        self.request("HEAD", url)
+    }
+
+    async fn execute(&self, request: RequestBuilder) -> Result<Response, GeneralError> {
+        Next(&self.0.middleware).run(request).await
+    }
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Client")
+            .field("base_url", &self.0.base_url)
+            .field("headers", &self.0.headers)
+            .field("middleware", &self.0.middleware.len())
+            .finish()
+    }
+}
+
+pub type ResponseFuture<'a> = Pin<Box<dyn Future<Output = Result<Response, GeneralError>> + 'a>>;
+
+/// Wraps the sending of every request made through a [`Client`].
+pub trait Middleware {
+    /// Handles `request`, usually by passing it, possibly modified, to `next`.
+    fn handle<'a>(&'a self, request: RequestBuilder, next: Next<'a>) -> ResponseFuture<'a>;
+}
+
+/// The rest of the middleware chain, ending in the actual fetch.
+#[derive(Clone, Copy)]
+pub struct Next<'a>(&'a [Rc<dyn Middleware>]);
+
+impl<'a> Next<'a> {
+    pub fn run(self, request: RequestBuilder) -> ResponseFuture<'a> {
+        match self.0.split_first() {
+            Some((middleware, rest)) => middleware.handle(request, Next(rest)),
+            None => Box::pin(request.fetch())
+        }
+    }
+}
+
+/// Middleware retrying requests which fail or get a 5xx response, unless they were cancelled.
+/// After the last attempt, the last response or error is returned.
+pub struct Retry(RetryPolicy);
+
+impl Retry {
+    pub fn new(policy: RetryPolicy) -> Self {
+        Retry(policy)
+    }
+}
+
+impl Middleware for Retry {
+    fn handle<'a>(&'a self, request: RequestBuilder, next: Next<'a>) -> ResponseFuture<'a> {
+        Box::pin(async move {
+            if request.try_clone().is_none() {
+                return next.run(request).await;
+            }
+            let attempt = || {
+                let request = request.try_clone().unwrap();
+                async move {
+                    match next.run(request).await {
+                        Ok(response) if response.status() >= 500 => Err(Ok(response)),
+                        Ok(response) => Ok(response),
+                        Err(e) => Err(Err(e))
+                    }
+                }
+            };
+            let cancelled = || request.cancel.as_ref().is_some_and(CancelToken::is_cancelled);
+            task::retry_if(&self.0, attempt, |_| !cancelled()).await.or_else(|last| last)
+        })
+    }
+}
+
+/// Middleware logging each request and its outcome to the console.
+pub struct Logging;
+
+impl Middleware for Logging {
+    fn handle<'a>(&'a self, request: RequestBuilder, next: Next<'a>) -> ResponseFuture<'a> {
+        Box::pin(async move {
+            let line = format!("{} {}", request.method(), request.url());
+            let start = global::now();
+            let result = next.run(request).await;
+            let elapsed = global::now() - start;
+            match &result {
+                Ok(response) => web_sys::console::log_1(&format!(
+                    "{} -> {} ({:.0}ms)", line, response.status(), elapsed
+                ).into()),
+                Err(e) => web_sys::console::warn_1(&format!(
+                    "{} -> {:?} ({:.0}ms)", line, e, elapsed
+                ).into())
+            }
+            result
+        })
+    }
+}
+
+/// The response to a request. The body can only be read once.
+#[derive(Clone, Debug)]
+pub struct Response(web_sys::Response, Option<CancelToken>);
+
+impl Response {
+    pub fn status(&self) -> u16 {
+        self.0.status()
+    }
+
+    /// Whether the status is in the 200-299 range.
+    pub fn ok(&self) -> bool {
+        self.0.ok()
+    }
+
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.0.headers().get(name).ok().flatten()
+    }
+
+    /// Fails with [`GeneralError::HttpStatus`] if the status isn't in the 200-299 range.
+    pub fn error_for_status(self) -> Result<Self, GeneralError> {
+        if self.ok() {
+            Ok(self)
+        } else {
+            Err(GeneralError::HttpStatus(self.status()))
+        }
+    }
+
+    pub async fn json<T: DeserializeOwned>(&self) -> Result<T, GeneralError> {
+        Ok(serde_json::from_str(&self.text().await?)?)
+    }
+
+    pub async fn text(&self) -> Result<String, GeneralError> {
+        let text = JsFuture::from(self.0.text()?).await?;
+        Ok(text.as_string().unwrap_or_default())
+    }
+
+    pub async fn bytes(&self) -> Result<Vec<u8>, GeneralError> {
+        let buffer = JsFuture::from(self.0.array_buffer()?).await?;
+        Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+    }
+
+    /// The body, read a chunk at a time as it arrives, such as for showing progress or
+    /// processing a large download without holding all of it.
+    pub fn body_stream(&self) -> Result<ByteStream, GeneralError> {
+        match self.0.body() {
+            Some(body) => streams::from_readable(&body),
+            None => Ok(ByteStream::empty())
+        }
+    }
+
+    /// Copies the response so that its body can be read twice, such as to cache it as well.
+    pub fn try_clone(&self) -> Result<Self, GeneralError> {
+        Ok(Response(self.0.clone()?, self.1.clone()))
+    }
+
+    /// Aborts reading the body. Reads in progress fail with an `AbortError` `DOMException`.
+    pub fn abort(&self) {
+        if let Some(token) = &self.1 {
+            token.cancel();
+        }
+    }
+
+    /// The underlying response, for anything not covered here such as streaming the body.
+    pub fn raw(&self) -> &web_sys::Response {
+        &self.0
+    }
+}
+
+impl From<web_sys::Response> for Response {
+    fn from(response: web_sys::Response) -> Self {
+        Response(response, None)
+    }
+}
@@ -0,0 +1,888 @@
+//! IndexedDB with typed object stores.
+//!
+//! Keys and values are converted to JS values with serde, with maps as plain objects so that
+//! key paths can reach into them.
+//!
+//! ```ignore
+//! let schema = Schema::new(1)
+//!     .store(StoreSchema::new("todos").key_path("id").index("by_owner", "owner"));
+//! let db = Database::open("app", &schema).await?;
+//! let todos = db.store::<u32, Todo>("todos");
+//! todos.put(&Todo { id: 1, owner: "ann".into(), .. }).await?;
+//! let todo = todos.get(&1).await?;
+//!
+//! let anns = todos.index::<String>("by_owner").range("ann".to_owned()..="ann".to_owned())?;
+//! let anns = anns.stream();
+//! while let Some(entry) = anns.next().await {
+//!     let (id, todo) = entry?;
+//! }
+//! ```
+//!
+//! Older versions of a database are brought up to date by migrations registered on its schema:
+//!
+//! ```ignore
+//! let schema = Schema::new(1)
+//!     .store(StoreSchema::new("todos").key_path("id").index("by_owner", "owner"))
+//!     .migration(2, |upgrade| async move {
+//!         let todos = upgrade.store::<u32, Todo>("todos");
+//!         for mut todo in todos.get_all().await? {
+//!             todo.owner = todo.owner.to_lowercase();
+//!             todos.put(&todo).await?;
+//!         }
+//!         Ok(())
+//!     });
+//! ```
+//!
+//! Transactions commit once no requests are left in them, so a transaction closure must not
+//! await anything but requests made in the transaction, or the rest of its requests fail.
+
+use crate::prelude::*;
+use crate::channel::{ Oneshot, Receiver, Sender, channel, oneshot };
+use crate::event::{ self, ListenerHandle };
+use crate::task::WebFutureExt;
+use serde::{ Serialize, de::DeserializeOwned };
+use std::cell::RefCell;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::ops::{ Bound, RangeBounds };
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+
+mod outbox;
+pub use outbox::*;
+
+/// The object stores and indexes a database should have at a version, and the migrations
+/// which bring older versions of it up to date.
+///
+/// When the database is opened with a newer version, the stores and indexes which are missing
+/// are created, and then the migrations for the versions in between are run in order. Stores
+/// and indexes which aren't declared are left alone, for migrations to deal with.
+#[derive(Clone)]
+pub struct Schema {
+    version: u32,
+    stores: Vec<StoreSchema>,
+    migrations: Vec<Migration>,
+    progress: Option<Rc<ProgressFn>>
+}
+
+#[derive(Clone)]
+struct Migration {
+    version: u32,
+    run: Rc<MigrationFn>
+}
+
+type ProgressFn = dyn Fn(&MigrationProgress);
+type MigrationFn = dyn Fn(Upgrade) -> Pin<Box<dyn Future<Output = Result<(), GeneralError>>>>;
+
+impl Schema {
+    pub fn new(version: u32) -> Self {
+        Schema { version, stores: vec![], migrations: vec![], progress: None }
+    }
+
+    pub fn store(mut self, store: StoreSchema) -> Self {
+        self.stores.push(store);
+        self
+    }
+
+    /// Registers a migration which runs when upgrading from a version below `version`, raising
+    /// the schema's version to `version` if it is lower.
+    ///
+    /// Migrations run in the upgrade's transaction, so like a transaction closure they must not
+    /// await anything but requests. Failing aborts the upgrade, leaving the database at its
+    /// old version, and opening it fails with the migration's error.
+    pub fn migration<F, Fut>(mut self, version: u32, f: F) -> Self
+    where
+        F: Fn(Upgrade) -> Fut + 'static,
+        Fut: Future<Output = Result<(), GeneralError>> + 'static
+    {
+        self.version = self.version.max(version);
+        self.migrations.push(Migration {
+            version,
+            run: Rc::new(move |upgrade| Box::pin(f(upgrade)))
+        });
+        self.migrations.sort_by_key(|m| m.version);
+        self
+    }
+
+    /// Calls `f` as migrations run, for showing progress while a slow upgrade runs.
+    pub fn on_progress(mut self, f: impl Fn(&MigrationProgress) + 'static) -> Self {
+        self.progress = Some(Rc::new(f));
+        self
+    }
+}
+
+impl std::fmt::Debug for Schema {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Schema")
+            .field("version", &self.version)
+            .field("stores", &self.stores)
+            .field("migrations", &self.migrations.iter().map(|m| m.version).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Progress of the migrations run while opening a database, reported to
+/// [`Schema::on_progress`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MigrationProgress {
+    /// Upgrading from version `from` to `to`, running `count` migrations.
+    Started { from: u32, to: u32, count: usize },
+    /// Running the migration to `version`, the `index`th of `count` starting from 0.
+    Running { version: u32, index: usize, count: usize },
+    /// The migrations are done, and the upgrade commits once their requests are.
+    Finished,
+    /// The migration to `version` failed and the upgrade was aborted.
+    Failed { version: u32 }
+}
+
+/// The database being upgraded, given to migrations.
+#[derive(Clone)]
+pub struct Upgrade {
+    db: web_sys::IdbDatabase,
+    tx: web_sys::IdbTransaction,
+    old_version: u32
+}
+
+impl Upgrade {
+    /// The version being upgraded from, which is 0 for a new database.
+    pub fn old_version(&self) -> u32 {
+        self.old_version
+    }
+
+    /// Creates the object store `store`, or those of its indexes which are missing if it
+    /// exists already.
+    pub fn create_store(&self, store: &StoreSchema) -> Result<(), GeneralError> {
+        let object_store = if self.db.object_store_names().contains(&store.name) {
+            self.tx.object_store(&store.name)?
+        } else {
+            let params = web_sys::IdbObjectStoreParameters::new();
+            params.set_key_path_opt_str(store.key_path.as_deref());
+            params.set_auto_increment(store.auto_increment);
+            self.db.create_object_store_with_optional_parameters(&store.name, &params)?
+        };
+        for index in &store.indexes {
+            if !object_store.index_names().contains(&index.name) {
+                let params = web_sys::IdbIndexParameters::new();
+                params.set_unique(index.unique);
+                object_store.create_index_with_str_and_optional_parameters(
+                    &index.name, &index.key_path, &params
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the object store `name` and everything in it.
+    pub fn delete_store(&self, name: &str) -> Result<(), GeneralError> {
+        self.db.delete_object_store(name)?;
+        Ok(())
+    }
+
+    /// Deletes the index `index` of the object store `store`.
+    pub fn delete_index(&self, store: &str, index: &str) -> Result<(), GeneralError> {
+        self.tx.object_store(store)?.delete_index(index)?;
+        Ok(())
+    }
+
+    /// A handle to the object store `name`, for rewriting its contents.
+    pub fn store<K, V>(&self, name: &str) -> Store<K, V> {
+        Store::new(Source::Transaction(self.tx.clone()), name)
+    }
+
+    /// The underlying database.
+    pub fn raw(&self) -> &web_sys::IdbDatabase {
+        &self.db
+    }
+}
+
+/// An object store declared in a [`Schema`]. Without a key path, keys are given separately
+/// with [`Store::put_with_key`].
+#[derive(Clone, Debug)]
+pub struct StoreSchema {
+    name: String,
+    key_path: Option<String>,
+    auto_increment: bool,
+    indexes: Vec<IndexSchema>
+}
+
+#[derive(Clone, Debug)]
+struct IndexSchema {
+    name: String,
+    key_path: String,
+    unique: bool
+}
+
+impl StoreSchema {
+    pub fn new(name: &str) -> Self {
+        StoreSchema {
+            name: name.to_owned(),
+            key_path: None,
+            auto_increment: false,
+            indexes: vec![]
+        }
+    }
+
+    /// Takes keys from the values at `path`, like `"id"` or `"user.id"`.
+    pub fn key_path(mut self, path: &str) -> Self {
+        self.key_path = Some(path.to_owned());
+        self
+    }
+
+    /// Generates increasing integer keys for values put without one.
+    pub fn auto_increment(mut self) -> Self {
+        self.auto_increment = true;
+        self
+    }
+
+    pub fn index(mut self, name: &str, key_path: &str) -> Self {
+        self.indexes.push(IndexSchema {
+            name: name.to_owned(), key_path: key_path.to_owned(), unique: false
+        });
+        self
+    }
+
+    /// Adds an index which rejects values with the same key as another value.
+    pub fn unique_index(mut self, name: &str, key_path: &str) -> Self {
+        self.indexes.push(IndexSchema {
+            name: name.to_owned(), key_path: key_path.to_owned(), unique: true
+        });
+        self
+    }
+}
+
+/// Whether a transaction may modify the database.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    ReadOnly,
+    ReadWrite
+}
+
+impl From<Mode> for web_sys::IdbTransactionMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::ReadOnly => web_sys::IdbTransactionMode::Readonly,
+            Mode::ReadWrite => web_sys::IdbTransactionMode::Readwrite
+        }
+    }
+}
+
+/// An open database.
+///
+/// The connection is closed when another page opens the database with a newer version, so
+/// that the upgrade isn't blocked.
+pub struct Database {
+    db: web_sys::IdbDatabase,
+    _listener: ListenerHandle
+}
+
+impl Database {
+    /// Opens the database `name`, creating or upgrading it to match `schema`.
+    pub async fn open(name: &str, schema: &Schema) -> Result<Self, GeneralError> {
+        let request = factory()?.open_with_u32(name, schema.version)?;
+
+        let failure = Rc::new(RefCell::new(None));
+        let fail = failure.clone();
+        let req = request.clone();
+        let schema = schema.clone();
+        let on_upgrade = request.add_event_listener(move |e: event::UpgradeNeeded| {
+            upgrade(&req, &schema, e.old_version() as u32, &fail);
+        });
+        let result = wait(&request).await;
+        drop(on_upgrade);
+        if let Some(e) = failure.borrow_mut().take() {
+            return Err(e);
+        }
+
+        let db: web_sys::IdbDatabase = result?.unchecked_into();
+        let d = db.clone();
+        let listener = db.add_event_listener(move |_: event::VersionChange| d.close());
+        Ok(Database { db, _listener: listener })
+    }
+
+    /// A handle to the object store `name`, whose requests each run in their own transaction.
+    pub fn store<K, V>(&self, name: &str) -> Store<K, V> {
+        Store::new(Source::Database(self.db.clone()), name)
+    }
+
+    /// Runs `f` in a transaction over the object stores `stores`, committing it once `f`
+    /// succeeds and its requests are done. Returning an error aborts the transaction.
+    pub async fn transaction<R, F, Fut>(
+        &self, stores: &[&str], mode: Mode, f: F
+    ) -> Result<R, GeneralError>
+    where
+        F: FnOnce(Transaction) -> Fut,
+        Fut: Future<Output = Result<R, GeneralError>>
+    {
+        let names: js_sys::Array = stores.iter().map(|&name| JsValue::from(name)).collect();
+        let tx = self.db.transaction_with_str_sequence_and_mode(&names, mode.into())?;
+        let done = completion(&tx);
+        match f(Transaction(tx.clone())).await {
+            Ok(v) => {
+                done.await?;
+                Ok(v)
+            }
+            Err(e) => {
+                let _ = tx.abort();
+                Err(e)
+            }
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.db.name()
+    }
+
+    pub fn version(&self) -> u32 {
+        self.db.version() as u32
+    }
+
+    /// Closes the connection once its transactions are done.
+    pub fn close(&self) {
+        self.db.close();
+    }
+
+    /// The underlying database.
+    pub fn raw(&self) -> &web_sys::IdbDatabase {
+        &self.db
+    }
+}
+
+/// Deletes the database `name`, waiting until other connections to it are closed.
+pub async fn delete_database(name: &str) -> Result<(), GeneralError> {
+    let request = factory()?.delete_database(name)?;
+    wait(&request).await?;
+    Ok(())
+}
+
+/// A transaction in progress, given to the closure passed to [`Database::transaction`].
+#[derive(Clone)]
+pub struct Transaction(web_sys::IdbTransaction);
+
+impl Transaction {
+    /// A handle to the object store `name`, which must be one of the transaction's stores.
+    pub fn store<K, V>(&self, name: &str) -> Store<K, V> {
+        Store::new(Source::Transaction(self.0.clone()), name)
+    }
+
+    /// Aborts the transaction, undoing its changes.
+    pub fn abort(&self) {
+        let _ = self.0.abort();
+    }
+
+    /// The underlying transaction.
+    pub fn raw(&self) -> &web_sys::IdbTransaction {
+        &self.0
+    }
+}
+
+#[derive(Clone)]
+enum Source {
+    Database(web_sys::IdbDatabase),
+    Transaction(web_sys::IdbTransaction)
+}
+
+/// An object store with keys `K` and values `V`.
+pub struct Store<K, V> {
+    source: Source,
+    name: String,
+    _phantom: PhantomData<fn(K, V)>
+}
+
+impl<K, V> Clone for Store<K, V> {
+    fn clone(&self) -> Self {
+        Store::new(self.source.clone(), &self.name)
+    }
+}
+
+impl<K, V> Store<K, V> {
+    fn new(source: Source, name: &str) -> Self {
+        Store { source, name: name.to_owned(), _phantom: PhantomData }
+    }
+
+    fn request(
+        &self,
+        mode: Mode,
+        f: impl FnOnce(&web_sys::IdbObjectStore) -> Result<web_sys::IdbRequest, JsValue>
+    ) -> impl Future<Output = Result<JsValue, GeneralError>> {
+        request(&self.source, &self.name, mode, f)
+    }
+
+    /// A handle to the index `name` of the store, with keys `IK`.
+    pub fn index<IK>(&self, name: &str) -> Index<IK, K, V> {
+        Index {
+            source: self.source.clone(),
+            store: self.name.clone(),
+            name: name.to_owned(),
+            _phantom: PhantomData
+        }
+    }
+}
+
+impl<K: Serialize + DeserializeOwned, V: Serialize + DeserializeOwned> Store<K, V> {
+    pub async fn get(&self, key: &K) -> Result<Option<V>, GeneralError> {
+        let key = to_js(key)?;
+        let v = self.request(Mode::ReadOnly, |store| store.get(&key)).await?;
+        Ok(serde_wasm_bindgen::from_value(v)?)
+    }
+
+    /// Inserts or replaces a value in a store with a key path or generated keys, returning
+    /// its key.
+    pub async fn put(&self, value: &V) -> Result<K, GeneralError> {
+        let value = to_js(value)?;
+        let key = self.request(Mode::ReadWrite, |store| store.put(&value)).await?;
+        Ok(serde_wasm_bindgen::from_value(key)?)
+    }
+
+    /// Inserts or replaces the value at `key` in a store without a key path.
+    pub async fn put_with_key(&self, key: &K, value: &V) -> Result<(), GeneralError> {
+        let (key, value) = (to_js(key)?, to_js(value)?);
+        self.request(Mode::ReadWrite, |store| store.put_with_key(&value, &key)).await?;
+        Ok(())
+    }
+
+    /// Deletes the value at `key`, if there is one.
+    pub async fn delete(&self, key: &K) -> Result<(), GeneralError> {
+        let key = to_js(key)?;
+        self.request(Mode::ReadWrite, |store| store.delete(&key)).await?;
+        Ok(())
+    }
+
+    /// Gets every value in the store, in key order.
+    pub async fn get_all(&self) -> Result<Vec<V>, GeneralError> {
+        let v = self.request(Mode::ReadOnly, |store| store.get_all()).await?;
+        Ok(serde_wasm_bindgen::from_value(v)?)
+    }
+
+    pub async fn count(&self) -> Result<u32, GeneralError> {
+        let v = self.request(Mode::ReadOnly, |store| store.count()).await?;
+        Ok(v.as_f64().unwrap_or(0.0) as u32)
+    }
+
+    pub async fn clear(&self) -> Result<(), GeneralError> {
+        self.request(Mode::ReadWrite, |store| store.clear()).await?;
+        Ok(())
+    }
+
+    /// Queries the values with keys in `bounds`, like `..` for all of them or `10..20`.
+    pub fn range(&self, bounds: impl RangeBounds<K>) -> Result<Query<K, V>, GeneralError> {
+        Query::new(&self.source, &self.name, None, bounds)
+    }
+}
+
+/// An index of an object store with keys `K` and values `V`, by the index keys `IK`.
+pub struct Index<IK, K, V> {
+    source: Source,
+    store: String,
+    name: String,
+    _phantom: PhantomData<fn(IK, K, V)>
+}
+
+impl<IK: Serialize, K: DeserializeOwned, V: DeserializeOwned> Index<IK, K, V> {
+    /// Gets the first value with the index key `key`.
+    pub async fn get(&self, key: &IK) -> Result<Option<V>, GeneralError> {
+        let key = to_js(key)?;
+        let v = request(&self.source, &self.store, Mode::ReadOnly, |store| {
+            store.index(&self.name)?.get(&key)
+        }).await?;
+        Ok(serde_wasm_bindgen::from_value(v)?)
+    }
+
+    /// Gets every value with the index key `key`, in primary key order.
+    pub async fn get_all(&self, key: &IK) -> Result<Vec<V>, GeneralError> {
+        let key = to_js(key)?;
+        let v = request(&self.source, &self.store, Mode::ReadOnly, |store| {
+            store.index(&self.name)?.get_all_with_key(&key)
+        }).await?;
+        Ok(serde_wasm_bindgen::from_value(v)?)
+    }
+
+    /// Queries the values with index keys in `bounds`.
+    pub fn range(&self, bounds: impl RangeBounds<IK>) -> Result<Query<K, V>, GeneralError> {
+        Query::new(&self.source, &self.store, Some(&self.name), bounds)
+    }
+}
+
+/// A range of an object store or index, made with [`Store::range`] or [`Index::range`].
+///
+/// Entries come in key order, and then in primary key order for indexes.
+pub struct Query<K, V> {
+    source: Source,
+    store: String,
+    index: Option<String>,
+    lower: Bound<JsValue>,
+    upper: Bound<JsValue>,
+    reverse: bool,
+    batch_size: u32,
+    _phantom: PhantomData<fn(K, V)>
+}
+
+impl<K, V> Query<K, V> {
+    fn new<T: Serialize>(
+        source: &Source, store: &str, index: Option<&str>, bounds: impl RangeBounds<T>
+    ) -> Result<Self, GeneralError> {
+        Ok(Query {
+            source: source.clone(),
+            store: store.to_owned(),
+            index: index.map(str::to_owned),
+            lower: to_bound(bounds.start_bound())?,
+            upper: to_bound(bounds.end_bound())?,
+            reverse: false,
+            batch_size: 100,
+            _phantom: PhantomData
+        })
+    }
+
+    /// Goes through the range from the end.
+    pub fn reverse(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+
+    /// How many entries [`stream`](Self::stream) reads per transaction, 100 by default.
+    pub fn batch_size(mut self, size: u32) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+}
+
+impl<K: DeserializeOwned + 'static, V: DeserializeOwned + 'static> Query<K, V> {
+    /// Gets every value in the range.
+    pub async fn get_all(&self) -> Result<Vec<V>, GeneralError> {
+        let range = key_range(self.lower.as_ref(), self.upper.as_ref())?;
+        let v = request(&self.source, &self.store, Mode::ReadOnly, |store| match &self.index {
+            Some(index) => store.index(index)?.get_all_with_key(&range),
+            None => store.get_all_with_key(&range)
+        }).await?;
+        let mut values: Vec<V> = serde_wasm_bindgen::from_value(v)?;
+        if self.reverse {
+            values.reverse();
+        }
+        Ok(values)
+    }
+
+    pub async fn count(&self) -> Result<u32, GeneralError> {
+        let range = key_range(self.lower.as_ref(), self.upper.as_ref())?;
+        let v = request(&self.source, &self.store, Mode::ReadOnly, |store| match &self.index {
+            Some(index) => store.index(index)?.count_with_key(&range),
+            None => store.count_with_key(&range)
+        }).await?;
+        Ok(v.as_f64().unwrap_or(0.0) as u32)
+    }
+
+    /// Streams the `(key, value)` pairs in the range, reading them lazily.
+    ///
+    /// Outside a transaction, entries are read in batches as they are needed, each batch in its
+    /// own transaction, so changes made between batches may show up. In a transaction, the
+    /// whole range is read at once when the first entry is needed.
+    pub fn stream(self) -> QueryStream<K, V> {
+        let (sender, items) = channel();
+        QueryStream {
+            query: self, items, sender,
+            progress: Rc::new(RefCell::new(Progress::Start))
+        }
+    }
+}
+
+/// The entries of a [`Query`], made with [`Query::stream`].
+pub struct QueryStream<K, V> {
+    query: Query<K, V>,
+    items: Receiver<Result<(K, V), GeneralError>>,
+    sender: Sender<Result<(K, V), GeneralError>>,
+    progress: Rc<RefCell<Progress>>
+}
+
+enum Progress {
+    Start,
+    /// The key and primary key of the last entry read.
+    After(JsValue, JsValue),
+    Done
+}
+
+type Finish = Rc<RefCell<Option<Oneshot<Result<(), GeneralError>>>>>;
+
+impl<K: DeserializeOwned + 'static, V: DeserializeOwned + 'static> QueryStream<K, V> {
+    /// Receives an entry if one has been read already.
+    pub fn try_next(&self) -> Option<Result<(K, V), GeneralError>> {
+        self.items.try_recv().ok()
+    }
+
+    /// Receives the next entry, reading more if needed, or `None` at the end of the range.
+    pub async fn next(&self) -> Option<Result<(K, V), GeneralError>> {
+        loop {
+            if let Ok(item) = self.items.try_recv() {
+                return Some(item);
+            }
+            let position = match &*self.progress.borrow() {
+                Progress::Start => None,
+                Progress::After(key, primary) => Some((key.clone(), primary.clone())),
+                Progress::Done => return None
+            };
+            if let Err(e) = self.read(position).await {
+                *self.progress.borrow_mut() = Progress::Done;
+                return Some(Err(e));
+            }
+        }
+    }
+
+    /// Reads a batch of entries into the channel, starting after `position`.
+    async fn read(&self, position: Option<(JsValue, JsValue)>) -> Result<(), GeneralError> {
+        let query = &self.query;
+        // resume at the last key read, skipping the entries with it which were read already
+        let (lower, upper) = match (&position, query.reverse) {
+            (Some((key, _)), false) => (Bound::Included(key), query.upper.as_ref()),
+            (Some((key, _)), true) => (query.lower.as_ref(), Bound::Included(key)),
+            (None, _) => (query.lower.as_ref(), query.upper.as_ref())
+        };
+        let range = key_range(lower, upper)?;
+        let direction = match query.reverse {
+            false => web_sys::IdbCursorDirection::Next,
+            true => web_sys::IdbCursorDirection::Prev
+        };
+        let store = object_store(&query.source, &query.store, Mode::ReadOnly)?;
+        let request = match &query.index {
+            Some(index) => {
+                store.index(index)?.open_cursor_with_range_and_direction(&range, direction)?
+            }
+            None => store.open_cursor_with_range_and_direction(&range, direction)?
+        };
+        // a transaction we were given can't be resumed later, so read all of it
+        let limit = match query.source {
+            Source::Database(_) => Some(query.batch_size),
+            Source::Transaction(_) => None
+        };
+
+        let (done, finished) = oneshot();
+        let done: Finish = Rc::new(RefCell::new(Some(done)));
+        let factory = factory()?;
+        let sender = self.sender.clone();
+        let progress = self.progress.clone();
+        let reverse = query.reverse;
+        let mut skip = position;
+        let mut count = 0;
+        let d = done.clone();
+        let req = request.clone();
+        let on_success = request.add_event_listener(move |_: event::Success| {
+            let step = (|| -> Result<bool, GeneralError> {
+                let cursor = req.result()?;
+                if cursor.is_null() {
+                    *progress.borrow_mut() = Progress::Done;
+                    return Ok(false);
+                }
+                let cursor: web_sys::IdbCursorWithValue = cursor.unchecked_into();
+                let (key, primary) = (cursor.key()?, cursor.primary_key()?);
+                if let Some((last_key, last_primary)) = &skip {
+                    let order = factory.cmp(&primary, last_primary)?;
+                    let already_read = if reverse { order >= 0 } else { order <= 0 };
+                    if already_read && factory.cmp(&key, last_key)? == 0 {
+                        cursor.continue_()?;
+                        return Ok(true);
+                    }
+                    skip = None;
+                }
+                let _ = sender.send(decode_entry(&primary, &cursor.value()?));
+                *progress.borrow_mut() = Progress::After(key, primary);
+                count += 1;
+                if limit == Some(count) {
+                    return Ok(false);
+                }
+                cursor.continue_()?;
+                Ok(true)
+            })();
+            match step {
+                Ok(true) => {}
+                Ok(false) => finish(&d, Ok(())),
+                Err(e) => finish(&d, Err(e))
+            }
+        });
+        let req = request.clone();
+        let on_error = request.add_event_listener(move |_: event::Error| {
+            finish(&done, Err(GeneralError::WebSys(req.error().ok().flatten().into())));
+        });
+
+        let result = finished.await.unwrap_or(Ok(()));
+        drop((on_success, on_error));
+        result
+    }
+}
+
+fn finish(done: &Finish, result: Result<(), GeneralError>) {
+    if let Some(done) = done.borrow_mut().take() {
+        let _ = done.resolve(result);
+    }
+}
+
+fn decode_entry<K: DeserializeOwned, V: DeserializeOwned>(
+    key: &JsValue, value: &JsValue
+) -> Result<(K, V), GeneralError> {
+    let key = serde_wasm_bindgen::from_value(key.clone())?;
+    Ok((key, serde_wasm_bindgen::from_value(value.clone())?))
+}
+
+fn object_store(
+    source: &Source, name: &str, mode: Mode
+) -> Result<web_sys::IdbObjectStore, GeneralError> {
+    let tx = match source {
+        Source::Database(db) => db.transaction_with_str_and_mode(name, mode.into())?,
+        Source::Transaction(tx) => tx.clone()
+    };
+    Ok(tx.object_store(name)?)
+}
+
+/// Makes a request against the store `name`. Requests outside a transaction get their own,
+/// and writes wait for it to commit.
+fn request(
+    source: &Source,
+    name: &str,
+    mode: Mode,
+    f: impl FnOnce(&web_sys::IdbObjectStore) -> Result<web_sys::IdbRequest, JsValue>
+) -> impl Future<Output = Result<JsValue, GeneralError>> {
+    // listen right away, since the request may finish before the future is first polled
+    let started = (|| -> Result<_, GeneralError> {
+        let store = object_store(source, name, mode)?;
+        let done = match (source, mode) {
+            (Source::Database(_), Mode::ReadWrite) => Some(completion(&store.transaction())),
+            _ => None
+        };
+        Ok((wait(&f(&store)?), done))
+    })();
+    async move {
+        let (result, done) = started?;
+        let v = result.await?;
+        if let Some(done) = done {
+            done.await?;
+        }
+        Ok(v)
+    }
+}
+
+fn factory() -> Result<web_sys::IdbFactory, GeneralError> {
+    let factory = js_sys::Reflect::get(&js_sys::global(), &"indexedDB".into())?;
+    if factory.is_undefined() || factory.is_null() {
+        return Err(GeneralError::message("IndexedDB is not available in this scope"));
+    }
+    Ok(factory.unchecked_into())
+}
+
+/// Brings the database up to date with `schema` during an upgrade from `old_version`.
+///
+/// Migrations are still running when this returns. If anything fails, the error is stored in
+/// `failure` and the upgrade is aborted.
+fn upgrade(
+    request: &web_sys::IdbOpenDbRequest,
+    schema: &Schema,
+    old_version: u32,
+    failure: &Rc<RefCell<Option<GeneralError>>>
+) {
+    let upgrade = Upgrade {
+        db: request.result().unwrap().unchecked_into(),
+        tx: request.transaction().expect("upgrade without a versionchange transaction"),
+        old_version
+    };
+    let fail = {
+        let failure = failure.clone();
+        let tx = upgrade.tx.clone();
+        move |e| {
+            *failure.borrow_mut() = Some(e);
+            let _ = tx.abort();
+        }
+    };
+    for store in &schema.stores {
+        if let Err(e) = upgrade.create_store(store) {
+            return fail(e);
+        }
+    }
+
+    let migrations: Vec<_> = schema.migrations.iter()
+        .filter(|m| m.version > old_version)
+        .cloned()
+        .collect();
+    if migrations.is_empty() {
+        return;
+    }
+    let progress = schema.progress.clone();
+    let report = move |p| if let Some(f) = &progress {
+        f(&p);
+    };
+    let to = schema.version;
+    // spawned tasks run before the upgrade's transaction would commit, so it stays usable
+    spawn_local(async move {
+        let count = migrations.len();
+        report(MigrationProgress::Started { from: old_version, to, count });
+        for (index, migration) in migrations.iter().enumerate() {
+            report(MigrationProgress::Running { version: migration.version, index, count });
+            if let Err(e) = (migration.run)(upgrade.clone()).await {
+                report(MigrationProgress::Failed { version: migration.version });
+                return fail(e);
+            }
+        }
+        report(MigrationProgress::Finished);
+    });
+}
+
+/// Waits for `request` to finish, listening right away.
+fn wait(request: &web_sys::IdbRequest) -> impl Future<Output = Result<JsValue, GeneralError>> {
+    let success = request.once::<event::Success>();
+    let error = request.once::<event::Error>();
+    let request = request.clone();
+    async move {
+        let ok = async { success.await; true }.race(async { error.await; false }).await;
+        if ok {
+            Ok(request.result()?)
+        } else {
+            Err(GeneralError::WebSys(request.error().ok().flatten().into()))
+        }
+    }
+}
+
+/// Waits for `tx` to commit, listening right away.
+fn completion(tx: &web_sys::IdbTransaction) -> impl Future<Output = Result<(), GeneralError>> {
+    let complete = tx.once::<event::TransactionComplete>();
+    let abort = tx.once::<event::Abort>();
+    let tx = tx.clone();
+    async move {
+        let ok = async { complete.await; true }.race(async { abort.await; false }).await;
+        if ok {
+            return Ok(());
+        }
+        let error = match tx.error() {
+            Some(e) => e.into(),
+            None => web_sys::DomException::new_with_message_and_name(
+                "transaction was aborted", "AbortError"
+            )?.into()
+        };
+        Err(GeneralError::WebSys(error))
+    }
+}
+
+fn to_bound<T: Serialize>(bound: Bound<&T>) -> Result<Bound<JsValue>, GeneralError> {
+    Ok(match bound {
+        Bound::Included(v) => Bound::Included(to_js(v)?),
+        Bound::Excluded(v) => Bound::Excluded(to_js(v)?),
+        Bound::Unbounded => Bound::Unbounded
+    })
+}
+
+/// Makes a key range, or `undefined` for an unbounded one.
+fn key_range(lower: Bound<&JsValue>, upper: Bound<&JsValue>) -> Result<JsValue, GeneralError> {
+    fn endpoint(bound: Bound<&JsValue>) -> Option<(&JsValue, bool)> {
+        match bound {
+            Bound::Included(v) => Some((v, false)),
+            Bound::Excluded(v) => Some((v, true)),
+            Bound::Unbounded => None
+        }
+    }
+    let range = match (endpoint(lower), endpoint(upper)) {
+        (None, None) => return Ok(JsValue::UNDEFINED),
+        (Some((l, lo)), None) => web_sys::IdbKeyRange::lower_bound_with_open(l, lo)?,
+        (None, Some((u, uo))) => web_sys::IdbKeyRange::upper_bound_with_open(u, uo)?,
+        (Some((l, lo)), Some((u, uo))) => {
+            web_sys::IdbKeyRange::bound_with_lower_open_and_upper_open(l, u, lo, uo)?
+        }
+    };
+    Ok(range.into())
+}
+
+fn to_js<T: Serialize>(v: &T) -> Result<JsValue, GeneralError> {
+    Ok(v.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
@@ -0,0 +1,109 @@
+use super::{ Database, Schema, Store, StoreSchema };
+use crate::prelude::*;
+use crate::channel::{ Receiver, Sender, channel };
+use crate::global;
+use crate::task::RetryPolicy;
+use serde::{ Serialize, de::DeserializeOwned };
+use std::future::Future;
+
+const STORE: &str = "messages";
+
+/// A queue of messages persisted in IndexedDB, so that messages which haven't been delivered
+/// when the page closes are delivered once it is loaded again.
+///
+/// Messages are delivered in order: [`next`](Self::next) returns the oldest message until it
+/// is acknowledged. A message delivered just before the page closes may not have been
+/// acknowledged yet, so messages can be delivered more than once. Only one page should deliver
+/// from an outbox at a time.
+///
+/// ```ignore
+/// let outbox = Outbox::<Score>::open("scores").await?;
+/// outbox.push(&score).await?;
+/// // elsewhere, usually in a spawned task
+/// outbox.deliver(&RetryPolicy::new(), |score| async move { socket.send(&score) }).await?;
+/// ```
+pub struct Outbox<T> {
+    _db: Database,
+    store: Store<u64, T>,
+    pushed: Receiver<()>,
+    notify: Sender<()>
+}
+
+impl<T: Serialize + DeserializeOwned + 'static> Outbox<T> {
+    /// Opens the outbox `name`, with the messages left in it by earlier page loads.
+    pub async fn open(name: &str) -> Result<Self, GeneralError> {
+        let schema = Schema::new(1).store(StoreSchema::new(STORE).auto_increment());
+        let db = Database::open(&format!("webutil-outbox:{}", name), &schema).await?;
+        let (notify, pushed) = channel();
+        Ok(Outbox { store: db.store(STORE), _db: db, pushed, notify })
+    }
+
+    /// Adds a message, waiting until it is stored.
+    pub async fn push(&self, v: &T) -> Result<(), GeneralError> {
+        self.store.put(v).await?;
+        let _ = self.notify.send(());
+        Ok(())
+    }
+
+    /// Waits for the oldest message, which stays in the outbox until acknowledged.
+    pub async fn next(&self) -> Result<Pending<T>, GeneralError> {
+        loop {
+            // everything pushed so far is stored, so it's found below
+            while self.pushed.try_recv().is_ok() {}
+            if let Some(entry) = self.store.range(..)?.batch_size(1).stream().next().await {
+                let (key, message) = entry?;
+                return Ok(Pending { message, key, store: self.store.clone() });
+            }
+            self.pushed.recv().await;
+        }
+    }
+
+    /// Delivers the messages in order with `send`, retrying a message after the delays of
+    /// `policy` for as long as it fails. Only returns if the outbox itself fails.
+    pub async fn deliver<F, Fut, E>(
+        &self, policy: &RetryPolicy, mut send: F
+    ) -> Result<(), GeneralError>
+    where
+        T: Clone,
+        F: FnMut(T) -> Fut,
+        Fut: Future<Output = Result<(), E>>
+    {
+        loop {
+            let pending = self.next().await?;
+            let mut failures = 0;
+            while send(pending.message.clone()).await.is_err() {
+                global::sleep(policy.delay(failures)).await;
+                failures = failures.saturating_add(1);
+            }
+            pending.ack().await?;
+        }
+    }
+
+    /// The number of messages waiting to be delivered.
+    pub async fn len(&self) -> Result<u32, GeneralError> {
+        self.store.count().await
+    }
+
+    pub async fn is_empty(&self) -> Result<bool, GeneralError> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Discards every message.
+    pub async fn clear(&self) -> Result<(), GeneralError> {
+        self.store.clear().await
+    }
+}
+
+/// A message taken from an [`Outbox`], which stays in it until acknowledged.
+pub struct Pending<T> {
+    pub message: T,
+    key: u64,
+    store: Store<u64, T>
+}
+
+impl<T: Serialize + DeserializeOwned> Pending<T> {
+    /// Removes the message from the outbox once it has been delivered.
+    pub async fn ack(self) -> Result<(), GeneralError> {
+        self.store.delete(&self.key).await
+    }
+}
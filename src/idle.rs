@@ -0,0 +1,118 @@
+use crate::prelude::*;
+use crate::global::{ self, IdleCallbackHandle };
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{ Context, Poll, Waker };
+
+/// Runs work only while the browser is idle, for background tasks like indexing which should
+/// never cause jank.
+///
+/// Queued closures run one after another until the idle period's time is used up, and the
+/// rest wait for the next idle period. Spawned futures are only polled during idle periods,
+/// so they yield to the browser at the first `.await` after the time is used up.
+///
+/// Dropping the queue discards the closures which haven't run yet. Spawned futures keep
+/// running.
+#[derive(Default)]
+pub struct Queue(Rc<RefCell<State>>);
+
+#[derive(Default)]
+struct State {
+    tasks: VecDeque<Box<dyn FnOnce()>>,
+    waiting: Vec<Waker>,
+    deadline: Option<web_sys::IdleDeadline>,
+    callback: Option<IdleCallbackHandle>
+}
+
+impl Queue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues `f` to run during an idle period.
+    pub fn push(&self, f: impl FnOnce() + 'static) {
+        self.0.borrow_mut().tasks.push_back(Box::new(f));
+        schedule(&self.0);
+    }
+
+    /// Spawns `f` on the current thread, polling it only during idle periods.
+    pub fn spawn(&self, f: impl Future<Output = ()> + 'static) {
+        spawn_local(Idle { state: self.0.clone(), future: Box::pin(f) });
+    }
+
+    /// The number of closures waiting to run.
+    pub fn len(&self) -> usize {
+        self.0.borrow().tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().tasks.is_empty()
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        self.0.borrow_mut().tasks.clear();
+    }
+}
+
+/// Requests an idle callback if there is work waiting for one and none is requested yet.
+fn schedule(state: &Rc<RefCell<State>>) {
+    let mut st = state.borrow_mut();
+    if st.callback.is_some() || st.tasks.is_empty() && st.waiting.is_empty() {
+        return;
+    }
+    let state = Rc::downgrade(state);
+    st.callback = Some(global::request_idle_callback(move |deadline| {
+        if let Some(state) = state.upgrade() {
+            run(&state, deadline);
+        }
+    }));
+}
+
+fn run(state: &Rc<RefCell<State>>, deadline: web_sys::IdleDeadline) {
+    let waiting = {
+        let mut st = state.borrow_mut();
+        if let Some(callback) = st.callback.take() {
+            callback.fired();
+        }
+        st.deadline = Some(deadline.clone());
+        std::mem::take(&mut st.waiting)
+    };
+    // the futures are polled right after this callback returns, still within the deadline
+    for waker in waiting {
+        waker.wake();
+    }
+    while deadline.time_remaining() > 0.0 {
+        let task = state.borrow_mut().tasks.pop_front();
+        match task {
+            Some(task) => task(),
+            None => break
+        }
+    }
+    schedule(state);
+}
+
+struct Idle {
+    state: Rc<RefCell<State>>,
+    future: Pin<Box<dyn Future<Output = ()>>>
+}
+
+impl Future for Idle {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let idle = self.state.borrow().deadline.as_ref()
+            .is_some_and(|deadline| deadline.time_remaining() > 0.0);
+        if idle {
+            self.future.as_mut().poll(cx)
+        } else {
+            self.state.borrow_mut().waiting.push(cx.waker().clone());
+            schedule(&self.state);
+            Poll::Pending
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Decoding images, off the main thread where the browser can.
+//!
+//! Decoded `ImageBitmap`s are [`Transferable`](crate::worker::Transferable), so they can be
+//! decoded on the main thread and sent to a worker rendering to an `OffscreenCanvas`:
+//!
+//! ```ignore
+//! let sprites = image::load_bitmap("sprites.png").await?;
+//! renderer.send(&RenderMessage::Sprites(Transfer(sprites)))?;
+//!
+//! // in the worker
+//! ctx.draw_image_with_image_bitmap(&sprites.0, 0.0, 0.0)?;
+//! ```
+
+use crate::prelude::*;
+use crate::global::GlobalScope;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Errors from decoding images.
+#[derive(Debug)]
+pub enum ImageError {
+    /// Images can't be decoded in this context, such as in a worklet, or `HtmlImageElement`s
+    /// outside of the main thread.
+    Unavailable,
+    /// The image couldn't be loaded, or isn't in a format the browser supports.
+    Decode,
+    Other(JsValue)
+}
+
+impl From<JsValue> for ImageError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("InvalidStateError") | Some("EncodingError") => ImageError::Decode,
+            _ => ImageError::Other(e)
+        }
+    }
+}
+
+impl From<ImageError> for GeneralError {
+    fn from(e: ImageError) -> Self {
+        let msg = match e {
+            ImageError::Unavailable => "image decoding is unavailable",
+            ImageError::Decode => "image could not be decoded",
+            ImageError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(msg)
+    }
+}
+
+/// How to scale images when resizing them while decoding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ResizeQuality {
+    /// Nearest neighbor, for pixel art.
+    Pixelated,
+    Low,
+    Medium,
+    High
+}
+
+/// How to decode an image into an `ImageBitmap`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+    resize: Option<(u32, u32, ResizeQuality)>,
+    flip_y: bool,
+    premultiply_alpha: Option<bool>
+}
+
+impl DecodeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scales the image to `width` by `height` pixels while decoding, which is cheaper than
+    /// drawing it scaled every frame.
+    pub fn resize(mut self, width: u32, height: u32, quality: ResizeQuality) -> Self {
+        self.resize = Some((width, height, quality));
+        self
+    }
+
+    /// Flips the image vertically, as WebGL textures usually need.
+    pub fn flip_y(mut self, flip: bool) -> Self {
+        self.flip_y = flip;
+        self
+    }
+
+    /// Whether to multiply colors by their alpha, instead of leaving it to the browser.
+    pub fn premultiply_alpha(mut self, premultiply: bool) -> Self {
+        self.premultiply_alpha = Some(premultiply);
+        self
+    }
+
+    fn to_js(self) -> web_sys::ImageBitmapOptions {
+        let options = web_sys::ImageBitmapOptions::new();
+        if let Some((width, height, quality)) = self.resize {
+            options.set_resize_width(width);
+            options.set_resize_height(height);
+            options.set_resize_quality(match quality {
+                ResizeQuality::Pixelated => web_sys::ResizeQuality::Pixelated,
+                ResizeQuality::Low => web_sys::ResizeQuality::Low,
+                ResizeQuality::Medium => web_sys::ResizeQuality::Medium,
+                ResizeQuality::High => web_sys::ResizeQuality::High
+            });
+        }
+        if self.flip_y {
+            options.set_image_orientation(web_sys::ImageOrientation::FlipY);
+        }
+        if let Some(premultiply) = self.premultiply_alpha {
+            options.set_premultiply_alpha(match premultiply {
+                true => web_sys::PremultiplyAlpha::Premultiply,
+                false => web_sys::PremultiplyAlpha::None
+            });
+        }
+        options
+    }
+}
+
+/// Decodes an image file, such as a PNG or JPEG, which works in workers too.
+pub async fn decode(blob: &web_sys::Blob) -> Result<web_sys::ImageBitmap, ImageError> {
+    decode_with(blob, &DecodeOptions::new()).await
+}
+
+pub async fn decode_with(
+    blob: &web_sys::Blob, options: &DecodeOptions
+) -> Result<web_sys::ImageBitmap, ImageError> {
+    let options = options.to_js();
+    let promise = match GlobalScope::current() {
+        GlobalScope::Window(window) => {
+            window.create_image_bitmap_with_blob_and_image_bitmap_options(blob, &options)?
+        }
+        GlobalScope::Worker(worker) => {
+            worker.create_image_bitmap_with_blob_and_image_bitmap_options(blob, &options)?
+        }
+        GlobalScope::Other(_) => return Err(ImageError::Unavailable)
+    };
+    Ok(JsFuture::from(promise).await?.unchecked_into())
+}
+
+/// Decodes an image file from its bytes, with `mime` being its type such as `image/png`.
+pub async fn decode_bytes(bytes: &[u8], mime: &str) -> Result<web_sys::ImageBitmap, ImageError> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime);
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+    decode(&blob).await
+}
+
+/// Loads and decodes the image at `url` as an image element, for putting in the page. This
+/// only works on the main thread.
+///
+/// Cross-origin images are requested with CORS, so that they can be drawn to a canvas
+/// without tainting it.
+pub async fn load(url: &str) -> Result<web_sys::HtmlImageElement, ImageError> {
+    if !GlobalScope::current().is_window() {
+        return Err(ImageError::Unavailable);
+    }
+    let image = web_sys::HtmlImageElement::new()?;
+    image.set_cross_origin(Some("anonymous"));
+    image.set_src(url);
+    JsFuture::from(image.decode()).await?;
+    Ok(image)
+}
+
+/// Copies a loaded image element into an `ImageBitmap`, such as for sending to a worker.
+pub async fn to_bitmap(
+    image: &web_sys::HtmlImageElement
+) -> Result<web_sys::ImageBitmap, ImageError> {
+    let window = match GlobalScope::current() {
+        GlobalScope::Window(window) => window,
+        _ => return Err(ImageError::Unavailable)
+    };
+    let promise = window.create_image_bitmap_with_html_image_element(image)?;
+    Ok(JsFuture::from(promise).await?.unchecked_into())
+}
+
+/// Loads and decodes the image at `url` as an `ImageBitmap`, such as for sending to a worker.
+/// This fetches the image, so it works in workers too.
+pub async fn load_bitmap(url: &str) -> Result<web_sys::ImageBitmap, ImageError> {
+    let request = web_sys::Request::new_with_str(url)?;
+    let promise = match GlobalScope::current() {
+        GlobalScope::Window(window) => window.fetch_with_request(&request),
+        GlobalScope::Worker(worker) => worker.fetch_with_request(&request),
+        GlobalScope::Other(_) => return Err(ImageError::Unavailable)
+    };
+    let response: web_sys::Response = JsFuture::from(promise).await?.unchecked_into();
+    if !response.ok() {
+        return Err(ImageError::Decode);
+    }
+    let blob = JsFuture::from(response.blob()?).await?;
+    decode(blob.unchecked_ref()).await
+}
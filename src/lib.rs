@@ -1,12 +1,72 @@
 pub mod event;
+pub mod codec;
 pub mod global;
+pub mod idle;
+pub mod timer;
+pub mod game_loop;
+pub mod lifecycle;
+pub mod video;
+pub mod fonts;
 pub mod channel;
 pub mod worker;
+pub mod shared;
+pub mod perf;
+pub mod promise;
+pub mod streams;
+pub mod compress;
+pub mod text;
+pub mod image;
+pub mod canvas;
+pub mod http;
+pub mod ws;
+pub mod webtransport;
+pub mod rtc;
+pub mod idb;
+pub mod storage;
+pub mod cache;
+pub mod cookies;
+pub mod url;
+pub mod router;
+pub mod clipboard;
+pub mod file;
+pub mod fs;
+pub mod opfs;
+pub mod upload;
+pub mod download;
+pub mod notify;
+pub mod push;
+pub mod geo;
+pub mod permissions;
+pub mod media;
+pub mod media_session;
+pub mod audio;
+pub mod speech;
+pub mod screen;
+pub mod wake_lock;
+pub mod battery;
+pub mod net;
+pub mod haptics;
+pub mod crypto;
+pub mod usb;
+pub mod bluetooth;
+pub mod serial;
+pub mod midi;
+pub mod task;
+
+pub use webutil_macros::{ worker, audio_processor };
+pub use task::{ spawn, JoinHandle };
+
+#[doc(hidden)]
+pub mod __private {
+    pub use wasm_bindgen;
+    pub use web_sys;
+}
 
 pub mod prelude {
     pub use wasm_bindgen::prelude::*;
     pub use wasm_bindgen_futures::spawn_local;
     pub use crate::event::EventTargetExt;
+    pub use crate::task::WebFutureExt;
     pub use crate::GeneralError;
 }
 
@@ -14,7 +74,22 @@ pub mod prelude {
 pub enum GeneralError {
     SerdeJson(serde_json::Error),
     Bincode(bincode::Error),
-    WebSys(wasm_bindgen::JsValue)
+    WebSys(wasm_bindgen::JsValue),
+    WorkerSpawn(String),
+    HttpStatus(u16)
+}
+
+impl GeneralError {
+    /// A JS `Error` with `msg` as its message, for failures noticed on the Rust side.
+    pub(crate) fn message(msg: &str) -> Self {
+        GeneralError::WebSys(js_sys::Error::new(msg).into())
+    }
+}
+
+/// The `name` of a JS error, which for a `DOMException` is the kind of failure, such as
+/// `NotAllowedError`. Modules match on it to turn errors into their own error types.
+pub(crate) fn error_name(e: &wasm_bindgen::JsValue) -> Option<String> {
+    js_sys::Reflect::get(e, &"name".into()).ok().and_then(|n| n.as_string())
 }
 
 impl From<serde_json::Error> for GeneralError {
@@ -29,12 +104,29 @@ impl From<bincode::Error> for GeneralError {
     }
 }
 
+impl From<serde_wasm_bindgen::Error> for GeneralError {
+    fn from(v: serde_wasm_bindgen::Error) -> Self {
+        GeneralError::WebSys(v.into())
+    }
+}
+
 impl From<wasm_bindgen::JsValue> for GeneralError {
     fn from(v: wasm_bindgen::JsValue) -> Self {
         GeneralError::WebSys(v)
     }
 }
 
+/// JS errors are passed through as they are, and other errors become a JS `Error` with their
+/// debug representation as the message.
+impl From<GeneralError> for wasm_bindgen::JsValue {
+    fn from(e: GeneralError) -> Self {
+        match e {
+            GeneralError::WebSys(v) => v,
+            e => js_sys::Error::new(&format!("{:?}", e)).into()
+        }
+    }
+}
+
 // use crate::prelude::*;
 // #[wasm_bindgen]
 // pub fn main() {
@@ -0,0 +1,78 @@
+use crate::prelude::*;
+use crate::channel::{ WatchReceiver, WatchSender, watch };
+use crate::event;
+use std::cell::RefCell;
+
+/// State of the page according to the Page Lifecycle API.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LifecycleState {
+    /// Visible and focused.
+    Active,
+    /// Visible but not focused.
+    Passive,
+    /// Not visible. This may be the last state the page is seen in, so state should be saved
+    /// when entering it.
+    Hidden,
+    /// Frozen by the browser to save resources, or put in the back/forward cache. No code runs
+    /// until the page is resumed.
+    Frozen,
+    /// Being unloaded.
+    Terminated
+}
+
+thread_local! {
+    static STATE: RefCell<Option<WatchSender<LifecycleState>>> = const { RefCell::new(None) };
+}
+
+/// Returns a receiver for the page's lifecycle state.
+///
+/// Frozen pages don't run code, so receivers usually only see `Frozen` once the page is
+/// resumed. Save state on `Hidden` instead, since a hidden page may be frozen or discarded
+/// without further notice.
+///
+/// Panics if called outside of a window.
+pub fn state() -> WatchReceiver<LifecycleState> {
+    STATE.with(|s| s.borrow_mut().get_or_insert_with(install).subscribe())
+}
+
+fn set(state: LifecycleState) {
+    STATE.with(|s| if let Some(s) = &*s.borrow() {
+        s.set_if_changed(state);
+    });
+}
+
+fn current(document: &web_sys::Document) -> LifecycleState {
+    if document.hidden() {
+        LifecycleState::Hidden
+    } else if document.has_focus().unwrap_or(true) {
+        LifecycleState::Active
+    } else {
+        LifecycleState::Passive
+    }
+}
+
+fn install() -> WatchSender<LifecycleState> {
+    let window = web_sys::window().expect("the page lifecycle is only available in windows");
+    let document = window.document().unwrap();
+
+    let doc = document.clone();
+    let update = move || set(current(&doc));
+    let upd = update.clone();
+    window.add_event_listener(move |_: event::Focus| upd()).forget();
+    let upd = update.clone();
+    window.add_event_listener(move |_: event::Blur| upd()).forget();
+    let upd = update.clone();
+    document.add_event_listener(move |_: event::VisibilityChange| upd()).forget();
+    let upd = update.clone();
+    document.add_event_listener(move |_: event::Resume| upd()).forget();
+    let upd = update;
+    window.add_event_listener(move |_: event::PageShow| upd()).forget();
+    document.add_event_listener(|_: event::Freeze| set(LifecycleState::Frozen)).forget();
+    window.add_event_listener(|e: event::PageHide| set(if e.persisted() {
+        LifecycleState::Frozen
+    } else {
+        LifecycleState::Terminated
+    })).forget();
+
+    watch(current(&document)).0
+}
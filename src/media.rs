@@ -0,0 +1,517 @@
+//! Cameras and microphones, from `getUserMedia`, and recording them.
+//!
+//! ```ignore
+//! let constraints = MediaConstraints::new()
+//!     .audio(AudioConstraints::new().echo_cancellation(true))
+//!     .video(VideoConstraints::new().size(1280, 720).facing(Facing::User));
+//! let stream = media::user_media(&constraints).await?;
+//! video.set_src_object(Some(&stream));
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, channel };
+use crate::event::{ self, EventStream, ListenerHandle };
+use crate::global::{ self, IntoDelay };
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Errors from using cameras and microphones.
+#[derive(Debug)]
+pub enum MediaError {
+    /// Media devices aren't supported, such as because the page isn't served securely.
+    Unavailable,
+    /// The user or browser didn't allow using the device.
+    PermissionDenied,
+    /// There is no device of the kind asked for.
+    NotFound,
+    /// The device is in use by another program, or failed to start.
+    NotReadable,
+    /// No device satisfies the named constraint.
+    Overconstrained(String),
+    Other(JsValue)
+}
+
+impl From<JsValue> for MediaError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotAllowedError") | Some("SecurityError") => MediaError::PermissionDenied,
+            Some("NotFoundError") => MediaError::NotFound,
+            Some("NotReadableError") | Some("AbortError") => MediaError::NotReadable,
+            Some("OverconstrainedError") => {
+                let constraint = js_sys::Reflect::get(&e, &"constraint".into()).ok()
+                    .and_then(|c| c.as_string())
+                    .unwrap_or_default();
+                MediaError::Overconstrained(constraint)
+            }
+            _ => MediaError::Other(e)
+        }
+    }
+}
+
+impl From<MediaError> for GeneralError {
+    fn from(e: MediaError) -> Self {
+        let msg = match e {
+            MediaError::Unavailable => "media devices are unavailable".to_owned(),
+            MediaError::PermissionDenied => "media device permission denied".to_owned(),
+            MediaError::NotFound => "media device not found".to_owned(),
+            MediaError::NotReadable => "media device could not be started".to_owned(),
+            MediaError::Overconstrained(constraint) => {
+                format!("no media device satisfies the {} constraint", constraint)
+            }
+            MediaError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(&msg)
+    }
+}
+
+/// Which devices to ask for in [`user_media`].
+#[derive(Clone, Debug, Default)]
+pub struct MediaConstraints {
+    audio: Option<AudioConstraints>,
+    video: Option<VideoConstraints>
+}
+
+impl MediaConstraints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn audio(mut self, audio: AudioConstraints) -> Self {
+        self.audio = Some(audio);
+        self
+    }
+
+    pub fn video(mut self, video: VideoConstraints) -> Self {
+        self.video = Some(video);
+        self
+    }
+
+    fn to_raw(&self) -> Result<web_sys::MediaStreamConstraints, JsValue> {
+        let raw = web_sys::MediaStreamConstraints::new();
+        raw.set_audio(&match &self.audio {
+            Some(audio) => audio.to_js()?,
+            None => false.into()
+        });
+        raw.set_video(&match &self.video {
+            Some(video) => video.to_js()?,
+            None => false.into()
+        });
+        Ok(raw)
+    }
+}
+
+/// Constraints on a microphone. Anything not set is left to the browser.
+#[derive(Clone, Debug, Default)]
+pub struct AudioConstraints {
+    device: Option<String>,
+    echo_cancellation: Option<bool>,
+    noise_suppression: Option<bool>,
+    auto_gain_control: Option<bool>,
+    channel_count: Option<u32>,
+    sample_rate: Option<u32>
+}
+
+impl AudioConstraints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses the device with this [`Device::id`], failing if it isn't available.
+    pub fn device(mut self, id: &str) -> Self {
+        self.device = Some(id.to_owned());
+        self
+    }
+
+    pub fn echo_cancellation(mut self, enabled: bool) -> Self {
+        self.echo_cancellation = Some(enabled);
+        self
+    }
+
+    pub fn noise_suppression(mut self, enabled: bool) -> Self {
+        self.noise_suppression = Some(enabled);
+        self
+    }
+
+    pub fn auto_gain_control(mut self, enabled: bool) -> Self {
+        self.auto_gain_control = Some(enabled);
+        self
+    }
+
+    /// The preferred number of channels, which the browser gets as close to as it can.
+    pub fn channel_count(mut self, count: u32) -> Self {
+        self.channel_count = Some(count);
+        self
+    }
+
+    /// The preferred sample rate in Hz, which the browser gets as close to as it can.
+    pub fn sample_rate(mut self, rate: u32) -> Self {
+        self.sample_rate = Some(rate);
+        self
+    }
+
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        let raw = js_sys::Object::new();
+        set_exact(&raw, "deviceId", self.device.as_deref().map(JsValue::from))?;
+        set(&raw, "echoCancellation", self.echo_cancellation.map(JsValue::from))?;
+        set(&raw, "noiseSuppression", self.noise_suppression.map(JsValue::from))?;
+        set(&raw, "autoGainControl", self.auto_gain_control.map(JsValue::from))?;
+        set_ideal(&raw, "channelCount", self.channel_count.map(JsValue::from))?;
+        set_ideal(&raw, "sampleRate", self.sample_rate.map(JsValue::from))?;
+        Ok(raw.into())
+    }
+}
+
+/// Which way a camera points.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Facing {
+    /// Towards the user, like a selfie camera.
+    User,
+    /// Away from the user.
+    Environment
+}
+
+/// Constraints on a camera. Anything not set is left to the browser.
+#[derive(Clone, Debug, Default)]
+pub struct VideoConstraints {
+    device: Option<String>,
+    size: Option<(u32, u32)>,
+    frame_rate: Option<f64>,
+    facing: Option<Facing>
+}
+
+impl VideoConstraints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses the device with this [`Device::id`], failing if it isn't available.
+    pub fn device(mut self, id: &str) -> Self {
+        self.device = Some(id.to_owned());
+        self
+    }
+
+    /// The preferred resolution, which the browser gets as close to as it can.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    /// The preferred frame rate, which the browser gets as close to as it can.
+    pub fn frame_rate(mut self, fps: f64) -> Self {
+        self.frame_rate = Some(fps);
+        self
+    }
+
+    /// Prefers cameras pointing this way, such as on phones.
+    pub fn facing(mut self, facing: Facing) -> Self {
+        self.facing = Some(facing);
+        self
+    }
+
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        let raw = js_sys::Object::new();
+        set_exact(&raw, "deviceId", self.device.as_deref().map(JsValue::from))?;
+        set_ideal(&raw, "width", self.size.map(|(w, _)| w.into()))?;
+        set_ideal(&raw, "height", self.size.map(|(_, h)| h.into()))?;
+        set_ideal(&raw, "frameRate", self.frame_rate.map(JsValue::from))?;
+        let facing = self.facing.map(|facing| match facing {
+            Facing::User => "user".into(),
+            Facing::Environment => "environment".into()
+        });
+        set_ideal(&raw, "facingMode", facing)?;
+        Ok(raw.into())
+    }
+}
+
+fn set(raw: &js_sys::Object, key: &str, value: Option<JsValue>) -> Result<(), JsValue> {
+    if let Some(value) = value {
+        js_sys::Reflect::set(raw, &key.into(), &value)?;
+    }
+    Ok(())
+}
+
+fn set_ideal(raw: &js_sys::Object, key: &str, value: Option<JsValue>) -> Result<(), JsValue> {
+    wrap_constraint(raw, key, "ideal", value)
+}
+
+fn set_exact(raw: &js_sys::Object, key: &str, value: Option<JsValue>) -> Result<(), JsValue> {
+    wrap_constraint(raw, key, "exact", value)
+}
+
+fn wrap_constraint(
+    raw: &js_sys::Object, key: &str, kind: &str, value: Option<JsValue>
+) -> Result<(), JsValue> {
+    if let Some(value) = value {
+        let constraint = js_sys::Object::new();
+        js_sys::Reflect::set(&constraint, &kind.into(), &value)?;
+        js_sys::Reflect::set(raw, &key.into(), &constraint)?;
+    }
+    Ok(())
+}
+
+fn media_devices() -> Result<web_sys::MediaDevices, MediaError> {
+    global::navigator_feature("mediaDevices").ok_or(MediaError::Unavailable)
+}
+
+/// Opens the cameras and microphones asked for, asking the user for permission if needed.
+///
+/// The devices stay in use until every track of the stream is stopped.
+pub async fn user_media(
+    constraints: &MediaConstraints
+) -> Result<web_sys::MediaStream, MediaError> {
+    let devices = media_devices()?;
+    let stream = devices.get_user_media_with_constraints(&constraints.to_raw()?)?;
+    Ok(JsFuture::from(stream).await?.unchecked_into())
+}
+
+/// Stops every track of `stream`, releasing the devices.
+pub fn stop(stream: &web_sys::MediaStream) {
+    for track in stream.get_tracks().iter() {
+        track.unchecked_into::<web_sys::MediaStreamTrack>().stop();
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeviceKind {
+    AudioInput,
+    AudioOutput,
+    VideoInput
+}
+
+/// A camera, microphone or speaker.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    pub id: String,
+    /// Shared by devices which are part of the same hardware, like a headset's microphone and
+    /// speakers.
+    pub group_id: String,
+    pub kind: DeviceKind,
+    /// The name of the device, which is empty until the user has allowed using a device.
+    pub label: String
+}
+
+/// Lists the available devices. Until the user has allowed using a device, browsers may list
+/// only one of each kind, without labels.
+pub async fn devices() -> Result<Vec<Device>, MediaError> {
+    let devices = media_devices()?;
+    let list = JsFuture::from(devices.enumerate_devices()?).await?;
+    Ok(js_sys::Array::from(&list).iter()
+        .map(JsCast::unchecked_into::<web_sys::MediaDeviceInfo>)
+        .filter_map(|info| {
+            let kind = match info.kind() {
+                web_sys::MediaDeviceKind::Audioinput => DeviceKind::AudioInput,
+                web_sys::MediaDeviceKind::Audiooutput => DeviceKind::AudioOutput,
+                web_sys::MediaDeviceKind::Videoinput => DeviceKind::VideoInput,
+                _ => return None
+            };
+            let (id, group_id, label) = (info.device_id(), info.group_id(), info.label());
+            Some(Device { id, group_id, kind, label })
+        })
+        .collect())
+}
+
+/// Watches for devices being plugged in or removed. Watching stops when this is dropped.
+pub fn device_changes() -> Result<DeviceChanges, MediaError> {
+    Ok(DeviceChanges(media_devices()?.on::<event::DeviceChange>()))
+}
+
+/// Changes to the available devices, from [`device_changes`].
+pub struct DeviceChanges(EventStream<event::DeviceChange>);
+
+impl DeviceChanges {
+    /// Waits for the devices to change, returning the new list.
+    pub async fn next(&self) -> Result<Vec<Device>, MediaError> {
+        self.0.next().await;
+        // several changes often come at once, such as for each part of a headset
+        while self.0.try_next().is_some() {}
+        devices().await
+    }
+}
+
+/// Whether [`record`] supports the MIME type, such as `video/webm;codecs=vp9`.
+pub fn can_record(mime_type: &str) -> bool {
+    js_sys::Reflect::has(&js_sys::global(), &"MediaRecorder".into()).unwrap_or(false)
+        && web_sys::MediaRecorder::is_type_supported(mime_type)
+}
+
+/// How to encode a recording.
+#[derive(Clone, Debug, Default)]
+pub struct RecordOptions {
+    mime_type: Option<String>,
+    timeslice: Option<f64>,
+    audio_bits_per_second: Option<u32>,
+    video_bits_per_second: Option<u32>
+}
+
+impl RecordOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The format to record in, which should be checked with [`can_record`]. The browser picks
+    /// one otherwise.
+    pub fn mime_type(mut self, mime_type: &str) -> Self {
+        self.mime_type = Some(mime_type.to_owned());
+        self
+    }
+
+    /// Produces a chunk every `timeslice`, instead of one chunk when recording stops.
+    pub fn timeslice(mut self, timeslice: impl IntoDelay) -> Self {
+        self.timeslice = Some(timeslice.into_millis());
+        self
+    }
+
+    pub fn audio_bits_per_second(mut self, bits: u32) -> Self {
+        self.audio_bits_per_second = Some(bits);
+        self
+    }
+
+    pub fn video_bits_per_second(mut self, bits: u32) -> Self {
+        self.video_bits_per_second = Some(bits);
+        self
+    }
+}
+
+/// Starts recording `stream`.
+///
+/// The chunks of encoded data are sent through [`Recorder::chunks`] as they are produced, and
+/// joined in order they make up the whole recording.
+/// ```ignore
+/// let recorder = media::record(&stream, &RecordOptions::new().timeslice(1000))?;
+/// let chunks = recorder.chunks().unwrap();
+/// while let Some(chunk) = chunks.recv().await {
+///     http::post("/recording").body_bytes(file::read_bytes(&chunk).await?).send().await?;
+/// }
+/// ```
+pub fn record(
+    stream: &web_sys::MediaStream, options: &RecordOptions
+) -> Result<Recorder, MediaError> {
+    if !js_sys::Reflect::has(&js_sys::global(), &"MediaRecorder".into())? {
+        return Err(MediaError::Unavailable);
+    }
+    let raw_options = web_sys::MediaRecorderOptions::new();
+    if let Some(mime_type) = &options.mime_type {
+        raw_options.set_mime_type(mime_type);
+    }
+    if let Some(bits) = options.audio_bits_per_second {
+        raw_options.set_audio_bits_per_second(bits);
+    }
+    if let Some(bits) = options.video_bits_per_second {
+        raw_options.set_video_bits_per_second(bits);
+    }
+    let raw = web_sys::MediaRecorder::new_with_media_stream_and_media_recorder_options(
+        stream, &raw_options
+    )?;
+
+    let (sender, chunks) = channel();
+    // the sender is dropped when recording stops, which ends the chunks
+    let sender = Rc::new(RefCell::new(Some(sender)));
+    let error = Rc::new(RefCell::new(None));
+    let data = raw.add_event_listener({
+        let sender = sender.clone();
+        move |e: event::DataAvailable| {
+            let data = e.data().filter(|data| data.size() > 0.0);
+            if let (Some(sender), Some(data)) = (&*sender.borrow(), data) {
+                let _ = sender.send(data);
+            }
+        }
+    });
+    let failed = raw.add_event_listener({
+        let error = error.clone();
+        move |e: event::Error| {
+            *error.borrow_mut() = js_sys::Reflect::get(&e, &"error".into()).ok();
+        }
+    });
+    // the last chunk is delivered before this
+    let stopped = raw.add_event_listener(move |_: event::RecorderStop| {
+        sender.borrow_mut().take();
+    });
+
+    match options.timeslice {
+        Some(timeslice) => raw.start_with_time_slice(timeslice.min(i32::MAX as f64) as i32)?,
+        None => raw.start()?
+    }
+    Ok(Recorder {
+        raw, error,
+        chunks: RefCell::new(Some(chunks)),
+        listeners: vec![data, failed, stopped]
+    })
+}
+
+/// A recording in progress, from [`record`]. Recording stops when this is dropped.
+pub struct Recorder {
+    raw: web_sys::MediaRecorder,
+    chunks: RefCell<Option<Receiver<web_sys::Blob>>>,
+    error: Rc<RefCell<Option<JsValue>>>,
+    listeners: Vec<ListenerHandle>
+}
+
+impl Recorder {
+    /// The chunks of the recording, which end once it stops. There is only one receiver for
+    /// them, so that no chunk is missing from it, and this returns `None` after the first call.
+    pub fn chunks(&self) -> Option<Receiver<web_sys::Blob>> {
+        self.chunks.borrow_mut().take()
+    }
+
+    /// The format being recorded in.
+    pub fn mime_type(&self) -> String {
+        self.raw.mime_type()
+    }
+
+    pub async fn pause(&self) -> Result<(), MediaError> {
+        if self.raw.state() != web_sys::RecordingState::Recording {
+            return Ok(());
+        }
+        let paused = self.raw.once::<event::Pause>();
+        self.raw.pause()?;
+        paused.await;
+        Ok(())
+    }
+
+    pub async fn resume(&self) -> Result<(), MediaError> {
+        if self.raw.state() != web_sys::RecordingState::Paused {
+            return Ok(());
+        }
+        let resumed = self.raw.once::<event::Resume>();
+        self.raw.resume()?;
+        resumed.await;
+        Ok(())
+    }
+
+    /// Stops recording, finishing once the last chunk has been sent. Fails if recording
+    /// stopped because of an error.
+    pub async fn stop(&self) -> Result<(), MediaError> {
+        if self.raw.state() != web_sys::RecordingState::Inactive {
+            let stopped = self.raw.once::<event::RecorderStop>();
+            self.raw.stop()?;
+            stopped.await;
+        }
+        match self.error.borrow_mut().take() {
+            Some(e) => Err(e.into()),
+            None => Ok(())
+        }
+    }
+
+    /// The underlying recorder.
+    pub fn raw(&self) -> &web_sys::MediaRecorder {
+        &self.raw
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if self.raw.state() != web_sys::RecordingState::Inactive {
+            // the last chunk arrives after stopping, so keep listening until then
+            let stopped = self.raw.once::<event::RecorderStop>();
+            let listeners = std::mem::take(&mut self.listeners);
+            if self.raw.stop().is_ok() {
+                spawn_local(async move {
+                    stopped.await;
+                    drop(listeners);
+                });
+            }
+        }
+    }
+}
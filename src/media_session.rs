@@ -0,0 +1,287 @@
+//! Lock screen, notification area and media key controls, from the Media Session API.
+//!
+//! Everything here does nothing where the API isn't supported, since the page still works
+//! without the controls.
+//!
+//! ```ignore
+//! media_session::set_metadata(
+//!     &Metadata::new("Overworld").artist("Composer").artwork("/cover.png", "512x512")
+//! )?;
+//! let actions = media_session::actions(&[Action::Play, Action::Pause, Action::NextTrack]);
+//! loop {
+//!     match actions.next().await {
+//!         ActionEvent::Play => player.play(),
+//!         ActionEvent::Pause => player.pause(),
+//!         ActionEvent::NextTrack => player.skip(),
+//!         _ => {}
+//!     }
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::global;
+use crate::channel::{ Receiver, channel };
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+
+#[wasm_bindgen]
+extern "C" {
+    // web-sys only binds the Media Session API as unstable
+    type MediaSession;
+    #[wasm_bindgen(method, setter)]
+    fn set_metadata(this: &MediaSession, metadata: Option<&MediaMetadata>);
+    #[wasm_bindgen(method, setter = playbackState)]
+    fn set_playback_state(this: &MediaSession, state: &str);
+    #[wasm_bindgen(method, catch, js_name = setActionHandler)]
+    fn set_action_handler(
+        this: &MediaSession, action: &str, handler: Option<&js_sys::Function>
+    ) -> Result<(), JsValue>;
+    #[wasm_bindgen(method, catch, js_name = setPositionState)]
+    fn set_position_state(this: &MediaSession, state: &JsValue) -> Result<(), JsValue>;
+
+    type MediaMetadata;
+    #[wasm_bindgen(constructor, catch)]
+    fn new(init: &JsValue) -> Result<MediaMetadata, JsValue>;
+}
+
+fn session() -> Option<MediaSession> {
+    global::navigator_feature("mediaSession")
+}
+
+pub fn is_supported() -> bool {
+    session().is_some()
+}
+
+/// What is playing, shown with the controls.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    title: String,
+    artist: Option<String>,
+    album: Option<String>,
+    artwork: Vec<(String, String)>
+}
+
+impl Metadata {
+    pub fn new(title: &str) -> Self {
+        Metadata { title: title.to_owned(), ..Default::default() }
+    }
+
+    pub fn artist(mut self, artist: &str) -> Self {
+        self.artist = Some(artist.to_owned());
+        self
+    }
+
+    pub fn album(mut self, album: &str) -> Self {
+        self.album = Some(album.to_owned());
+        self
+    }
+
+    /// Adds an image of the cover art, with its size such as `512x512`. The browser picks the
+    /// best size of those added.
+    pub fn artwork(mut self, url: &str, sizes: &str) -> Self {
+        self.artwork.push((url.to_owned(), sizes.to_owned()));
+        self
+    }
+
+    fn to_raw(&self) -> Result<MediaMetadata, JsValue> {
+        let init = js_sys::Object::new();
+        js_sys::Reflect::set(&init, &"title".into(), &self.title.as_str().into())?;
+        if let Some(artist) = &self.artist {
+            js_sys::Reflect::set(&init, &"artist".into(), &artist.as_str().into())?;
+        }
+        if let Some(album) = &self.album {
+            js_sys::Reflect::set(&init, &"album".into(), &album.as_str().into())?;
+        }
+        let artwork = js_sys::Array::new();
+        for (url, sizes) in &self.artwork {
+            let image = js_sys::Object::new();
+            js_sys::Reflect::set(&image, &"src".into(), &url.as_str().into())?;
+            js_sys::Reflect::set(&image, &"sizes".into(), &sizes.as_str().into())?;
+            artwork.push(&image);
+        }
+        js_sys::Reflect::set(&init, &"artwork".into(), &artwork)?;
+        MediaMetadata::new(&init)
+    }
+}
+
+/// Shows `metadata` with the controls. Fails if an artwork URL is invalid.
+pub fn set_metadata(metadata: &Metadata) -> Result<(), GeneralError> {
+    if let Some(session) = session() {
+        session.set_metadata(Some(&metadata.to_raw()?));
+    }
+    Ok(())
+}
+
+pub fn clear_metadata() {
+    if let Some(session) = session() {
+        session.set_metadata(None);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlaybackState {
+    /// Lets the browser work it out from the page's media elements.
+    None,
+    Paused,
+    Playing
+}
+
+/// Tells the browser whether the page is playing, for showing the play or pause button. This
+/// is needed when playing through Web Audio rather than a media element.
+pub fn set_playback_state(state: PlaybackState) {
+    if let Some(session) = session() {
+        session.set_playback_state(match state {
+            PlaybackState::None => "none",
+            PlaybackState::Paused => "paused",
+            PlaybackState::Playing => "playing"
+        });
+    }
+}
+
+/// Tells the browser the position in the current track, in seconds, for showing progress and
+/// seeking. The browser moves it along at `rate` until it is set again.
+pub fn set_position(duration: f64, position: f64, rate: f64) -> Result<(), GeneralError> {
+    if let Some(session) = session() {
+        let state = js_sys::Object::new();
+        js_sys::Reflect::set(&state, &"duration".into(), &duration.into())?;
+        js_sys::Reflect::set(&state, &"position".into(), &position.into())?;
+        js_sys::Reflect::set(&state, &"playbackRate".into(), &rate.into())?;
+        session.set_position_state(&state)?;
+    }
+    Ok(())
+}
+
+/// A control which the page can handle with [`actions`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Play,
+    Pause,
+    Stop,
+    SeekBackward,
+    SeekForward,
+    SeekTo,
+    PreviousTrack,
+    NextTrack
+}
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Play => "play",
+            Action::Pause => "pause",
+            Action::Stop => "stop",
+            Action::SeekBackward => "seekbackward",
+            Action::SeekForward => "seekforward",
+            Action::SeekTo => "seekto",
+            Action::PreviousTrack => "previoustrack",
+            Action::NextTrack => "nexttrack"
+        }
+    }
+}
+
+/// A control used by the user, from [`MediaActions`]. Times are in seconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ActionEvent {
+    Play,
+    Pause,
+    Stop,
+    /// Seek back by `offset`, or an amount of the page's choosing if there is none.
+    SeekBackward { offset: Option<f64> },
+    /// Seek ahead by `offset`, or an amount of the page's choosing if there is none.
+    SeekForward { offset: Option<f64> },
+    /// Seek to `time`. `fast` is set while the user is still scrubbing, when seeking can be
+    /// less precise.
+    SeekTo { time: f64, fast: bool },
+    PreviousTrack,
+    NextTrack
+}
+
+impl ActionEvent {
+    fn from_details(action: Action, details: &JsValue) -> Option<Self> {
+        let number = |key: &str| {
+            js_sys::Reflect::get(details, &key.into()).ok().and_then(|v| v.as_f64())
+        };
+        Some(match action {
+            Action::Play => ActionEvent::Play,
+            Action::Pause => ActionEvent::Pause,
+            Action::Stop => ActionEvent::Stop,
+            Action::SeekBackward => ActionEvent::SeekBackward { offset: number("seekOffset") },
+            Action::SeekForward => ActionEvent::SeekForward { offset: number("seekOffset") },
+            Action::SeekTo => ActionEvent::SeekTo {
+                time: number("seekTime")?,
+                fast: js_sys::Reflect::get(details, &"fastSeek".into()).ok()
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false)
+            },
+            Action::PreviousTrack => ActionEvent::PreviousTrack,
+            Action::NextTrack => ActionEvent::NextTrack
+        })
+    }
+}
+
+/// Handles the controls in `actions`, which the browser shows while they are handled.
+/// Browsers don't support every action, and those are left out.
+///
+/// There is one handler for each action, so handling an action here replaces any other
+/// [`MediaActions`] handling it, and dropping either stops it being handled.
+pub fn actions(actions: &[Action]) -> MediaActions {
+    let (sender, events) = channel();
+    let session = match session() {
+        Some(session) => session,
+        None => return MediaActions { events, session: None, handlers: vec![] }
+    };
+    let mut handlers = vec![];
+    for &action in actions {
+        let sender = sender.clone();
+        let handler = Closure::wrap(Box::new(move |details: JsValue| {
+            if let Some(event) = ActionEvent::from_details(action, &details) {
+                let _ = sender.send(event);
+            }
+        }) as Box<dyn FnMut(JsValue)>);
+        // unsupported actions are rejected with a TypeError
+        if session.set_action_handler(action.as_str(), Some(handler.as_ref().unchecked_ref()))
+            .is_ok()
+        {
+            handlers.push((action, handler));
+        }
+    }
+    MediaActions { events, session: Some(session), handlers }
+}
+
+/// Controls used by the user, from [`actions`]. The actions stop being handled when this is
+/// dropped.
+pub struct MediaActions {
+    events: Receiver<ActionEvent>,
+    session: Option<MediaSession>,
+    handlers: Vec<(Action, Handler)>
+}
+
+type Handler = Closure<dyn FnMut(JsValue)>;
+
+impl MediaActions {
+    /// Whether the browser supports `action`, so that it is being handled.
+    pub fn is_handled(&self, action: Action) -> bool {
+        self.handlers.iter().any(|&(a, _)| a == action)
+    }
+
+    pub fn try_next(&self) -> Option<ActionEvent> {
+        self.events.try_recv().ok()
+    }
+
+    pub async fn next(&self) -> ActionEvent {
+        match self.events.recv().await {
+            Some(event) => event,
+            None => std::future::pending().await
+        }
+    }
+}
+
+impl Drop for MediaActions {
+    fn drop(&mut self) {
+        if let Some(session) = &self.session {
+            for (action, _) in &self.handlers {
+                let _ = session.set_action_handler(action.as_str(), None);
+            }
+        }
+    }
+}
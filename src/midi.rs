@@ -0,0 +1,457 @@
+//! MIDI devices, from the Web MIDI API.
+//!
+//! Firefox asks the user before giving access, and Safari doesn't support Web MIDI.
+//!
+//! ```ignore
+//! let midi = midi::access(false).await?;
+//! let keyboard = midi.inputs().into_iter().next().ok_or("no keyboard")?;
+//! let synth = midi.outputs().into_iter().next().ok_or("no synth")?;
+//! let messages = keyboard.messages().await?;
+//! loop {
+//!     let TimedMessage { message, at } = messages.next().await;
+//!     // echo each note an eighth of a second later
+//!     synth.send_at(&message, at + Duration::from_millis(125))?;
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, channel };
+use crate::event::{ self, ListenerHandle };
+use crate::global::{ self, GlobalScope, IntoDelay };
+use crate::perf::PerfInstant;
+use std::collections::HashSet;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Errors from using MIDI devices.
+#[derive(Debug)]
+pub enum MidiError {
+    /// Web MIDI isn't supported.
+    Unavailable,
+    /// The user or browser didn't allow using MIDI devices, or system exclusive messages.
+    PermissionDenied,
+    /// The port was disconnected.
+    Disconnected,
+    /// The bytes sent aren't a valid MIDI message, or are a system exclusive message without
+    /// access to them.
+    InvalidMessage,
+    Other(JsValue)
+}
+
+impl From<JsValue> for MidiError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("SecurityError") | Some("NotAllowedError") => MidiError::PermissionDenied,
+            Some("InvalidStateError") => MidiError::Disconnected,
+            Some("TypeError") | Some("InvalidAccessError") => MidiError::InvalidMessage,
+            Some("NotSupportedError") => MidiError::Unavailable,
+            _ => MidiError::Other(e)
+        }
+    }
+}
+
+impl From<MidiError> for GeneralError {
+    fn from(e: MidiError) -> Self {
+        let msg = match e {
+            MidiError::Unavailable => "Web MIDI is unavailable",
+            MidiError::PermissionDenied => "MIDI permission denied",
+            MidiError::Disconnected => "MIDI port is disconnected",
+            MidiError::InvalidMessage => "invalid MIDI message",
+            MidiError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(msg)
+    }
+}
+
+/// A MIDI message. Channels are numbered from 0 to 15.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Message {
+    /// Also parsed from a note on with a velocity of 0, which devices commonly send instead.
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    /// Polyphonic aftertouch.
+    KeyPressure { channel: u8, note: u8, pressure: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    /// Channel aftertouch.
+    ChannelPressure { channel: u8, pressure: u8 },
+    /// From -8192 to 8191, with 0 being centered.
+    PitchBend { channel: u8, value: i16 },
+    /// A system exclusive message, including the leading `0xF0` and trailing `0xF7`.
+    SysEx(Vec<u8>),
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset,
+    /// Any other message, such as MIDI time code, or a channel message missing its data bytes.
+    Other(Vec<u8>)
+}
+
+impl Message {
+    pub fn parse(bytes: &[u8]) -> Message {
+        let status = bytes.first().copied().unwrap_or(0);
+        let channel = status & 0xF;
+        let data = |i: usize| bytes[i] & 0x7F;
+        let length = match status & 0xF0 {
+            0xC0 | 0xD0 => 2,
+            0x80..=0xE0 => 3,
+            _ => 0
+        };
+        if bytes.len() < length {
+            return Message::Other(bytes.to_vec());
+        }
+        match status & 0xF0 {
+            0x80 => Message::NoteOff { channel, note: data(1), velocity: data(2) },
+            0x90 if data(2) == 0 => Message::NoteOff { channel, note: data(1), velocity: 0 },
+            0x90 => Message::NoteOn { channel, note: data(1), velocity: data(2) },
+            0xA0 => Message::KeyPressure { channel, note: data(1), pressure: data(2) },
+            0xB0 => Message::ControlChange { channel, controller: data(1), value: data(2) },
+            0xC0 => Message::ProgramChange { channel, program: data(1) },
+            0xD0 => Message::ChannelPressure { channel, pressure: data(1) },
+            0xE0 => {
+                let value = (data(1) as i16 | (data(2) as i16) << 7) - 8192;
+                Message::PitchBend { channel, value }
+            }
+            _ => match status {
+                0xF0 => Message::SysEx(bytes.to_vec()),
+                0xF8 => Message::Clock,
+                0xFA => Message::Start,
+                0xFB => Message::Continue,
+                0xFC => Message::Stop,
+                0xFE => Message::ActiveSensing,
+                0xFF => Message::Reset,
+                _ => Message::Other(bytes.to_vec())
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let voice = |kind: u8, channel: u8, data: &[u8]| {
+            let mut bytes = vec![kind | (channel & 0xF)];
+            bytes.extend(data.iter().map(|d| d & 0x7F));
+            bytes
+        };
+        match *self {
+            Message::NoteOff { channel, note, velocity } => voice(0x80, channel, &[note, velocity]),
+            Message::NoteOn { channel, note, velocity } => voice(0x90, channel, &[note, velocity]),
+            Message::KeyPressure { channel, note, pressure } => {
+                voice(0xA0, channel, &[note, pressure])
+            }
+            Message::ControlChange { channel, controller, value } => {
+                voice(0xB0, channel, &[controller, value])
+            }
+            Message::ProgramChange { channel, program } => voice(0xC0, channel, &[program]),
+            Message::ChannelPressure { channel, pressure } => voice(0xD0, channel, &[pressure]),
+            Message::PitchBend { channel, value } => {
+                let value = (value.clamp(-8192, 8191) + 8192) as u16;
+                voice(0xE0, channel, &[value as u8, (value >> 7) as u8])
+            }
+            Message::SysEx(ref bytes) | Message::Other(ref bytes) => bytes.clone(),
+            Message::Clock => vec![0xF8],
+            Message::Start => vec![0xFA],
+            Message::Continue => vec![0xFB],
+            Message::Stop => vec![0xFC],
+            Message::ActiveSensing => vec![0xFE],
+            Message::Reset => vec![0xFF]
+        }
+    }
+}
+
+/// A message along with when it was received.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimedMessage {
+    pub message: Message,
+    pub at: PerfInstant
+}
+
+/// Asks for access to the MIDI devices, including sending and receiving system exclusive
+/// messages if `sysex` is set, which browsers are more reluctant to allow.
+pub async fn access(sysex: bool) -> Result<Midi, MidiError> {
+    let navigator = match GlobalScope::current() {
+        GlobalScope::Window(window) => window.navigator(),
+        _ => return Err(MidiError::Unavailable)
+    };
+    if !js_sys::Reflect::has(&navigator, &"requestMIDIAccess".into())? {
+        return Err(MidiError::Unavailable);
+    }
+    let options = web_sys::MidiOptions::new();
+    options.set_sysex(sysex);
+    let access = JsFuture::from(navigator.request_midi_access_with_options(&options)?).await?;
+    Ok(Midi(access.unchecked_into()))
+}
+
+/// Access to the MIDI devices, from [`access`].
+#[derive(Clone, Debug)]
+pub struct Midi(web_sys::MidiAccess);
+
+impl Midi {
+    /// The connected input ports.
+    pub fn inputs(&self) -> Vec<MidiInput> {
+        js_sys::Array::from(&self.0.inputs().values()).iter()
+            .map(|p| MidiInput(p.unchecked_into()))
+            .collect()
+    }
+
+    /// The connected output ports.
+    pub fn outputs(&self) -> Vec<MidiOutput> {
+        js_sys::Array::from(&self.0.outputs().values()).iter()
+            .map(|p| MidiOutput(p.unchecked_into()))
+            .collect()
+    }
+
+    pub fn sysex_enabled(&self) -> bool {
+        self.0.sysex_enabled()
+    }
+
+    /// Ports being connected and disconnected, such as when a device is plugged in.
+    pub fn port_changes(&self) -> PortChanges {
+        let (sender, changes) = channel();
+        let mut connected: HashSet<_> = self.inputs().iter().map(MidiInput::id)
+            .chain(self.outputs().iter().map(MidiOutput::id))
+            .collect();
+        let listener = self.0.add_event_listener(move |e: event::MidiStateChange| {
+            let port = match e.port() {
+                Some(port) => port,
+                None => return
+            };
+            let port = match port.type_() {
+                web_sys::MidiPortType::Input => Port::Input(MidiInput(port.unchecked_into())),
+                _ => Port::Output(MidiOutput(port.unchecked_into()))
+            };
+            // opening or closing a port also fires statechange, so only report device changes
+            let change = match port.is_connected() {
+                true if connected.insert(port.id()) => PortChange::Connected(port),
+                false if connected.remove(&port.id()) => PortChange::Disconnected(port),
+                _ => return
+            };
+            let _ = sender.send(change);
+        });
+        PortChanges { changes, _listener: listener }
+    }
+}
+
+macro_rules! port_info {
+    ($ty:ty) => {
+        impl $ty {
+            /// An identifier for the port, which stays the same across page loads.
+            pub fn id(&self) -> String {
+                self.0.id()
+            }
+
+            pub fn name(&self) -> Option<String> {
+                self.0.name()
+            }
+
+            pub fn manufacturer(&self) -> Option<String> {
+                self.0.manufacturer()
+            }
+
+            pub fn is_connected(&self) -> bool {
+                self.0.state() == web_sys::MidiPortDeviceState::Connected
+            }
+        }
+    };
+}
+
+/// A port which MIDI messages are received from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiInput(web_sys::MidiInput);
+
+port_info!(MidiInput);
+
+impl MidiInput {
+    /// Messages received on the port, once it has been opened.
+    pub async fn messages(&self) -> Result<MidiMessages, MidiError> {
+        let (sender, messages) = channel();
+        let listener = self.0.add_event_listener(move |e: event::MidiMessage| {
+            if let Ok(data) = e.data() {
+                let _ = sender.send(TimedMessage {
+                    message: Message::parse(&data),
+                    at: PerfInstant::from_millis_f64(e.time_stamp())
+                });
+            }
+        });
+        JsFuture::from(self.0.open()).await?;
+        Ok(MidiMessages { messages, _listener: listener })
+    }
+}
+
+/// Messages received on a port, from [`MidiInput::messages`].
+pub struct MidiMessages {
+    messages: Receiver<TimedMessage>,
+    _listener: ListenerHandle
+}
+
+impl MidiMessages {
+    pub fn try_next(&self) -> Option<TimedMessage> {
+        self.messages.try_recv().ok()
+    }
+
+    pub async fn next(&self) -> TimedMessage {
+        self.messages.recv().await.unwrap()
+    }
+}
+
+/// A port which MIDI messages are sent to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiOutput(web_sys::MidiOutput);
+
+port_info!(MidiOutput);
+
+impl MidiOutput {
+    /// Sends a message right away, opening the port if needed.
+    pub fn send(&self, message: &Message) -> Result<(), MidiError> {
+        self.send_raw(&message.to_bytes())
+    }
+
+    /// Sends a message at `at`, which keeps timing accurate regardless of how busy the page
+    /// is. Messages scheduled for the past are sent right away.
+    pub fn send_at(&self, message: &Message, at: PerfInstant) -> Result<(), MidiError> {
+        let data = js_sys::Uint8Array::from(&message.to_bytes()[..]);
+        self.0.send_with_timestamp(&data, at.as_millis_f64())?;
+        Ok(())
+    }
+
+    /// Sends a message after `delay`.
+    pub fn send_after(&self, message: &Message, delay: impl IntoDelay) -> Result<(), MidiError> {
+        let at = PerfInstant::from_millis_f64(global::now() + delay.into_millis());
+        self.send_at(message, at)
+    }
+
+    /// Sends raw bytes, which must be one or more complete messages.
+    pub fn send_raw(&self, bytes: &[u8]) -> Result<(), MidiError> {
+        self.0.send(&js_sys::Uint8Array::from(bytes))?;
+        Ok(())
+    }
+
+    /// Drops messages scheduled with [`send_at`](Self::send_at) which haven't been sent yet.
+    /// Not supported by every browser, in which case this does nothing.
+    pub fn clear(&self) {
+        if js_sys::Reflect::has(&self.0, &"clear".into()).unwrap_or(false) {
+            self.0.clear();
+        }
+    }
+}
+
+/// Either kind of port.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Port {
+    Input(MidiInput),
+    Output(MidiOutput)
+}
+
+impl Port {
+    pub fn id(&self) -> String {
+        match self {
+            Port::Input(input) => input.id(),
+            Port::Output(output) => output.id()
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        match self {
+            Port::Input(input) => input.is_connected(),
+            Port::Output(output) => output.is_connected()
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PortChange {
+    Connected(Port),
+    Disconnected(Port)
+}
+
+/// Ports being connected and disconnected, from [`Midi::port_changes`].
+pub struct PortChanges {
+    changes: Receiver<PortChange>,
+    _listener: ListenerHandle
+}
+
+impl PortChanges {
+    pub fn try_next(&self) -> Option<PortChange> {
+        self.changes.try_recv().ok()
+    }
+
+    pub async fn next(&self) -> PortChange {
+        self.changes.recv().await.unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_voice_messages() {
+        assert_eq!(
+            Message::parse(&[0x93, 60, 100]),
+            Message::NoteOn { channel: 3, note: 60, velocity: 100 }
+        );
+        assert_eq!(
+            Message::parse(&[0x93, 60, 0]),
+            Message::NoteOff { channel: 3, note: 60, velocity: 0 }
+        );
+        assert_eq!(
+            Message::parse(&[0xBF, 7, 127]),
+            Message::ControlChange { channel: 15, controller: 7, value: 127 }
+        );
+        assert_eq!(Message::parse(&[0xC0, 5]), Message::ProgramChange { channel: 0, program: 5 });
+        assert_eq!(Message::parse(&[0xE0, 0, 0x40]), Message::PitchBend { channel: 0, value: 0 });
+        assert_eq!(Message::parse(&[0xE0, 0, 0]), Message::PitchBend { channel: 0, value: -8192 });
+        assert_eq!(
+            Message::parse(&[0xE0, 0x7F, 0x7F]),
+            Message::PitchBend { channel: 0, value: 8191 }
+        );
+    }
+
+    #[test]
+    fn parse_system_messages() {
+        assert_eq!(Message::parse(&[0xF8]), Message::Clock);
+        assert_eq!(Message::parse(&[0xFF]), Message::Reset);
+        assert_eq!(Message::parse(&[0xF0, 1, 2, 0xF7]), Message::SysEx(vec![0xF0, 1, 2, 0xF7]));
+        assert_eq!(Message::parse(&[0xF1, 3]), Message::Other(vec![0xF1, 3]));
+    }
+
+    #[test]
+    fn parse_short_messages() {
+        assert_eq!(Message::parse(&[0x80]), Message::Other(vec![0x80]));
+        assert_eq!(Message::parse(&[0x93, 60]), Message::Other(vec![0x93, 60]));
+        assert_eq!(Message::parse(&[0xC0]), Message::Other(vec![0xC0]));
+        assert_eq!(Message::parse(&[]), Message::Other(vec![]));
+    }
+
+    #[test]
+    fn round_trip() {
+        let messages = [
+            Message::NoteOff { channel: 1, note: 64, velocity: 10 },
+            Message::NoteOn { channel: 2, note: 64, velocity: 90 },
+            Message::KeyPressure { channel: 3, note: 1, pressure: 2 },
+            Message::ControlChange { channel: 4, controller: 64, value: 127 },
+            Message::ProgramChange { channel: 5, program: 100 },
+            Message::ChannelPressure { channel: 6, pressure: 50 },
+            Message::PitchBend { channel: 7, value: -1234 },
+            Message::PitchBend { channel: 8, value: 4321 },
+            Message::SysEx(vec![0xF0, 0x7E, 0x7F, 0xF7]),
+            Message::Clock,
+            Message::Start,
+            Message::Continue,
+            Message::Stop,
+            Message::ActiveSensing,
+            Message::Reset
+        ];
+        for message in messages {
+            assert_eq!(Message::parse(&message.to_bytes()), message);
+        }
+    }
+
+    #[test]
+    fn to_bytes_masks_out_of_range_values() {
+        let message = Message::NoteOn { channel: 0x12, note: 0xC0, velocity: 0xFF };
+        assert_eq!(message.to_bytes(), vec![0x92, 0x40, 0x7F]);
+        let bend = Message::PitchBend { channel: 0, value: i16::MAX };
+        assert_eq!(bend.to_bytes(), vec![0xE0, 0x7F, 0x7F]);
+    }
+}
@@ -0,0 +1,112 @@
+//! Whether the device is online and how good its connection is, from `navigator.onLine` and
+//! the Network Information API.
+//!
+//! Only Chromium-based browsers report the connection quality, so the other fields of
+//! [`NetStatus`] are unknown elsewhere.
+//!
+//! ```ignore
+//! let net = net::status();
+//! loop {
+//!     let status = net.get();
+//!     offline_banner.set_hidden(status.online);
+//!     video.set_quality(match status.effective_type {
+//!         Some(EffectiveType::FourG) | None => Quality::High,
+//!         _ => Quality::Low
+//!     });
+//!     net.changed().await;
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ WatchReceiver, WatchSender, watch };
+use crate::event;
+use crate::global::{ self, GlobalScope };
+use std::cell::RefCell;
+
+thread_local! {
+    static STATUS: RefCell<Option<WatchSender<NetStatus>>> = const { RefCell::new(None) };
+}
+
+/// How fast the connection is, as a similar kind of mobile connection.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EffectiveType {
+    Slow2G,
+    TwoG,
+    ThreeG,
+    /// Anything fast, including wired and Wi-Fi connections.
+    FourG
+}
+
+/// The state of the network connection.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NetStatus {
+    /// Whether the device is connected to a network. Being connected doesn't mean the server
+    /// can be reached, but not being connected means it can't.
+    pub online: bool,
+    pub effective_type: Option<EffectiveType>,
+    /// Whether the user asked for less data to be used.
+    pub save_data: bool,
+    /// Estimated bandwidth in megabits per second.
+    pub downlink: Option<f64>,
+    /// Estimated round trip time in milliseconds.
+    pub rtt: Option<f64>
+}
+
+fn connection() -> Option<web_sys::EventTarget> {
+    global::navigator_feature("connection")
+}
+
+fn current() -> NetStatus {
+    let online = js_sys::Reflect::get(&global::navigator(), &"onLine".into()).ok()
+        .and_then(|o| o.as_bool())
+        .unwrap_or(true);
+    let mut status = NetStatus {
+        online,
+        effective_type: None,
+        save_data: false,
+        downlink: None,
+        rtt: None
+    };
+    if let Some(connection) = connection() {
+        let get = |key: &str| js_sys::Reflect::get(&connection, &key.into()).ok();
+        status.effective_type = match get("effectiveType").and_then(|t| t.as_string()).as_deref() {
+            Some("slow-2g") => Some(EffectiveType::Slow2G),
+            Some("2g") => Some(EffectiveType::TwoG),
+            Some("3g") => Some(EffectiveType::ThreeG),
+            Some("4g") => Some(EffectiveType::FourG),
+            _ => None
+        };
+        status.save_data = get("saveData").and_then(|s| s.as_bool()).unwrap_or(false);
+        status.downlink = get("downlink").and_then(|d| d.as_f64());
+        status.rtt = get("rtt").and_then(|r| r.as_f64());
+    }
+    status
+}
+
+fn update() {
+    STATUS.with(|s| if let Some(s) = &*s.borrow() {
+        s.set_if_changed(current());
+    });
+}
+
+/// The state of the network connection, which changes as the device goes on and offline or
+/// the connection gets better or worse.
+pub fn status() -> WatchReceiver<NetStatus> {
+    STATUS.with(|s| s.borrow_mut().get_or_insert_with(install).subscribe())
+}
+
+/// Whether the device is connected to a network.
+pub fn is_online() -> bool {
+    current().online
+}
+
+fn install() -> WatchSender<NetStatus> {
+    // the network is watched for as long as the page or worker lives
+    let global = GlobalScope::current();
+    global.event_target().add_event_listener(|_: event::Online| update()).forget();
+    global.event_target().add_event_listener(|_: event::Offline| update()).forget();
+    if let Some(connection) = connection() {
+        connection.add_event_listener(|_: event::Change| update()).forget();
+    }
+    watch(current()).0
+}
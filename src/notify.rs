@@ -0,0 +1,362 @@
+//! System notifications.
+//!
+//! ```ignore
+//! if notify::request_permission().await? == Permission::Granted {
+//!     let notification = Notification::builder("Your turn")
+//!         .body("Your opponent played e4")
+//!         .icon("/icon.png")
+//!         .show()
+//!         .await?;
+//!     if let NotifyEvent::Click { .. } = notification.events().next().await {
+//!         window.focus().ok();
+//!     }
+//! }
+//! ```
+//!
+//! Notifications are shown by the page where possible. Some browsers, such as Chrome on
+//! Android, only show them through a service worker registration, which is also needed for
+//! actions. Events on those are sent to the service worker, and only reach the page if the
+//! service worker calls [`forward_events`].
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, channel, oneshot };
+use crate::event::{ self, ListenerHandle };
+use crate::global::{ self, GlobalScope };
+use std::cell::Cell;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen_futures::JsFuture;
+
+/// The property of messages from [`forward_events`] holding the notification's tag.
+const FORWARD_KEY: &str = "webutilNotification";
+
+thread_local! {
+    static NEXT_TAG: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Errors from showing notifications.
+#[derive(Debug)]
+pub enum NotifyError {
+    /// Notifications aren't supported here, such as because the page isn't served securely.
+    Unavailable,
+    /// The user hasn't allowed notifications.
+    PermissionDenied,
+    Other(JsValue)
+}
+
+impl From<JsValue> for NotifyError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotAllowedError") => NotifyError::PermissionDenied,
+            _ => NotifyError::Other(e)
+        }
+    }
+}
+
+impl From<NotifyError> for GeneralError {
+    fn from(e: NotifyError) -> Self {
+        match e {
+            NotifyError::Unavailable => GeneralError::message("notifications are unavailable"),
+            NotifyError::PermissionDenied => {
+                GeneralError::message("notification permission denied")
+            }
+            NotifyError::Other(e) => GeneralError::WebSys(e)
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Permission {
+    Granted,
+    Denied,
+    /// The user hasn't been asked yet, or dismissed the prompt.
+    Default
+}
+
+/// Whether notifications are supported here.
+pub fn is_supported() -> bool {
+    js_sys::Reflect::has(&js_sys::global(), &"Notification".into()).unwrap_or(false)
+}
+
+/// Whether the page may show notifications. This is [`Permission::Denied`] where they aren't
+/// supported.
+pub fn permission() -> Permission {
+    if !is_supported() {
+        return Permission::Denied;
+    }
+    match web_sys::Notification::permission() {
+        web_sys::NotificationPermission::Granted => Permission::Granted,
+        web_sys::NotificationPermission::Default => Permission::Default,
+        _ => Permission::Denied
+    }
+}
+
+/// Asks the user to allow notifications, if they haven't decided yet. Browsers only ask in
+/// response to user input, such as in a click handler, and only in windows.
+pub async fn request_permission() -> Result<Permission, NotifyError> {
+    if !is_supported() || !GlobalScope::current().is_window() {
+        return Err(NotifyError::Unavailable);
+    }
+    let (sender, decided) = oneshot();
+    let callback = Closure::once(move |_: JsValue| {
+        let _ = sender.resolve(());
+    });
+    // older Safari only calls the callback, while other browsers also return a promise
+    let _ = web_sys::Notification::request_permission_with_permission_callback(
+        callback.as_ref().unchecked_ref()
+    )?;
+    decided.await;
+    Ok(permission())
+}
+
+/// Builds a notification, created with [`Notification::builder`].
+pub struct NotificationBuilder {
+    title: String,
+    tag: Option<String>,
+    actions: js_sys::Array,
+    options: web_sys::NotificationOptions
+}
+
+impl NotificationBuilder {
+    pub fn body(self, body: &str) -> Self {
+        self.options.set_body(body);
+        self
+    }
+
+    /// The URL of an icon shown beside the notification.
+    pub fn icon(self, url: &str) -> Self {
+        self.options.set_icon(url);
+        self
+    }
+
+    /// The URL of an image shown in the notification.
+    pub fn image(self, url: &str) -> Self {
+        self.options.set_image(url);
+        self
+    }
+
+    /// Replaces any notification shown with the same tag, instead of showing another one.
+    /// Notifications get a unique tag otherwise.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_owned());
+        self
+    }
+
+    /// Keeps the notification on screen until the user dismisses it.
+    pub fn require_interaction(self, require: bool) -> Self {
+        self.options.set_require_interaction(require);
+        self
+    }
+
+    pub fn silent(self, silent: bool) -> Self {
+        self.options.set_silent(Some(silent));
+        self
+    }
+
+    /// Adds a button, reported as [`NotifyEvent::Click`] with `action` when clicked. Only
+    /// notifications shown through a service worker have buttons, so adding one shows the
+    /// notification through the service worker.
+    pub fn action(self, action: &str, title: &str) -> Self {
+        self.actions.push(&web_sys::NotificationAction::new(action, title));
+        self.options.set_actions(&self.actions);
+        self
+    }
+
+    /// Shows the notification. This fails with [`NotifyError::PermissionDenied`] unless
+    /// [`permission`] is granted.
+    pub async fn show(self) -> Result<Notification, NotifyError> {
+        if !is_supported() {
+            return Err(NotifyError::Unavailable);
+        }
+        if permission() != Permission::Granted {
+            return Err(NotifyError::PermissionDenied);
+        }
+        let tag = self.tag.unwrap_or_else(|| {
+            let n = NEXT_TAG.with(|next| next.replace(next.get() + 1));
+            format!("webutil-{}-{}", js_sys::Date::now(), n)
+        });
+        self.options.set_tag(&tag);
+
+        if self.actions.length() == 0 && !is_service_worker() {
+            match web_sys::Notification::new_with_options(&self.title, &self.options) {
+                Ok(raw) => return Ok(Notification { raw, tag, by_worker: false }),
+                // browsers which only show notifications through a service worker throw this
+                Err(e) if e.is_instance_of::<js_sys::TypeError>() => {}
+                Err(e) => return Err(e.into())
+            }
+        }
+
+        let registration = global::service_worker_registration().await?
+            .ok_or(NotifyError::Unavailable)?;
+        let shown = registration.show_notification_with_options(&self.title, &self.options)?;
+        JsFuture::from(shown).await?;
+        let shown = JsFuture::from(registration.get_notifications()?).await?;
+        let raw = js_sys::Array::from(&shown).iter()
+            .map(JsCast::unchecked_into::<web_sys::Notification>)
+            .find(|n| n.tag().as_deref() == Some(&tag))
+            .ok_or_else(|| {
+                NotifyError::Other(js_sys::Error::new("notification was closed right away").into())
+            })?;
+        Ok(Notification { raw, tag, by_worker: true })
+    }
+}
+
+/// A notification which has been shown.
+pub struct Notification {
+    raw: web_sys::Notification,
+    tag: String,
+    by_worker: bool
+}
+
+impl Notification {
+    pub fn builder(title: &str) -> NotificationBuilder {
+        NotificationBuilder {
+            title: title.to_owned(),
+            tag: None,
+            actions: js_sys::Array::new(),
+            options: web_sys::NotificationOptions::new()
+        }
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Whether the notification was shown through a service worker.
+    pub fn is_by_worker(&self) -> bool {
+        self.by_worker
+    }
+
+    /// Listens for the notification being clicked or closed.
+    ///
+    /// For notifications shown through a service worker, these only arrive if the service
+    /// worker calls [`forward_events`], and only in windows it controls.
+    pub fn events(&self) -> NotificationEvents {
+        let (sender, events) = channel();
+        if !self.by_worker {
+            let clicks = self.raw.add_event_listener({
+                let sender = sender.clone();
+                move |_: event::NotificationClick| {
+                    let _ = sender.send(NotifyEvent::Click { action: None });
+                }
+            });
+            let closes = self.raw.add_event_listener(move |_: event::NotificationClose| {
+                let _ = sender.send(NotifyEvent::Close);
+            });
+            return NotificationEvents { events, _listeners: vec![clicks, closes] };
+        }
+
+        let container = match global::service_worker_container() {
+            Some(container) => container,
+            None => return NotificationEvents { events, _listeners: vec![] }
+        };
+        let tag = self.tag.clone();
+        let messages = container.add_event_listener(move |e: event::Message| {
+            let data = e.data();
+            let get = |key: &str| {
+                js_sys::Reflect::get(&data, &key.into()).ok().and_then(|v| v.as_string())
+            };
+            if get(FORWARD_KEY).as_deref() != Some(&tag) {
+                return;
+            }
+            let event = match get("event").as_deref() {
+                Some("click") => NotifyEvent::Click { action: get("action") },
+                Some("close") => NotifyEvent::Close,
+                _ => return
+            };
+            let _ = sender.send(event);
+        });
+        // messages from service workers are held until `startMessages` is called or a listener
+        // is set with `onmessage`
+        let start = js_sys::Reflect::get(&container, &"startMessages".into());
+        if let Ok(start) = start.and_then(JsCast::dyn_into::<js_sys::Function>) {
+            let _ = start.call0(&container);
+        }
+        NotificationEvents { events, _listeners: vec![messages] }
+    }
+
+    pub fn close(&self) {
+        self.raw.close();
+    }
+
+    /// The underlying notification.
+    pub fn raw(&self) -> &web_sys::Notification {
+        &self.raw
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotifyEvent {
+    /// The notification was clicked, or one of its buttons if `action` is set.
+    Click { action: Option<String> },
+    /// The notification was dismissed or closed.
+    Close
+}
+
+/// Clicks and closes of a notification, from [`Notification::events`]. Listening stops when
+/// this is dropped.
+pub struct NotificationEvents {
+    events: Receiver<NotifyEvent>,
+    _listeners: Vec<ListenerHandle>
+}
+
+impl NotificationEvents {
+    pub fn try_next(&self) -> Option<NotifyEvent> {
+        self.events.try_recv().ok()
+    }
+
+    pub async fn next(&self) -> NotifyEvent {
+        self.events.recv().await.unwrap()
+    }
+}
+
+/// Sends clicks and closes of notifications to the windows the service worker controls, for
+/// [`Notification::events`]. This must be called in the service worker while its script is
+/// first run, and lasts for the life of the service worker.
+///
+/// Service workers often also want to focus or open a window when a notification is clicked,
+/// which they can do by listening for [`event::WorkerNotificationClick`] themselves.
+pub fn forward_events() -> Result<(), NotifyError> {
+    let scope = js_sys::global().dyn_into::<web_sys::ServiceWorkerGlobalScope>()
+        .map_err(|_| NotifyError::Unavailable)?;
+    scope.add_event_listener({
+        let scope = scope.clone();
+        move |e: event::WorkerNotificationClick| forward(&scope, &e, "click")
+    }).forget();
+    scope.add_event_listener({
+        let scope = scope.clone();
+        move |e: event::WorkerNotificationClose| forward(&scope, &e, "close")
+    }).forget();
+    Ok(())
+}
+
+fn forward(scope: &web_sys::ServiceWorkerGlobalScope, e: &web_sys::NotificationEvent, kind: &str) {
+    let message = js_sys::Object::new();
+    let tag = e.notification().tag().unwrap_or_default();
+    let action = js_sys::Reflect::get(e, &"action".into()).ok()
+        .and_then(|a| a.as_string())
+        .filter(|a| !a.is_empty());
+    let _ = js_sys::Reflect::set(&message, &FORWARD_KEY.into(), &tag.into());
+    let _ = js_sys::Reflect::set(&message, &"event".into(), &kind.into());
+    if let Some(action) = action {
+        let _ = js_sys::Reflect::set(&message, &"action".into(), &action.into());
+    }
+
+    let options = web_sys::ClientQueryOptions::new();
+    options.set_include_uncontrolled(true);
+    options.set_type(web_sys::ClientType::Window);
+    let clients = scope.clients().match_all_with_options(&options);
+    let sent = wasm_bindgen_futures::future_to_promise(async move {
+        let clients = JsFuture::from(clients).await?;
+        for client in js_sys::Array::from(&clients).iter() {
+            client.unchecked_into::<web_sys::Client>().post_message(&message)?;
+        }
+        Ok(JsValue::UNDEFINED)
+    });
+    // keeps the service worker running until the messages are sent
+    let _ = e.wait_until(&sent);
+}
+
+fn is_service_worker() -> bool {
+    js_sys::global().is_instance_of::<web_sys::ServiceWorkerGlobalScope>()
+}
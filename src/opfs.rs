@@ -0,0 +1,317 @@
+//! The origin private file system, a file system for the page's origin which isn't visible to
+//! the user, built on the handles from [`fs`](crate::fs).
+//!
+//! Dedicated workers can also open files for synchronous access with [`SyncFile`], which is
+//! much faster for many small reads and writes, such as for an embedded database. The main
+//! thread can use that through a worker serving the [`Opfs`] protocol:
+//! ```ignore
+//! #[webutil::worker]
+//! async fn storage(_: (), requests: Requests<Opfs>) {
+//!     opfs::serve(requests).await;
+//! }
+//!
+//! let storage = SyncWorker::new::<storage>("./worker.js").await?;
+//! storage.write_at("db/pages", 4096, &page).await?;
+//! let page = storage.read_at("db/pages", 4096, 4096).await?;
+//! ```
+
+use crate::prelude::*;
+use crate::codec::{ Bincode, Codec };
+use crate::fs::{ DirectoryHandle, FileHandle, FsError };
+use crate::global::{ self, GlobalScope };
+use crate::worker::{ Protocol, ProtocolEntry, ProtocolWorker, Requests, Script };
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// The root directory of the origin private file system.
+pub async fn root() -> Result<DirectoryHandle, FsError> {
+    let storage: web_sys::StorageManager = global::navigator_feature("storage")
+        .ok_or(FsError::Unavailable)?;
+    let root = JsFuture::from(storage.get_directory()).await?;
+    Ok(web_sys::FileSystemDirectoryHandle::unchecked_from_js(root).into())
+}
+
+/// The directory at `path`, such as `saves/slot1`, relative to the root. Missing directories
+/// are created if `create` is set.
+pub async fn directory(path: &str, create: bool) -> Result<DirectoryHandle, FsError> {
+    let mut dir = root().await?;
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        dir = dir.directory(name, create).await?;
+    }
+    Ok(dir)
+}
+
+/// The file at `path`, relative to the root. The file and missing directories are created if
+/// `create` is set.
+pub async fn file(path: &str, create: bool) -> Result<FileHandle, FsError> {
+    let (dir, name) = split(path)?;
+    directory(dir, create).await?.file(name, create).await
+}
+
+/// Removes the file or directory at `path`, including everything in it.
+pub async fn remove(path: &str) -> Result<(), FsError> {
+    let (dir, name) = split(path)?;
+    directory(dir, false).await?.remove(name, true).await
+}
+
+fn split(path: &str) -> Result<(&str, &str), FsError> {
+    let path = path.trim_end_matches('/');
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    if name.is_empty() {
+        return Err(FsError::NotFound);
+    }
+    Ok((dir, name))
+}
+
+/// A file opened for synchronous access, which is only possible in dedicated workers.
+///
+/// The file is locked while it is open, so opening it again fails until this is dropped.
+pub struct SyncFile(web_sys::FileSystemSyncAccessHandle);
+
+impl SyncFile {
+    pub async fn open(file: &FileHandle) -> Result<Self, FsError> {
+        if !GlobalScope::current().is_worker() {
+            return Err(FsError::Unavailable);
+        }
+        let file = file.raw().ok_or(FsError::Unavailable)?;
+        let handle = JsFuture::from(file.create_sync_access_handle()).await?;
+        Ok(SyncFile(handle.unchecked_into()))
+    }
+
+    /// Opens the file at `path`, creating it if it doesn't exist.
+    pub async fn open_path(path: &str) -> Result<Self, FsError> {
+        Self::open(&file(path, true).await?).await
+    }
+
+    /// Reads into `buf` from `at`, returning how many bytes were read, which is less than
+    /// asked for at the end of the file.
+    pub fn read_at(&self, buf: &mut [u8], at: u64) -> Result<usize, FsError> {
+        let options = web_sys::FileSystemReadWriteOptions::new();
+        options.set_at(at as f64);
+        Ok(self.0.read_with_u8_array_and_options(buf, &options)? as usize)
+    }
+
+    /// Writes `data` at `at`, growing the file if needed.
+    pub fn write_at(&self, data: &[u8], at: u64) -> Result<(), FsError> {
+        let options = web_sys::FileSystemReadWriteOptions::new();
+        options.set_at(at as f64);
+        self.0.write_with_u8_array_and_options(data, &options)?;
+        Ok(())
+    }
+
+    pub fn size(&self) -> Result<u64, FsError> {
+        Ok(self.0.get_size()? as u64)
+    }
+
+    /// Shrinks or grows the file to `size` bytes. Growing fills it with zeros.
+    pub fn truncate(&self, size: u64) -> Result<(), FsError> {
+        self.0.truncate_with_f64(size as f64)?;
+        Ok(())
+    }
+
+    /// Makes sure the writes so far are stored.
+    pub fn flush(&self) -> Result<(), FsError> {
+        self.0.flush()?;
+        Ok(())
+    }
+
+    /// The underlying handle.
+    pub fn raw(&self) -> &web_sys::FileSystemSyncAccessHandle {
+        &self.0
+    }
+}
+
+impl Drop for SyncFile {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// The protocol of a worker giving the main thread synchronous file access, served by
+/// [`serve`] and used through [`SyncWorker`].
+pub struct Opfs;
+
+impl Protocol for Opfs {
+    type Request = OpfsRequest;
+    type Response = Result<OpfsResponse, RemoteFsError>;
+    type Event = ();
+}
+
+/// Operations on files, by path relative to the root. Files are opened as needed and stay
+/// open until closed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum OpfsRequest {
+    Read { path: String, at: u64, len: u32 },
+    Write { path: String, at: u64, data: Vec<u8> },
+    Size { path: String },
+    Truncate { path: String, size: u64 },
+    Flush { path: String },
+    Close { path: String }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum OpfsResponse {
+    Data(Vec<u8>),
+    Size(u64),
+    Done
+}
+
+/// An [`FsError`] sent from the worker.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum RemoteFsError {
+    Unavailable,
+    PermissionDenied,
+    NotFound,
+    QuotaExceeded,
+    /// Any other error, as its debug representation.
+    Other(String)
+}
+
+impl From<FsError> for RemoteFsError {
+    fn from(e: FsError) -> Self {
+        match e {
+            FsError::Unavailable => RemoteFsError::Unavailable,
+            FsError::PermissionDenied => RemoteFsError::PermissionDenied,
+            FsError::NotFound => RemoteFsError::NotFound,
+            FsError::QuotaExceeded => RemoteFsError::QuotaExceeded,
+            FsError::Other(e) => RemoteFsError::Other(format!("{:?}", e))
+        }
+    }
+}
+
+impl From<RemoteFsError> for FsError {
+    fn from(e: RemoteFsError) -> Self {
+        match e {
+            RemoteFsError::Unavailable => FsError::Unavailable,
+            RemoteFsError::PermissionDenied => FsError::PermissionDenied,
+            RemoteFsError::NotFound => FsError::NotFound,
+            RemoteFsError::QuotaExceeded => FsError::QuotaExceeded,
+            RemoteFsError::Other(msg) => FsError::Other(js_sys::Error::new(&msg).into())
+        }
+    }
+}
+
+/// Serves [`Opfs`] requests in a worker until it shuts down. Open files are flushed and
+/// closed when it does.
+pub async fn serve<C: Codec>(requests: Requests<Opfs, C>) {
+    let mut files = HashMap::new();
+    while let Some(request) = requests.next().await {
+        let response = handle(&mut files, &request.body).await.map_err(RemoteFsError::from);
+        request.respond(&response);
+    }
+    for file in files.values() {
+        file.flush().ok();
+    }
+}
+
+async fn handle(
+    files: &mut HashMap<String, SyncFile>, request: &OpfsRequest
+) -> Result<OpfsResponse, FsError> {
+    let path = match request {
+        OpfsRequest::Close { path } => {
+            if let Some(file) = files.remove(path) {
+                file.flush()?;
+            }
+            return Ok(OpfsResponse::Done);
+        }
+        OpfsRequest::Read { path, .. } | OpfsRequest::Write { path, .. }
+            | OpfsRequest::Size { path } | OpfsRequest::Truncate { path, .. }
+            | OpfsRequest::Flush { path } => path
+    };
+    if !files.contains_key(path) {
+        files.insert(path.clone(), SyncFile::open_path(path).await?);
+    }
+    let file = &files[path];
+    Ok(match request {
+        OpfsRequest::Read { at, len, .. } => {
+            let mut buf = vec![0; *len as usize];
+            let read = file.read_at(&mut buf, *at)?;
+            buf.truncate(read);
+            OpfsResponse::Data(buf)
+        }
+        OpfsRequest::Write { at, data, .. } => {
+            file.write_at(data, *at)?;
+            OpfsResponse::Done
+        }
+        OpfsRequest::Size { .. } => OpfsResponse::Size(file.size()?),
+        OpfsRequest::Truncate { size, .. } => {
+            file.truncate(*size)?;
+            OpfsResponse::Done
+        }
+        OpfsRequest::Flush { .. } => {
+            file.flush()?;
+            OpfsResponse::Done
+        }
+        OpfsRequest::Close { .. } => unreachable!()
+    })
+}
+
+/// A worker serving [`Opfs`] requests, for synchronous file access from the main thread.
+///
+/// Requests are handled one at a time, in order, so a read sees every write made before it.
+pub struct SyncWorker<C = Bincode>(ProtocolWorker<Opfs, C>);
+
+impl<C: Codec> SyncWorker<C> {
+    /// Spawns a new worker running the entry point `E`, which calls [`serve`].
+    pub async fn new<E>(script: impl Into<Script>) -> Result<Self, GeneralError>
+    where
+        E: ProtocolEntry<Args = (), Protocol = Opfs, Codec = C>
+    {
+        Ok(SyncWorker(ProtocolWorker::new::<E>(script, &()).await?))
+    }
+
+    /// Reads up to `len` bytes from `at`, which is fewer at the end of the file.
+    pub async fn read_at(&self, path: &str, at: u64, len: u32) -> Result<Vec<u8>, FsError> {
+        match self.call(OpfsRequest::Read { path: path.to_owned(), at, len }).await? {
+            OpfsResponse::Data(data) => Ok(data),
+            _ => Err(unexpected())
+        }
+    }
+
+    pub async fn write_at(&self, path: &str, at: u64, data: &[u8]) -> Result<(), FsError> {
+        let request = OpfsRequest::Write { path: path.to_owned(), at, data: data.to_vec() };
+        self.call(request).await?;
+        Ok(())
+    }
+
+    pub async fn size(&self, path: &str) -> Result<u64, FsError> {
+        match self.call(OpfsRequest::Size { path: path.to_owned() }).await? {
+            OpfsResponse::Size(size) => Ok(size),
+            _ => Err(unexpected())
+        }
+    }
+
+    pub async fn truncate(&self, path: &str, size: u64) -> Result<(), FsError> {
+        self.call(OpfsRequest::Truncate { path: path.to_owned(), size }).await?;
+        Ok(())
+    }
+
+    pub async fn flush(&self, path: &str) -> Result<(), FsError> {
+        self.call(OpfsRequest::Flush { path: path.to_owned() }).await?;
+        Ok(())
+    }
+
+    /// Closes the file, unlocking it for others.
+    pub async fn close(&self, path: &str) -> Result<(), FsError> {
+        self.call(OpfsRequest::Close { path: path.to_owned() }).await?;
+        Ok(())
+    }
+
+    /// The underlying worker.
+    pub fn worker(&self) -> &ProtocolWorker<Opfs, C> {
+        &self.0
+    }
+
+    async fn call(&self, request: OpfsRequest) -> Result<OpfsResponse, FsError> {
+        match self.0.call(&request).await {
+            Ok(response) => Ok(response?),
+            Err(e) => Err(FsError::Other(js_sys::Error::new(&format!("{:?}", e)).into()))
+        }
+    }
+}
+
+fn unexpected() -> FsError {
+    FsError::Other(js_sys::Error::new("unexpected response from storage worker").into())
+}
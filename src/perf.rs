@@ -0,0 +1,100 @@
+use crate::prelude::*;
+use crate::global;
+use std::cell::Cell;
+use std::time::Duration;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = mark, catch)]
+    fn performance_mark(name: &str) -> Result<(), JsValue>;
+    #[wasm_bindgen(js_namespace = performance, js_name = measure, catch)]
+    fn performance_measure(name: &str, start_mark: &str) -> Result<(), JsValue>;
+    #[wasm_bindgen(js_namespace = performance, js_name = clearMarks)]
+    fn performance_clear_marks(name: &str);
+}
+
+/// A point in time measured with [`global::now`], like `std::time::Instant`, which isn't
+/// available in the browser.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct PerfInstant(f64);
+
+impl PerfInstant {
+    pub fn now() -> Self {
+        PerfInstant(global::now())
+    }
+
+    /// The instant `ms` milliseconds after the page or worker started, such as an event's
+    /// timestamp.
+    pub fn from_millis_f64(ms: f64) -> Self {
+        PerfInstant(ms)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    /// Time passed since `earlier`, or zero if `earlier` is later than `self`.
+    pub fn duration_since(&self, earlier: PerfInstant) -> Duration {
+        Duration::from_secs_f64((self.0 - earlier.0).max(0.0) / 1000.0)
+    }
+
+    /// Milliseconds since the page or worker started.
+    pub fn as_millis_f64(&self) -> f64 {
+        self.0
+    }
+}
+
+impl std::ops::Add<Duration> for PerfInstant {
+    type Output = PerfInstant;
+    fn add(self, rhs: Duration) -> PerfInstant {
+        PerfInstant(self.0 + rhs.as_secs_f64() * 1000.0)
+    }
+}
+
+/// Adds a User Timing mark, which shows up in the performance timeline of the devtools.
+pub fn mark(name: &str) {
+    let _ = performance_mark(name);
+}
+
+/// Adds a User Timing measure spanning from the mark `start_mark` to now.
+pub fn measure(name: &str, start_mark: &str) {
+    let _ = performance_measure(name, start_mark);
+}
+
+thread_local! {
+    static NEXT_SCOPE: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Measures the time until the returned guard is dropped as the User Timing measure `name`.
+/// ```ignore
+/// {
+///     let _scope = perf::scope("physics");
+///     world.step();
+/// }
+/// ```
+pub fn scope(name: &str) -> Scope {
+    let id = NEXT_SCOPE.with(|n| n.replace(n.get().wrapping_add(1)));
+    let start_mark = format!("{} start #{}", name, id);
+    mark(&start_mark);
+    Scope { name: name.to_owned(), start_mark, start: PerfInstant::now() }
+}
+
+/// Guard returned by [`scope`].
+pub struct Scope {
+    name: String,
+    start_mark: String,
+    start: PerfInstant
+}
+
+impl Scope {
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        measure(&self.name, &self.start_mark);
+        performance_clear_marks(&self.start_mark);
+    }
+}
@@ -0,0 +1,146 @@
+//! Whether the user has allowed the page to use features such as the camera or notifications,
+//! from the Permissions API.
+//!
+//! Querying doesn't ask the user. Permissions are asked for by using the feature, such as with
+//! [`notify::request_permission`](crate::notify::request_permission).
+//!
+//! ```ignore
+//! let changes = permissions::changes(PermissionName::Camera).await?;
+//! loop {
+//!     camera_button.set_hidden(changes.state() == PermissionState::Denied);
+//!     changes.next().await;
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::global;
+use crate::event::{ self, EventStream };
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Errors from querying permissions.
+#[derive(Debug)]
+pub enum PermissionError {
+    /// The Permissions API isn't supported, or the browser doesn't know the permission.
+    Unavailable,
+    Other(JsValue)
+}
+
+impl From<JsValue> for PermissionError {
+    fn from(e: JsValue) -> Self {
+        // unknown permission names are rejected with a TypeError
+        if e.is_instance_of::<js_sys::TypeError>() {
+            return PermissionError::Unavailable;
+        }
+        PermissionError::Other(e)
+    }
+}
+
+impl From<PermissionError> for GeneralError {
+    fn from(e: PermissionError) -> Self {
+        match e {
+            PermissionError::Unavailable => GeneralError::message("permission is unavailable"),
+            PermissionError::Other(e) => GeneralError::WebSys(e)
+        }
+    }
+}
+
+/// A permission which can be queried. Browsers only support some of them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PermissionName {
+    Camera,
+    Microphone,
+    Geolocation,
+    Notifications,
+    Push,
+    PersistentStorage,
+    ClipboardRead,
+    ClipboardWrite,
+    Midi,
+    ScreenWakeLock
+}
+
+impl PermissionName {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PermissionName::Camera => "camera",
+            PermissionName::Microphone => "microphone",
+            PermissionName::Geolocation => "geolocation",
+            PermissionName::Notifications => "notifications",
+            PermissionName::Push => "push",
+            PermissionName::PersistentStorage => "persistent-storage",
+            PermissionName::ClipboardRead => "clipboard-read",
+            PermissionName::ClipboardWrite => "clipboard-write",
+            PermissionName::Midi => "midi",
+            PermissionName::ScreenWakeLock => "screen-wake-lock"
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PermissionState {
+    Granted,
+    Denied,
+    /// Using the feature will ask the user.
+    Prompt
+}
+
+impl From<web_sys::PermissionState> for PermissionState {
+    fn from(state: web_sys::PermissionState) -> Self {
+        match state {
+            web_sys::PermissionState::Granted => PermissionState::Granted,
+            web_sys::PermissionState::Prompt => PermissionState::Prompt,
+            _ => PermissionState::Denied
+        }
+    }
+}
+
+/// Whether the permission is granted.
+pub async fn query(name: PermissionName) -> Result<PermissionState, PermissionError> {
+    Ok(status(name).await?.state().into())
+}
+
+/// Watches for the permission changing, such as when the user revokes it in the browser's
+/// settings.
+pub async fn changes(name: PermissionName) -> Result<PermissionChanges, PermissionError> {
+    let status = status(name).await?;
+    let events = status.on::<event::Change>();
+    Ok(PermissionChanges { status, events })
+}
+
+async fn status(name: PermissionName) -> Result<web_sys::PermissionStatus, PermissionError> {
+    let permissions: web_sys::Permissions = global::navigator_feature("permissions")
+        .ok_or(PermissionError::Unavailable)?;
+
+    let descriptor = js_sys::Object::new();
+    js_sys::Reflect::set(&descriptor, &"name".into(), &name.as_str().into())?;
+    if name == PermissionName::Push {
+        // browsers only support push subscriptions which show notifications
+        js_sys::Reflect::set(&descriptor, &"userVisibleOnly".into(), &true.into())?;
+    }
+    let status = JsFuture::from(permissions.query(&descriptor)?).await?;
+    Ok(status.unchecked_into())
+}
+
+/// Changes to a permission, from [`changes`]. Watching stops when this is dropped.
+pub struct PermissionChanges {
+    status: web_sys::PermissionStatus,
+    events: EventStream<event::Change>
+}
+
+impl PermissionChanges {
+    /// The current state of the permission.
+    pub fn state(&self) -> PermissionState {
+        self.status.state().into()
+    }
+
+    pub fn try_next(&self) -> Option<PermissionState> {
+        self.events.try_next().map(|_| self.state())
+    }
+
+    /// Waits for the permission to change, returning the new state.
+    pub async fn next(&self) -> PermissionState {
+        self.events.next().await;
+        self.state()
+    }
+}
@@ -0,0 +1,39 @@
+//! Conversions between JS promises and Rust futures, for the web APIs this crate doesn't wrap.
+
+use crate::prelude::*;
+use serde::{ Serialize, de::DeserializeOwned };
+use std::future::Future;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Waits for `promise` and deserializes the value it resolves to. Rejections are returned as
+/// [`GeneralError::WebSys`].
+pub async fn to_future<T: DeserializeOwned>(promise: js_sys::Promise) -> Result<T, GeneralError> {
+    let v = JsFuture::from(promise).await?;
+    Ok(serde_wasm_bindgen::from_value(v)?)
+}
+
+/// Waits for `promise` and casts the value it resolves to, for promises resolving to JS objects
+/// such as a `web_sys::Response`. Fails with the value itself if it isn't a `T`.
+pub async fn to_future_cast<T: JsCast>(promise: js_sys::Promise) -> Result<T, GeneralError> {
+    let v = JsFuture::from(promise).await?;
+    Ok(v.dyn_into()?)
+}
+
+/// Waits for the promise returned by a web API call which can also fail before returning it,
+/// as web-sys binds most promise-returning methods.
+pub(crate) async fn done(promise: Result<js_sys::Promise, JsValue>) -> Result<JsValue, JsValue> {
+    JsFuture::from(promise?).await
+}
+
+/// Converts `f` into a promise for JS code. The output is serialized with maps as plain
+/// objects. Errors reject the promise, with JS errors passed through as they are.
+pub fn future_to_promise<T: Serialize>(
+    f: impl Future<Output = Result<T, GeneralError>> + 'static
+) -> js_sys::Promise {
+    wasm_bindgen_futures::future_to_promise(async move {
+        let v = f.await?;
+        let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+        Ok(v.serialize(&serializer).map_err(GeneralError::from)?)
+    })
+}
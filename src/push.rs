@@ -0,0 +1,233 @@
+//! Push messages sent from a server through the browser's push service, delivered to the
+//! page's service worker even when the page isn't open.
+//!
+//! The page subscribes and sends the subscription to the server:
+//! ```ignore
+//! let subscription = push::subscribe(VAPID_PUBLIC_KEY).await?;
+//! http::post("/push/subscribe").body_json(&subscription.info()?).send().await?;
+//! ```
+//!
+//! And the service worker receives the messages, which browsers expect it to show a
+//! notification for:
+//! ```ignore
+//! let messages = push::messages::<Message>()?;
+//! loop {
+//!     let message = messages.next().await;
+//!     if let Ok(Message::YourTurn { game }) = &message.data {
+//!         Notification::builder("Your turn").tag(game).show().await.ok();
+//!     }
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, channel };
+use crate::event::{ self, ListenerHandle };
+use crate::global;
+use serde::{ Deserialize, Serialize, de::DeserializeOwned };
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// Errors from subscribing to push messages.
+#[derive(Debug)]
+pub enum PushError {
+    /// Push messages aren't supported, or there is no service worker to receive them.
+    Unavailable,
+    /// The user hasn't allowed notifications, which push messages need.
+    PermissionDenied,
+    Other(JsValue)
+}
+
+impl From<JsValue> for PushError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotAllowedError") => PushError::PermissionDenied,
+            Some("NotSupportedError") => PushError::Unavailable,
+            _ => PushError::Other(e)
+        }
+    }
+}
+
+impl From<PushError> for GeneralError {
+    fn from(e: PushError) -> Self {
+        match e {
+            PushError::Unavailable => GeneralError::message("push messages are unavailable"),
+            PushError::PermissionDenied => GeneralError::message("push permission denied"),
+            PushError::Other(e) => GeneralError::WebSys(e)
+        }
+    }
+}
+
+/// Subscribes to push messages from the server with the VAPID public key `vapid_key`, given
+/// in base64url as usual. Returns the existing subscription if there is one with the same key.
+///
+/// This asks for permission to show notifications if it hasn't been decided yet, since every
+/// push message is expected to show one.
+pub async fn subscribe(vapid_key: &str) -> Result<Subscription, PushError> {
+    let mut key = decode_base64url(vapid_key)
+        .ok_or_else(|| PushError::Other(js_sys::Error::new("invalid VAPID key").into()))?;
+    let options = web_sys::PushSubscriptionOptionsInit::new();
+    options.set_application_server_key_opt_u8_slice(Some(&mut key));
+    options.set_user_visible_only(true);
+    let manager = push_manager().await?;
+    let subscription = JsFuture::from(manager.subscribe_with_options(&options)?).await?;
+    Ok(Subscription(subscription.unchecked_into()))
+}
+
+/// The current subscription, if there is one.
+pub async fn subscription() -> Result<Option<Subscription>, PushError> {
+    let manager = push_manager().await?;
+    let subscription = JsFuture::from(manager.get_subscription()?).await?;
+    Ok(subscription.dyn_into().ok().map(Subscription))
+}
+
+async fn push_manager() -> Result<web_sys::PushManager, PushError> {
+    let registration = global::service_worker_registration().await?
+        .ok_or(PushError::Unavailable)?;
+    if !js_sys::Reflect::has(&registration, &"pushManager".into())? {
+        return Err(PushError::Unavailable);
+    }
+    Ok(registration.push_manager()?)
+}
+
+/// A subscription to push messages.
+#[derive(Clone, Debug)]
+pub struct Subscription(web_sys::PushSubscription);
+
+impl Subscription {
+    /// The URL of the push service the server sends messages to.
+    pub fn endpoint(&self) -> String {
+        self.0.endpoint()
+    }
+
+    /// What the server needs to send messages to the subscription, which serializes like the
+    /// JS `toJSON()` representation understood by most push libraries.
+    pub fn info(&self) -> Result<SubscriptionInfo, PushError> {
+        let json = self.0.to_json()?;
+        serde_wasm_bindgen::from_value(json.into())
+            .map_err(|e| PushError::Other(e.into()))
+    }
+
+    /// Stops the subscription. The server should also forget it.
+    pub async fn unsubscribe(&self) -> Result<(), PushError> {
+        JsFuture::from(self.0.unsubscribe()?).await?;
+        Ok(())
+    }
+
+    /// The underlying subscription.
+    pub fn raw(&self) -> &web_sys::PushSubscription {
+        &self.0
+    }
+}
+
+/// The parts of a [`Subscription`] the server needs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionInfo {
+    pub endpoint: String,
+    /// When the subscription expires, in milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub expiration_time: Option<f64>,
+    pub keys: SubscriptionKeys
+}
+
+/// The keys messages to a subscription are encrypted with, in base64url.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SubscriptionKeys {
+    pub p256dh: String,
+    pub auth: String
+}
+
+/// Push messages received by a service worker, with JSON data deserialized as `T`.
+///
+/// Messages are only received while this exists, and it must be created while the service
+/// worker's script is first run, since browsers ignore listeners added later.
+pub struct PushMessages<T> {
+    messages: Receiver<PushMessage<T>>,
+    _listener: ListenerHandle
+}
+
+/// Starts receiving push messages. This only works in service workers.
+pub fn messages<T: DeserializeOwned + 'static>() -> Result<PushMessages<T>, PushError> {
+    let scope = js_sys::global().dyn_into::<web_sys::ServiceWorkerGlobalScope>()
+        .map_err(|_| PushError::Unavailable)?;
+    let (sender, messages) = channel();
+    let listener = scope.add_event_listener(move |e: event::Push| {
+        let data = match e.data() {
+            Some(data) => serde_json::from_str(&data.text()).map_err(GeneralError::from),
+            None => Err(GeneralError::message("push message has no data"))
+        };
+        // the browser may stop the service worker once all the promises passed here finish
+        let mut done = None;
+        let handled = js_sys::Promise::new(&mut |resolve, _| done = Some(resolve));
+        let _ = e.wait_until(&handled);
+        let _ = sender.send(PushMessage { data, done: done.unwrap() });
+    });
+    Ok(PushMessages { messages, _listener: listener })
+}
+
+impl<T> PushMessages<T> {
+    pub fn try_next(&self) -> Option<PushMessage<T>> {
+        self.messages.try_recv().ok()
+    }
+
+    pub async fn next(&self) -> PushMessage<T> {
+        self.messages.recv().await.unwrap()
+    }
+}
+
+/// A push message. The service worker is kept running until this is dropped, so it should be
+/// kept while handling the message, such as while showing a notification.
+pub struct PushMessage<T> {
+    /// The message's data, or why it couldn't be deserialized.
+    pub data: Result<T, GeneralError>,
+    done: js_sys::Function
+}
+
+impl<T> Drop for PushMessage<T> {
+    fn drop(&mut self) {
+        let _ = self.done.call0(&JsValue::UNDEFINED);
+    }
+}
+
+fn decode_base64url(s: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(s.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in s.bytes().take_while(|&c| c != b'=') {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' | b'+' => 62,
+            b'_' | b'/' => 63,
+            _ => return None
+        };
+        buffer = buffer << 6 | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_base64url_alphabets() {
+        assert_eq!(decode_base64url("").unwrap(), b"");
+        assert_eq!(decode_base64url("aGVsbG8").unwrap(), b"hello");
+        assert_eq!(decode_base64url("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(decode_base64url("-_8").unwrap(), [0xFB, 0xFF]);
+        assert_eq!(decode_base64url("+/8=").unwrap(), [0xFB, 0xFF]);
+    }
+
+    #[test]
+    fn decode_base64url_rejects_invalid_characters() {
+        assert_eq!(decode_base64url("aGV*bG8"), None);
+        assert_eq!(decode_base64url("aGV sbG8"), None);
+    }
+}
@@ -0,0 +1,305 @@
+//! Client-side routing using the History API.
+//!
+//! Routes are matched against the path with patterns like `/room/:id`, where `:name`
+//! captures a segment and `*name` captures the rest of the path. Captures are deserialized
+//! like query parameters, into a struct with a field for each.
+//!
+//! ```ignore
+//! #[derive(Clone, PartialEq)]
+//! enum Page { Lobby, Room(RoomParams) }
+//!
+//! #[derive(Clone, PartialEq, Serialize, Deserialize)]
+//! struct RoomParams { id: u32 }
+//!
+//! impl Route for Page {
+//!     fn routes() -> Routes<Self> {
+//!         Routes::new()
+//!             .route("/", |()| Page::Lobby)
+//!             .route("/room/:id", Page::Room)
+//!     }
+//!
+//!     fn to_path(&self) -> String {
+//!         match self {
+//!             Page::Lobby => "/".to_owned(),
+//!             Page::Room(params) => Pattern::new("/room/:id").fill(params).unwrap()
+//!         }
+//!     }
+//! }
+//!
+//! let router = Router::<Page>::new()?;
+//! // or, for static hosts that can't serve the app at every path:
+//! let router = Router::<Page>::with_mode(Mode::Hash)?;
+//! let page = router.current();
+//! router.navigate(&Page::Room(RoomParams { id: 3 }));
+//! while page.changed().await.is_some() {
+//!     render(page.get());
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ WatchReceiver, WatchSender, watch };
+use crate::event::{ self, ListenerHandle };
+use crate::global::GlobalScope;
+use crate::url::{ self, UrlError };
+use serde::{ Serialize, de::DeserializeOwned };
+use std::rc::Rc;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Segment {
+    Literal(String),
+    Capture(String),
+    Rest(String)
+}
+
+/// A path pattern, such as `/room/:id` or `/files/*path`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pattern(Vec<Segment>);
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        Pattern(segments(pattern).map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                Segment::Capture(name.to_owned())
+            } else if let Some(name) = segment.strip_prefix('*') {
+                Segment::Rest(name.to_owned())
+            } else {
+                Segment::Literal(segment.to_owned())
+            }
+        }).collect())
+    }
+
+    /// Matches `path`, returning the captures if it matches.
+    ///
+    /// Empty segments are ignored, so `/room/3/` matches `/room/:id`.
+    pub fn captures(&self, path: &str) -> Option<Vec<(String, String)>> {
+        let mut path = segments(path);
+        let mut captures = vec![];
+        for segment in &self.0 {
+            match segment {
+                Segment::Literal(literal) => if decode(path.next()?) != *literal {
+                    return None;
+                },
+                Segment::Capture(name) => captures.push((name.clone(), decode(path.next()?))),
+                Segment::Rest(name) => {
+                    let rest = path.by_ref().map(decode).collect::<Vec<_>>().join("/");
+                    captures.push((name.clone(), rest));
+                }
+            }
+        }
+        match path.next() {
+            Some(_) => None,
+            None => Some(captures)
+        }
+    }
+
+    /// Matches `path`, deserializing the captures if it matches.
+    pub fn matches<P: DeserializeOwned>(&self, path: &str) -> Option<Result<P, UrlError>> {
+        self.captures(path).map(url::from_pairs)
+    }
+
+    /// Builds a path by filling in the captures from the fields of `params`.
+    pub fn fill<P: Serialize + ?Sized>(&self, params: &P) -> Result<String, UrlError> {
+        let params = url::to_pairs(params)?;
+        let param = |name: &str| params.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+            .ok_or_else(|| UrlError::Query(format!("missing route parameter {}", name)));
+        let mut path = String::new();
+        for segment in &self.0 {
+            path.push('/');
+            match segment {
+                Segment::Literal(literal) => path.push_str(&encode(literal)),
+                Segment::Capture(name) => path.push_str(&encode(param(name)?)),
+                Segment::Rest(name) => {
+                    let rest = segments(param(name)?).map(encode).collect::<Vec<_>>();
+                    path.push_str(&rest.join("/"));
+                }
+            }
+        }
+        if path.is_empty() {
+            path.push('/');
+        }
+        Ok(path)
+    }
+}
+
+type Matcher<R> = Box<dyn Fn(&str) -> Option<R>>;
+
+/// The routes of an app, tried in the order they were added.
+pub struct Routes<R> {
+    routes: Vec<Matcher<R>>
+}
+
+impl<R: 'static> Routes<R> {
+    pub fn new() -> Self {
+        Routes { routes: vec![] }
+    }
+
+    /// Adds a route matching `pattern`, built from its captures. Paths where the captures
+    /// can't be deserialized into `P` don't match.
+    pub fn route<P: DeserializeOwned>(
+        mut self, pattern: &str, f: impl Fn(P) -> R + 'static
+    ) -> Self {
+        let pattern = Pattern::new(pattern);
+        self.routes.push(Box::new(move |path| pattern.matches(path)?.ok().map(&f)));
+        self
+    }
+
+    /// The route matching `path`, if any do.
+    pub fn recognize(&self, path: &str) -> Option<R> {
+        self.routes.iter().find_map(|route| route(path))
+    }
+}
+
+impl<R: 'static> Default for Routes<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A type with a variant for each page of an app.
+pub trait Route: Clone + 'static {
+    fn routes() -> Routes<Self>;
+
+    /// The path of the route, which may include a query string and fragment.
+    fn to_path(&self) -> String;
+}
+
+/// Where a [`Router`] keeps the route in the URL.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    /// In the path, like `/room/3`. The server must serve the app at every route's path.
+    History,
+    /// In the fragment, like `/#/room/3`, so the app is only ever loaded from one path.
+    Hash
+}
+
+impl Mode {
+    fn path(self, location: &web_sys::Location) -> Option<String> {
+        match self {
+            Mode::History => location.pathname().ok(),
+            Mode::Hash => {
+                let hash = location.hash().ok()?;
+                let hash = hash.strip_prefix('#').unwrap_or(&hash);
+                // the route's own query string stays in the fragment
+                Some(hash.split('?').next().unwrap().to_owned())
+            }
+        }
+    }
+
+    fn url(self, path: &str) -> String {
+        match self {
+            Mode::History => path.to_owned(),
+            Mode::Hash => format!("#{}", path)
+        }
+    }
+}
+
+/// Tracks the route of the page, updating it as the user navigates through history.
+///
+/// The current route is `None` when no route matches the path.
+pub struct Router<R: Route> {
+    mode: Mode,
+    history: web_sys::History,
+    location: web_sys::Location,
+    routes: Rc<Routes<R>>,
+    current: Rc<WatchSender<Option<R>>>,
+    _listener: ListenerHandle
+}
+
+impl<R: Route> Router<R> {
+    /// Recognizes the route of the page from its path, and starts following history
+    /// navigation. Routing is only available in windows.
+    pub fn new() -> Result<Self, GeneralError> {
+        Self::with_mode(Mode::History)
+    }
+
+    /// Like [`new`](Self::new), keeping the route where `mode` says.
+    pub fn with_mode(mode: Mode) -> Result<Self, GeneralError> {
+        let window = match GlobalScope::current() {
+            GlobalScope::Window(window) => window,
+            _ => return Err(GeneralError::message("routing is only available in windows"))
+        };
+        let history = window.history()?;
+        let location = window.location();
+        let routes = Rc::new(R::routes());
+        let (current, _) = watch(mode.path(&location).and_then(|path| routes.recognize(&path)));
+        let current = Rc::new(current);
+
+        let update = {
+            let routes = routes.clone();
+            let current = current.clone();
+            let location = location.clone();
+            move || if let Some(path) = mode.path(&location) {
+                current.set(routes.recognize(&path));
+            }
+        };
+        // editing the fragment by hand doesn't always add a history entry
+        let listener = match mode {
+            Mode::History => window.add_event_listener(move |_: event::PopState| update()),
+            Mode::Hash => window.add_event_listener(move |_: event::HashChange| update())
+        };
+        Ok(Router { mode, history, location, routes, current, _listener: listener })
+    }
+
+    /// The current route, which changes as the user navigates.
+    pub fn current(&self) -> WatchReceiver<Option<R>> {
+        self.current.subscribe()
+    }
+
+    /// Navigates to `route`, adding a history entry.
+    pub fn navigate(&self, route: &R) -> Result<(), GeneralError> {
+        let url = self.mode.url(&route.to_path());
+        self.history.push_state_with_url(&JsValue::NULL, "", Some(&url))?;
+        self.update();
+        Ok(())
+    }
+
+    /// Navigates to `route`, replacing the current history entry.
+    pub fn replace(&self, route: &R) -> Result<(), GeneralError> {
+        let url = self.mode.url(&route.to_path());
+        self.history.replace_state_with_url(&JsValue::NULL, "", Some(&url))?;
+        self.update();
+        Ok(())
+    }
+
+    /// Goes back a history entry. The route changes once the browser has navigated.
+    pub fn back(&self) -> Result<(), GeneralError> {
+        self.history.back()?;
+        Ok(())
+    }
+
+    pub fn forward(&self) -> Result<(), GeneralError> {
+        self.history.forward()?;
+        Ok(())
+    }
+
+    /// The route a path is recognized as, such as for handling links.
+    pub fn recognize(&self, path: &str) -> Option<R> {
+        self.routes.recognize(path)
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Recognizes the route from the path the history entry now has. The path is taken as
+    /// the browser normalized it, so relative paths work too.
+    fn update(&self) {
+        if let Some(path) = self.mode.path(&self.location) {
+            self.current.set(self.routes.recognize(&path));
+        }
+    }
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn encode(segment: &str) -> String {
+    js_sys::encode_uri_component(segment).into()
+}
+
+fn decode(segment: &str) -> String {
+    js_sys::decode_uri_component(segment).map(String::from).unwrap_or_else(|_| segment.to_owned())
+}
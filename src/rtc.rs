@@ -0,0 +1,360 @@
+//! Peer-to-peer connections with typed data channels.
+//!
+//! Connecting two peers takes a signaling path between them, usually a server both are
+//! connected to, which [`Signal`]s are forwarded over. One peer creates a data channel and an
+//! offer, the other answers it, and both pass on their ICE candidates as they are found.
+//!
+//! ```ignore
+//! let peer = Peer::new(&["stun:stun.l.google.com:19302"])?;
+//! let channel = peer.channel::<Move, Move, Json>("moves");
+//! server.send(&Signal::Offer(peer.create_offer().await?))?;
+//! // meanwhile, forward `peer.next_candidate()`s to the server and pass everything received
+//! // from it to `peer.handle_signal`
+//! channel.opened().await?;
+//! channel.send(&Move { .. })?;
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, Sender, TryRecvError, channel };
+use crate::codec::{ Codec, Json };
+use crate::event::{ self, ListenerHandle };
+use crate::task::WebFutureExt;
+use crate::ws::{ self, MessageError, MessageState };
+use serde::{ Serialize, Deserialize, de::DeserializeOwned };
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+/// A message to be delivered to the remote peer over the signaling path.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Signal {
+    Offer(String),
+    Answer(String),
+    Candidate(IceCandidate)
+}
+
+/// A network address at which a peer may be reachable.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IceCandidate {
+    pub candidate: String,
+    pub sdp_mid: Option<String>,
+    pub sdp_m_line_index: Option<u16>
+}
+
+/// A connection to a remote peer. Dropping it closes the connection.
+pub struct Peer {
+    connection: web_sys::RtcPeerConnection,
+    candidates: Receiver<IceCandidate>,
+    channels: Receiver<web_sys::RtcDataChannel>,
+    channel_sender: ChannelSender,
+    _listeners: Vec<ListenerHandle>
+}
+
+type ChannelSender = Rc<RefCell<Option<Sender<web_sys::RtcDataChannel>>>>;
+
+impl Peer {
+    /// Creates a connection using the STUN or TURN servers at `ice_servers`.
+    pub fn new(ice_servers: &[&str]) -> Result<Self, GeneralError> {
+        let urls: js_sys::Array = ice_servers.iter().map(|&url| JsValue::from(url)).collect();
+        let server = web_sys::RtcIceServer::new();
+        server.set_urls(&urls);
+        let config = web_sys::RtcConfiguration::new();
+        config.set_ice_servers(&js_sys::Array::of1(&server));
+        Self::with_config(&config)
+    }
+
+    /// Creates a connection with the given configuration, for TURN credentials and such.
+    pub fn with_config(config: &web_sys::RtcConfiguration) -> Result<Self, GeneralError> {
+        let connection = web_sys::RtcPeerConnection::new_with_configuration(config)?;
+
+        let (sender, candidates) = channel();
+        let sender = RefCell::new(Some(sender));
+        let on_candidate = connection.add_event_listener(move |e: event::IceCandidate| {
+            match e.candidate() {
+                Some(c) => if let Some(sender) = &*sender.borrow() {
+                    let _ = sender.send(IceCandidate {
+                        candidate: c.candidate(),
+                        sdp_mid: c.sdp_mid(),
+                        sdp_m_line_index: c.sdp_m_line_index()
+                    });
+                },
+                // gathering is complete
+                None => *sender.borrow_mut() = None
+            }
+        });
+        let (sender, channels) = channel();
+        let channel_sender: ChannelSender = Rc::new(RefCell::new(Some(sender)));
+        let sender = channel_sender.clone();
+        let on_channel = connection.add_event_listener(move |e: event::DataChannel| {
+            if let Some(sender) = &*sender.borrow() {
+                let _ = sender.send(e.channel());
+            }
+        });
+        // no more channels can arrive once the connection is over
+        let sender = channel_sender.clone();
+        let conn = connection.clone();
+        let on_state = connection.add_event_listener(move |_: event::ConnectionStateChange| {
+            match conn.connection_state() {
+                web_sys::RtcPeerConnectionState::Closed
+                | web_sys::RtcPeerConnectionState::Failed => *sender.borrow_mut() = None,
+                _ => {}
+            }
+        });
+
+        Ok(Peer {
+            connection, candidates, channels, channel_sender,
+            _listeners: vec![on_candidate, on_channel, on_state]
+        })
+    }
+
+    /// Creates an offer to send to the remote peer. Create the data channels first, since
+    /// the offer describes them.
+    pub async fn create_offer(&self) -> Result<String, GeneralError> {
+        let offer = JsFuture::from(self.connection.create_offer()).await?;
+        self.set_local(offer).await
+    }
+
+    /// Accepts an offer from the remote peer, returning the answer to send back.
+    pub async fn accept_offer(&self, sdp: &str) -> Result<String, GeneralError> {
+        self.set_remote(web_sys::RtcSdpType::Offer, sdp).await?;
+        let answer = JsFuture::from(self.connection.create_answer()).await?;
+        self.set_local(answer).await
+    }
+
+    /// Accepts the remote peer's answer to an offer made with
+    /// [`create_offer`](Self::create_offer).
+    pub async fn accept_answer(&self, sdp: &str) -> Result<(), GeneralError> {
+        self.set_remote(web_sys::RtcSdpType::Answer, sdp).await
+    }
+
+    /// Adds an ICE candidate received from the remote peer.
+    pub async fn add_candidate(&self, candidate: &IceCandidate) -> Result<(), GeneralError> {
+        let init = web_sys::RtcIceCandidateInit::new(&candidate.candidate);
+        init.set_sdp_mid(candidate.sdp_mid.as_deref());
+        init.set_sdp_m_line_index(candidate.sdp_m_line_index);
+        let added = self.connection.add_ice_candidate_with_opt_rtc_ice_candidate_init(Some(&init));
+        JsFuture::from(added).await?;
+        Ok(())
+    }
+
+    /// Handles a signal from the remote peer, returning the answer to send back if it was an
+    /// offer.
+    pub async fn handle_signal(&self, signal: &Signal) -> Result<Option<Signal>, GeneralError> {
+        match signal {
+            Signal::Offer(sdp) => Ok(Some(Signal::Answer(self.accept_offer(sdp).await?))),
+            Signal::Answer(sdp) => self.accept_answer(sdp).await.map(|_| None),
+            Signal::Candidate(c) => self.add_candidate(c).await.map(|_| None)
+        }
+    }
+
+    /// Receives the next local ICE candidate to send to the remote peer, or `None` once all
+    /// have been found.
+    pub async fn next_candidate(&self) -> Option<IceCandidate> {
+        self.candidates.recv().await
+    }
+
+    /// Creates a data channel with the default options, which are reliable and ordered.
+    pub fn channel<O, I, C>(&self, label: &str) -> DataChannel<O, I, C>
+    where
+        O: Serialize,
+        I: DeserializeOwned + 'static,
+        C: Codec
+    {
+        DataChannel::new(self.connection.create_data_channel(label))
+    }
+
+    /// Creates a data channel with the given options, such as unordered delivery without
+    /// retransmits for state updates which are quickly outdated.
+    pub fn channel_with<O, I, C>(
+        &self, label: &str, options: &web_sys::RtcDataChannelInit
+    ) -> DataChannel<O, I, C>
+    where
+        O: Serialize,
+        I: DeserializeOwned + 'static,
+        C: Codec
+    {
+        DataChannel::new(self.connection.create_data_channel_with_data_channel_dict(label, options))
+    }
+
+    /// Waits for the remote peer to create a data channel. Channels created before this is
+    /// called are queued. Returns `None` once the connection is closed or has failed and the
+    /// queued channels have been accepted.
+    pub async fn accept_channel<O, I, C>(&self) -> Option<DataChannel<O, I, C>>
+    where
+        O: Serialize,
+        I: DeserializeOwned + 'static,
+        C: Codec
+    {
+        self.channels.recv().await.map(DataChannel::new)
+    }
+
+    pub fn state(&self) -> web_sys::RtcPeerConnectionState {
+        self.connection.connection_state()
+    }
+
+    pub fn close(&self) {
+        self.connection.close();
+        // closing it here doesn't fire a state change
+        *self.channel_sender.borrow_mut() = None;
+    }
+
+    /// The underlying connection.
+    pub fn raw(&self) -> &web_sys::RtcPeerConnection {
+        &self.connection
+    }
+
+    async fn set_local(&self, description: JsValue) -> Result<String, GeneralError> {
+        let description = description.unchecked_into();
+        JsFuture::from(self.connection.set_local_description(&description)).await?;
+        Ok(self.connection.local_description().map(|d| d.sdp()).unwrap_or_default())
+    }
+
+    async fn set_remote(&self, kind: web_sys::RtcSdpType, sdp: &str) -> Result<(), GeneralError> {
+        let description = web_sys::RtcSessionDescriptionInit::new(kind);
+        description.set_sdp(sdp);
+        JsFuture::from(self.connection.set_remote_description(&description)).await?;
+        Ok(())
+    }
+}
+
+impl Drop for Peer {
+    fn drop(&mut self) {
+        self.connection.close();
+    }
+}
+
+/// A data channel sending `O`s and receiving `I`s, encoded like a [`Socket`]'s messages.
+///
+/// Dropping the channel closes it.
+///
+/// [`Socket`]: crate::ws::Socket
+pub struct DataChannel<O, I, C = Json> {
+    channel: web_sys::RtcDataChannel,
+    incoming: Receiver<Result<I, DataChannelError>>,
+    state: Rc<RefCell<State<I>>>,
+    _listeners: Vec<ListenerHandle>,
+    _phantom: PhantomData<fn(O, C)>
+}
+
+type State<I> = MessageState<I, DataChannelError, ()>;
+
+/// Errors reported by a [`DataChannel`] instead of a message.
+#[derive(Debug)]
+pub enum DataChannelError {
+    /// The channel failed. A [`Closed`](Self::Closed) follows.
+    Error,
+    /// A message could not be decoded.
+    Decode(GeneralError),
+    /// The channel is closed and there are no more messages.
+    Closed
+}
+
+impl MessageError for DataChannelError {
+    fn failed() -> Self {
+        DataChannelError::Error
+    }
+
+    fn decode(e: GeneralError) -> Self {
+        DataChannelError::Decode(e)
+    }
+}
+
+impl<O, I, C> DataChannel<O, I, C>
+where
+    O: Serialize,
+    I: DeserializeOwned + 'static,
+    C: Codec
+{
+    fn new(raw: web_sys::RtcDataChannel) -> Self {
+        raw.set_binary_type(web_sys::RtcDataChannelType::Arraybuffer);
+
+        let (sender, incoming) = channel();
+        let state = State::new(sender);
+        let listeners = ws::listen::<_, C, _, _>(&raw, &state, |_| ());
+        DataChannel { channel: raw, incoming, state, _listeners: listeners, _phantom: PhantomData }
+    }
+
+    /// Sends a message. Fails if the channel isn't open yet.
+    pub fn send(&self, v: &O) -> Result<(), GeneralError> {
+        let (msg, _) = C::encode(v)?;
+        ws::send_frame(
+            &msg,
+            |text| self.channel.send_with_str(text),
+            |bytes| self.channel.send_with_js_u8_array(bytes)
+        )
+    }
+
+    /// Receives a message if one is available.
+    pub fn try_recv(&self) -> Option<Result<I, DataChannelError>> {
+        match self.incoming.try_recv() {
+            Ok(v) => Some(v),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Closed) => Some(Err(DataChannelError::Closed))
+        }
+    }
+
+    pub async fn recv(&self) -> Result<I, DataChannelError> {
+        self.incoming.recv().await.unwrap_or(Err(DataChannelError::Closed))
+    }
+}
+
+impl<O, I, C> DataChannel<O, I, C> {
+    /// Waits until the channel is open, failing if it closes first.
+    pub async fn opened(&self) -> Result<(), GeneralError> {
+        let open = match self.channel.ready_state() {
+            web_sys::RtcDataChannelState::Open => true,
+            web_sys::RtcDataChannelState::Connecting => {
+                let opened = self.channel.once::<event::Open>();
+                let closed = self.channel.once::<event::Close>();
+                async { opened.await; true }.race(async { closed.await; false }).await
+            }
+            _ => false
+        };
+        if open {
+            Ok(())
+        } else {
+            let msg = format!("data channel {} closed before opening", self.channel.label());
+            Err(GeneralError::message(&msg))
+        }
+    }
+
+    /// Waits until the channel is closed.
+    pub async fn closed(&self) {
+        if !self.is_closed() {
+            self.channel.once::<event::Close>().await;
+        }
+    }
+
+    /// Whether the channel has closed. Messages received before that may still be waiting to
+    /// be received.
+    pub fn is_closed(&self) -> bool {
+        self.state.borrow().close.is_some()
+    }
+
+    pub fn label(&self) -> String {
+        self.channel.label()
+    }
+
+    /// Bytes queued by [`send`](DataChannel::send) but not yet transmitted, for applying
+    /// backpressure.
+    pub fn buffered_amount(&self) -> u32 {
+        self.channel.buffered_amount()
+    }
+
+    pub fn close(&self) {
+        self.channel.close();
+    }
+
+    /// The underlying channel.
+    pub fn raw(&self) -> &web_sys::RtcDataChannel {
+        &self.channel
+    }
+}
+
+impl<O, I, C> Drop for DataChannel<O, I, C> {
+    fn drop(&mut self) {
+        self.channel.close();
+    }
+}
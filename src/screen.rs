@@ -0,0 +1,199 @@
+//! The orientation of the screen, from the Screen Orientation API.
+//!
+//! ```ignore
+//! document.document_element().unwrap().request_fullscreen()?;
+//! match screen::lock(OrientationLock::Landscape).await {
+//!     Ok(()) => {}
+//!     // desktop browsers can't lock, but the window is usually landscape anyway
+//!     Err(ScreenError::Unavailable) => {}
+//!     Err(e) => return Err(e.into())
+//! }
+//!
+//! let orientation = screen::orientation()?;
+//! loop {
+//!     rotate_hint.set_hidden(orientation.get().is_landscape());
+//!     orientation.changed().await;
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ WatchReceiver, WatchSender, watch };
+use crate::event;
+use crate::global::GlobalScope;
+use std::cell::RefCell;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+thread_local! {
+    static ORIENTATION: RefCell<Option<WatchSender<Orientation>>> = const { RefCell::new(None) };
+}
+
+/// Errors from locking the orientation.
+#[derive(Debug)]
+pub enum ScreenError {
+    /// The orientation can't be locked, such as because this isn't a window, or on desktop
+    /// browsers.
+    Unavailable,
+    /// The browser didn't allow locking, usually because the page isn't fullscreen.
+    NotAllowed,
+    /// The lock was replaced by another call to [`lock`] or [`unlock`] before it took effect.
+    Interrupted,
+    Other(JsValue)
+}
+
+impl From<JsValue> for ScreenError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotSupportedError") => ScreenError::Unavailable,
+            Some("SecurityError") | Some("InvalidStateError") => ScreenError::NotAllowed,
+            Some("AbortError") => ScreenError::Interrupted,
+            _ => ScreenError::Other(e)
+        }
+    }
+}
+
+impl From<ScreenError> for GeneralError {
+    fn from(e: ScreenError) -> Self {
+        let msg = match e {
+            ScreenError::Unavailable => "orientation lock is unavailable",
+            ScreenError::NotAllowed => "orientation lock is not allowed",
+            ScreenError::Interrupted => "orientation lock was interrupted",
+            ScreenError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(msg)
+    }
+}
+
+/// Which way up the screen is. The primary orientations are the ones the device is usually
+/// held in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    PortraitPrimary,
+    PortraitSecondary,
+    LandscapePrimary,
+    LandscapeSecondary
+}
+
+impl Orientation {
+    pub fn is_portrait(self) -> bool {
+        matches!(self, Orientation::PortraitPrimary | Orientation::PortraitSecondary)
+    }
+
+    pub fn is_landscape(self) -> bool {
+        !self.is_portrait()
+    }
+}
+
+/// Orientations which [`lock`] can keep the screen in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OrientationLock {
+    /// Any orientation, while still stopping the user's setting from taking effect.
+    Any,
+    /// The device's natural orientation, such as portrait on phones.
+    Natural,
+    /// Either landscape orientation, turning with the device.
+    Landscape,
+    /// Either portrait orientation, turning with the device.
+    Portrait,
+    PortraitPrimary,
+    PortraitSecondary,
+    LandscapePrimary,
+    LandscapeSecondary
+}
+
+fn window() -> Option<web_sys::Window> {
+    match GlobalScope::current() {
+        GlobalScope::Window(window) => Some(window),
+        _ => None
+    }
+}
+
+fn screen_orientation(window: &web_sys::Window) -> Option<web_sys::ScreenOrientation> {
+    // Safari only added the API in version 16.4
+    let screen = window.screen().ok()?;
+    let orientation = js_sys::Reflect::get(&screen, &"orientation".into()).ok()?;
+    match orientation.is_undefined() {
+        true => None,
+        false => Some(orientation.unchecked_into())
+    }
+}
+
+fn current(window: &web_sys::Window) -> Orientation {
+    let kind = screen_orientation(window).and_then(|o| o.type_().ok());
+    match kind {
+        Some(web_sys::OrientationType::PortraitPrimary) => Orientation::PortraitPrimary,
+        Some(web_sys::OrientationType::PortraitSecondary) => Orientation::PortraitSecondary,
+        Some(web_sys::OrientationType::LandscapePrimary) => Orientation::LandscapePrimary,
+        Some(web_sys::OrientationType::LandscapeSecondary) => Orientation::LandscapeSecondary,
+        _ => {
+            let width = window.inner_width().ok().and_then(|w| w.as_f64()).unwrap_or(0.0);
+            let height = window.inner_height().ok().and_then(|h| h.as_f64()).unwrap_or(0.0);
+            match width > height {
+                true => Orientation::LandscapePrimary,
+                false => Orientation::PortraitPrimary
+            }
+        }
+    }
+}
+
+fn update() {
+    if let Some(window) = window() {
+        ORIENTATION.with(|o| if let Some(sender) = &*o.borrow() {
+            sender.set_if_changed(current(&window));
+        });
+    }
+}
+
+/// The orientation of the screen, which changes as the device is turned.
+///
+/// Where the API isn't supported, this is worked out from the shape of the window.
+pub fn orientation() -> Result<WatchReceiver<Orientation>, ScreenError> {
+    let window = window().ok_or(ScreenError::Unavailable)?;
+    Ok(ORIENTATION.with(|o| {
+        let mut o = o.borrow_mut();
+        let sender = o.get_or_insert_with(|| {
+            // the orientation is watched for as long as the page lives
+            match screen_orientation(&window) {
+                Some(orientation) => orientation.add_event_listener(|_: event::Change| update()),
+                None => window.add_event_listener(|_: event::Resize| update())
+            }.forget();
+            watch(current(&window)).0
+        });
+        sender.subscribe()
+    }))
+}
+
+/// The angle the screen is turned from the device's natural orientation, in degrees
+/// counterclockwise.
+pub fn angle() -> u16 {
+    window().and_then(|w| screen_orientation(&w)).and_then(|o| o.angle().ok()).unwrap_or(0)
+}
+
+/// Keeps the screen in `lock` until [`unlock`], turning it if needed.
+///
+/// Most browsers only allow this while the page is fullscreen, and the lock is lost when it
+/// leaves fullscreen.
+pub async fn lock(lock: OrientationLock) -> Result<(), ScreenError> {
+    let orientation = window()
+        .and_then(|w| screen_orientation(&w))
+        .ok_or(ScreenError::Unavailable)?;
+    let kind = match lock {
+        OrientationLock::Any => web_sys::OrientationLockType::Any,
+        OrientationLock::Natural => web_sys::OrientationLockType::Natural,
+        OrientationLock::Landscape => web_sys::OrientationLockType::Landscape,
+        OrientationLock::Portrait => web_sys::OrientationLockType::Portrait,
+        OrientationLock::PortraitPrimary => web_sys::OrientationLockType::PortraitPrimary,
+        OrientationLock::PortraitSecondary => web_sys::OrientationLockType::PortraitSecondary,
+        OrientationLock::LandscapePrimary => web_sys::OrientationLockType::LandscapePrimary,
+        OrientationLock::LandscapeSecondary => web_sys::OrientationLockType::LandscapeSecondary
+    };
+    JsFuture::from(orientation.lock(kind)?).await?;
+    Ok(())
+}
+
+/// Lets the screen turn with the device again.
+pub fn unlock() {
+    if let Some(orientation) = window().and_then(|w| screen_orientation(&w)) {
+        let _ = orientation.unlock();
+    }
+}
@@ -0,0 +1,472 @@
+//! Serial ports, from the Web Serial API.
+//!
+//! Only Chromium-based desktop browsers support Web Serial, and only for pages served
+//! securely. Ports are chosen by the user with [`request_port`], which needs to be called
+//! while handling a click or key press.
+//!
+//! ```ignore
+//! let port = serial::request_port(&[PortFilter::new().usb_vendor_id(0x2341)]).await?;
+//! let connection = port.open(&OpenOptions::new(115200)).await?;
+//! // pulse DTR to reset the board into its bootloader
+//! connection.set_signals(&OutputSignals { dtr: Some(false), ..Default::default() }).await?;
+//! connection.set_signals(&OutputSignals { dtr: Some(true), ..Default::default() }).await?;
+//! connection.write(b"version\n").await?;
+//! while let Some(bytes) = connection.read().await {
+//!     log.push(bytes?);
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::global;
+use crate::promise::done;
+use crate::channel::{ Receiver, Sender, channel };
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    // web-sys only binds Web Serial as unstable
+    #[wasm_bindgen(extends = web_sys::EventTarget)]
+    type Serial;
+    #[wasm_bindgen(method, catch, js_name = requestPort)]
+    fn request_port(this: &Serial, options: &JsValue) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = getPorts)]
+    fn get_ports(this: &Serial) -> Result<js_sys::Promise, JsValue>;
+
+    #[derive(Clone, Debug, PartialEq)]
+    #[wasm_bindgen(extends = web_sys::EventTarget, js_name = SerialPort)]
+    type RawPort;
+    #[wasm_bindgen(method, js_name = getInfo)]
+    fn get_info(this: &RawPort) -> JsValue;
+    #[wasm_bindgen(method, getter)]
+    fn readable(this: &RawPort) -> Option<web_sys::ReadableStream>;
+    #[wasm_bindgen(method, getter)]
+    fn writable(this: &RawPort) -> Option<web_sys::WritableStream>;
+    #[wasm_bindgen(method, catch)]
+    fn open(this: &RawPort, options: &JsValue) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    fn close(this: &RawPort) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    fn forget(this: &RawPort) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = setSignals)]
+    fn set_signals(this: &RawPort, signals: &JsValue) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = getSignals)]
+    fn get_signals(this: &RawPort) -> Result<js_sys::Promise, JsValue>;
+}
+
+/// Errors from using serial ports.
+#[derive(Debug)]
+pub enum SerialError {
+    /// Web Serial isn't supported, such as because the page isn't served securely.
+    Unavailable,
+    /// The user didn't choose a port.
+    NotFound,
+    /// The browser didn't allow using the port, such as because [`request_port`] wasn't called
+    /// while handling user input.
+    PermissionDenied,
+    /// The port is already open, or isn't open.
+    Busy,
+    /// The device was disconnected, or the port couldn't be opened, such as because another
+    /// program is using it.
+    Disconnected,
+    /// The other end held the line low for longer than a byte, to signal a break. Reading
+    /// continues afterwards.
+    Break,
+    /// A byte wasn't framed by a stop bit, usually because the baud rate is wrong. Reading
+    /// continues afterwards.
+    Framing,
+    /// A byte failed its parity check. Reading continues afterwards.
+    Parity,
+    /// Bytes arrived faster than they were read and some were lost. Reading continues
+    /// afterwards.
+    BufferOverrun,
+    Other(JsValue)
+}
+
+impl From<JsValue> for SerialError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotFoundError") => SerialError::NotFound,
+            Some("SecurityError") | Some("NotAllowedError") => SerialError::PermissionDenied,
+            Some("InvalidStateError") => SerialError::Busy,
+            Some("NetworkError") => SerialError::Disconnected,
+            Some("BreakError") => SerialError::Break,
+            Some("FramingError") => SerialError::Framing,
+            Some("ParityError") => SerialError::Parity,
+            Some("BufferOverrunError") => SerialError::BufferOverrun,
+            _ => SerialError::Other(e)
+        }
+    }
+}
+
+impl From<SerialError> for GeneralError {
+    fn from(e: SerialError) -> Self {
+        let msg = match e {
+            SerialError::Unavailable => "Web Serial is unavailable",
+            SerialError::NotFound => "no serial port chosen",
+            SerialError::PermissionDenied => "serial port permission denied",
+            SerialError::Busy => "serial port is busy",
+            SerialError::Disconnected => "serial port is disconnected",
+            SerialError::Break => "serial break received",
+            SerialError::Framing => "serial framing error",
+            SerialError::Parity => "serial parity error",
+            SerialError::BufferOverrun => "serial buffer overrun",
+            SerialError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(msg)
+    }
+}
+
+fn serial() -> Result<Serial, SerialError> {
+    global::navigator_feature("serial").ok_or(SerialError::Unavailable)
+}
+
+/// Which ports the user can choose from. A port matches if it matches everything set.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PortFilter {
+    usb_vendor_id: Option<u16>,
+    usb_product_id: Option<u16>
+}
+
+impl PortFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn usb_vendor_id(mut self, id: u16) -> Self {
+        self.usb_vendor_id = Some(id);
+        self
+    }
+
+    /// Matches the product ID, which is only allowed together with the vendor ID.
+    pub fn usb_product_id(mut self, id: u16) -> Self {
+        self.usb_product_id = Some(id);
+        self
+    }
+
+    fn to_js(self) -> Result<JsValue, JsValue> {
+        let filter = js_sys::Object::new();
+        if let Some(id) = self.usb_vendor_id {
+            js_sys::Reflect::set(&filter, &"usbVendorId".into(), &id.into())?;
+        }
+        if let Some(id) = self.usb_product_id {
+            js_sys::Reflect::set(&filter, &"usbProductId".into(), &id.into())?;
+        }
+        Ok(filter.into())
+    }
+}
+
+/// Asks the user to choose a port matching any of `filters`, or any port if there are none,
+/// allowing the page to use it.
+pub async fn request_port(filters: &[PortFilter]) -> Result<SerialPort, SerialError> {
+    let filters = filters.iter()
+        .map(|f| f.to_js())
+        .collect::<Result<js_sys::Array, _>>()?;
+    let options = js_sys::Object::new();
+    js_sys::Reflect::set(&options, &"filters".into(), &filters)?;
+    let port = done(serial()?.request_port(&options)).await?;
+    Ok(SerialPort(port.unchecked_into()))
+}
+
+/// The ports which the user has allowed the page to use before.
+pub async fn ports() -> Result<Vec<SerialPort>, SerialError> {
+    let ports = done(serial()?.get_ports()).await?;
+    Ok(js_sys::Array::from(&ports).iter().map(|p| SerialPort(p.unchecked_into())).collect())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Parity {
+    None,
+    Even,
+    Odd
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FlowControl {
+    None,
+    /// RTS/CTS flow control.
+    Hardware
+}
+
+/// How to talk to the other end of a port, which must match its settings.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OpenOptions {
+    baud_rate: u32,
+    data_bits: u8,
+    stop_bits: u8,
+    parity: Parity,
+    flow_control: FlowControl,
+    buffer_size: u32
+}
+
+impl OpenOptions {
+    /// 8 data bits, 1 stop bit, no parity and no flow control at `baud_rate`, the most common
+    /// settings.
+    pub fn new(baud_rate: u32) -> Self {
+        OpenOptions {
+            baud_rate,
+            data_bits: 8,
+            stop_bits: 1,
+            parity: Parity::None,
+            flow_control: FlowControl::None,
+            buffer_size: 255
+        }
+    }
+
+    /// 7 or 8.
+    pub fn data_bits(mut self, bits: u8) -> Self {
+        self.data_bits = bits;
+        self
+    }
+
+    /// 1 or 2.
+    pub fn stop_bits(mut self, bits: u8) -> Self {
+        self.stop_bits = bits;
+        self
+    }
+
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    pub fn flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.flow_control = flow_control;
+        self
+    }
+
+    /// How many bytes the browser buffers in each direction.
+    pub fn buffer_size(mut self, size: u32) -> Self {
+        self.buffer_size = size;
+        self
+    }
+
+    fn to_js(self) -> Result<JsValue, JsValue> {
+        let options = js_sys::Object::new();
+        let parity = match self.parity {
+            Parity::None => "none",
+            Parity::Even => "even",
+            Parity::Odd => "odd"
+        };
+        let flow_control = match self.flow_control {
+            FlowControl::None => "none",
+            FlowControl::Hardware => "hardware"
+        };
+        js_sys::Reflect::set(&options, &"baudRate".into(), &self.baud_rate.into())?;
+        js_sys::Reflect::set(&options, &"dataBits".into(), &self.data_bits.into())?;
+        js_sys::Reflect::set(&options, &"stopBits".into(), &self.stop_bits.into())?;
+        js_sys::Reflect::set(&options, &"parity".into(), &parity.into())?;
+        js_sys::Reflect::set(&options, &"flowControl".into(), &flow_control.into())?;
+        js_sys::Reflect::set(&options, &"bufferSize".into(), &self.buffer_size.into())?;
+        Ok(options.into())
+    }
+}
+
+/// Control signals sent to the other end. Signals left as `None` are unchanged.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct OutputSignals {
+    /// Data Terminal Ready, which many boards use to reset.
+    pub dtr: Option<bool>,
+    /// Request To Send.
+    pub rts: Option<bool>,
+    /// Whether to hold the line low, signalling a break.
+    pub brk: Option<bool>
+}
+
+/// Control signals from the other end.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputSignals {
+    /// Data Carrier Detect.
+    pub dcd: bool,
+    /// Clear To Send.
+    pub cts: bool,
+    /// Ring Indicator.
+    pub ri: bool,
+    /// Data Set Ready.
+    pub dsr: bool
+}
+
+/// A serial port the page may use.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SerialPort(RawPort);
+
+impl SerialPort {
+    /// The vendor ID of the port's USB device, if it is one.
+    pub fn usb_vendor_id(&self) -> Option<u16> {
+        self.info("usbVendorId")
+    }
+
+    /// The product ID of the port's USB device, if it is one.
+    pub fn usb_product_id(&self) -> Option<u16> {
+        self.info("usbProductId")
+    }
+
+    fn info(&self, key: &str) -> Option<u16> {
+        js_sys::Reflect::get(&self.0.get_info(), &key.into()).ok()?.as_f64().map(|id| id as u16)
+    }
+
+    /// Opens the port, starting to read from it.
+    pub async fn open(&self, options: &OpenOptions) -> Result<SerialConnection, SerialError> {
+        done(self.0.open(&options.to_js()?)).await?;
+        let writer = match self.0.writable() {
+            Some(writable) => writable.get_writer()?,
+            None => return Err(SerialError::Disconnected)
+        };
+        let (sender, data) = channel();
+        let reading = Rc::new(RefCell::new(Reading { reader: None, closing: false }));
+        spawn_local(read_loop(self.0.clone(), reading.clone(), sender));
+        Ok(SerialConnection { port: self.0.clone(), data, writer, reading, closed: false })
+    }
+
+    /// Revokes the page's permission to use the port, so that the user has to choose it again.
+    pub async fn forget(&self) -> Result<(), SerialError> {
+        done(self.0.forget()).await?;
+        Ok(())
+    }
+}
+
+struct Reading {
+    reader: Option<web_sys::ReadableStreamDefaultReader>,
+    closing: bool
+}
+
+async fn read_loop(
+    port: RawPort,
+    reading: Rc<RefCell<Reading>>,
+    sender: Sender<Result<Vec<u8>, SerialError>>
+) {
+    // errors such as parity errors end the readable stream, after which the port has a new one
+    while let Some(readable) = port.readable() {
+        if reading.borrow().closing {
+            break;
+        }
+        let reader = match web_sys::ReadableStreamDefaultReader::new(&readable) {
+            Ok(reader) => reader,
+            Err(e) => {
+                let _ = sender.send(Err(e.into()));
+                break;
+            }
+        };
+        reading.borrow_mut().reader = Some(reader.clone());
+        loop {
+            let result = match JsFuture::from(reader.read()).await {
+                Ok(result) => result.unchecked_into::<web_sys::ReadableStreamReadResult>(),
+                Err(e) => {
+                    let _ = sender.send(Err(e.into()));
+                    break;
+                }
+            };
+            if result.get_done().unwrap_or(false) {
+                break;
+            }
+            let chunk: js_sys::Uint8Array = result.get_value().unchecked_into();
+            let _ = sender.send(Ok(chunk.to_vec()));
+        }
+        reader.release_lock();
+        reading.borrow_mut().reader = None;
+    }
+}
+
+/// An open serial port, from [`SerialPort::open`]. The port is closed when this is dropped.
+pub struct SerialConnection {
+    port: RawPort,
+    data: Receiver<Result<Vec<u8>, SerialError>>,
+    writer: web_sys::WritableStreamDefaultWriter,
+    reading: Rc<RefCell<Reading>>,
+    closed: bool
+}
+
+impl SerialConnection {
+    /// Bytes received which haven't been read yet, without waiting.
+    pub fn try_read(&self) -> Option<Result<Vec<u8>, SerialError>> {
+        self.data.try_recv().ok()
+    }
+
+    /// Waits for bytes to be received, or returns `None` once the port has closed, such as
+    /// because the device was disconnected. Errors such as [`SerialError::Parity`] lose some
+    /// bytes but don't end reading.
+    pub async fn read(&self) -> Option<Result<Vec<u8>, SerialError>> {
+        self.data.recv().await
+    }
+
+    /// The received bytes, such as for handing to a task which only reads.
+    pub fn receiver(&self) -> &Receiver<Result<Vec<u8>, SerialError>> {
+        &self.data
+    }
+
+    /// Sends bytes, waiting until the browser has buffered them.
+    pub async fn write(&self, bytes: &[u8]) -> Result<(), SerialError> {
+        let chunk = js_sys::Uint8Array::from(bytes);
+        JsFuture::from(self.writer.write_with_chunk(&chunk)).await?;
+        Ok(())
+    }
+
+    pub async fn set_signals(&self, signals: &OutputSignals) -> Result<(), SerialError> {
+        let js = js_sys::Object::new();
+        let fields = [
+            ("dataTerminalReady", signals.dtr),
+            ("requestToSend", signals.rts),
+            ("break", signals.brk)
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                js_sys::Reflect::set(&js, &key.into(), &value.into())?;
+            }
+        }
+        done(self.port.set_signals(&js)).await?;
+        Ok(())
+    }
+
+    pub async fn signals(&self) -> Result<InputSignals, SerialError> {
+        let signals = done(self.port.get_signals()).await?;
+        let get = |key: &str| {
+            js_sys::Reflect::get(&signals, &key.into()).ok().is_some_and(|v| v.is_truthy())
+        };
+        Ok(InputSignals {
+            dcd: get("dataCarrierDetect"),
+            cts: get("clearToSend"),
+            ri: get("ringIndicator"),
+            dsr: get("dataSetReady")
+        })
+    }
+
+    /// Closes the port once everything written has been sent.
+    pub async fn close(mut self) -> Result<(), SerialError> {
+        self.closed = true;
+        shutdown(self.port.clone(), self.reading.clone(), self.writer.clone()).await
+    }
+}
+
+impl Drop for SerialConnection {
+    fn drop(&mut self) {
+        if !self.closed {
+            let shutdown = shutdown(self.port.clone(), self.reading.clone(), self.writer.clone());
+            spawn_local(async move {
+                let _ = shutdown.await;
+            });
+        }
+    }
+}
+
+async fn shutdown(
+    port: RawPort,
+    reading: Rc<RefCell<Reading>>,
+    writer: web_sys::WritableStreamDefaultWriter
+) -> Result<(), SerialError> {
+    // the port can only be closed once neither of its streams are locked
+    let reader = {
+        let mut reading = reading.borrow_mut();
+        reading.closing = true;
+        reading.reader.take()
+    };
+    if let Some(reader) = reader {
+        let _ = JsFuture::from(reader.cancel()).await;
+        reader.release_lock();
+    }
+    let _ = JsFuture::from(writer.close()).await;
+    writer.release_lock();
+    done(port.close()).await?;
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use crate::GeneralError;
+use js_sys::{ Atomics, Int32Array, SharedArrayBuffer, Uint8Array };
+use std::marker::PhantomData;
+use std::mem::size_of;
+
+pub use bytemuck::{ Pod, Zeroable };
+
+const HEAD: u32 = 0;
+const TAIL: u32 = 1;
+const HEADER_BYTES: u32 = 8;
+
+/// Lock-free single-producer single-consumer queue of plain-old-data values living in a
+/// `SharedArrayBuffer`.
+///
+/// Create the buffer on one side, send [`RingBuffer::buffer`] to the other side (e.g. in a
+/// [`Transfer`](crate::worker::Transfer) sent with `send`, which shares rather than copies it),
+/// and attach to it there with [`RingBuffer::from_buffer`]. Values then flow without any
+/// `postMessage` traffic. Only one side may push and only one side may pop.
+///
+/// Requires the page to be cross-origin isolated so that `SharedArrayBuffer` is available.
+pub struct RingBuffer<T> {
+    buffer: SharedArrayBuffer,
+    header: Int32Array,
+    data: Uint8Array,
+    slots: u32,
+    _phantom: PhantomData<T>
+}
+
+impl<T: Pod> RingBuffer<T> {
+    /// Allocates a new ring buffer which can hold up to `capacity` values.
+    ///
+    /// Fails if `T` is zero-sized or the buffer would be larger than 4 GiB.
+    pub fn new(capacity: u32) -> Result<Self, GeneralError> {
+        // one slot is always left empty to tell a full buffer from an empty one
+        let bytes = capacity.checked_add(1)
+            .and_then(|slots| slots.checked_mul(size_of::<T>() as u32))
+            .and_then(|data| data.checked_add(HEADER_BYTES))
+            .ok_or_else(|| GeneralError::message("ring buffer capacity is too large"))?;
+        Self::from_buffer(SharedArrayBuffer::new(bytes))
+    }
+
+    /// Attaches to a ring buffer created elsewhere.
+    ///
+    /// Fails if `T` is zero-sized or the buffer is too small to be a ring buffer of `T`.
+    pub fn from_buffer(buffer: SharedArrayBuffer) -> Result<Self, GeneralError> {
+        let slots = slot_count(buffer.byte_length(), size_of::<T>())
+            .map_err(GeneralError::message)?;
+        let data_bytes = slots * size_of::<T>() as u32;
+        Ok(RingBuffer {
+            header: Int32Array::new_with_byte_offset_and_length(&buffer, 0, 2),
+            data: Uint8Array::new_with_byte_offset_and_length(&buffer, HEADER_BYTES, data_bytes),
+            slots,
+            buffer,
+            _phantom: PhantomData
+        })
+    }
+
+    /// The underlying buffer, to be sent to the other side.
+    pub fn buffer(&self) -> &SharedArrayBuffer {
+        &self.buffer
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.slots - 1
+    }
+
+    pub fn len(&self) -> u32 {
+        let head = self.load(HEAD);
+        let tail = self.load(TAIL);
+        (tail + self.slots - head) % self.slots
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pushes a value, returning it back if the buffer is full.
+    pub fn push(&self, v: T) -> Result<(), T> {
+        if self.push_slice(std::slice::from_ref(&v)) == 1 {
+            Ok(())
+        } else {
+            Err(v)
+        }
+    }
+
+    /// Pushes as many values from the slice as fit, returning how many were pushed.
+    pub fn push_slice(&self, values: &[T]) -> usize {
+        let head = self.load(HEAD);
+        let mut tail = self.load(TAIL);
+        let free = (head + self.slots - tail - 1) % self.slots;
+        let count = values.len().min(free as usize);
+        let mut offset = 0;
+        for (start, len) in self.segments(tail, count) {
+            self.slot_bytes(start, len)
+                .copy_from(bytemuck::cast_slice(&values[offset..offset + len]));
+            offset += len;
+        }
+        tail = (tail + count as u32) % self.slots;
+        self.store(TAIL, tail);
+        count
+    }
+
+    /// Pops a value, if one is available.
+    pub fn pop(&self) -> Option<T> {
+        let mut v = T::zeroed();
+        if self.pop_slice(std::slice::from_mut(&mut v)) == 1 {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    /// Pops as many values as are available into the slice, returning how many were popped.
+    pub fn pop_slice(&self, out: &mut [T]) -> usize {
+        let mut head = self.load(HEAD);
+        let tail = self.load(TAIL);
+        let available = (tail + self.slots - head) % self.slots;
+        let count = out.len().min(available as usize);
+        let mut offset = 0;
+        for (start, len) in self.segments(head, count) {
+            self.slot_bytes(start, len)
+                .copy_to(bytemuck::cast_slice_mut(&mut out[offset..offset + len]));
+            offset += len;
+        }
+        head = (head + count as u32) % self.slots;
+        self.store(HEAD, head);
+        count
+    }
+
+    /// Splits `count` slots starting at `start` into at most two contiguous runs.
+    fn segments(&self, start: u32, count: usize) -> impl Iterator<Item = (u32, usize)> {
+        let first = count.min((self.slots - start) as usize);
+        let second = count - first;
+        std::iter::once((start, first))
+            .chain(std::iter::once((0, second)))
+            .filter(|&(_, len)| len > 0)
+    }
+
+    fn slot_bytes(&self, start: u32, len: usize) -> Uint8Array {
+        let size = size_of::<T>() as u32;
+        self.data.subarray(start * size, (start + len as u32) * size)
+    }
+
+    fn load(&self, index: u32) -> u32 {
+        Atomics::load(&self.header, index).unwrap() as u32
+    }
+
+    fn store(&self, index: u32, v: u32) {
+        Atomics::store(&self.header, index, v as i32).unwrap();
+    }
+}
+
+/// How many slots of `size` bytes fit in a ring buffer of `byte_length` bytes.
+fn slot_count(byte_length: u32, size: usize) -> Result<u32, &'static str> {
+    if size == 0 {
+        return Err("ring buffer values can't be zero-sized");
+    }
+    match byte_length.checked_sub(HEADER_BYTES).map(|data| data / size as u32) {
+        Some(slots) if slots > 0 => Ok(slots),
+        _ => Err("buffer is too small to be a ring buffer")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slot_count_fits_whole_slots() {
+        assert_eq!(slot_count(HEADER_BYTES + 16, 4).unwrap(), 4);
+        assert_eq!(slot_count(HEADER_BYTES + 18, 4).unwrap(), 4);
+        assert_eq!(slot_count(HEADER_BYTES + 1, 1).unwrap(), 1);
+    }
+
+    #[test]
+    fn slot_count_rejects_bad_buffers() {
+        assert!(slot_count(64, 0).is_err());
+        assert!(slot_count(HEADER_BYTES - 1, 4).is_err());
+        assert!(slot_count(HEADER_BYTES, 4).is_err());
+        assert!(slot_count(HEADER_BYTES + 3, 4).is_err());
+    }
+}
@@ -0,0 +1,403 @@
+//! Reading text aloud and transcribing the microphone, from the Web Speech API.
+//!
+//! ```ignore
+//! let voices = speech::voices().await?;
+//! let voice = voices.iter().find(|v| v.lang().starts_with("en"));
+//! let mut options = SpeakOptions::new().rate(1.2);
+//! if let Some(voice) = voice {
+//!     options = options.voice(voice);
+//! }
+//! speech::speak("Level complete", &options).await?;
+//!
+//! let recognition = speech::recognize(&RecognizeOptions::new().lang("en-US"))?;
+//! while let Some(transcript) = recognition.next().await {
+//!     let transcript = transcript?;
+//!     if transcript.is_final {
+//!         run_command(transcript.text());
+//!     }
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, channel };
+use crate::event::{ self, ListenerHandle };
+use crate::global::GlobalScope;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+
+/// How long [`voices`] waits for the browser to load its voices before giving up.
+const VOICES_TIMEOUT: u32 = 1000;
+
+/// Errors from speaking text or recognizing speech.
+#[derive(Debug)]
+pub enum SpeechError {
+    /// Speech isn't supported, such as because this isn't a window, there is no voice for
+    /// the language, or there is no microphone.
+    Unavailable,
+    /// Speaking was stopped by [`cancel`], or recognition by [`Recognition::abort`].
+    Interrupted,
+    /// The browser or user didn't allow speaking or using the microphone, such as because the
+    /// user hasn't interacted with the page yet.
+    NotAllowed,
+    /// Recognition heard nothing for a while.
+    NoSpeech,
+    Other(JsValue)
+}
+
+impl From<JsValue> for SpeechError {
+    fn from(e: JsValue) -> Self {
+        // error events have a code instead of a name
+        let code = js_sys::Reflect::get(&e, &"error".into()).ok().and_then(|c| c.as_string());
+        match code.as_deref() {
+            Some("canceled") | Some("interrupted") | Some("aborted") => SpeechError::Interrupted,
+            Some("not-allowed") | Some("service-not-allowed") => SpeechError::NotAllowed,
+            Some("no-speech") => SpeechError::NoSpeech,
+            Some("synthesis-unavailable") | Some("language-unavailable")
+                | Some("voice-unavailable") | Some("language-not-supported")
+                | Some("audio-capture") => SpeechError::Unavailable,
+            _ => SpeechError::Other(e)
+        }
+    }
+}
+
+impl From<SpeechError> for GeneralError {
+    fn from(e: SpeechError) -> Self {
+        let msg = match e {
+            SpeechError::Unavailable => "speech is unavailable",
+            SpeechError::Interrupted => "speech was interrupted",
+            SpeechError::NotAllowed => "speech is not allowed",
+            SpeechError::NoSpeech => "no speech was heard",
+            SpeechError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(msg)
+    }
+}
+
+fn synthesis() -> Result<web_sys::SpeechSynthesis, SpeechError> {
+    match GlobalScope::current() {
+        GlobalScope::Window(window) => {
+            window.speech_synthesis().map_err(|_| SpeechError::Unavailable)
+        }
+        _ => Err(SpeechError::Unavailable)
+    }
+}
+
+/// A voice to speak with, from [`voices`].
+#[derive(Clone, Debug)]
+pub struct Voice(web_sys::SpeechSynthesisVoice);
+
+impl Voice {
+    pub fn name(&self) -> String {
+        self.0.name()
+    }
+
+    /// The BCP 47 language tag of the voice, such as `en-US`.
+    pub fn lang(&self) -> String {
+        self.0.lang()
+    }
+
+    /// Whether the voice is synthesized on the device rather than by a remote service.
+    pub fn is_local(&self) -> bool {
+        self.0.local_service()
+    }
+
+    /// Whether this is the voice used when none is given.
+    pub fn is_default(&self) -> bool {
+        self.0.default()
+    }
+
+    /// The underlying voice.
+    pub fn raw(&self) -> &web_sys::SpeechSynthesisVoice {
+        &self.0
+    }
+}
+
+/// Lists the voices available to [`speak`] with.
+///
+/// Some browsers load their voices after the page, in which case this waits for them. The
+/// list is empty if the browser has none.
+pub async fn voices() -> Result<Vec<Voice>, SpeechError> {
+    let synthesis = synthesis()?;
+    let mut voices = synthesis.get_voices();
+    if voices.length() == 0 {
+        let changed = synthesis.once::<event::VoicesChanged>();
+        // browsers with no voices never report them changing
+        if changed.timeout(VOICES_TIMEOUT).await.is_ok() {
+            voices = synthesis.get_voices();
+        }
+    }
+    Ok(voices.iter().map(|v| Voice(v.unchecked_into())).collect())
+}
+
+/// How to speak text.
+#[derive(Clone, Debug, Default)]
+pub struct SpeakOptions {
+    voice: Option<web_sys::SpeechSynthesisVoice>,
+    lang: Option<String>,
+    rate: Option<f32>,
+    pitch: Option<f32>,
+    volume: Option<f32>
+}
+
+impl SpeakOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn voice(mut self, voice: &Voice) -> Self {
+        self.voice = Some(voice.0.clone());
+        self
+    }
+
+    /// The BCP 47 language tag of the text, which picks the voice if none is given.
+    pub fn lang(mut self, lang: &str) -> Self {
+        self.lang = Some(lang.to_owned());
+        self
+    }
+
+    /// Speed, from `0.1` to `10.0`, where `1.0` is the voice's normal speed.
+    pub fn rate(mut self, rate: f32) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Pitch, from `0.0` to `2.0`, where `1.0` is the voice's normal pitch.
+    pub fn pitch(mut self, pitch: f32) -> Self {
+        self.pitch = Some(pitch);
+        self
+    }
+
+    /// Volume, from `0.0` to `1.0`.
+    pub fn volume(mut self, volume: f32) -> Self {
+        self.volume = Some(volume);
+        self
+    }
+}
+
+/// Speaks `text`, finishing once it has been spoken.
+///
+/// Text is queued behind anything already being spoken. Dropping the future doesn't stop
+/// speaking; use [`cancel`] for that.
+pub async fn speak(text: &str, options: &SpeakOptions) -> Result<(), SpeechError> {
+    let synthesis = synthesis()?;
+    let utterance = web_sys::SpeechSynthesisUtterance::new_with_text(text)?;
+    utterance.set_voice(options.voice.as_ref());
+    if let Some(lang) = &options.lang {
+        utterance.set_lang(lang);
+    }
+    if let Some(rate) = options.rate {
+        utterance.set_rate(rate);
+    }
+    if let Some(pitch) = options.pitch {
+        utterance.set_pitch(pitch);
+    }
+    if let Some(volume) = options.volume {
+        utterance.set_volume(volume);
+    }
+
+    let ended = utterance.once::<event::SynthesisEnd>();
+    let failed = utterance.once::<event::SynthesisError>();
+    synthesis.speak(&utterance);
+    async { ended.await; Ok(()) }
+        .race(async { Err(JsValue::from(&*failed.await).into()) })
+        .await
+}
+
+/// Whether text is being spoken, even if paused.
+pub fn is_speaking() -> bool {
+    synthesis().map(|s| s.speaking()).unwrap_or(false)
+}
+
+pub fn is_paused() -> bool {
+    synthesis().map(|s| s.paused()).unwrap_or(false)
+}
+
+/// Pauses speaking, which continues from the same place on [`resume`].
+pub fn pause() {
+    if let Ok(synthesis) = synthesis() {
+        synthesis.pause();
+    }
+}
+
+pub fn resume() {
+    if let Ok(synthesis) = synthesis() {
+        synthesis.resume();
+    }
+}
+
+/// Stops speaking and clears the queue. Every unfinished [`speak`] fails with
+/// [`SpeechError::Interrupted`].
+pub fn cancel() {
+    if let Ok(synthesis) = synthesis() {
+        synthesis.cancel();
+    }
+}
+
+/// How to recognize speech.
+#[derive(Clone, Debug, Default)]
+pub struct RecognizeOptions {
+    lang: Option<String>,
+    continuous: bool,
+    interim: bool,
+    max_alternatives: Option<u32>
+}
+
+impl RecognizeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The BCP 47 language tag of the speech. The page's language is used otherwise.
+    pub fn lang(mut self, lang: &str) -> Self {
+        self.lang = Some(lang.to_owned());
+        self
+    }
+
+    /// Keeps listening after the user stops speaking, until stopped. Recognition ends after
+    /// the first pause otherwise.
+    pub fn continuous(mut self, continuous: bool) -> Self {
+        self.continuous = continuous;
+        self
+    }
+
+    /// Reports guesses while the user is still speaking, before the final transcript.
+    pub fn interim(mut self, interim: bool) -> Self {
+        self.interim = interim;
+        self
+    }
+
+    /// How many alternative transcripts to report for each result.
+    pub fn max_alternatives(mut self, count: u32) -> Self {
+        self.max_alternatives = Some(count);
+        self
+    }
+}
+
+/// A possible transcript of what was said.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alternative {
+    pub text: String,
+    /// How likely this transcript is to be right, from `0.0` to `1.0`.
+    pub confidence: f32
+}
+
+/// What was said, from [`Recognition`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transcript {
+    /// Which part of the speech this is. Interim transcripts are replaced by later ones with
+    /// the same index, until a final one.
+    pub index: u32,
+    pub is_final: bool,
+    /// The possible transcripts, most likely first.
+    pub alternatives: Vec<Alternative>
+}
+
+impl Transcript {
+    /// The most likely transcript.
+    pub fn text(&self) -> &str {
+        self.alternatives.first().map_or("", |a| &a.text)
+    }
+
+    pub fn confidence(&self) -> f32 {
+        self.alternatives.first().map_or(0.0, |a| a.confidence)
+    }
+}
+
+/// Starts transcribing the microphone, asking the user for permission if needed.
+pub fn recognize(options: &RecognizeOptions) -> Result<Recognition, SpeechError> {
+    let global = js_sys::global();
+    // Chrome and Safari only have the prefixed constructor
+    let constructor = ["SpeechRecognition", "webkitSpeechRecognition"].iter()
+        .filter_map(|name| js_sys::Reflect::get(&global, &(*name).into()).ok())
+        .find(JsValue::is_function)
+        .ok_or(SpeechError::Unavailable)?;
+    let constructor: &js_sys::Function = constructor.unchecked_ref();
+    let raw: web_sys::SpeechRecognition =
+        js_sys::Reflect::construct(constructor, &js_sys::Array::new())?.unchecked_into();
+    if let Some(lang) = &options.lang {
+        raw.set_lang(lang);
+    }
+    raw.set_continuous(options.continuous)?;
+    raw.set_interim_results(options.interim);
+    if let Some(count) = options.max_alternatives {
+        raw.set_max_alternatives(count);
+    }
+
+    let (sender, transcripts) = channel();
+    // the sender is dropped when recognition ends, which ends the transcripts
+    let sender = Rc::new(RefCell::new(Some(sender)));
+    let results = raw.add_event_listener({
+        let sender = sender.clone();
+        move |e: event::RecognitionResult| {
+            let (sender, results) = match (&*sender.borrow(), e.results()) {
+                (Some(sender), Some(results)) => (sender.clone(), results),
+                _ => return
+            };
+            for index in e.result_index()..results.length() {
+                let result = results.item(index);
+                let alternatives = (0..result.length())
+                    .map(|i| result.item(i))
+                    .map(|a| Alternative { text: a.transcript(), confidence: a.confidence() })
+                    .collect();
+                let is_final = result.is_final();
+                let _ = sender.send(Ok(Transcript { index, is_final, alternatives }));
+            }
+        }
+    });
+    let failed = raw.add_event_listener({
+        let sender = sender.clone();
+        move |e: event::RecognitionError| {
+            if let Some(sender) = &*sender.borrow() {
+                let _ = sender.send(Err(JsValue::from(&*e).into()));
+            }
+        }
+    });
+    // errors are reported before this
+    let ended = raw.add_event_listener(move |_: event::RecognitionEnd| {
+        sender.borrow_mut().take();
+    });
+
+    raw.start()?;
+    Ok(Recognition { raw, transcripts, _listeners: [results, failed, ended] })
+}
+
+/// Speech being recognized, from [`recognize`]. Recognition is aborted when this is dropped.
+pub struct Recognition {
+    raw: web_sys::SpeechRecognition,
+    transcripts: Receiver<Result<Transcript, SpeechError>>,
+    _listeners: [ListenerHandle; 3]
+}
+
+impl Recognition {
+    pub fn try_next(&self) -> Option<Result<Transcript, SpeechError>> {
+        self.transcripts.try_recv().ok()
+    }
+
+    /// Waits for the next transcript, or `None` once recognition has ended. Recognition ends
+    /// after any error.
+    pub async fn next(&self) -> Option<Result<Transcript, SpeechError>> {
+        self.transcripts.recv().await
+    }
+
+    /// Stops listening, still transcribing what was already heard.
+    pub fn stop(&self) {
+        self.raw.stop();
+    }
+
+    /// Stops listening and discards anything not yet transcribed.
+    pub fn abort(&self) {
+        self.raw.abort();
+    }
+
+    /// The underlying recognition.
+    pub fn raw(&self) -> &web_sys::SpeechRecognition {
+        &self.raw
+    }
+}
+
+impl Drop for Recognition {
+    fn drop(&mut self) {
+        self.raw.abort();
+    }
+}
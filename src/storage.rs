@@ -0,0 +1,229 @@
+//! Typed keys in `localStorage` and `sessionStorage`.
+//!
+//! Values are stored as JSON.
+//!
+//! ```ignore
+//! let settings = Namespace::local("my-game");
+//! let volume = settings.key::<f32>("volume");
+//! volume.set(&0.8)?;
+//! let volume = volume.get()?.unwrap_or(1.0);
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, channel };
+use crate::event::{ self, ListenerHandle };
+use crate::global::GlobalScope;
+use serde::{ Serialize, de::DeserializeOwned };
+use std::marker::PhantomData;
+
+/// A storage area.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Area {
+    /// `localStorage`, which persists and is shared by the pages of an origin.
+    Local,
+    /// `sessionStorage`, which lasts as long as the tab.
+    Session
+}
+
+impl Area {
+    /// The underlying storage. Storage is only available in windows, and can be disabled.
+    pub fn storage(self) -> Result<web_sys::Storage, StorageError> {
+        let window = match GlobalScope::current() {
+            GlobalScope::Window(window) => window,
+            _ => return Err(StorageError::Unavailable)
+        };
+        let storage = match self {
+            Area::Local => window.local_storage(),
+            Area::Session => window.session_storage()
+        };
+        storage.ok().flatten().ok_or(StorageError::Unavailable)
+    }
+}
+
+/// Errors from accessing storage.
+#[derive(Debug)]
+pub enum StorageError {
+    /// The storage area can't be used, because it is disabled or this isn't a window.
+    Unavailable,
+    /// There isn't enough space left to store the value.
+    QuotaExceeded,
+    /// The value could not be converted to or from JSON.
+    Json(serde_json::Error),
+    Other(JsValue)
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Json(e)
+    }
+}
+
+impl From<JsValue> for StorageError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            // Firefox has used its own name for it
+            Some("QuotaExceededError") | Some("NS_ERROR_DOM_QUOTA_REACHED") => {
+                StorageError::QuotaExceeded
+            }
+            _ => StorageError::Other(e)
+        }
+    }
+}
+
+impl From<StorageError> for GeneralError {
+    fn from(e: StorageError) -> Self {
+        match e {
+            StorageError::Unavailable => GeneralError::message("storage is unavailable"),
+            StorageError::QuotaExceeded => GeneralError::message("storage quota exceeded"),
+            StorageError::Json(e) => GeneralError::SerdeJson(e),
+            StorageError::Other(e) => GeneralError::WebSys(e)
+        }
+    }
+}
+
+/// A key holding a `T`.
+pub struct TypedKey<T> {
+    area: Area,
+    key: String,
+    _phantom: PhantomData<fn(T)>
+}
+
+impl<T> Clone for TypedKey<T> {
+    fn clone(&self) -> Self {
+        TypedKey::new(self.area, &self.key)
+    }
+}
+
+impl<T> TypedKey<T> {
+    pub fn new(area: Area, key: &str) -> Self {
+        TypedKey { area, key: key.to_owned(), _phantom: PhantomData }
+    }
+
+    pub fn local(key: &str) -> Self {
+        Self::new(Area::Local, key)
+    }
+
+    pub fn session(key: &str) -> Self {
+        Self::new(Area::Session, key)
+    }
+
+    /// The key as stored, including the namespace.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn remove(&self) -> Result<(), StorageError> {
+        self.area.storage()?.remove_item(&self.key)?;
+        Ok(())
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> TypedKey<T> {
+    /// Gets the value, or `None` if the key isn't set.
+    pub fn get(&self) -> Result<Option<T>, StorageError> {
+        match self.area.storage()?.get_item(&self.key)? {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None)
+        }
+    }
+
+    pub fn set(&self, v: &T) -> Result<(), StorageError> {
+        let json = serde_json::to_string(v)?;
+        self.area.storage()?.set_item(&self.key, &json)?;
+        Ok(())
+    }
+
+    /// Watches for other pages changing the key. Changes made by this page aren't reported.
+    pub fn watch(&self) -> Result<KeyWatch<T>, StorageError>
+    where
+        T: 'static
+    {
+        let storage = self.area.storage()?;
+        let key = self.key.clone();
+        let (sender, changes) = channel();
+        let listener = GlobalScope::current().event_target()
+            .add_event_listener(move |e: event::Storage| {
+                if e.storage_area().as_ref() != Some(&storage) {
+                    return;
+                }
+                // a missing key means the whole area was cleared
+                let value = match e.key() {
+                    Some(k) if k == key => e.new_value(),
+                    Some(_) => return,
+                    None => None
+                };
+                match value.map(|json| serde_json::from_str(&json)) {
+                    Some(Ok(v)) => { let _ = sender.send(Some(v)); }
+                    Some(Err(_)) => {}
+                    None => { let _ = sender.send(None); }
+                }
+            });
+        Ok(KeyWatch { changes, _listener: listener })
+    }
+}
+
+/// Changes to a key made by other pages, from [`TypedKey::watch`]. Values which can't be
+/// decoded are skipped.
+pub struct KeyWatch<T> {
+    changes: Receiver<Option<T>>,
+    _listener: ListenerHandle
+}
+
+impl<T> KeyWatch<T> {
+    /// Receives a change if there is one, which is `None` if the key was removed.
+    pub fn try_next(&self) -> Option<Option<T>> {
+        self.changes.try_recv().ok()
+    }
+
+    /// Waits for the next change, returning the new value or `None` if the key was removed.
+    pub async fn next(&self) -> Option<T> {
+        self.changes.recv().await.unwrap()
+    }
+}
+
+/// A prefix grouping keys, so that different parts of an origin don't clash.
+#[derive(Clone, Debug)]
+pub struct Namespace {
+    area: Area,
+    prefix: String
+}
+
+impl Namespace {
+    /// Groups keys named `"{name}:key"`.
+    pub fn new(area: Area, name: &str) -> Self {
+        Namespace { area, prefix: format!("{}:", name) }
+    }
+
+    pub fn local(name: &str) -> Self {
+        Self::new(Area::Local, name)
+    }
+
+    pub fn session(name: &str) -> Self {
+        Self::new(Area::Session, name)
+    }
+
+    pub fn key<T>(&self, key: &str) -> TypedKey<T> {
+        TypedKey::new(self.area, &format!("{}{}", self.prefix, key))
+    }
+
+    /// A namespace nested inside this one.
+    pub fn namespace(&self, name: &str) -> Namespace {
+        Namespace { area: self.area, prefix: format!("{}{}:", self.prefix, name) }
+    }
+
+    /// Removes every key in the namespace.
+    pub fn clear(&self) -> Result<(), StorageError> {
+        let storage = self.area.storage()?;
+        let mut keys = vec![];
+        for i in 0..storage.length()? {
+            match storage.key(i)? {
+                Some(key) if key.starts_with(&self.prefix) => keys.push(key),
+                _ => {}
+            }
+        }
+        for key in keys {
+            storage.remove_item(&key)?;
+        }
+        Ok(())
+    }
+}
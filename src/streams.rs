@@ -0,0 +1,682 @@
+//! Conversions between JS streams and Rust, so Rust code can read fetch bodies and file
+//! streams, and produce streams for JS APIs which consume them.
+//!
+//! ```ignore
+//! let response = http::get("/large.bin").send().await?.error_for_status()?;
+//! let body = response.body_stream()?;
+//! while let Some(chunk) = body.next().await {
+//!     hasher.update(&chunk?);
+//! }
+//!
+//! let events = streams::ndjson::<Event>(http::get("/events").send().await?.body_stream()?);
+//! while let Some(event) = events.next().await {
+//!     handle(event?);
+//! }
+//!
+//! let (sender, chunks) = channel();
+//! let upload = streams::to_readable(chunks)?;
+//!
+//! let mut decoder = Decoder::new();
+//! let decode = streams::transform(move |chunk| {
+//!     let frames = decoder.decode(&chunk);
+//!     async move { Ok(frames) }
+//! })?;
+//! let frames = body.pipe_through(&decode)?;
+//!
+//! if let Some(file) = fs::pick_save_file("recording.webm", &[]).await? {
+//!     let body = http::get("/recording.webm").send().await?.body_stream()?;
+//!     body.pipe_to(file.write_stream().await?).await?;
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, Sender, channel };
+use serde::de::DeserializeOwned;
+use std::cell::{ Cell, RefCell };
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen_futures::JsFuture;
+
+/// Chunks of bytes, from a JS stream or from Rust.
+///
+/// Chunks from a JS stream are only read as they are asked for, so a slow consumer slows the
+/// producer down rather than chunks piling up in memory. Dropping it cancels the JS stream.
+pub struct ByteStream(Option<Source>);
+
+enum Source {
+    Js { stream: web_sys::ReadableStream, reader: web_sys::ReadableStreamDefaultReader },
+    Channel(Receiver<Vec<u8>>)
+}
+
+/// Reads `stream`, which must produce `Uint8Array`s or `ArrayBuffer`s as fetch bodies and
+/// `Blob::stream` do. Fails if the stream is already being read.
+pub fn from_readable(stream: &web_sys::ReadableStream) -> Result<ByteStream, GeneralError> {
+    let reader = web_sys::ReadableStreamDefaultReader::new(stream)?;
+    Ok(ByteStream(Some(Source::Js { stream: stream.clone(), reader })))
+}
+
+impl ByteStream {
+    /// A stream with no chunks.
+    pub fn empty() -> Self {
+        ByteStream::from(channel().1)
+    }
+
+    /// Reads the next chunk, or returns `None` once the stream has ended.
+    pub async fn next(&self) -> Option<Result<Vec<u8>, GeneralError>> {
+        match self.0.as_ref()? {
+            Source::Js { reader, .. } => {
+                let result: web_sys::ReadableStreamReadResult =
+                    match JsFuture::from(reader.read()).await {
+                        Ok(result) => result.unchecked_into(),
+                        Err(e) => return Some(Err(e.into()))
+                    };
+                if result.get_done().unwrap_or(false) {
+                    return None;
+                }
+                let chunk = result.get_value();
+                Some(Ok(match chunk.dyn_ref::<js_sys::Uint8Array>() {
+                    Some(bytes) => bytes.to_vec(),
+                    None => js_sys::Uint8Array::new(&chunk).to_vec()
+                }))
+            }
+            Source::Channel(receiver) => Some(Ok(receiver.recv().await?))
+        }
+    }
+
+    /// Reads every chunk, concatenated.
+    pub async fn read_all(self) -> Result<Vec<u8>, GeneralError> {
+        let mut bytes = vec![];
+        while let Some(chunk) = self.next().await {
+            bytes.extend(chunk?);
+        }
+        Ok(bytes)
+    }
+
+    /// Writes every chunk to `sink`, then closes it. Chunks are only read as fast as `sink`
+    /// accepts them. If writing fails, the stream is cancelled and `sink` is aborted.
+    pub async fn pipe_to(self, sink: ByteSink) -> Result<(), GeneralError> {
+        while let Some(chunk) = self.next().await {
+            match chunk {
+                Ok(chunk) => sink.write(&chunk).await?,
+                Err(e) => {
+                    // pass the failure on, so it isn't mistaken for the end of the stream
+                    let reason = JsValue::from(e);
+                    sink.abort(reason.clone());
+                    return Err(reason.into());
+                }
+            }
+        }
+        sink.close().await
+    }
+
+    /// The output of `transform` with this stream as its input, such as a
+    /// `DecompressionStream` or one from [`transform`].
+    pub fn pipe_through(
+        self, transform: &web_sys::TransformStream
+    ) -> Result<ByteStream, GeneralError> {
+        let readable = to_readable(self)?.pipe_through(transform.unchecked_ref());
+        from_readable(&readable)
+    }
+
+    /// Reads chunks into a channel as fast as they arrive, without slowing the producer down.
+    /// The channel closes after the stream ends or fails.
+    pub fn into_receiver(self) -> Receiver<Result<Vec<u8>, GeneralError>> {
+        let (sender, receiver) = channel();
+        spawn_local(async move {
+            while let Some(chunk) = self.next().await {
+                let failed = chunk.is_err();
+                if sender.send(chunk).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+}
+
+impl From<Receiver<Vec<u8>>> for ByteStream {
+    /// A stream of the chunks sent on the channel, which ends once every sender is dropped.
+    fn from(receiver: Receiver<Vec<u8>>) -> Self {
+        ByteStream(Some(Source::Channel(receiver)))
+    }
+}
+
+impl Drop for ByteStream {
+    fn drop(&mut self) {
+        if let Some(Source::Js { reader, .. }) = &self.0 {
+            let _ = reader.cancel();
+        }
+    }
+}
+
+/// The default for [`Lines::max_line_length`], 1 MiB.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 1 << 20;
+
+/// The lines of `bytes` as UTF-8, without their `\n` or `\r\n` endings. Invalid UTF-8 is
+/// replaced with `U+FFFD`.
+///
+/// Bytes are only read as lines are asked for, so a slow consumer slows the producer down.
+pub fn lines(bytes: impl Into<ByteStream>) -> Lines {
+    Lines {
+        bytes: bytes.into(),
+        buffer: RefCell::new(vec![]),
+        scanned: Cell::new(0),
+        ended: Cell::new(false),
+        max_line_length: DEFAULT_MAX_LINE_LENGTH
+    }
+}
+
+/// Lines read from a [`ByteStream`], from [`lines`].
+pub struct Lines {
+    bytes: ByteStream,
+    buffer: RefCell<Vec<u8>>,
+    /// How much of the buffer is known not to contain a line break.
+    scanned: Cell<usize>,
+    ended: Cell<bool>,
+    max_line_length: usize
+}
+
+impl Lines {
+    /// Sets the longest line in bytes which is accepted, so that input without line breaks
+    /// can't be buffered without limit. Defaults to [`DEFAULT_MAX_LINE_LENGTH`].
+    pub fn max_line_length(mut self, bytes: usize) -> Self {
+        self.max_line_length = bytes;
+        self
+    }
+
+    /// Reads the next line, or returns `None` once the bytes have ended. Fails if a read
+    /// fails or a line is too long, after which there are no more lines.
+    pub async fn next(&self) -> Option<Result<String, GeneralError>> {
+        loop {
+            if self.ended.get() {
+                return None;
+            }
+            let found = {
+                let mut scanned = self.scanned.get();
+                let line = take_line(&mut self.buffer.borrow_mut(), &mut scanned);
+                self.scanned.set(scanned);
+                line
+            };
+            let too_long = match &found {
+                Some(line) => line.len() > self.max_line_length + 1,
+                None => self.buffer.borrow().len() > self.max_line_length + 1
+            };
+            if too_long {
+                self.end();
+                let msg = format!("line is longer than the maximum of {}", self.max_line_length);
+                return Some(Err(GeneralError::message(&msg)));
+            }
+            if let Some(line) = found {
+                return Some(Ok(to_line(line)));
+            }
+            match self.bytes.next().await {
+                Some(Ok(chunk)) => self.buffer.borrow_mut().extend(chunk),
+                Some(Err(e)) => {
+                    self.end();
+                    return Some(Err(e));
+                }
+                None => {
+                    // the last line needn't end with a line break
+                    let rest = std::mem::take(&mut *self.buffer.borrow_mut());
+                    self.end();
+                    return match rest.is_empty() {
+                        true => None,
+                        false => Some(Ok(to_line(rest)))
+                    };
+                }
+            }
+        }
+    }
+
+    fn end(&self) {
+        self.ended.set(true);
+        self.buffer.borrow_mut().clear();
+    }
+}
+
+/// Takes the first line out of `buffer`, without its `\n`. The first `scanned` bytes are known
+/// not to contain a line break, and it is updated for the next call.
+fn take_line(buffer: &mut Vec<u8>, scanned: &mut usize) -> Option<Vec<u8>> {
+    match buffer[*scanned..].iter().position(|&b| b == b'\n') {
+        Some(i) => {
+            let rest = buffer.split_off(*scanned + i + 1);
+            let mut line = std::mem::replace(buffer, rest);
+            line.pop();
+            *scanned = 0;
+            Some(line)
+        }
+        None => {
+            *scanned = buffer.len();
+            None
+        }
+    }
+}
+
+/// Decodes a line without its `\n`, where `\r` may be left from a `\r\n` ending.
+fn to_line(mut line: Vec<u8>) -> String {
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    match String::from_utf8(line) {
+        Ok(line) => line,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned()
+    }
+}
+
+/// Values deserialized from each line of newline-delimited JSON, as streamed by many APIs.
+/// Lines are read as they are asked for, like for [`lines`].
+pub fn ndjson<T: DeserializeOwned>(bytes: impl Into<ByteStream>) -> Ndjson<T> {
+    Ndjson { lines: lines(bytes), _phantom: PhantomData }
+}
+
+/// Values read from newline-delimited JSON, from [`ndjson`].
+pub struct Ndjson<T> {
+    lines: Lines,
+    _phantom: PhantomData<fn() -> T>
+}
+
+impl<T: DeserializeOwned> Ndjson<T> {
+    /// Sets the longest line in bytes which is accepted, like [`Lines::max_line_length`].
+    pub fn max_line_length(mut self, bytes: usize) -> Self {
+        self.lines = self.lines.max_line_length(bytes);
+        self
+    }
+
+    /// Reads the next value, or returns `None` once the bytes have ended. Blank lines are
+    /// skipped. A line which fails to deserialize gives an error without ending the stream.
+    pub async fn next(&self) -> Option<Result<T, GeneralError>> {
+        loop {
+            match self.lines.next().await? {
+                Ok(line) if line.trim().is_empty() => {}
+                Ok(line) => return Some(serde_json::from_str(&line).map_err(GeneralError::from)),
+                Err(e) => return Some(Err(e))
+            }
+        }
+    }
+}
+
+type PullFn = Closure<dyn FnMut(web_sys::ReadableStreamDefaultController) -> js_sys::Promise>;
+type Callbacks = Rc<RefCell<Option<(PullFn, Closure<dyn FnMut()>)>>>;
+
+/// A JS stream of the chunks in `stream`, as `Uint8Array`s, such as for a fetch request body.
+///
+/// A chunk is only read from `stream` when the JS stream is asked for one. A [`ByteStream`]
+/// from [`from_readable`] gives back the original JS stream instead.
+pub fn to_readable(
+    stream: impl Into<ByteStream>
+) -> Result<web_sys::ReadableStream, GeneralError> {
+    let stream = match stream.into().0.take() {
+        Some(Source::Js { stream, reader }) => {
+            reader.release_lock();
+            return Ok(stream);
+        }
+        source => Rc::new(ByteStream(source))
+    };
+    // the callbacks drop themselves once the stream ends, but live forever if the JS stream is
+    // abandoned without being read to the end or cancelled
+    let callbacks: Callbacks = Rc::default();
+    let pull: PullFn = Closure::new({
+        let stream = stream.clone();
+        let callbacks = callbacks.clone();
+        move |controller: web_sys::ReadableStreamDefaultController| {
+            let stream = stream.clone();
+            let callbacks = callbacks.clone();
+            wasm_bindgen_futures::future_to_promise(async move {
+                match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        let chunk = js_sys::Uint8Array::from(&chunk[..]);
+                        controller.enqueue_with_chunk(&chunk)?;
+                    }
+                    Some(Err(e)) => {
+                        controller.error_with_e(&e.into());
+                        callbacks.borrow_mut().take();
+                    }
+                    None => {
+                        controller.close()?;
+                        callbacks.borrow_mut().take();
+                    }
+                }
+                Ok(JsValue::UNDEFINED)
+            })
+        }
+    });
+    let cancel = Closure::<dyn FnMut()>::new({
+        let callbacks = callbacks.clone();
+        move || {
+            // this callback can't be dropped while it is running
+            let callbacks = callbacks.clone();
+            spawn_local(async move {
+                callbacks.borrow_mut().take();
+            });
+        }
+    });
+    let source = js_sys::Object::new();
+    js_sys::Reflect::set(&source, &"pull".into(), pull.as_ref())?;
+    js_sys::Reflect::set(&source, &"cancel".into(), cancel.as_ref())?;
+    // don't read ahead of what the consumer asks for
+    let strategy = web_sys::QueuingStrategy::new();
+    strategy.set_high_water_mark(0.0);
+    *callbacks.borrow_mut() = Some((pull, cancel));
+    Ok(web_sys::ReadableStream::new_with_underlying_source_and_strategy(&source, &strategy)?)
+}
+
+type WriteFn = Box<dyn FnMut(Vec<u8>) -> Pin<Box<dyn Future<Output = Result<(), GeneralError>>>>>;
+type AbortFn = Box<dyn FnOnce(GeneralError)>;
+type ChunkSender = Sender<Result<Vec<u8>, GeneralError>>;
+
+/// A destination for chunks of bytes, in a JS stream or in Rust.
+///
+/// Writes wait until the destination has taken the chunk, so a slow destination slows the
+/// producer down. Dropping it without [`close`](Self::close) aborts it, which for files means
+/// discarding what was written.
+pub struct ByteSink {
+    target: Target,
+    closed: Cell<bool>,
+    on_abort: RefCell<Option<AbortFn>>
+}
+
+enum Target {
+    Js { stream: web_sys::WritableStream, writer: web_sys::WritableStreamDefaultWriter },
+    Channel(RefCell<Option<ChunkSender>>),
+    Fn(RefCell<Option<WriteFn>>)
+}
+
+/// Writes to `stream`, as `Uint8Array`s. Fails if the stream is already being written to.
+pub fn from_writable(stream: &web_sys::WritableStream) -> Result<ByteSink, GeneralError> {
+    let writer = stream.get_writer()?;
+    Ok(ByteSink::new(Target::Js { stream: stream.clone(), writer }))
+}
+
+impl ByteSink {
+    fn new(target: Target) -> Self {
+        ByteSink { target, closed: Cell::new(false), on_abort: RefCell::new(None) }
+    }
+
+    /// A sink which calls `write` with each chunk, and waits for the future it returns before
+    /// taking the next one.
+    pub fn from_fn<F>(mut write: impl FnMut(Vec<u8>) -> F + 'static) -> Self
+    where
+        F: Future<Output = Result<(), GeneralError>> + 'static
+    {
+        let write: WriteFn = Box::new(move |chunk| Box::pin(write(chunk)));
+        ByteSink::new(Target::Fn(RefCell::new(Some(write))))
+    }
+
+    /// Calls `f` with the reason if the sink is aborted, such as when a fetch body piped into
+    /// it fails partway through, so that what was written so far isn't mistaken for
+    /// everything.
+    pub fn on_abort(self, f: impl FnOnce(GeneralError) + 'static) -> Self {
+        self.on_abort.replace(Some(Box::new(f)));
+        self
+    }
+
+    pub async fn write(&self, bytes: &[u8]) -> Result<(), GeneralError> {
+        match &self.target {
+            Target::Js { writer, .. } => {
+                let chunk = js_sys::Uint8Array::from(bytes);
+                JsFuture::from(writer.write_with_chunk(&chunk)).await?;
+                Ok(())
+            }
+            Target::Channel(sender) => match &*sender.borrow() {
+                Some(sender) if sender.send(Ok(bytes.to_vec())).is_ok() => Ok(()),
+                _ => Err(closed())
+            },
+            Target::Fn(write) => {
+                // the borrow can't be held while waiting, in case the future writes again
+                let write = write.borrow_mut().as_mut().map(|write| write(bytes.to_vec()));
+                write.ok_or_else(closed)?.await
+            }
+        }
+    }
+
+    /// Waits for everything written to be taken, then closes the destination.
+    pub async fn close(self) -> Result<(), GeneralError> {
+        self.finish().await
+    }
+
+    async fn finish(&self) -> Result<(), GeneralError> {
+        self.closed.set(true);
+        match &self.target {
+            Target::Js { writer, .. } => {
+                JsFuture::from(writer.close()).await?;
+            }
+            Target::Channel(sender) => drop(sender.borrow_mut().take()),
+            Target::Fn(write) => drop(write.borrow_mut().take())
+        }
+        self.on_abort.borrow_mut().take();
+        Ok(())
+    }
+
+    /// Stops taking chunks because whatever was writing to this failed.
+    fn abort(&self, reason: JsValue) {
+        self.closed.set(true);
+        match &self.target {
+            Target::Js { writer, .. } => drop(writer.abort_with_reason(&reason)),
+            Target::Channel(sender) => if let Some(sender) = sender.borrow_mut().take() {
+                let _ = sender.send(Err(reason.clone().into()));
+            },
+            Target::Fn(write) => drop(write.borrow_mut().take())
+        }
+        let on_abort = self.on_abort.borrow_mut().take();
+        if let Some(f) = on_abort {
+            f(reason.into());
+        }
+    }
+}
+
+fn closed() -> GeneralError {
+    GeneralError::message("the sink is closed")
+}
+
+impl From<ChunkSender> for ByteSink {
+    /// A sink which sends each chunk on the channel, which doesn't wait for them to be
+    /// received. Closing the sink drops the sender, and aborting it sends the reason as an
+    /// error first.
+    fn from(sender: ChunkSender) -> Self {
+        ByteSink::new(Target::Channel(RefCell::new(Some(sender))))
+    }
+}
+
+impl Drop for ByteSink {
+    fn drop(&mut self) {
+        if !self.closed.get() {
+            self.abort(js_sys::Error::new("the sink was dropped without being closed").into());
+        }
+    }
+}
+
+type SinkWriteFn = Closure<dyn FnMut(JsValue) -> js_sys::Promise>;
+type SinkCloseFn = Closure<dyn FnMut() -> js_sys::Promise>;
+type SinkAbortFn = Closure<dyn FnMut(JsValue)>;
+type SinkCallbacks = Rc<RefCell<Option<(SinkWriteFn, SinkCloseFn, SinkAbortFn)>>>;
+
+/// A JS stream which writes to `sink`, such as for piping a fetch body or a
+/// `CompressionStream` into Rust. Accepts `Uint8Array`s, `ArrayBuffer`s and strings, which are
+/// written as UTF-8.
+///
+/// Each write waits for `sink` to take the chunk before the JS stream accepts another one, and
+/// aborting the JS stream aborts `sink`. A [`ByteSink`] from [`from_writable`] gives back the
+/// original JS stream instead.
+pub fn to_writable(sink: impl Into<ByteSink>) -> Result<web_sys::WritableStream, GeneralError> {
+    let sink = sink.into();
+    if let Target::Js { stream, writer } = &sink.target {
+        sink.closed.set(true);
+        writer.release_lock();
+        return Ok(stream.clone());
+    }
+    let sink = Rc::new(sink);
+    // like for to_readable, the callbacks drop themselves once the stream is closed or aborted
+    let callbacks: SinkCallbacks = Rc::default();
+    let write: SinkWriteFn = Closure::new({
+        let sink = sink.clone();
+        move |chunk: JsValue| {
+            let sink = sink.clone();
+            wasm_bindgen_futures::future_to_promise(async move {
+                let bytes = match chunk.as_string() {
+                    Some(text) => text.into_bytes(),
+                    None => js_sys::Uint8Array::new(&chunk).to_vec()
+                };
+                sink.write(&bytes).await?;
+                Ok(JsValue::UNDEFINED)
+            })
+        }
+    });
+    let close: SinkCloseFn = Closure::new({
+        let sink = sink.clone();
+        let callbacks = callbacks.clone();
+        move || {
+            let sink = sink.clone();
+            let callbacks = callbacks.clone();
+            wasm_bindgen_futures::future_to_promise(async move {
+                callbacks.borrow_mut().take();
+                sink.finish().await?;
+                Ok(JsValue::UNDEFINED)
+            })
+        }
+    });
+    let abort: SinkAbortFn = Closure::new({
+        let callbacks = callbacks.clone();
+        move |reason: JsValue| {
+            sink.abort(reason);
+            // this callback can't be dropped while it is running
+            let callbacks = callbacks.clone();
+            spawn_local(async move {
+                callbacks.borrow_mut().take();
+            });
+        }
+    });
+    let sink_object = js_sys::Object::new();
+    js_sys::Reflect::set(&sink_object, &"write".into(), write.as_ref())?;
+    js_sys::Reflect::set(&sink_object, &"close".into(), close.as_ref())?;
+    js_sys::Reflect::set(&sink_object, &"abort".into(), abort.as_ref())?;
+    *callbacks.borrow_mut() = Some((write, close, abort));
+    Ok(web_sys::WritableStream::new_with_underlying_sink(&sink_object)?)
+}
+
+type TransformFn = Closure<
+    dyn FnMut(JsValue, web_sys::TransformStreamDefaultController) -> js_sys::Promise
+>;
+type FlushFn = Closure<dyn FnMut(web_sys::TransformStreamDefaultController) -> js_sys::Promise>;
+type TransformCallbacks = Rc<RefCell<Option<(TransformFn, FlushFn)>>>;
+
+/// A JS stream which passes each chunk written to it through `f`, such as for decoding a fetch
+/// body before handing it to a JS API. Chunks are converted like for [`to_writable`], and
+/// empty outputs are skipped.
+///
+/// Each chunk waits for the future `f` returns before the next one is taken, so `f` can hand
+/// the work to a worker without it being done out of order.
+pub fn transform<F>(
+    f: impl FnMut(Vec<u8>) -> F + 'static
+) -> Result<web_sys::TransformStream, GeneralError>
+where
+    F: Future<Output = Result<Vec<u8>, GeneralError>> + 'static
+{
+    transform_with_flush(f, || async { Ok(vec![]) })
+}
+
+/// Like [`transform`], but calls `flush` once the input has ended, so that bytes held back
+/// waiting for more input, such as a partial line, can be passed on.
+pub fn transform_with_flush<F, G>(
+    mut f: impl FnMut(Vec<u8>) -> F + 'static,
+    flush: impl FnOnce() -> G + 'static
+) -> Result<web_sys::TransformStream, GeneralError>
+where
+    F: Future<Output = Result<Vec<u8>, GeneralError>> + 'static,
+    G: Future<Output = Result<Vec<u8>, GeneralError>> + 'static
+{
+    // like for to_readable, the callbacks drop themselves once the input has ended
+    let callbacks: TransformCallbacks = Rc::default();
+    let transform: TransformFn = Closure::new({
+        let callbacks = callbacks.clone();
+        move |chunk: JsValue, controller: web_sys::TransformStreamDefaultController| {
+            let bytes = match chunk.as_string() {
+                Some(text) => text.into_bytes(),
+                None => js_sys::Uint8Array::new(&chunk).to_vec()
+            };
+            let output = f(bytes);
+            let callbacks = callbacks.clone();
+            wasm_bindgen_futures::future_to_promise(async move {
+                match output.await {
+                    Ok(bytes) => enqueue(&controller, bytes)?,
+                    Err(e) => {
+                        // the error ends the stream, so nothing more will be transformed
+                        callbacks.borrow_mut().take();
+                        return Err(e.into());
+                    }
+                }
+                Ok(JsValue::UNDEFINED)
+            })
+        }
+    });
+    let mut flush = Some(flush);
+    let flush: FlushFn = Closure::new({
+        let callbacks = callbacks.clone();
+        move |controller: web_sys::TransformStreamDefaultController| {
+            let output = flush.take().map(|flush| flush());
+            let callbacks = callbacks.clone();
+            wasm_bindgen_futures::future_to_promise(async move {
+                callbacks.borrow_mut().take();
+                if let Some(output) = output {
+                    enqueue(&controller, output.await?)?;
+                }
+                Ok(JsValue::UNDEFINED)
+            })
+        }
+    });
+    let transformer = js_sys::Object::new();
+    js_sys::Reflect::set(&transformer, &"transform".into(), transform.as_ref())?;
+    js_sys::Reflect::set(&transformer, &"flush".into(), flush.as_ref())?;
+    *callbacks.borrow_mut() = Some((transform, flush));
+    Ok(web_sys::TransformStream::new_with_transformer(&transformer)?)
+}
+
+fn enqueue(
+    controller: &web_sys::TransformStreamDefaultController, bytes: Vec<u8>
+) -> Result<(), JsValue> {
+    if bytes.is_empty() {
+        return Ok(());
+    }
+    controller.enqueue_with_chunk(&js_sys::Uint8Array::from(&bytes[..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(chunks: &[&[u8]]) -> (Vec<String>, Vec<u8>) {
+        let mut buffer = vec![];
+        let mut scanned = 0;
+        let mut lines = vec![];
+        for chunk in chunks {
+            buffer.extend_from_slice(chunk);
+            while let Some(line) = take_line(&mut buffer, &mut scanned) {
+                lines.push(to_line(line));
+            }
+        }
+        (lines, buffer)
+    }
+
+    #[test]
+    fn splits_lines_across_chunks() {
+        let (lines, rest) = split(&[b"one\ntw", b"o\n", b"\nthree\nfo", b"ur"]);
+        assert_eq!(lines, ["one", "two", "", "three"]);
+        assert_eq!(rest, b"four");
+    }
+
+    #[test]
+    fn strips_carriage_returns() {
+        let (lines, rest) = split(&[b"one\r", b"\ntwo\r\n\r\n", b"a\rb\n"]);
+        assert_eq!(lines, ["one", "two", "", "a\rb"]);
+        assert!(rest.is_empty());
+        assert_eq!(to_line(b"last\r".to_vec()), "last");
+    }
+
+    #[test]
+    fn decodes_invalid_utf8_lossily() {
+        assert_eq!(to_line(vec![b'a', 0xFF, b'b']), "a\u{FFFD}b");
+    }
+}
@@ -0,0 +1,620 @@
+use crate::prelude::*;
+use crate::event::{ self, ListenerHandle };
+use crate::global::{ self, IntoDelay };
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{ Context, Poll, Waker };
+
+/// Spawns `f` on the current thread, returning a handle to wait for its output or abort it.
+///
+/// Dropping the handle detaches the task, which then keeps running to completion.
+pub fn spawn<T: 'static>(f: impl Future<Output = T> + 'static) -> JoinHandle<T> {
+    let state = Rc::new(RefCell::new(State {
+        output: None,
+        finished: false,
+        aborted: false,
+        task: None,
+        join: None,
+        scope: None
+    }));
+    spawn_local(Task { state: state.clone(), future: Box::pin(f) });
+    JoinHandle(state)
+}
+
+struct State<T> {
+    output: Option<T>,
+    finished: bool,
+    aborted: bool,
+    task: Option<Waker>,
+    join: Option<Waker>,
+    /// The [`Scope`] waiting for the task, if it was spawned in one.
+    scope: Option<Waker>
+}
+
+fn abort<T>(state: &RefCell<State<T>>) {
+    let (task, join, scope) = {
+        let mut state = state.borrow_mut();
+        if state.finished {
+            return;
+        }
+        state.aborted = true;
+        (state.task.take(), state.join.take(), state.scope.take())
+    };
+    // the task wakes up to drop its future, whoever waits on it to see the abort
+    for waker in task.into_iter().chain(join).chain(scope) {
+        waker.wake();
+    }
+}
+
+/// Handle to a task started with [`spawn`]. Resolves to the task's output, or `None` if the
+/// task was aborted.
+pub struct JoinHandle<T>(Rc<RefCell<State<T>>>);
+
+impl<T> JoinHandle<T> {
+    /// Aborts the task. Its future is dropped the next time the executor would have run it,
+    /// without being polled again.
+    ///
+    /// Does nothing if the task already finished.
+    pub fn abort(&self) {
+        abort(&self.0);
+    }
+
+    /// Whether the task has completed or been aborted.
+    pub fn is_finished(&self) -> bool {
+        let state = self.0.borrow();
+        state.finished || state.aborted
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        let mut state = self.0.borrow_mut();
+        if state.finished {
+            Poll::Ready(state.output.take())
+        } else if state.aborted {
+            Poll::Ready(None)
+        } else {
+            state.join = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+struct Task<T> {
+    state: Rc<RefCell<State<T>>>,
+    future: Pin<Box<dyn Future<Output = T>>>
+}
+
+impl<T> Future for Task<T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        {
+            let mut state = self.state.borrow_mut();
+            if state.aborted {
+                return Poll::Ready(());
+            }
+            state.task = Some(cx.waker().clone());
+        }
+        let output = match self.future.as_mut().poll(cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => return Poll::Pending
+        };
+        let (join, scope) = {
+            let mut state = self.state.borrow_mut();
+            state.output = Some(output);
+            state.finished = true;
+            state.task = None;
+            (state.join.take(), state.scope.take())
+        };
+        for waker in join.into_iter().chain(scope) {
+            waker.wake();
+        }
+        Poll::Ready(())
+    }
+}
+
+/// Runs `f` with a [`Scope`] for spawning tasks, then waits for all tasks spawned in the scope
+/// to complete before returning `f`'s result.
+///
+/// If the returned future is dropped before then, the remaining tasks are aborted, so tasks
+/// tied to a component can't outlive it.
+///
+/// ```ignore
+/// task::scope(|s| {
+///     s.spawn(load_textures());
+///     s.spawn(load_sounds());
+/// }).await;
+/// ```
+pub async fn scope<R>(f: impl FnOnce(&Scope) -> R) -> R {
+    let scope = Scope(Rc::new(RefCell::new(ScopeState { children: vec![], closed: false })));
+    let guard = CloseOnDrop(&scope);
+    let result = f(&scope);
+    std::future::poll_fn(|cx| {
+        // tasks may spawn more tasks into the scope while we wait
+        let mut state = scope.0.borrow_mut();
+        state.children.retain(|child| !child.poll_finished(cx));
+        if state.children.is_empty() { Poll::Ready(()) } else { Poll::Pending }
+    }).await;
+    drop(guard);
+    result
+}
+
+/// Spawns tasks which are waited for or aborted by [`scope`]. Clones refer to the same scope,
+/// so tasks can spawn more tasks into it.
+#[derive(Clone)]
+pub struct Scope(Rc<RefCell<ScopeState>>);
+
+struct ScopeState {
+    children: Vec<Rc<dyn Child>>,
+    closed: bool
+}
+
+trait Child {
+    fn abort(&self);
+    fn poll_finished(&self, cx: &mut Context) -> bool;
+}
+
+impl<T> Child for RefCell<State<T>> {
+    fn abort(&self) {
+        abort(self);
+    }
+
+    fn poll_finished(&self, cx: &mut Context) -> bool {
+        let mut state = self.borrow_mut();
+        if state.finished || state.aborted {
+            return true;
+        }
+        state.scope = Some(cx.waker().clone());
+        false
+    }
+}
+
+impl Scope {
+    /// Spawns `f` as a task of the scope. Tasks spawned after the scope has ended are aborted
+    /// immediately.
+    pub fn spawn<T: 'static>(&self, f: impl Future<Output = T> + 'static) -> JoinHandle<T> {
+        let handle = spawn(f);
+        let closed = self.0.borrow().closed;
+        if closed {
+            handle.abort();
+        } else {
+            self.0.borrow_mut().children.push(handle.0.clone());
+        }
+        handle
+    }
+
+    /// Aborts all tasks currently running in the scope.
+    pub fn cancel(&self) {
+        let children = std::mem::take(&mut self.0.borrow_mut().children);
+        for child in children {
+            child.abort();
+        }
+    }
+}
+
+struct CloseOnDrop<'a>(&'a Scope);
+
+impl Drop for CloseOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.0.borrow_mut().closed = true;
+        self.0.cancel();
+    }
+}
+
+/// How [`retry`] spaces out attempts: exponential backoff with random jitter.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: f64,
+    max_delay: f64,
+    multiplier: f64,
+    jitter: f64
+}
+
+impl RetryPolicy {
+    /// 5 attempts, with delays starting at 100ms and doubling up to 10 seconds, each shortened
+    /// by up to half at random.
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_delay: 100.0,
+            max_delay: 10_000.0,
+            multiplier: 2.0,
+            jitter: 0.5
+        }
+    }
+
+    /// The total number of attempts, including the first. 0 is treated as 1.
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// The delay before the first retry, and the limit the delays grow to.
+    pub fn backoff(mut self, initial: impl IntoDelay, max: impl IntoDelay) -> Self {
+        self.initial_delay = initial.into_millis();
+        self.max_delay = max.into_millis();
+        self
+    }
+
+    /// How much the delay grows after each retry.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// The fraction, from 0 to 1, by which each delay is randomly shortened so that clients
+    /// failing together don't retry in lockstep.
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The total number of attempts allowed, at least 1.
+    pub(crate) fn attempts(&self) -> u32 {
+        self.max_attempts.max(1)
+    }
+
+    /// The delay after `retry` previous retries.
+    pub(crate) fn delay(&self, retry: u32) -> f64 {
+        let delay = self.initial_delay * self.multiplier.powi(retry as i32);
+        delay.min(self.max_delay) * (1.0 - self.jitter * js_sys::Math::random())
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new()
+    }
+}
+
+/// Calls `f` until the future it returns succeeds or the attempts allowed by `policy` run out,
+/// returning the last error in the latter case.
+pub async fn retry<T, E, F: Future<Output = Result<T, E>>>(
+    policy: &RetryPolicy, f: impl FnMut() -> F
+) -> Result<T, E> {
+    retry_if(policy, f, |_| true).await
+}
+
+/// Like [`retry`], but gives up immediately on errors for which `retryable` returns `false`.
+pub async fn retry_if<T, E, F: Future<Output = Result<T, E>>>(
+    policy: &RetryPolicy, mut f: impl FnMut() -> F, mut retryable: impl FnMut(&E) -> bool
+) -> Result<T, E> {
+    let mut retries = 0;
+    loop {
+        match f().await {
+            Ok(v) => return Ok(v),
+            Err(e) if retries + 1 >= policy.attempts() || !retryable(&e) => return Err(e),
+            Err(_) => {}
+        }
+        global::sleep(policy.delay(retries)).await;
+        retries += 1;
+    }
+}
+
+/// Calls `f` on each item of `iter`, yielding to the browser with [`global::yield_now`]
+/// whenever a slice of work has taken longer than `budget`, so CPU-heavy loops can run on the
+/// main thread without freezing the UI.
+///
+/// At least one item is processed per slice, so a single slow item can still exceed the
+/// budget.
+pub async fn run_chunked<I: IntoIterator>(
+    iter: I, budget: impl IntoDelay, mut f: impl FnMut(I::Item)
+) {
+    let budget = budget.into_millis();
+    let mut slice_start = global::now();
+    for item in iter {
+        f(item);
+        if global::now() - slice_start >= budget {
+            global::yield_now().await;
+            slice_start = global::now();
+        }
+    }
+}
+
+/// Combinators for local futures, in the prelude.
+pub trait WebFutureExt: Future + Sized {
+    /// Resolves to `Err(TimeoutError)` if the future doesn't complete within `delay`, dropping
+    /// it. The delay starts when the returned future is first polled.
+    fn timeout(
+        self, delay: impl IntoDelay
+    ) -> impl Future<Output = Result<Self::Output, TimeoutError>> {
+        let delay = delay.into_millis();
+        async move {
+            let mut f = Box::pin(self);
+            let mut sleep = global::sleep(delay);
+            std::future::poll_fn(|cx| {
+                if let Poll::Ready(v) = f.as_mut().poll(cx) {
+                    Poll::Ready(Ok(v))
+                } else if Pin::new(&mut sleep).poll(cx).is_ready() {
+                    Poll::Ready(Err(TimeoutError))
+                } else {
+                    Poll::Pending
+                }
+            }).await
+        }
+    }
+
+    /// Resolves to `None` if `token` is cancelled first. See [`CancelToken::run`].
+    fn with_cancel(self, token: &CancelToken) -> impl Future<Output = Option<Self::Output>> {
+        let token = token.clone();
+        async move { token.run(self).await }
+    }
+
+    /// Resolves to the output of whichever future completes first, dropping the other.
+    fn race(
+        self, other: impl Future<Output = Self::Output>
+    ) -> impl Future<Output = Self::Output> {
+        async move {
+            let mut a = Box::pin(self);
+            let mut b = Box::pin(other);
+            std::future::poll_fn(|cx| match a.as_mut().poll(cx) {
+                Poll::Ready(v) => Poll::Ready(v),
+                Poll::Pending => b.as_mut().poll(cx)
+            }).await
+        }
+    }
+
+    /// Spawns the future on the current thread. See [`spawn`].
+    fn spawn(self) -> JoinHandle<Self::Output> where Self: 'static {
+        spawn(self)
+    }
+}
+
+impl<F: Future> WebFutureExt for F {}
+
+/// Error returned by [`WebFutureExt::timeout`].
+#[derive(Debug, Eq, PartialEq)]
+pub struct TimeoutError;
+
+/// A cancellation flag shared between Rust and JS, backed by an `AbortController`.
+///
+/// Pass [`signal`](CancelToken::signal) to web APIs taking an `AbortSignal`, such as `fetch`,
+/// and wrap Rust futures in [`run`](CancelToken::run), so that one [`cancel`](CancelToken::cancel)
+/// stops both. Clones share the same flag.
+#[derive(Clone)]
+pub struct CancelToken {
+    controller: web_sys::AbortController,
+    signal: web_sys::AbortSignal,
+    /// Listener forwarding the parent signal's abort, removed once the last clone is dropped.
+    _parent: Option<Rc<ListenerHandle>>
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        let controller = web_sys::AbortController::new().unwrap();
+        let signal = controller.signal();
+        CancelToken { controller, signal, _parent: None }
+    }
+
+    /// Creates a token which is cancelled when `signal` aborts, such as one received from JS.
+    pub fn from_signal(signal: &web_sys::AbortSignal) -> Self {
+        let mut token = CancelToken::new();
+        token._parent = token.follow(signal).map(Rc::new);
+        token
+    }
+
+    /// Creates a token which is cancelled along with this one, but can also be cancelled on
+    /// its own without affecting this one.
+    pub fn child(&self) -> Self {
+        CancelToken::from_signal(&self.signal)
+    }
+
+    fn follow(&self, parent: &web_sys::AbortSignal) -> Option<ListenerHandle> {
+        if parent.aborted() {
+            self.controller.abort_with_reason(&parent.reason());
+            return None;
+        }
+        let controller = self.controller.clone();
+        let p = parent.clone();
+        Some(parent.add_event_listener_once(move |_: event::Abort| {
+            controller.abort_with_reason(&p.reason());
+        }))
+    }
+
+    pub fn cancel(&self) {
+        self.controller.abort();
+    }
+
+    /// Cancels the token with `reason`, which is what web APIs using the signal reject with.
+    pub fn cancel_with_reason(&self, reason: &JsValue) {
+        self.controller.abort_with_reason(reason);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.signal.aborted()
+    }
+
+    /// The reason the token was cancelled with, if it was.
+    pub fn reason(&self) -> Option<JsValue> {
+        if self.is_cancelled() { Some(self.signal.reason()) } else { None }
+    }
+
+    pub fn signal(&self) -> &web_sys::AbortSignal {
+        &self.signal
+    }
+
+    /// Waits until the token is cancelled.
+    pub async fn cancelled(&self) {
+        if !self.is_cancelled() {
+            self.signal.once::<event::Abort>().await;
+        }
+    }
+
+    /// Runs `f` until it completes or the token is cancelled, returning `None` in the latter
+    /// case. `f` is dropped as soon as the token is cancelled.
+    pub async fn run<T>(&self, f: impl Future<Output = T>) -> Option<T> {
+        if self.is_cancelled() {
+            return None;
+        }
+        let mut f = Box::pin(f);
+        let mut cancelled = Box::pin(self.cancelled());
+        std::future::poll_fn(|cx| {
+            if let Poll::Ready(v) = f.as_mut().poll(cx) {
+                Poll::Ready(Some(v))
+            } else if cancelled.as_mut().poll(cx).is_ready() {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            }
+        }).await
+    }
+}
+
+impl std::fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CancelToken").field("cancelled", &self.is_cancelled()).finish()
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        CancelToken::new()
+    }
+}
+
+impl From<web_sys::AbortSignal> for CancelToken {
+    fn from(signal: web_sys::AbortSignal) -> Self {
+        CancelToken::from_signal(&signal)
+    }
+}
+
+impl From<CancelToken> for web_sys::AbortSignal {
+    fn from(token: CancelToken) -> Self {
+        token.signal
+    }
+}
+
+/// Waits for the first of `futures` to complete and returns its output. The others are
+/// dropped.
+///
+/// Futures of different types can be raced by boxing them, for example into
+/// `Pin<Box<dyn Future<Output = T>>>`.
+///
+/// Panics if `futures` is empty.
+pub async fn race<F: Future>(futures: impl IntoIterator<Item = F>) -> F::Output {
+    let mut futures: Vec<_> = futures.into_iter().map(Box::pin).collect();
+    assert!(!futures.is_empty(), "race of no futures");
+    std::future::poll_fn(|cx| {
+        for future in &mut futures {
+            if let Poll::Ready(v) = future.as_mut().poll(cx) {
+                return Poll::Ready(v);
+            }
+        }
+        Poll::Pending
+    }).await
+}
+
+/// Waits for the first of `futures` to complete successfully and returns its output. If they
+/// all fail, returns their errors in the order the futures were given.
+pub async fn race_ok<'a, T, E, F>(futures: impl IntoIterator<Item = F>) -> Result<T, Vec<E>>
+where
+    F: Future<Output = Result<T, E>> + 'a
+{
+    let mut futures: Vec<_> = futures.into_iter().map(MaybeDone::new).collect();
+    std::future::poll_fn(|cx| {
+        let mut done = true;
+        for future in &mut futures {
+            if !future.poll(cx) {
+                done = false;
+            } else if let Some(Ok(_)) = future.output {
+                return Poll::Ready(());
+            }
+        }
+        if done { Poll::Ready(()) } else { Poll::Pending }
+    }).await;
+
+    let mut errors = vec![];
+    for future in futures {
+        match future.output {
+            Some(Ok(v)) => return Ok(v),
+            Some(Err(e)) => errors.push(e),
+            None => {}
+        }
+    }
+    Err(errors)
+}
+
+/// Waits for all of `futures` to complete and returns their outputs in order. See
+/// [`join!`](crate::join) for futures of different types.
+pub async fn join_all<'a, F>(futures: impl IntoIterator<Item = F>) -> Vec<F::Output>
+where
+    F: Future + 'a
+{
+    let mut futures: Vec<_> = futures.into_iter().map(MaybeDone::new).collect();
+    std::future::poll_fn(|cx| {
+        let mut done = true;
+        for future in &mut futures {
+            done &= future.poll(cx);
+        }
+        if done { Poll::Ready(()) } else { Poll::Pending }
+    }).await;
+    futures.iter_mut().map(MaybeDone::take).collect()
+}
+
+/// A future which keeps its output once it completes, for polling several futures together.
+#[doc(hidden)]
+pub struct MaybeDone<'a, T> {
+    future: Option<Pin<Box<dyn Future<Output = T> + 'a>>>,
+    output: Option<T>
+}
+
+impl<'a, T> MaybeDone<'a, T> {
+    pub fn new(future: impl Future<Output = T> + 'a) -> Self {
+        MaybeDone { future: Some(Box::pin(future)), output: None }
+    }
+
+    /// Polls the future if it hasn't completed yet, returning whether it has.
+    pub fn poll(&mut self, cx: &mut Context) -> bool {
+        if let Some(future) = &mut self.future {
+            if let Poll::Ready(v) = future.as_mut().poll(cx) {
+                self.output = Some(v);
+                self.future = None;
+            }
+        }
+        self.future.is_none()
+    }
+
+    pub fn take(&mut self) -> T {
+        self.output.take().expect("future not done")
+    }
+}
+
+/// Waits for all the given futures to complete, returning a tuple of their outputs. Must be
+/// used in an `async` context.
+///
+/// ```ignore
+/// let (a, b) = webutil::join!(load_texture(), fonts::ready());
+/// ```
+#[macro_export]
+macro_rules! join {
+    // give each future the list of `_` patterns which skip to its position in the tuple
+    (@ ( $($count:tt)* ) $( ( $($skip:tt)* ) $e:expr, )* ; $head:expr, $($tail:expr,)*) => {
+        $crate::join!(
+            @ ( $($count)* _ ) $( ( $($skip)* ) $e, )* ( $($count)* ) $head, ; $($tail,)*
+        )
+    };
+    (@ ( $($count:tt)* ) $( ( $($skip:tt)* ) $e:expr, )* ;) => {{
+        let mut futures = ( $( $crate::task::MaybeDone::new($e), )* );
+        ::std::future::poll_fn(|cx| {
+            let mut done = true;
+            $({
+                let ( $($skip,)* future, .. ) = &mut futures;
+                done &= future.poll(cx);
+            })*
+            if done { ::std::task::Poll::Ready(()) } else { ::std::task::Poll::Pending }
+        }).await;
+        ( $({
+            let ( $($skip,)* future, .. ) = &mut futures;
+            future.take()
+        },)* )
+    }};
+    ($($e:expr),+ $(,)?) => {
+        $crate::join!(@ () ; $($e,)*)
+    };
+}
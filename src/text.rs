@@ -0,0 +1,92 @@
+//! Decoding streams of bytes into text, for reading text formats like CSV as they download.
+//!
+//! ```ignore
+//! let body = http::get("/scores.csv").send().await?.body_stream()?;
+//! let text = text::decode_stream(body, "utf-8")?;
+//! while let Some(piece) = text.next().await {
+//!     parser.feed(&piece?);
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, channel };
+use crate::streams::ByteStream;
+use std::cell::Cell;
+
+/// Text decoded from a [`ByteStream`], from [`decode_stream`].
+pub struct TextStream {
+    bytes: ByteStream,
+    decoder: web_sys::TextDecoder,
+    ended: Cell<bool>
+}
+
+/// Decodes `bytes` as text in `encoding`, which is a label such as `utf-8`, `utf-16le` or
+/// `windows-1252`. Characters split between chunks are decoded whole, and invalid bytes are
+/// replaced with `U+FFFD`. Fails if the encoding isn't supported.
+pub fn decode_stream(
+    bytes: impl Into<ByteStream>, encoding: &str
+) -> Result<TextStream, GeneralError> {
+    Ok(TextStream {
+        bytes: bytes.into(),
+        decoder: web_sys::TextDecoder::new_with_label(encoding)?,
+        ended: Cell::new(false)
+    })
+}
+
+impl TextStream {
+    /// The encoding being decoded, by its standard name.
+    pub fn encoding(&self) -> String {
+        self.decoder.encoding()
+    }
+
+    /// Decodes the next piece of text, or returns `None` once the bytes have ended. Pieces are
+    /// never empty.
+    pub async fn next(&self) -> Option<Result<String, GeneralError>> {
+        if self.ended.get() {
+            return None;
+        }
+        let options = web_sys::TextDecodeOptions::new();
+        options.set_stream(true);
+        loop {
+            let text = match self.bytes.next().await {
+                Some(Ok(chunk)) => self.decoder.decode_with_u8_array_and_options(&chunk, &options),
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    // a character left incomplete at the end becomes U+FFFD
+                    self.ended.set(true);
+                    self.decoder.decode()
+                }
+            };
+            match text {
+                Ok(text) if text.is_empty() && !self.ended.get() => {}
+                Ok(text) if text.is_empty() => return None,
+                Ok(text) => return Some(Ok(text)),
+                Err(e) => return Some(Err(e.into()))
+            }
+        }
+    }
+
+    /// Decodes all of the text.
+    pub async fn read_all(self) -> Result<String, GeneralError> {
+        let mut text = String::new();
+        while let Some(piece) = self.next().await {
+            text.push_str(&piece?);
+        }
+        Ok(text)
+    }
+}
+
+/// Encodes text sent on `text` as UTF-8, such as for streaming a generated file to
+/// [`streams::to_readable`](crate::streams::to_readable). The stream ends once every sender is
+/// dropped.
+pub fn encode_stream(text: Receiver<String>) -> ByteStream {
+    let (sender, bytes) = channel();
+    spawn_local(async move {
+        while let Some(text) = text.recv().await {
+            if sender.send(text.into_bytes()).is_err() {
+                break;
+            }
+        }
+    });
+    ByteStream::from(bytes)
+}
@@ -0,0 +1,153 @@
+use crate::global::{ self, IntervalHandle, IntoDelay };
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{ BinaryHeap, HashMap };
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::{ Rc, Weak };
+use std::task::{ Context, Poll, Waker };
+
+/// Multiplexes many timers onto a single JS interval, for apps with thousands of concurrent
+/// timeouts where a closure and JS timer for each one would be too costly.
+///
+/// Timers fire on the first tick of the interval after their deadline, so they can be late by
+/// up to the wheel's resolution. The interval only runs while there are pending timers.
+///
+/// Dropping the wheel completes all of its pending sleeps immediately.
+pub struct Wheel(Rc<RefCell<WheelState>>);
+
+struct WheelState {
+    resolution: f64,
+    deadlines: BinaryHeap<Reverse<(u64, u64)>>,
+    timers: HashMap<u64, Timer>,
+    next_id: u64,
+    ticker: Option<IntervalHandle>
+}
+
+#[derive(Default)]
+struct Timer {
+    fired: bool,
+    waker: Option<Waker>
+}
+
+impl Wheel {
+    /// Creates a wheel which checks for expired timers every `resolution`.
+    pub fn new(resolution: impl IntoDelay) -> Self {
+        Wheel(Rc::new(RefCell::new(WheelState {
+            resolution: resolution.into_millis(),
+            deadlines: BinaryHeap::new(),
+            timers: HashMap::new(),
+            next_id: 0,
+            ticker: None
+        })))
+    }
+
+    /// Returns a future which resolves at the time `deadline` as returned by
+    /// [`global::now`]. Dropping the future cancels the timer.
+    pub fn sleep_until(&self, deadline: f64) -> WheelSleep {
+        let mut state = self.0.borrow_mut();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.deadlines.push(Reverse((deadline.max(0.0).ceil() as u64, id)));
+        state.timers.insert(id, Timer::default());
+        if state.ticker.is_none() {
+            let wheel = Rc::downgrade(&self.0);
+            state.ticker = Some(global::set_interval(state.resolution, move || tick(&wheel)));
+        }
+        WheelSleep { wheel: Rc::downgrade(&self.0), id }
+    }
+
+    /// Returns a future which resolves after `delay`.
+    pub fn sleep(&self, delay: impl IntoDelay) -> WheelSleep {
+        self.sleep_until(global::now() + delay.into_millis())
+    }
+
+    /// The number of pending timers.
+    pub fn len(&self) -> usize {
+        self.0.borrow().timers.values().filter(|t| !t.fired).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for Wheel {
+    fn drop(&mut self) {
+        let mut state = self.0.borrow_mut();
+        state.ticker = None;
+        let wakers: Vec<_> = state.timers.values_mut()
+            .filter_map(|timer| {
+                timer.fired = true;
+                timer.waker.take()
+            })
+            .collect();
+        drop(state);
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+fn tick(wheel: &Weak<RefCell<WheelState>>) {
+    let wheel = match wheel.upgrade() {
+        Some(wheel) => wheel,
+        None => return
+    };
+    let now = global::now();
+    let mut wakers = vec![];
+    let mut state = wheel.borrow_mut();
+    while let Some(&Reverse((deadline, id))) = state.deadlines.peek() {
+        if deadline as f64 > now {
+            break;
+        }
+        state.deadlines.pop();
+        // cancelled timers are no longer in the map
+        if let Some(timer) = state.timers.get_mut(&id) {
+            timer.fired = true;
+            wakers.extend(timer.waker.take());
+        }
+    }
+    if state.deadlines.is_empty() {
+        state.ticker = None;
+    }
+    drop(state);
+    for waker in wakers {
+        waker.wake();
+    }
+}
+
+/// Future returned by [`Wheel::sleep_until`] and [`Wheel::sleep`].
+pub struct WheelSleep {
+    wheel: Weak<RefCell<WheelState>>,
+    id: u64
+}
+
+impl Future for WheelSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let wheel = match self.wheel.upgrade() {
+            Some(wheel) => wheel,
+            // the wheel completed its sleeps when it was dropped
+            None => return Poll::Ready(())
+        };
+        let mut state = wheel.borrow_mut();
+        match state.timers.get_mut(&self.id) {
+            Some(timer) if timer.fired => Poll::Ready(()),
+            Some(timer) => {
+                timer.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            None => Poll::Pending
+        }
+    }
+}
+
+impl Drop for WheelSleep {
+    fn drop(&mut self) {
+        if let Some(wheel) = self.wheel.upgrade() {
+            wheel.borrow_mut().timers.remove(&self.id);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Picking or dropping files and uploading them with progress.
+//!
+//! ```ignore
+//! let drop_zone = DropZone::new(&element);
+//! let uploader = Uploader::new(|file| http::post(&format!("/upload/{}", file.name())))
+//!     .chunk_size(1 << 20);
+//! loop {
+//!     let files = drop_zone.next().await;
+//!     let events = uploader.upload(files.clone());
+//!     while let Some(event) = events.recv().await {
+//!         match event {
+//!             UploadEvent::Progress { file, loaded, total } => show(&files[file], loaded, total),
+//!             UploadEvent::Done { file, result } => finish(&files[file], result)
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{ Receiver, Sender, WatchReceiver, channel, watch };
+use crate::event::{ self, ListenerHandle };
+use crate::file;
+use crate::global::GlobalScope;
+use crate::http::{ RequestBuilder, Response };
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+
+/// Asks the user to pick files with a file input. `accept` lists the MIME types and
+/// extensions to offer, such as `"image/*,.json"`, or is empty to offer any file.
+///
+/// Returns no files if the user cancels. Some browsers don't report cancelling, in which case
+/// this never finishes.
+pub async fn pick_files(accept: &str, multiple: bool) -> Result<Vec<web_sys::File>, GeneralError> {
+    let document = match GlobalScope::current() {
+        GlobalScope::Window(window) => window.document(),
+        _ => None
+    };
+    let document = document.ok_or_else(|| {
+        GeneralError::message("file inputs are only available in windows")
+    })?;
+    let input: web_sys::HtmlInputElement = document.create_element("input")?.unchecked_into();
+    input.set_type("file");
+    input.set_multiple(multiple);
+    input.set_accept(accept);
+
+    let change = input.once::<event::Change>();
+    let cancel = input.once::<event::Cancel>();
+    input.click();
+    let picked = async { change.await; true }.race(async { cancel.await; false }).await;
+    match input.files() {
+        Some(files) if picked => Ok(file::files(&files)),
+        _ => Ok(vec![])
+    }
+}
+
+/// Files dropped on an element.
+///
+/// The element accepts drops for as long as this exists.
+pub struct DropZone {
+    drops: Receiver<Vec<web_sys::File>>,
+    hovering: WatchReceiver<bool>,
+    _listeners: [ListenerHandle; 4]
+}
+
+impl DropZone {
+    pub fn new(target: &web_sys::EventTarget) -> Self {
+        let (sender, drops) = channel();
+        let (hover, hovering) = watch(false);
+        let hover = Rc::new(hover);
+        // enter and leave fire for every child element too, so they are counted
+        let depth = Rc::new(Cell::new(0u32));
+
+        let over = target.add_event_listener(|e: event::DragOver| e.prevent_default());
+        let enter = target.add_event_listener({
+            let (hover, depth) = (hover.clone(), depth.clone());
+            move |e: event::DragEnter| {
+                e.prevent_default();
+                depth.set(depth.get() + 1);
+                hover.set_if_changed(true);
+            }
+        });
+        let leave = target.add_event_listener({
+            let (hover, depth) = (hover.clone(), depth.clone());
+            move |_: event::DragLeave| {
+                depth.set(depth.get().saturating_sub(1));
+                hover.set_if_changed(depth.get() > 0);
+            }
+        });
+        let drop = target.add_event_listener(move |e: event::DragDrop| {
+            e.prevent_default();
+            depth.set(0);
+            hover.set_if_changed(false);
+            let files = e.data_transfer().and_then(|data| data.files());
+            if let Some(files) = files {
+                let _ = sender.send(file::files(&files));
+            }
+        });
+
+        DropZone { drops, hovering, _listeners: [over, enter, leave, drop] }
+    }
+
+    pub fn try_next(&self) -> Option<Vec<web_sys::File>> {
+        self.drops.try_recv().ok()
+    }
+
+    /// Waits for files to be dropped.
+    pub async fn next(&self) -> Vec<web_sys::File> {
+        self.drops.recv().await.unwrap()
+    }
+
+    /// Whether files are being dragged over the element, such as for highlighting it.
+    pub fn hovering(&self) -> WatchReceiver<bool> {
+        self.hovering.clone()
+    }
+}
+
+/// Progress and results of an upload started with [`Uploader::upload`]. Files are identified
+/// by their index in the list being uploaded.
+#[derive(Debug)]
+pub enum UploadEvent {
+    Progress { file: usize, loaded: u64, total: u64 },
+    /// The file has been uploaded, with the response to its last request, or failed.
+    Done { file: usize, result: Result<Response, GeneralError> }
+}
+
+type MakeRequest = dyn Fn(&web_sys::File) -> RequestBuilder;
+
+/// Uploads files one at a time, with a request built for each.
+#[derive(Clone)]
+pub struct Uploader {
+    request: Rc<MakeRequest>,
+    chunk_size: Option<usize>
+}
+
+impl Uploader {
+    /// Uploads each file with the request returned by `request`, which the file is set as the
+    /// body of.
+    pub fn new(request: impl Fn(&web_sys::File) -> RequestBuilder + 'static) -> Self {
+        Uploader { request: Rc::new(request), chunk_size: None }
+    }
+
+    /// Uploads files in chunks of `size` bytes, each with its own request marked with a
+    /// `Content-Range` header, so that large files don't have to fit in memory at once.
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "chunk size must be positive");
+        self.chunk_size = Some(size);
+        self
+    }
+
+    /// Starts uploading `files`. Dropping the returned receiver stops after the current
+    /// request.
+    pub fn upload(&self, files: Vec<web_sys::File>) -> Receiver<UploadEvent> {
+        let (sender, events) = channel();
+        let uploader = self.clone();
+        spawn_local(async move {
+            for (i, file) in files.iter().enumerate() {
+                let result = uploader.upload_file(i, file, &sender).await;
+                if sender.send(UploadEvent::Done { file: i, result }).is_err() {
+                    return;
+                }
+            }
+        });
+        events
+    }
+
+    async fn upload_file(
+        &self, i: usize, file: &web_sys::File, events: &Sender<UploadEvent>
+    ) -> Result<Response, GeneralError> {
+        let total = file.size() as u64;
+        let chunk_size = match self.chunk_size {
+            Some(size) => size,
+            None => {
+                let body = file::read_bytes(file).await?;
+                return send(i, (self.request)(file).body_bytes(body), 0, total, events).await;
+            }
+        };
+
+        // each chunk is read just before it is sent, so only one is in memory at a time
+        let mut start = 0;
+        let mut last = None;
+        while start < total {
+            let end = (start + chunk_size as u64).min(total);
+            let chunk = file::read_bytes(&file.slice_with_f64_and_f64(start as f64, end as f64)?)
+                .await?;
+            let range = format!("bytes {}-{}/{}", start, end - 1, total);
+            let request = (self.request)(file).header("Content-Range", &range).body_bytes(chunk);
+            last = Some(send(i, request, start, total, events).await?);
+            start = end;
+        }
+        match last {
+            Some(response) => Ok(response),
+            // an empty file still gets a request
+            None => send(i, (self.request)(file).body_bytes(vec![]), 0, 0, events).await
+        }
+    }
+}
+
+/// Sends part of a file starting at `offset`, forwarding its progress as the file's.
+async fn send(
+    i: usize, request: RequestBuilder, offset: u64, total: u64, events: &Sender<UploadEvent>
+) -> Result<Response, GeneralError> {
+    let (request, progress) = request.upload_progress();
+    let forward = {
+        let events = events.clone();
+        async move {
+            while let Some(progress) = progress.recv().await {
+                let loaded = offset + progress.loaded;
+                let _ = events.send(UploadEvent::Progress { file: i, loaded, total });
+            }
+        }
+    };
+    let response = request.send().race(async { forward.await; std::future::pending().await })
+        .await?
+        .error_for_status()?;
+    Ok(response)
+}
@@ -0,0 +1,178 @@
+//! Parsing URLs, with query strings and fragments decoded into serde types.
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Join { room: String, spectate: Option<bool> }
+//!
+//! let join: Join = Url::current()?.query()?;
+//! let mut invite = Url::current()?;
+//! invite.set_query(&Join { room: "abc".into(), spectate: None })?;
+//! ```
+//!
+//! Fragments are often used for parameters that shouldn't be sent to the server, which
+//! [`Url::fragment_params`] decodes the same way.
+
+use crate::prelude::*;
+use crate::global::GlobalScope;
+use serde::{ Serialize, de::DeserializeOwned };
+use std::fmt;
+
+mod object;
+mod query;
+pub use object::*;
+pub use query::*;
+
+/// Errors from parsing URLs and query strings.
+#[derive(Debug)]
+pub enum UrlError {
+    /// The URL isn't valid, or isn't a valid relative URL for the base it was parsed with.
+    Invalid(String),
+    /// The query string or fragment couldn't be converted to or from the type.
+    Query(String)
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UrlError::Invalid(url) => write!(f, "invalid URL: {}", url),
+            UrlError::Query(msg) => write!(f, "invalid query: {}", msg)
+        }
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+impl serde::de::Error for UrlError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        UrlError::Query(msg.to_string())
+    }
+}
+
+impl From<UrlError> for GeneralError {
+    fn from(e: UrlError) -> Self {
+        GeneralError::message(&e.to_string())
+    }
+}
+
+/// A parsed URL.
+#[derive(Clone, Debug)]
+pub struct Url(web_sys::Url);
+
+impl Url {
+    /// Parses an absolute URL.
+    pub fn parse(url: &str) -> Result<Self, UrlError> {
+        web_sys::Url::new(url).map(Url).map_err(|_| UrlError::Invalid(url.to_owned()))
+    }
+
+    /// Parses `url` relative to `base`.
+    pub fn parse_with_base(url: &str, base: &str) -> Result<Self, UrlError> {
+        web_sys::Url::new_with_base(url, base)
+            .map(Url)
+            .map_err(|_| UrlError::Invalid(url.to_owned()))
+    }
+
+    /// Parses `url` relative to the page or worker script, like links and `fetch` do.
+    pub fn resolve(url: &str) -> Result<Self, UrlError> {
+        match GlobalScope::current().location_href() {
+            Some(base) => Self::parse_with_base(url, &base),
+            None => Self::parse(url)
+        }
+    }
+
+    /// The URL of the page or worker script.
+    pub fn current() -> Result<Self, UrlError> {
+        let href = GlobalScope::current().location_href()
+            .ok_or_else(|| UrlError::Invalid(String::new()))?;
+        Self::parse(&href)
+    }
+
+    pub fn href(&self) -> String {
+        self.0.href()
+    }
+
+    /// The scheme, host and port, such as `https://example.com:8080`.
+    pub fn origin(&self) -> String {
+        self.0.origin()
+    }
+
+    pub fn host(&self) -> String {
+        self.0.host()
+    }
+
+    /// The path, which starts with a `/` for URLs like `http` ones.
+    pub fn path(&self) -> String {
+        self.0.pathname()
+    }
+
+    pub fn set_path(&mut self, path: &str) {
+        self.0.set_pathname(path);
+    }
+
+    /// The query string without the leading `?`, which is empty if there isn't one.
+    pub fn query_string(&self) -> String {
+        strip(self.0.search(), '?')
+    }
+
+    pub fn set_query_string(&mut self, query: &str) {
+        self.0.set_search(query);
+    }
+
+    /// Decodes the query string. See [`from_query_string`].
+    pub fn query<T: DeserializeOwned>(&self) -> Result<T, UrlError> {
+        from_query_string(&self.query_string())
+    }
+
+    /// Replaces the query string with `params`. See [`to_query_string`].
+    pub fn set_query<T: Serialize + ?Sized>(&mut self, params: &T) -> Result<(), UrlError> {
+        self.set_query_string(&to_query_string(params)?);
+        Ok(())
+    }
+
+    /// The fragment without the leading `#`, which is empty if there isn't one.
+    pub fn fragment(&self) -> String {
+        strip(self.0.hash(), '#')
+    }
+
+    pub fn set_fragment(&mut self, fragment: &str) {
+        self.0.set_hash(fragment);
+    }
+
+    /// Decodes the fragment as if it were a query string.
+    pub fn fragment_params<T: DeserializeOwned>(&self) -> Result<T, UrlError> {
+        from_query_string(&self.fragment())
+    }
+
+    /// Replaces the fragment with `params` encoded as a query string.
+    pub fn set_fragment_params<T: Serialize + ?Sized>(
+        &mut self, params: &T
+    ) -> Result<(), UrlError> {
+        self.set_fragment(&to_query_string(params)?);
+        Ok(())
+    }
+
+    /// The underlying URL.
+    pub fn raw(&self) -> &web_sys::Url {
+        &self.0
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.href())
+    }
+}
+
+impl PartialEq for Url {
+    fn eq(&self, other: &Url) -> bool {
+        self.href() == other.href()
+    }
+}
+
+impl Eq for Url {}
+
+fn strip(s: String, prefix: char) -> String {
+    match s.strip_prefix(prefix) {
+        Some(s) => s.to_owned(),
+        None => s
+    }
+}
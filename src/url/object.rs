@@ -0,0 +1,97 @@
+use crate::prelude::*;
+use crate::global;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    static LIVE: RefCell<HashMap<String, ObjectUrlInfo>> = RefCell::new(HashMap::new());
+}
+
+/// A `blob:` URL serving a blob, which is revoked when dropped.
+///
+/// The blob stays in memory for as long as the URL exists, so URLs which are never revoked
+/// leak it. [`live_object_urls`] lists the URLs which still exist to help find those.
+#[derive(Debug)]
+pub struct ObjectUrl(Option<String>);
+
+/// An object URL which hasn't been revoked, from [`live_object_urls`].
+#[derive(Clone, Debug)]
+pub struct ObjectUrlInfo {
+    pub url: String,
+    /// Size of the blob in bytes.
+    pub size: f64,
+    /// MIME type of the blob.
+    pub mime: String,
+    /// When the URL was created, in milliseconds on the [`global::now`] clock.
+    pub created: f64,
+    /// Whether the URL was [`forget`](ObjectUrl::forget)ten rather than still owned.
+    pub forgotten: bool
+}
+
+impl ObjectUrl {
+    pub fn new(blob: &web_sys::Blob) -> Result<Self, GeneralError> {
+        Ok(Self::create(blob)?)
+    }
+
+    pub(crate) fn create(blob: &web_sys::Blob) -> Result<Self, JsValue> {
+        let url = web_sys::Url::create_object_url_with_blob(blob)?;
+        let info = ObjectUrlInfo {
+            url: url.clone(),
+            size: blob.size(),
+            mime: blob.type_(),
+            created: global::now(),
+            forgotten: false
+        };
+        LIVE.with(|live| live.borrow_mut().insert(url.clone(), info));
+        Ok(ObjectUrl(Some(url)))
+    }
+
+    pub fn url(&self) -> &str {
+        self.0.as_deref().unwrap()
+    }
+
+    /// Keeps the URL alive forever, or until it is passed to [`revoke_object_url`].
+    pub fn forget(mut self) -> String {
+        let url = self.0.take().unwrap();
+        LIVE.with(|live| {
+            if let Some(info) = live.borrow_mut().get_mut(&url) {
+                info.forgotten = true;
+            }
+        });
+        url
+    }
+
+    /// Revokes the URL after `delay`, for when whatever uses it only starts loading it later.
+    pub fn revoke_after(self, delay: impl global::IntoDelay) {
+        global::set_timeout(delay, move || drop(self)).forget();
+    }
+}
+
+impl std::ops::Deref for ObjectUrl {
+    type Target = str;
+    fn deref(&self) -> &str {
+        self.url()
+    }
+}
+
+impl Drop for ObjectUrl {
+    fn drop(&mut self) {
+        if let Some(url) = self.0.take() {
+            revoke_object_url(&url);
+        }
+    }
+}
+
+/// Revokes an object URL, such as one which was forgotten.
+pub fn revoke_object_url(url: &str) {
+    LIVE.with(|live| live.borrow_mut().remove(url));
+    let _ = web_sys::Url::revoke_object_url(url);
+}
+
+/// The object URLs created with [`ObjectUrl`] in this thread which haven't been revoked, oldest
+/// first.
+pub fn live_object_urls() -> Vec<ObjectUrlInfo> {
+    let mut urls = LIVE.with(|live| live.borrow().values().cloned().collect::<Vec<_>>());
+    urls.sort_by(|a, b| a.created.total_cmp(&b.created));
+    urls
+}
@@ -0,0 +1,192 @@
+use super::UrlError;
+use serde::de::{ self, Deserializer, IntoDeserializer, Visitor };
+use serde::de::value::{ MapDeserializer, SeqDeserializer };
+use serde::forward_to_deserialize_any;
+use serde::{ Serialize, de::DeserializeOwned };
+
+/// Encodes `v` as `name=value` pairs.
+///
+/// `v` must serialize as a struct or map. Each field's value must be a string, number, bool,
+/// unit enum variant, `None` (which leaves the field out) or a sequence of those (which
+/// repeats the name).
+pub fn to_query_string<T: Serialize + ?Sized>(v: &T) -> Result<String, UrlError> {
+    let params = web_sys::UrlSearchParams::new().unwrap();
+    for (name, value) in to_pairs(v)? {
+        params.append(&name, &value);
+    }
+    Ok(params.to_string().into())
+}
+
+/// Decodes `name=value` pairs, with or without a leading `?` or `#`.
+///
+/// Names which appear more than once can be decoded as sequences. Other fields take the last
+/// value given.
+pub fn from_query_string<T: DeserializeOwned>(query: &str) -> Result<T, UrlError> {
+    let query = query.strip_prefix(|c| c == '?' || c == '#').unwrap_or(query);
+    let params = web_sys::UrlSearchParams::new_with_str(query).unwrap();
+    let pairs = params.entries().into_iter().map(|entry| {
+        let entry: js_sys::Array = entry.unwrap().into();
+        let name = entry.get(0).as_string().unwrap_or_default();
+        (name, entry.get(1).as_string().unwrap_or_default())
+    });
+    from_pairs(pairs)
+}
+
+/// Decodes `name=value` pairs that have already been split up and percent-decoded.
+pub(crate) fn from_pairs<T: DeserializeOwned>(
+    pairs: impl IntoIterator<Item = (String, String)>
+) -> Result<T, UrlError> {
+    let mut params: Vec<(String, Values)> = vec![];
+    for (name, value) in pairs {
+        match params.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, values)) => values.0.push(value),
+            None => params.push((name, Values(vec![value])))
+        }
+    }
+    T::deserialize(Params(params))
+}
+
+pub(crate) fn to_pairs<T: Serialize + ?Sized>(v: &T) -> Result<Vec<(String, String)>, UrlError> {
+    let fields = match serde_json::to_value(v).map_err(|e| UrlError::Query(e.to_string()))? {
+        serde_json::Value::Object(fields) => fields,
+        _ => return Err(UrlError::Query("parameters must be a struct or map".to_owned()))
+    };
+    let mut pairs = vec![];
+    for (name, value) in fields {
+        match value {
+            serde_json::Value::Array(values) => for value in values {
+                if let Some(value) = scalar(&name, value)? {
+                    pairs.push((name.clone(), value));
+                }
+            },
+            value => if let Some(value) = scalar(&name, value)? {
+                pairs.push((name, value));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+fn scalar(name: &str, value: serde_json::Value) -> Result<Option<String>, UrlError> {
+    match value {
+        serde_json::Value::Null => Ok(None),
+        serde_json::Value::String(s) => Ok(Some(s)),
+        serde_json::Value::Bool(b) => Ok(Some(b.to_string())),
+        serde_json::Value::Number(n) => Ok(Some(n.to_string())),
+        _ => Err(UrlError::Query(format!("{} can't be a query parameter", name)))
+    }
+}
+
+/// The parameters as a whole, which are a map unless there aren't any expected.
+struct Params(Vec<(String, Values)>);
+
+impl<'de> Deserializer<'de> for Params {
+    type Error = UrlError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, UrlError> {
+        visitor.visit_map(MapDeserializer::new(self.0.into_iter()))
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, UrlError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self, _name: &'static str, visitor: V
+    ) -> Result<V::Value, UrlError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self, _name: &'static str, visitor: V
+    ) -> Result<V::Value, UrlError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Every value given for a name, which is decoded from the last one unless a sequence is
+/// expected.
+struct Values(Vec<String>);
+
+impl Values {
+    fn last(mut self) -> String {
+        self.0.pop().unwrap_or_default()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, UrlError> for Values {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! parse {
+    ($($method:ident $visit:ident;)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, UrlError> {
+                let value = self.last();
+                match value.parse() {
+                    Ok(v) => visitor.$visit(v),
+                    Err(_) => Err(de::Error::invalid_value(
+                        de::Unexpected::Str(&value), &visitor
+                    ))
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Values {
+    type Error = UrlError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, UrlError> {
+        visitor.visit_string(self.last())
+    }
+
+    parse! {
+        deserialize_bool visit_bool;
+        deserialize_i8 visit_i8;
+        deserialize_i16 visit_i16;
+        deserialize_i32 visit_i32;
+        deserialize_i64 visit_i64;
+        deserialize_u8 visit_u8;
+        deserialize_u16 visit_u16;
+        deserialize_u32 visit_u32;
+        deserialize_u64 visit_u64;
+        deserialize_f32 visit_f32;
+        deserialize_f64 visit_f64;
+        deserialize_char visit_char;
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, UrlError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, UrlError> {
+        visitor.visit_seq(SeqDeserializer::new(self.0.into_iter().map(|v| Values(vec![v]))))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self, _name: &'static str, visitor: V
+    ) -> Result<V::Value, UrlError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self, _name: &'static str, _variants: &'static [&'static str], visitor: V
+    ) -> Result<V::Value, UrlError> {
+        visitor.visit_enum(self.last().into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
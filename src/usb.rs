@@ -0,0 +1,471 @@
+//! USB devices, from the WebUSB API.
+//!
+//! Only Chromium-based browsers support WebUSB, and only for pages served securely. Devices
+//! are chosen by the user with [`request_device`], which needs to be called while handling
+//! a click or key press.
+//!
+//! ```ignore
+//! let device = usb::request_device(&[DeviceFilter::new().vendor_id(0x2e8a)]).await?;
+//! device.open().await?;
+//! device.select_configuration(1).await?;
+//! device.claim_interface(0).await?;
+//! for block in firmware.chunks(64) {
+//!     device.transfer_out(1, block).await?;
+//! }
+//! let status = device.transfer_in(1, 64).await?;
+//! ```
+
+use crate::prelude::*;
+use crate::global;
+use crate::promise::done;
+use crate::channel::{ Receiver, channel };
+use crate::event::{ self, ListenerHandle };
+use wasm_bindgen::JsCast;
+
+#[wasm_bindgen]
+extern "C" {
+    // web-sys only binds WebUSB as unstable
+    #[wasm_bindgen(extends = web_sys::EventTarget, js_name = USB)]
+    type Usb;
+    #[wasm_bindgen(method, catch, js_name = requestDevice)]
+    fn request_device(this: &Usb, options: &JsValue) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = getDevices)]
+    fn get_devices(this: &Usb) -> Result<js_sys::Promise, JsValue>;
+
+    #[derive(Clone, Debug, PartialEq)]
+    #[wasm_bindgen(js_name = USBDevice)]
+    type RawDevice;
+    #[wasm_bindgen(method, getter, js_name = vendorId)]
+    fn vendor_id(this: &RawDevice) -> u16;
+    #[wasm_bindgen(method, getter, js_name = productId)]
+    fn product_id(this: &RawDevice) -> u16;
+    #[wasm_bindgen(method, getter, js_name = productName)]
+    fn product_name(this: &RawDevice) -> Option<String>;
+    #[wasm_bindgen(method, getter, js_name = manufacturerName)]
+    fn manufacturer_name(this: &RawDevice) -> Option<String>;
+    #[wasm_bindgen(method, getter, js_name = serialNumber)]
+    fn serial_number(this: &RawDevice) -> Option<String>;
+    #[wasm_bindgen(method, getter)]
+    fn opened(this: &RawDevice) -> bool;
+    #[wasm_bindgen(method, catch)]
+    fn open(this: &RawDevice) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    fn close(this: &RawDevice) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    fn forget(this: &RawDevice) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch)]
+    fn reset(this: &RawDevice) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = selectConfiguration)]
+    fn select_configuration(this: &RawDevice, value: u8) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = claimInterface)]
+    fn claim_interface(this: &RawDevice, number: u8) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = releaseInterface)]
+    fn release_interface(this: &RawDevice, number: u8) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = selectAlternateInterface)]
+    fn select_alternate_interface(
+        this: &RawDevice, number: u8, alternate: u8
+    ) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = clearHalt)]
+    fn clear_halt(
+        this: &RawDevice, direction: &str, endpoint: u8
+    ) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = controlTransferIn)]
+    fn control_transfer_in(
+        this: &RawDevice, setup: &JsValue, length: u16
+    ) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = controlTransferOut)]
+    fn control_transfer_out(
+        this: &RawDevice, setup: &JsValue, data: &[u8]
+    ) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = transferIn)]
+    fn transfer_in(
+        this: &RawDevice, endpoint: u8, length: u32
+    ) -> Result<js_sys::Promise, JsValue>;
+    #[wasm_bindgen(method, catch, js_name = transferOut)]
+    fn transfer_out(
+        this: &RawDevice, endpoint: u8, data: &[u8]
+    ) -> Result<js_sys::Promise, JsValue>;
+}
+
+/// Errors from using USB devices.
+#[derive(Debug)]
+pub enum UsbError {
+    /// WebUSB isn't supported, such as because the page isn't served securely.
+    Unavailable,
+    /// The user didn't choose a device, or the device was disconnected.
+    NotFound,
+    /// The browser didn't allow using the device, such as because [`request_device`] wasn't
+    /// called while handling user input, or the device is blocked.
+    PermissionDenied,
+    /// The device or interface is in use by another program or page, or isn't open.
+    Busy,
+    /// The endpoint stalled, which is cleared with [`UsbDevice::clear_halt`].
+    Stall,
+    /// The device sent more data than was asked for.
+    Babble,
+    Other(JsValue)
+}
+
+impl From<JsValue> for UsbError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotFoundError") => UsbError::NotFound,
+            Some("SecurityError") | Some("NotAllowedError") => UsbError::PermissionDenied,
+            Some("InvalidStateError") | Some("NetworkError") => UsbError::Busy,
+            _ => UsbError::Other(e)
+        }
+    }
+}
+
+impl From<UsbError> for GeneralError {
+    fn from(e: UsbError) -> Self {
+        let msg = match e {
+            UsbError::Unavailable => "WebUSB is unavailable",
+            UsbError::NotFound => "USB device not found",
+            UsbError::PermissionDenied => "USB device permission denied",
+            UsbError::Busy => "USB device is busy",
+            UsbError::Stall => "USB endpoint stalled",
+            UsbError::Babble => "USB device sent too much data",
+            UsbError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(msg)
+    }
+}
+
+fn usb() -> Result<Usb, UsbError> {
+    global::navigator_feature("usb").ok_or(UsbError::Unavailable)
+}
+
+fn check_status(result: &JsValue) -> Result<(), UsbError> {
+    let status = js_sys::Reflect::get(result, &"status".into())?.as_string();
+    match status.as_deref() {
+        Some("stall") => Err(UsbError::Stall),
+        Some("babble") => Err(UsbError::Babble),
+        _ => Ok(())
+    }
+}
+
+fn data_in(result: JsValue) -> Result<Vec<u8>, UsbError> {
+    check_status(&result)?;
+    let data = js_sys::Reflect::get(&result, &"data".into())?;
+    if data.is_undefined() || data.is_null() {
+        return Ok(vec![]);
+    }
+    let data: js_sys::DataView = data.unchecked_into();
+    let bytes = js_sys::Uint8Array::new_with_byte_offset_and_length(
+        &data.buffer(), data.byte_offset() as u32, data.byte_length() as u32
+    );
+    Ok(bytes.to_vec())
+}
+
+fn bytes_written(result: JsValue) -> Result<usize, UsbError> {
+    check_status(&result)?;
+    let written = js_sys::Reflect::get(&result, &"bytesWritten".into())?;
+    Ok(written.as_f64().unwrap_or(0.0) as usize)
+}
+
+/// Which devices the user can choose from in [`request_device`]. Anything not set matches
+/// every device.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceFilter {
+    vendor_id: Option<u16>,
+    product_id: Option<u16>,
+    class_code: Option<u8>,
+    subclass_code: Option<u8>,
+    protocol_code: Option<u8>,
+    serial_number: Option<String>
+}
+
+impl DeviceFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn vendor_id(mut self, id: u16) -> Self {
+        self.vendor_id = Some(id);
+        self
+    }
+
+    /// Only matches if the vendor ID is also set.
+    pub fn product_id(mut self, id: u16) -> Self {
+        self.product_id = Some(id);
+        self
+    }
+
+    /// Matches devices with the class code, or an interface with it.
+    pub fn class_code(mut self, code: u8) -> Self {
+        self.class_code = Some(code);
+        self
+    }
+
+    /// Only matches if the class code is also set.
+    pub fn subclass_code(mut self, code: u8) -> Self {
+        self.subclass_code = Some(code);
+        self
+    }
+
+    /// Only matches if the subclass code is also set.
+    pub fn protocol_code(mut self, code: u8) -> Self {
+        self.protocol_code = Some(code);
+        self
+    }
+
+    pub fn serial_number(mut self, serial_number: &str) -> Self {
+        self.serial_number = Some(serial_number.to_owned());
+        self
+    }
+
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        let filter = js_sys::Object::new();
+        let fields = [
+            ("vendorId", self.vendor_id.map(JsValue::from)),
+            ("productId", self.product_id.map(JsValue::from)),
+            ("classCode", self.class_code.map(JsValue::from)),
+            ("subclassCode", self.subclass_code.map(JsValue::from)),
+            ("protocolCode", self.protocol_code.map(JsValue::from)),
+            ("serialNumber", self.serial_number.as_deref().map(JsValue::from))
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                js_sys::Reflect::set(&filter, &key.into(), &value)?;
+            }
+        }
+        Ok(filter.into())
+    }
+}
+
+/// Asks the user to choose a device matching any of `filters`, allowing the page to use it.
+pub async fn request_device(filters: &[DeviceFilter]) -> Result<UsbDevice, UsbError> {
+    let usb = usb()?;
+    let options = js_sys::Object::new();
+    let filters = filters.iter().map(DeviceFilter::to_js).collect::<Result<js_sys::Array, _>>()?;
+    js_sys::Reflect::set(&options, &"filters".into(), &filters)?;
+    Ok(UsbDevice(done(usb.request_device(&options)).await?.unchecked_into()))
+}
+
+/// The connected devices which the user has allowed the page to use before.
+pub async fn devices() -> Result<Vec<UsbDevice>, UsbError> {
+    let devices = done(usb()?.get_devices()).await?;
+    Ok(js_sys::Array::from(&devices).iter().map(|d| UsbDevice(d.unchecked_into())).collect())
+}
+
+/// The kind of a control transfer's request.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RequestType {
+    Standard,
+    Class,
+    Vendor
+}
+
+/// What a control transfer's request is for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Recipient {
+    Device,
+    Interface,
+    Endpoint,
+    Other
+}
+
+/// The setup packet of a control transfer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ControlSetup {
+    pub request_type: RequestType,
+    pub recipient: Recipient,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16
+}
+
+impl ControlSetup {
+    fn to_js(self) -> Result<JsValue, JsValue> {
+        let setup = js_sys::Object::new();
+        let request_type = match self.request_type {
+            RequestType::Standard => "standard",
+            RequestType::Class => "class",
+            RequestType::Vendor => "vendor"
+        };
+        let recipient = match self.recipient {
+            Recipient::Device => "device",
+            Recipient::Interface => "interface",
+            Recipient::Endpoint => "endpoint",
+            Recipient::Other => "other"
+        };
+        js_sys::Reflect::set(&setup, &"requestType".into(), &request_type.into())?;
+        js_sys::Reflect::set(&setup, &"recipient".into(), &recipient.into())?;
+        js_sys::Reflect::set(&setup, &"request".into(), &self.request.into())?;
+        js_sys::Reflect::set(&setup, &"value".into(), &self.value.into())?;
+        js_sys::Reflect::set(&setup, &"index".into(), &self.index.into())?;
+        Ok(setup.into())
+    }
+}
+
+/// Which way an endpoint transfers data, from the point of view of the page.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out
+}
+
+/// A USB device the page may use. It needs to be [opened](Self::open) and an interface
+/// claimed before transferring data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UsbDevice(RawDevice);
+
+impl UsbDevice {
+    pub fn vendor_id(&self) -> u16 {
+        self.0.vendor_id()
+    }
+
+    pub fn product_id(&self) -> u16 {
+        self.0.product_id()
+    }
+
+    pub fn product_name(&self) -> Option<String> {
+        self.0.product_name()
+    }
+
+    pub fn manufacturer_name(&self) -> Option<String> {
+        self.0.manufacturer_name()
+    }
+
+    pub fn serial_number(&self) -> Option<String> {
+        self.0.serial_number()
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.0.opened()
+    }
+
+    pub async fn open(&self) -> Result<(), UsbError> {
+        done(self.0.open()).await?;
+        Ok(())
+    }
+
+    /// Closes the device, releasing its interfaces.
+    pub async fn close(&self) -> Result<(), UsbError> {
+        done(self.0.close()).await?;
+        Ok(())
+    }
+
+    /// Revokes the page's permission to use the device, so that the user has to choose it
+    /// again.
+    pub async fn forget(&self) -> Result<(), UsbError> {
+        done(self.0.forget()).await?;
+        Ok(())
+    }
+
+    /// Resets the device, cancelling any transfers in progress.
+    pub async fn reset(&self) -> Result<(), UsbError> {
+        done(self.0.reset()).await?;
+        Ok(())
+    }
+
+    /// Selects the configuration with this `bConfigurationValue`, which is usually `1`.
+    pub async fn select_configuration(&self, value: u8) -> Result<(), UsbError> {
+        done(self.0.select_configuration(value)).await?;
+        Ok(())
+    }
+
+    /// Claims an interface of the selected configuration for the page. Fails with
+    /// [`UsbError::Busy`] if another program has claimed it, such as an operating system
+    /// driver.
+    pub async fn claim_interface(&self, number: u8) -> Result<(), UsbError> {
+        done(self.0.claim_interface(number)).await?;
+        Ok(())
+    }
+
+    pub async fn release_interface(&self, number: u8) -> Result<(), UsbError> {
+        done(self.0.release_interface(number)).await?;
+        Ok(())
+    }
+
+    pub async fn select_alternate_interface(
+        &self, number: u8, alternate: u8
+    ) -> Result<(), UsbError> {
+        done(self.0.select_alternate_interface(number, alternate)).await?;
+        Ok(())
+    }
+
+    /// Clears a stall on an endpoint, after a transfer failed with [`UsbError::Stall`].
+    pub async fn clear_halt(&self, direction: Direction, endpoint: u8) -> Result<(), UsbError> {
+        let direction = match direction {
+            Direction::In => "in",
+            Direction::Out => "out"
+        };
+        done(self.0.clear_halt(direction, endpoint)).await?;
+        Ok(())
+    }
+
+    /// Sends a control request and receives up to `length` bytes in response.
+    pub async fn control_in(
+        &self, setup: ControlSetup, length: u16
+    ) -> Result<Vec<u8>, UsbError> {
+        data_in(done(self.0.control_transfer_in(&setup.to_js()?, length)).await?)
+    }
+
+    /// Sends a control request with `data`, returning how many bytes were sent.
+    pub async fn control_out(&self, setup: ControlSetup, data: &[u8]) -> Result<usize, UsbError> {
+        bytes_written(done(self.0.control_transfer_out(&setup.to_js()?, data)).await?)
+    }
+
+    /// Receives up to `length` bytes from a bulk or interrupt endpoint.
+    pub async fn transfer_in(&self, endpoint: u8, length: u32) -> Result<Vec<u8>, UsbError> {
+        data_in(done(self.0.transfer_in(endpoint, length)).await?)
+    }
+
+    /// Sends `data` to a bulk or interrupt endpoint, returning how many bytes were sent.
+    pub async fn transfer_out(&self, endpoint: u8, data: &[u8]) -> Result<usize, UsbError> {
+        bytes_written(done(self.0.transfer_out(endpoint, data)).await?)
+    }
+
+    /// The underlying `USBDevice`.
+    pub fn raw(&self) -> &JsValue {
+        &self.0
+    }
+}
+
+/// A device the page may use being plugged in or removed, from [`UsbConnections`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum UsbEvent {
+    Connected(UsbDevice),
+    Disconnected(UsbDevice)
+}
+
+/// Watches for devices the page may use being plugged in or removed.
+pub fn connections() -> Result<UsbConnections, UsbError> {
+    let usb = usb()?;
+    let (sender, events) = channel();
+    let device = |e: &web_sys::Event| {
+        let device = js_sys::Reflect::get(e, &"device".into()).ok()?;
+        Some(UsbDevice(device.dyn_into().ok()?))
+    };
+    let connected = usb.add_event_listener({
+        let sender = sender.clone();
+        move |e: event::DeviceConnect| if let Some(device) = device(&e) {
+            let _ = sender.send(UsbEvent::Connected(device));
+        }
+    });
+    let disconnected = usb.add_event_listener(move |e: event::DeviceDisconnect| {
+        if let Some(device) = device(&e) {
+            let _ = sender.send(UsbEvent::Disconnected(device));
+        }
+    });
+    Ok(UsbConnections { events, _listeners: [connected, disconnected] })
+}
+
+/// Devices being plugged in or removed, from [`connections`]. Watching stops when this is
+/// dropped.
+pub struct UsbConnections {
+    events: Receiver<UsbEvent>,
+    _listeners: [ListenerHandle; 2]
+}
+
+impl UsbConnections {
+    pub fn try_next(&self) -> Option<UsbEvent> {
+        self.events.try_recv().ok()
+    }
+
+    pub async fn next(&self) -> UsbEvent {
+        self.events.recv().await.unwrap()
+    }
+}
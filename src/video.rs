@@ -0,0 +1,127 @@
+use crate::prelude::*;
+use crate::channel::{ WatchReceiver, watch };
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use std::cell::{ Cell, OnceCell };
+use std::rc::Rc;
+
+#[wasm_bindgen]
+extern "C" {
+    /// `HTMLVideoElement` with the frame callback methods web-sys doesn't bind yet.
+    #[wasm_bindgen(extends = web_sys::HtmlVideoElement)]
+    type VideoElement;
+    #[wasm_bindgen(method, js_name = requestVideoFrameCallback)]
+    fn request_video_frame_callback(
+        this: &VideoElement, f: &Closure<dyn FnMut(f64, FrameMetadata)>
+    ) -> u32;
+    #[wasm_bindgen(method, js_name = cancelVideoFrameCallback)]
+    fn cancel_video_frame_callback(this: &VideoElement, handle: u32);
+
+    type FrameMetadata;
+    #[wasm_bindgen(method, getter, js_name = presentationTime)]
+    fn presentation_time(this: &FrameMetadata) -> f64;
+    #[wasm_bindgen(method, getter, js_name = expectedDisplayTime)]
+    fn expected_display_time(this: &FrameMetadata) -> f64;
+    #[wasm_bindgen(method, getter)]
+    fn width(this: &FrameMetadata) -> u32;
+    #[wasm_bindgen(method, getter)]
+    fn height(this: &FrameMetadata) -> u32;
+    #[wasm_bindgen(method, getter, js_name = mediaTime)]
+    fn media_time(this: &FrameMetadata) -> f64;
+    #[wasm_bindgen(method, getter, js_name = presentedFrames)]
+    fn presented_frames(this: &FrameMetadata) -> u32;
+    #[wasm_bindgen(method, getter, js_name = processingDuration)]
+    fn processing_duration(this: &FrameMetadata) -> Option<f64>;
+}
+
+/// Metadata of a video frame sent to the compositor. Times are in milliseconds on the
+/// [`global::now`](crate::global::now) clock unless noted otherwise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VideoFrame {
+    /// When the callback ran.
+    pub now: f64,
+    /// When the frame was submitted for composition.
+    pub presentation_time: f64,
+    /// When the frame is expected to be visible.
+    pub expected_display_time: f64,
+    pub width: u32,
+    pub height: u32,
+    /// Position of the frame in the media, in seconds.
+    pub media_time: f64,
+    /// Number of frames submitted for composition so far. Gaps between consecutive values
+    /// mean frames were presented without being seen by the stream.
+    pub presented_frames: u32,
+    /// Time taken to decode the frame, in seconds, if the browser reports it.
+    pub processing_duration: Option<f64>
+}
+
+/// Returns a stream of the frames presented by `video`, from `requestVideoFrameCallback`.
+pub fn frame_callbacks(video: &web_sys::HtmlVideoElement) -> VideoFrameStream {
+    let (s, r) = watch(None);
+    let frame_loop = Rc::new(FrameLoop {
+        video: video.clone().unchecked_into(),
+        id: Cell::new(0),
+        closure: OnceCell::new()
+    });
+    let weak = Rc::downgrade(&frame_loop);
+    let closure = Closure::wrap(Box::new(move |now, metadata: FrameMetadata| {
+        s.set(Some(VideoFrame {
+            now,
+            presentation_time: metadata.presentation_time(),
+            expected_display_time: metadata.expected_display_time(),
+            width: metadata.width(),
+            height: metadata.height(),
+            media_time: metadata.media_time(),
+            presented_frames: metadata.presented_frames(),
+            processing_duration: metadata.processing_duration()
+        }));
+        if let Some(frame_loop) = weak.upgrade() {
+            frame_loop.request();
+        }
+    }) as Box<dyn FnMut(f64, FrameMetadata)>);
+    let _ = frame_loop.closure.set(closure);
+    frame_loop.request();
+    VideoFrameStream(r, frame_loop)
+}
+
+/// Stream of video frames returned by [`frame_callbacks`].
+///
+/// Like [`AnimationFrameStream`](crate::global::AnimationFrameStream), frames which pass while
+/// the stream isn't being polled are coalesced into the latest one.
+pub struct VideoFrameStream(WatchReceiver<Option<VideoFrame>>, Rc<FrameLoop>);
+
+struct FrameLoop {
+    video: VideoElement,
+    id: Cell<u32>,
+    closure: OnceCell<Closure<dyn FnMut(f64, FrameMetadata)>>
+}
+
+impl FrameLoop {
+    fn request(&self) {
+        if let Some(closure) = self.closure.get() {
+            self.id.set(self.video.request_video_frame_callback(closure));
+        }
+    }
+}
+
+impl VideoFrameStream {
+    pub fn try_next(&self) -> Option<VideoFrame> {
+        match self.0.has_changed() {
+            true => self.0.get(),
+            false => None
+        }
+    }
+
+    pub async fn next(&self) -> VideoFrame {
+        // the sender lives in the frame loop, which lives as long as the stream, and is only
+        // ever set to a frame
+        self.0.changed().await.unwrap();
+        self.0.get().unwrap()
+    }
+}
+
+impl Drop for VideoFrameStream {
+    fn drop(&mut self) {
+        self.1.video.cancel_video_frame_callback(self.1.id.get());
+    }
+}
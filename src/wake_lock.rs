@@ -0,0 +1,138 @@
+//! Keeping the screen on, from the Screen Wake Lock API.
+//!
+//! ```ignore
+//! // keep the screen on while the slideshow is open
+//! let _guard = wake_lock::acquire().await?;
+//! slideshow.closed().await;
+//! ```
+
+use crate::prelude::*;
+use crate::event::{ self, ListenerHandle };
+use crate::global::GlobalScope;
+use std::cell::RefCell;
+use std::rc::{ Rc, Weak };
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    // web-sys only binds the Screen Wake Lock API as unstable
+    #[derive(Clone)]
+    type WakeLock;
+    #[wasm_bindgen(method, catch)]
+    fn request(this: &WakeLock, kind: &str) -> Result<js_sys::Promise, JsValue>;
+
+    type WakeLockSentinel;
+    #[wasm_bindgen(method, getter)]
+    fn released(this: &WakeLockSentinel) -> bool;
+    #[wasm_bindgen(method, catch)]
+    fn release(this: &WakeLockSentinel) -> Result<js_sys::Promise, JsValue>;
+}
+
+/// Errors from acquiring a wake lock.
+#[derive(Debug)]
+pub enum WakeLockError {
+    /// Wake locks aren't supported, such as because this isn't a window or the page isn't
+    /// served securely.
+    Unavailable,
+    /// The browser didn't allow keeping the screen on, such as because the page is hidden or
+    /// the battery is low.
+    NotAllowed,
+    Other(JsValue)
+}
+
+impl From<JsValue> for WakeLockError {
+    fn from(e: JsValue) -> Self {
+        match crate::error_name(&e).as_deref() {
+            Some("NotAllowedError") => WakeLockError::NotAllowed,
+            _ => WakeLockError::Other(e)
+        }
+    }
+}
+
+impl From<WakeLockError> for GeneralError {
+    fn from(e: WakeLockError) -> Self {
+        let msg = match e {
+            WakeLockError::Unavailable => "wake lock is unavailable",
+            WakeLockError::NotAllowed => "wake lock is not allowed",
+            WakeLockError::Other(e) => return GeneralError::WebSys(e)
+        };
+        GeneralError::message(msg)
+    }
+}
+
+fn wake_lock() -> Result<(web_sys::Document, WakeLock), WakeLockError> {
+    let window = match GlobalScope::current() {
+        GlobalScope::Window(window) => window,
+        _ => return Err(WakeLockError::Unavailable)
+    };
+    let wake_lock = js_sys::Reflect::get(&window.navigator(), &"wakeLock".into())?;
+    match (window.document(), wake_lock.is_undefined()) {
+        (Some(document), false) => Ok((document, wake_lock.unchecked_into())),
+        _ => Err(WakeLockError::Unavailable)
+    }
+}
+
+async fn request(wake_lock: &WakeLock) -> Result<WakeLockSentinel, WakeLockError> {
+    Ok(JsFuture::from(wake_lock.request("screen")?).await?.unchecked_into())
+}
+
+/// Keeps the screen on until the guard is dropped.
+///
+/// Browsers release the lock while the page is hidden, so it is acquired again whenever the
+/// page becomes visible.
+pub async fn acquire() -> Result<WakeLockGuard, WakeLockError> {
+    let (document, wake_lock) = wake_lock()?;
+    let sentinel = Rc::new(RefCell::new(Some(request(&wake_lock).await?)));
+
+    let listener = document.clone().add_event_listener({
+        let sentinel = Rc::downgrade(&sentinel);
+        move |_: event::VisibilityChange| {
+            let held = match sentinel.upgrade() {
+                Some(sentinel) => sentinel.borrow().as_ref().is_some_and(|s| !s.released()),
+                None => return
+            };
+            if !document.hidden() && !held {
+                spawn_local(reacquire(wake_lock.clone(), sentinel.clone()));
+            }
+        }
+    });
+    Ok(WakeLockGuard { sentinel, _listener: listener })
+}
+
+async fn reacquire(wake_lock: WakeLock, sentinel: Weak<RefCell<Option<WakeLockSentinel>>>) {
+    // failing is fine, since the lock is tried again the next time the page is shown
+    if let Ok(new) = request(&wake_lock).await {
+        match sentinel.upgrade() {
+            Some(sentinel) => {
+                // showing the page twice quickly can acquire it twice
+                if let Some(old) = sentinel.borrow_mut().replace(new) {
+                    let _ = old.release();
+                }
+            }
+            // the guard was dropped while waiting
+            None => drop(new.release())
+        }
+    }
+}
+
+/// A wake lock from [`acquire`]. The screen can turn off again once this is dropped.
+pub struct WakeLockGuard {
+    sentinel: Rc<RefCell<Option<WakeLockSentinel>>>,
+    _listener: ListenerHandle
+}
+
+impl WakeLockGuard {
+    /// Whether the screen is being kept on, which it isn't while the page is hidden.
+    pub fn is_active(&self) -> bool {
+        self.sentinel.borrow().as_ref().is_some_and(|s| !s.released())
+    }
+}
+
+impl Drop for WakeLockGuard {
+    fn drop(&mut self) {
+        if let Some(sentinel) = self.sentinel.borrow_mut().take() {
+            let _ = sentinel.release();
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! WebTransport sessions with typed streams and datagrams.
+//!
+//! Messages are encoded using a codec producing bytes, [`Bincode`] by default. On streams,
+//! each message is prefixed with its length so that message boundaries survive; datagrams
+//! hold one message each.
+//!
+//! ```ignore
+//! let transport: Transport = Transport::connect("https://example.com:4433/game").await?;
+//! let datagrams = transport.datagrams::<Input, Snapshot>()?;
+//! datagrams.send(&input).await?;
+//! let (send, mut recv) = transport.open_bi::<Command, Reply>().await?;
+//! send.send(&Command::Join).await?;
+//! let reply = recv.recv().await;
+//! ```
+
+use crate::prelude::*;
+use crate::codec::{ Bincode, Codec };
+use serde::{ Serialize, de::DeserializeOwned };
+use std::marker::PhantomData;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+
+#[wasm_bindgen]
+extern "C" {
+    /// `WebTransport`, which web-sys only binds as an unstable API.
+    #[wasm_bindgen(js_name = WebTransport)]
+    type RawTransport;
+    #[wasm_bindgen(constructor, js_class = "WebTransport", catch)]
+    fn new(url: &str) -> Result<RawTransport, JsValue>;
+    #[wasm_bindgen(method, getter)]
+    fn ready(this: &RawTransport) -> js_sys::Promise;
+    #[wasm_bindgen(method, getter)]
+    fn closed(this: &RawTransport) -> js_sys::Promise;
+    #[wasm_bindgen(method)]
+    fn close(this: &RawTransport);
+    #[wasm_bindgen(method, getter)]
+    fn datagrams(this: &RawTransport) -> DuplexStream;
+    #[wasm_bindgen(method, js_name = createBidirectionalStream)]
+    fn create_bidirectional_stream(this: &RawTransport) -> js_sys::Promise;
+    #[wasm_bindgen(method, js_name = createUnidirectionalStream)]
+    fn create_unidirectional_stream(this: &RawTransport) -> js_sys::Promise;
+    #[wasm_bindgen(method, getter, js_name = incomingBidirectionalStreams)]
+    fn incoming_bidirectional_streams(this: &RawTransport) -> web_sys::ReadableStream;
+    #[wasm_bindgen(method, getter, js_name = incomingUnidirectionalStreams)]
+    fn incoming_unidirectional_streams(this: &RawTransport) -> web_sys::ReadableStream;
+
+    /// A pair of streams, such as a bidirectional stream or the datagram streams.
+    type DuplexStream;
+    #[wasm_bindgen(method, getter)]
+    fn readable(this: &DuplexStream) -> web_sys::ReadableStream;
+    #[wasm_bindgen(method, getter)]
+    fn writable(this: &DuplexStream) -> web_sys::WritableStream;
+}
+
+/// A WebTransport session, encoding messages with `C`. Dropping it closes the session.
+pub struct Transport<C = Bincode> {
+    raw: RawTransport,
+    incoming_bi: web_sys::ReadableStreamDefaultReader,
+    incoming_uni: web_sys::ReadableStreamDefaultReader,
+    _phantom: PhantomData<fn(C)>
+}
+
+impl<C: Codec> Transport<C> {
+    /// Connects to `url`, which must be `https`, waiting until the session is ready.
+    pub async fn connect(url: &str) -> Result<Self, GeneralError> {
+        let raw = RawTransport::new(url)?;
+        JsFuture::from(raw.ready()).await?;
+        let incoming_bi = reader(&raw.incoming_bidirectional_streams())?;
+        let incoming_uni = reader(&raw.incoming_unidirectional_streams())?;
+        Ok(Transport { raw, incoming_bi, incoming_uni, _phantom: PhantomData })
+    }
+
+    /// Opens a bidirectional stream.
+    pub async fn open_bi<O: Serialize, I: DeserializeOwned>(
+        &self
+    ) -> Result<BiStream<O, I, C>, GeneralError> {
+        let stream = JsFuture::from(self.raw.create_bidirectional_stream()).await?;
+        bi_stream(stream.unchecked_into())
+    }
+
+    /// Opens a unidirectional stream to the server.
+    pub async fn open_uni<O: Serialize>(
+        &self
+    ) -> Result<StreamSender<O, C>, GeneralError> {
+        let stream = JsFuture::from(self.raw.create_unidirectional_stream()).await?;
+        StreamSender::new(&stream.unchecked_into())
+    }
+
+    /// Waits for the server to open a bidirectional stream. Returns `None` once the session
+    /// is closed.
+    pub async fn accept_bi<O: Serialize, I: DeserializeOwned>(
+        &self
+    ) -> Option<Result<BiStream<O, I, C>, GeneralError>> {
+        Some(match read_chunk(&self.incoming_bi).await {
+            Ok(stream) => bi_stream(stream?.unchecked_into()),
+            Err(e) => Err(e)
+        })
+    }
+
+    /// Waits for the server to open a unidirectional stream. Returns `None` once the session
+    /// is closed.
+    pub async fn accept_uni<I: DeserializeOwned>(
+        &self
+    ) -> Option<Result<StreamReceiver<I, C>, GeneralError>> {
+        Some(match read_chunk(&self.incoming_uni).await {
+            Ok(stream) => StreamReceiver::new(&stream?.unchecked_into()),
+            Err(e) => Err(e)
+        })
+    }
+
+    /// Returns the session's datagram channel. Fails if it was already taken and is still
+    /// alive.
+    pub fn datagrams<O: Serialize, I: DeserializeOwned>(
+        &self
+    ) -> Result<Datagrams<O, I, C>, GeneralError> {
+        let datagrams = self.raw.datagrams();
+        Ok(Datagrams {
+            reader: reader(&datagrams.readable())?,
+            writer: datagrams.writable().get_writer()?,
+            _phantom: PhantomData
+        })
+    }
+
+    pub fn close(&self) {
+        self.raw.close();
+    }
+
+    /// Waits until the session is closed, failing if it was closed because of an error.
+    pub async fn closed(&self) -> Result<(), GeneralError> {
+        JsFuture::from(self.raw.closed()).await?;
+        Ok(())
+    }
+}
+
+impl<C> Drop for Transport<C> {
+    fn drop(&mut self) {
+        self.raw.close();
+    }
+}
+
+/// Unreliable, unordered messages, each sent as one datagram. Messages too large for a
+/// datagram are dropped by the browser.
+pub struct Datagrams<O, I, C = Bincode> {
+    reader: web_sys::ReadableStreamDefaultReader,
+    writer: web_sys::WritableStreamDefaultWriter,
+    _phantom: PhantomData<fn(O, I, C)>
+}
+
+impl<O: Serialize, I: DeserializeOwned, C: Codec> Datagrams<O, I, C> {
+    /// Sends a message, waiting until the browser has accepted it. Fails if the session is
+    /// closed.
+    pub async fn send(&self, v: &O) -> Result<(), GeneralError> {
+        JsFuture::from(self.writer.write_with_chunk(&encode::<_, C>(v)?.into())).await?;
+        Ok(())
+    }
+
+    /// Receives the next datagram, or `None` once the session is closed.
+    pub async fn recv(&self) -> Option<Result<I, GeneralError>> {
+        match read_chunk(&self.reader).await {
+            Ok(chunk) => Some(C::decode(chunk?)),
+            Err(e) => Some(Err(e))
+        }
+    }
+}
+
+impl<O, I, C> Drop for Datagrams<O, I, C> {
+    fn drop(&mut self) {
+        // let the datagram streams be taken again
+        self.reader.release_lock();
+        self.writer.release_lock();
+    }
+}
+
+/// Both halves of a bidirectional stream.
+pub type BiStream<O, I, C = Bincode> = (StreamSender<O, C>, StreamReceiver<I, C>);
+
+/// The sending half of a stream.
+pub struct StreamSender<O, C = Bincode> {
+    writer: web_sys::WritableStreamDefaultWriter,
+    _phantom: PhantomData<fn(O, C)>
+}
+
+impl<O: Serialize, C: Codec> StreamSender<O, C> {
+    fn new(stream: &web_sys::WritableStream) -> Result<Self, GeneralError> {
+        Ok(StreamSender { writer: stream.get_writer()?, _phantom: PhantomData })
+    }
+
+    /// Sends a message, waiting until the stream has accepted it.
+    pub async fn send(&self, v: &O) -> Result<(), GeneralError> {
+        let payload = encode::<_, C>(v)?;
+        let frame = js_sys::Uint8Array::new_with_length(payload.length() + 4);
+        frame.copy_from(&payload.length().to_le_bytes());
+        frame.set(&payload, 4);
+        JsFuture::from(self.writer.write_with_chunk(&frame)).await?;
+        Ok(())
+    }
+
+    /// Sends raw bytes, without a length prefix.
+    pub async fn write(&self, bytes: &[u8]) -> Result<(), GeneralError> {
+        let chunk = js_sys::Uint8Array::from(bytes);
+        JsFuture::from(self.writer.write_with_chunk(&chunk)).await?;
+        Ok(())
+    }
+
+    /// Finishes the stream, waiting until everything sent has been delivered.
+    pub async fn finish(self) -> Result<(), GeneralError> {
+        JsFuture::from(self.writer.close()).await?;
+        Ok(())
+    }
+}
+
+/// The receiving half of a stream.
+pub struct StreamReceiver<I, C = Bincode> {
+    reader: web_sys::ReadableStreamDefaultReader,
+    buffer: Vec<u8>,
+    max_message_size: usize,
+    _phantom: PhantomData<fn(I, C)>
+}
+
+/// The default for [`StreamReceiver::max_message_size`], 16 MiB.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 << 20;
+
+impl<I: DeserializeOwned, C: Codec> StreamReceiver<I, C> {
+    fn new(stream: &web_sys::ReadableStream) -> Result<Self, GeneralError> {
+        Ok(StreamReceiver {
+            reader: reader(stream)?,
+            buffer: vec![],
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            _phantom: PhantomData
+        })
+    }
+
+    /// Sets the largest message in bytes which [`recv`](Self::recv) accepts, so that a bogus
+    /// length prefix can't make it buffer without limit. Defaults to
+    /// [`DEFAULT_MAX_MESSAGE_SIZE`].
+    pub fn max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Receives the next message, or `None` once the stream has finished. Fails if the stream
+    /// ends partway through a message.
+    ///
+    /// Also fails if a message is larger than the [maximum](Self::max_message_size), after
+    /// which the stream is cancelled, since the rest of it can't be split into messages.
+    pub async fn recv(&mut self) -> Option<Result<I, GeneralError>> {
+        loop {
+            if self.buffer.len() >= 4 {
+                let prefix = [self.buffer[0], self.buffer[1], self.buffer[2], self.buffer[3]];
+                let len = u32::from_le_bytes(prefix) as usize;
+                let end = match len.checked_add(4) {
+                    Some(end) if len <= self.max_message_size => end,
+                    _ => {
+                        self.buffer.clear();
+                        let _ = self.reader.cancel();
+                        return Some(Err(GeneralError::message(&format!(
+                            "message of {} bytes is larger than the maximum of {}",
+                            len, self.max_message_size
+                        ))));
+                    }
+                };
+                if self.buffer.len() >= end {
+                    let payload = js_sys::Uint8Array::from(&self.buffer[4..end]);
+                    self.buffer.drain(..end);
+                    return Some(C::decode(payload.into()));
+                }
+            }
+            match self.read_more().await? {
+                Ok(true) => {}
+                Ok(false) if self.buffer.is_empty() => return None,
+                Ok(false) => {
+                    let error = "stream ended partway through a message";
+                    return Some(Err(GeneralError::message(error)));
+                }
+                Err(e) => return Some(Err(e))
+            }
+        }
+    }
+
+    /// Receives the next chunk of raw bytes, or `None` once the stream has finished. Bytes
+    /// received but not yet returned by [`recv`](Self::recv) come first.
+    pub async fn read(&mut self) -> Option<Result<Vec<u8>, GeneralError>> {
+        if self.buffer.is_empty() {
+            match self.read_more().await? {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e))
+            }
+        }
+        Some(Ok(std::mem::take(&mut self.buffer)))
+    }
+
+    /// Reads a chunk into the buffer, returning whether the stream had one.
+    async fn read_more(&mut self) -> Option<Result<bool, GeneralError>> {
+        Some(match read_chunk(&self.reader).await {
+            Ok(Some(chunk)) => {
+                self.buffer.extend(chunk.unchecked_into::<js_sys::Uint8Array>().to_vec());
+                Ok(true)
+            }
+            Ok(None) => Ok(false),
+            Err(e) => Err(e)
+        })
+    }
+}
+
+fn bi_stream<O: Serialize, I: DeserializeOwned, C: Codec>(
+    stream: DuplexStream
+) -> Result<BiStream<O, I, C>, GeneralError> {
+    Ok((StreamSender::new(&stream.writable())?, StreamReceiver::new(&stream.readable())?))
+}
+
+fn reader(
+    stream: &web_sys::ReadableStream
+) -> Result<web_sys::ReadableStreamDefaultReader, GeneralError> {
+    Ok(web_sys::ReadableStreamDefaultReader::new(stream)?)
+}
+
+/// Reads the next chunk of `reader`, or `None` once it is done.
+async fn read_chunk(
+    reader: &web_sys::ReadableStreamDefaultReader
+) -> Result<Option<JsValue>, GeneralError> {
+    let result: web_sys::ReadableStreamReadResult = JsFuture::from(reader.read()).await?
+        .unchecked_into();
+    if result.get_done().unwrap_or(false) {
+        Ok(None)
+    } else {
+        Ok(Some(result.get_value()))
+    }
+}
+
+fn encode<T: Serialize, C: Codec>(v: &T) -> Result<js_sys::Uint8Array, GeneralError> {
+    let (msg, _) = C::encode(v)?;
+    msg.dyn_into().map_err(|_| {
+        GeneralError::WebSys(js_sys::TypeError::new("codec output is not bytes").into())
+    })
+}
@@ -1,167 +1,888 @@
 use crate::prelude::*;
-use crate::channel::{ Receiver, channel };
-use crate::event;
-use serde::{ Serialize, de::DeserializeOwned };
+use crate::channel::{ Receiver, Sender, Oneshot, Once, TryRecvError, channel, oneshot };
+use crate::codec::{ Codec, Bincode };
+use crate::event::{ self, ListenerHandle };
+use crate::global::{ self, IntoDelay };
+use crate::perf::PerfInstant;
+use crate::task::CancelToken;
+use serde::{ Serialize, Serializer, Deserialize, Deserializer, de::DeserializeOwned };
+use serde::de::Error as _;
 use wasm_bindgen::JsCast;
 use std::marker::PhantomData;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::future::Future;
+use std::pin::Pin;
+
+mod supervisor;
+mod protocol;
+mod script;
+pub mod worklet;
+mod offscreen;
+#[cfg(target_feature = "atomics")]
+mod thread;
+pub use supervisor::*;
+pub use protocol::*;
+pub use script::*;
+pub use offscreen::*;
+#[cfg(target_feature = "atomics")]
+pub use thread::*;
 
 /// Wrapper for dedicated web workers.
 /// 
 /// Dropping the worker immediately terminates the associated web worker, preventing
 /// and messages it may have yet to process from being received.
 /// 
-/// This interfaces requires that you build using `--target no-modules` and that
-/// a `worker.js` file exists with the following content:
+/// Workers are started from a loader script which initializes the wasm module and calls
+/// `_web_worker_entry_point`. [`init_script`] generates it; for `--target no-modules` it looks
+/// like this:
 /// ```js
 /// importScripts("./<your-app>.js");
-/// const { _web_worker_entry_point } = wasm_bindgen;
-/// async function run() {
-///     await wasm_bindgen("./<your-app>_bg.wasm");
-///     _web_worker_entry_point(self);
-/// }
-/// run();
+/// wasm_bindgen("./<your-app>_bg.wasm").then(() => {
+///     wasm_bindgen._web_worker_entry_point(self, wasm_bindgen);
+/// });
 /// ```
-pub struct Worker<O, I> {
+///
+/// The code run by the worker is an entry point declared with
+/// [`#[webutil::worker]`](crate::worker).
+///
+/// Messages are encoded using the codec `C`, which is [`Bincode`] by default.
+pub struct Worker<O, I, C = Bincode> {
     worker: web_sys::Worker,
-    incoming: Receiver<I>,
-    _phantom: PhantomData<fn(O)>
+    incoming: Receiver<Result<I, WorkerError>>,
+    state: Rc<RefCell<State<I>>>,
+    watchdog: RefCell<Option<global::IntervalHandle>>,
+    listeners: RefCell<Vec<ListenerHandle>>,
+    _phantom: PhantomData<fn(O, C)>
 }
 
-impl<I, O> Worker<O, I>
+impl<I, O, C> Worker<O, I, C>
 where
     I: Serialize + DeserializeOwned + 'static,
-    O: Serialize + DeserializeOwned + 'static
+    O: Serialize + DeserializeOwned + 'static,
+    C: Codec
 {
-    /// Spawns a new worker and runs the specified function in it.
-    pub async fn new<T: Serialize + DeserializeOwned + 'static>(
-        uri: &str, f: fn(T, Receiver<O>, WorkerSender<I>), args: &T
+    /// Spawns a new worker running the entry point `E`, declared with
+    /// [`#[webutil::worker]`](crate::worker).
+    ///
+    /// Fails with [`GeneralError::WorkerSpawn`] if the worker doesn't start within
+    /// [`DEFAULT_SPAWN_TIMEOUT`]; see [`new_with_timeout`](Self::new_with_timeout).
+    pub async fn new<E>(script: impl Into<Script>, args: &E::Args) -> Result<Self, GeneralError>
+    where
+        E: Entry<Incoming = O, Outgoing = I, Codec = C>
+    {
+        Self::new_with_timeout::<E>(script, args, DEFAULT_SPAWN_TIMEOUT).await
+    }
+
+    /// Like [`new`](Self::new), but fails if the worker takes longer than `timeout` to load
+    /// and initialize.
+    pub async fn new_with_timeout<E>(
+        script: impl Into<Script>, args: &E::Args, timeout: impl IntoDelay
+    ) -> Result<Self, GeneralError>
+    where
+        E: Entry<Incoming = O, Outgoing = I, Codec = C>
+    {
+        let args = serialize_args(&(E::fingerprint(), args))?;
+        Self::spawn(&script.into(), E::ID, args, timeout.into_millis()).await
+    }
+
+    /// Spawns a new worker running the entry point `E` from a loader generated at runtime, so
+    /// no loader script needs to be deployed. See [`Script::Inline`].
+    pub async fn new_inline<E>(
+        app: &str, target: ScriptTarget, args: &E::Args
+    ) -> Result<Self, GeneralError>
+    where
+        E: Entry<Incoming = O, Outgoing = I, Codec = C>
+    {
+        Self::new::<E>(Script::Inline { app: app.to_owned(), target }, args).await
+    }
+
+    async fn spawn(
+        script: &Script, id: &str, (userdata, objects): (Vec<u8>, Vec<JsValue>), timeout: f64
     ) -> Result<Self, GeneralError> {
-        let worker = web_sys::Worker::new(uri)?;
-        // wait for signal that web worker has spawned and is ready to receive messages
-        worker.once::<event::Message>().await;
-
-        // send the bootstrapper, user function, and user data to the worker.
-        let msg: (usize, usize, Vec<u8>) = unsafe {(
-            std::mem::transmute::<
-                fn(web_sys::DedicatedWorkerGlobalScope, usize, Vec<u8>), _
-            >(bootstrapper::<T, I, O>),
-            std::mem::transmute(f),
-            bincode::serialize(&args)?
-        )};
-        let data = bincode::serialize(&msg)?;
-        let buf = js_sys::Uint8Array::from(&*data);
-        worker.post_message_with_transfer(&buf, &js_sys::Array::of1(&buf.buffer()))?;
+        let worker = script.spawn()?;
+        if let Err(e) = start(&worker, id, userdata, objects, timeout).await {
+            worker.terminate();
+            return Err(e);
+        }
 
         // setup message receiver
         let (sender, incoming) = channel();
-        let wrker = worker.clone();
-        spawn_local(async move {
-            let incoming = wrker.on::<event::Message>();
-            loop {
-                let msg = bincode::deserialize(
-                    &incoming.next()
-                        .await
-                        .data()
-                        .dyn_into::<js_sys::Uint8Array>()
-                        .unwrap()
-                        .to_vec()
-                ).unwrap();
-                if sender.send(msg).is_err() {
-                    break
-                }
-            }
-        });
+        let state = Rc::new(RefCell::new(State::new(sender)));
+        let listeners = listen::<_, C>(&worker, &state);
 
         Ok(Worker {
-            worker, incoming,
+            worker, incoming, state,
+            watchdog: RefCell::new(None),
+            listeners: RefCell::new(listeners),
             _phantom: PhantomData
         })
     }
 
-    pub fn try_recv(&self) -> Option<I> {
-        self.incoming.try_recv().ok()
+    /// Receives a message if one is available.
+    ///
+    /// Once the worker has failed, the failure is reported once and every later call returns
+    /// `WorkerError::Closed`.
+    pub fn try_recv(&self) -> Option<Result<I, WorkerError>> {
+        match self.incoming.try_recv() {
+            Ok(v) => Some(v),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Closed) => Some(Err(WorkerError::Closed))
+        }
     }
 
-    pub async fn recv(&self) -> I {
-        self.incoming.recv().await.unwrap()
+    pub async fn recv(&self) -> Result<I, WorkerError> {
+        self.incoming.recv().await.unwrap_or(Err(WorkerError::Closed))
     }
 
+    /// Receives up to `max` messages, waiting until at least one is available. Returns no
+    /// messages right away if `max` is 0.
+    ///
+    /// Batching avoids waking up once per message when the worker produces many small ones.
+    pub async fn recv_many(&self, max: usize) -> Vec<Result<I, WorkerError>> {
+        if max == 0 {
+            return Vec::new();
+        }
+        let mut batch = vec![self.recv().await];
+        while batch.len() < max {
+            match self.incoming.try_recv() {
+                Ok(v) => batch.push(v),
+                Err(_) => break
+            }
+        }
+        batch
+    }
+
+    /// Returns another receiver for the messages from the worker.
+    ///
+    /// All receivers share one queue, so each message is received by exactly one of them,
+    /// including [`recv`](Self::recv) on the worker itself. This lets independent tasks take
+    /// work from the same worker. The receiver yields `None` once the worker is closed.
+    pub fn messages(&self) -> Receiver<Result<I, WorkerError>> {
+        self.incoming.clone()
+    }
+
+    /// Whether the worker has failed or shut down. Messages received before that may still be
+    /// waiting to be received.
+    pub fn is_closed(&self) -> bool {
+        self.state.borrow().incoming.is_none()
+    }
+
+    /// Returns a receiver for the progress updates reported with [`WorkerSender::progress`].
+    /// There is only one receiver for them, so this returns `None` after the first call.
+    ///
+    /// Updates reported before the first call to this are discarded.
+    pub fn progress(&self) -> Option<Receiver<Progress>> {
+        let mut state = self.state.borrow_mut();
+        if state.progress.is_some() {
+            return None;
+        }
+        let (sender, progress) = channel();
+        state.progress = Some(sender);
+        Some(progress)
+    }
+
+    /// Measures the round-trip time of a message to the worker's event loop and back.
+    ///
+    /// A worker stuck in a long synchronous computation won't answer until it finishes.
+    pub async fn ping(&self) -> Result<Duration, WorkerError> {
+        let (s, r) = oneshot();
+        let id = self.state.borrow_mut().register_ping(s).ok_or(WorkerError::Closed)?;
+        self.post_control(&Control::Ping(id))?;
+        let start = PerfInstant::now();
+        r.await.ok_or(WorkerError::Closed)?;
+        Ok(start.elapsed())
+    }
+
+    /// Starts pinging the worker every `period` milliseconds, returning a receiver for changes
+    /// in its health.
+    ///
+    /// The worker is flagged as unresponsive after `max_missed` consecutive pings go
+    /// unanswered, and as responsive again once it answers. A `max_missed` of 0 is treated as
+    /// 1, flagging it on the first missed ping. Calling this again replaces the previous
+    /// watchdog.
+    pub fn watchdog(&self, period: impl IntoDelay, max_missed: u32) -> Receiver<Health> {
+        let max_missed = max_missed.max(1);
+        let health = subscribe(&mut self.state.borrow_mut().health);
+        let state = Rc::downgrade(&self.state);
+        let worker = self.worker.clone();
+        let mut outstanding = None;
+        let mut missed = 0;
+        let handle = global::set_interval(period, move || {
+            let state = match state.upgrade() {
+                Some(state) => state,
+                None => return
+            };
+            let mut state = state.borrow_mut();
+            if let Some(id) = outstanding.take() {
+                if state.pings.remove(&id).is_some() {
+                    missed += 1;
+                    if missed == max_missed {
+                        state.report(Health::Unresponsive);
+                    }
+                } else {
+                    if missed >= max_missed {
+                        state.report(Health::Responsive);
+                    }
+                    missed = 0;
+                }
+            }
+            outstanding = state.register_ping(oneshot().0);
+            if let Some(id) = outstanding {
+                let ping = serde_wasm_bindgen::to_value(&Control::Ping(id)).unwrap();
+                let _ = worker.post_message(&ping);
+            }
+        });
+        self.watchdog.replace(Some(handle));
+        health
+    }
+
+    /// Sends a message to the worker.
+    ///
+    /// Any [`Transfer`] values in the message are structured-cloned.
     pub fn send(&self, v: &O) -> Result<(), GeneralError> {
-        let data = bincode::serialize(v)?;
-        let buf = js_sys::Uint8Array::from(&*data);
-        self.worker.post_message_with_transfer(&buf, &js_sys::Array::of1(&buf.buffer()))?;
+        let (msg, transfer) = encode::<_, C>(v, false)?;
+        self.worker.post_message_with_transfer(&msg, &transfer)?;
+        Ok(())
+    }
+
+    /// Sends a message to the worker, transferring ownership of any [`Transfer`] values in it.
+    ///
+    /// The transferred objects are detached and become unusable on this side.
+    pub fn send_transfer(&self, v: &O) -> Result<(), GeneralError> {
+        let (msg, transfer) = encode::<_, C>(v, true)?;
+        self.worker.post_message_with_transfer(&msg, &transfer)?;
         Ok(())
     }
+
+    /// Shuts down the worker gracefully, returning the messages it sent before stopping along
+    /// with the first error reported meanwhile, if any.
+    ///
+    /// The worker's receiver is closed once it has received every message sent so far, and
+    /// the worker is terminated after the entry point drops its receiver and, if it is `async`,
+    /// its future completes, or after `timeout` milliseconds if that doesn't happen. Messages
+    /// received before a timeout are still returned, with [`WorkerError::ShutdownTimeout`].
+    pub async fn shutdown(self, timeout: impl IntoDelay) -> (Vec<I>, Result<(), WorkerError>) {
+        if let Err(e) = self.post_control(&Control::Close) {
+            return (vec![], Err(e));
+        }
+
+        let state = self.state.clone();
+        let _timeout = global::set_timeout(
+            timeout, move || state.borrow_mut().fail(WorkerError::ShutdownTimeout)
+        );
+
+        let mut drained = vec![];
+        let mut result = Ok(());
+        while let Some(msg) = self.incoming.recv().await {
+            match msg {
+                Ok(msg) => drained.push(msg),
+                Err(e) => if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        (drained, result)
+    }
+
+    fn post_control(&self, control: &Control) -> Result<(), WorkerError> {
+        let msg = serde_wasm_bindgen::to_value(control).unwrap();
+        self.worker.post_message(&msg).map_err(|_| WorkerError::Closed)
+    }
 }
 
-impl<I, O> Drop for Worker<O, I> {
+impl<I, O, C> Worker<O, I, C> {
+    /// Terminates the worker immediately, without waiting for it to finish processing messages.
+    ///
+    /// Messages it already sent can still be received.
+    pub fn terminate(&self) {
+        self.worker.terminate();
+        self.state.borrow_mut().close();
+    }
+
+    /// Terminates the worker when `token` is cancelled, like [`terminate`](Self::terminate).
+    pub fn terminate_on(&self, token: &CancelToken) where I: 'static {
+        if token.is_cancelled() {
+            self.terminate();
+            return;
+        }
+        let worker = self.worker.clone();
+        let state = Rc::downgrade(&self.state);
+        let listener = token.signal().add_event_listener_once(move |_: event::Abort| {
+            worker.terminate();
+            if let Some(state) = state.upgrade() {
+                state.borrow_mut().close();
+            }
+        });
+        self.listeners.borrow_mut().push(listener);
+    }
+}
+
+impl<I, O, C> Drop for Worker<O, I, C> {
     fn drop(&mut self) {
         self.worker.terminate();
     }
 }
 
+/// How long a newly spawned worker may take to load and initialize, in milliseconds, before
+/// spawning fails with [`GeneralError::WorkerSpawn`].
+pub const DEFAULT_SPAWN_TIMEOUT: f64 = 10_000.0;
+
+/// Starts the entry point `id` on a newly spawned worker.
+async fn start(
+    worker: &web_sys::Worker, id: &str, userdata: Vec<u8>, objects: Vec<JsValue>, timeout: f64
+) -> Result<(), GeneralError> {
+    // wait for signal that web worker has spawned and is ready to receive messages
+    wait_message(worker, timeout).await?;
+
+    // send the entry point ID and user data to the worker.
+    let data = bincode::serialize(&(id, userdata))?;
+    let buf = js_sys::Uint8Array::from(&*data);
+    let msg = js_sys::Array::of1(&buf);
+    let transfer = js_sys::Array::of1(&buf.buffer());
+    for object in objects {
+        msg.push(&object);
+        transfer.push(&object);
+    }
+    worker.post_message_with_transfer(&msg, &transfer)?;
+
+    // the worker acknowledges once it has checked that it was built with the same entry point
+    let reply = wait_message(worker, timeout).await?;
+    match serde_wasm_bindgen::from_value(reply) {
+        Ok(Control::Started) => Ok(()),
+        Ok(Control::VersionMismatch(expected, found)) => Err(GeneralError::WorkerSpawn(format!(
+            "the worker's {} has fingerprint {:08x} instead of {:08x}, it is probably running \
+             a stale build", id, found, expected
+        ))),
+        Ok(Control::Panic(msg)) => Err(GeneralError::WorkerSpawn(format!(
+            "the worker panicked while starting: {}", msg
+        ))),
+        _ => Err(GeneralError::WorkerSpawn("the worker failed to acknowledge starting".to_owned()))
+    }
+}
+
+/// Waits for the next message from a worker which is starting up.
+///
+/// A loader script that fails to load or a wasm module that fails to initialize shows up as
+/// an error event, or as nothing at all.
+async fn wait_message(worker: &web_sys::Worker, timeout: f64) -> Result<JsValue, GeneralError> {
+    let (s, r) = oneshot();
+    let s = Rc::new(RefCell::new(Some(s)));
+    let finish = move |s: &RefCell<Option<Oneshot<_>>>, result| {
+        if let Some(s) = s.borrow_mut().take() {
+            let _ = s.resolve(result);
+        }
+    };
+
+    let st = s.clone();
+    let _on_message = worker.add_event_listener(move |e: event::Message| finish(&st, Ok(e.data())));
+    let st = s.clone();
+    let _on_error = worker.add_event_listener(move |e: event::Error| {
+        let msg = e.dyn_ref::<web_sys::ErrorEvent>()
+            .map(|e| e.message())
+            .filter(|msg| !msg.is_empty())
+            .unwrap_or_else(|| "the worker script could not be loaded".to_owned());
+        finish(&st, Err(GeneralError::WorkerSpawn(msg)));
+    });
+    let _timeout = global::set_timeout(timeout, move || finish(&s, Err(GeneralError::WorkerSpawn(
+        format!("the worker did not start within {} ms", timeout)
+    ))));
+
+    r.await.unwrap()
+}
+
+/// Errors reported by a [`Worker`] instead of a message.
+#[derive(Debug)]
+pub enum WorkerError {
+    /// The worker function panicked. Contains the panic message.
+    Panicked(String),
+    /// An uncaught exception occurred in the worker. Contains the error message.
+    Error(String),
+    /// A message from the worker could not be deserialized by the browser.
+    MessageError,
+    /// A message from the worker could not be decoded.
+    Decode(GeneralError),
+    /// The worker did not acknowledge a shutdown request in time.
+    ShutdownTimeout,
+    /// A replacement worker could not be spawned.
+    Spawn(GeneralError),
+    /// A message could not be sent to the worker.
+    Send(GeneralError),
+    /// The worker has failed or shut down and will not produce any more messages.
+    Closed
+}
+
+/// Messages used by the worker machinery itself, sent as plain JS objects so they can't be
+/// confused with user messages, which are always arrays.
+#[derive(Serialize, Deserialize)]
+enum Control {
+    Panic(String),
+    Progress(Progress),
+    Close,
+    CloseAck,
+    Ping(u32),
+    Pong(u32),
+    Started,
+    /// The fingerprint the main thread expected and the worker's own.
+    VersionMismatch(u32, u32)
+}
+
+fn post_control(scope: &web_sys::DedicatedWorkerGlobalScope, control: &Control) {
+    if let Ok(msg) = serde_wasm_bindgen::to_value(control) {
+        let _ = scope.post_message(&msg);
+    }
+}
+
+/// Health of a worker as determined by [`Worker::watchdog`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Health {
+    Responsive,
+    Unresponsive,
+    /// The worker failed and will not recover.
+    Failed
+}
+
+/// Main thread state shared between a [`Worker`] and the listeners attached to it.
+struct State<I> {
+    /// Feeds the worker's incoming channel. Taken when the worker fails or shuts down.
+    incoming: Option<Sender<Result<I, WorkerError>>>,
+    progress: Option<Sender<Progress>>,
+    health: Option<Sender<Health>>,
+    pings: HashMap<u32, Oneshot<()>>,
+    next_ping: u32
+}
+
+impl<I> State<I> {
+    fn new(incoming: Sender<Result<I, WorkerError>>) -> Self {
+        State {
+            incoming: Some(incoming),
+            progress: None,
+            health: None,
+            pings: HashMap::new(),
+            next_ping: 0
+        }
+    }
+
+    fn send(&self, v: Result<I, WorkerError>) {
+        if let Some(sender) = &self.incoming {
+            let _ = sender.send(v);
+        }
+    }
+
+    fn close(&mut self) {
+        self.incoming.take();
+        self.pings.clear();
+    }
+
+    fn fail(&mut self, e: WorkerError) {
+        if let Some(sender) = self.incoming.take() {
+            let _ = sender.send(Err(e));
+            self.report(Health::Failed);
+        }
+        self.pings.clear();
+    }
+
+    fn report(&self, health: Health) {
+        if let Some(sender) = &self.health {
+            let _ = sender.send(health);
+        }
+    }
+
+    /// Registers a pending ping, returning its id, or `None` if the worker is gone.
+    fn register_ping(&mut self, pong: Oneshot<()>) -> Option<u32> {
+        self.incoming.as_ref()?;
+        let id = self.next_ping;
+        self.next_ping = self.next_ping.wrapping_add(1);
+        self.pings.insert(id, pong);
+        Some(id)
+    }
+}
+
+/// Returns a new receiver on the channel in `slot`, creating the channel if there isn't a live
+/// one already.
+fn subscribe<T>(slot: &mut Option<Sender<T>>) -> Receiver<T> {
+    if let Some(receiver) = slot.as_ref().and_then(Sender::subscribe) {
+        return receiver;
+    }
+    let (sender, receiver) = channel();
+    *slot = Some(sender);
+    receiver
+}
+
+/// Listens for messages and errors from a worker on the main thread.
+fn listen<I: DeserializeOwned + 'static, C: Codec>(
+    worker: &web_sys::Worker, state: &Rc<RefCell<State<I>>>
+) -> Vec<ListenerHandle> {
+    let st = state.clone();
+    let on_message = worker.add_event_listener(move |e: event::Message| {
+        let data = e.data();
+        let mut state = st.borrow_mut();
+        if js_sys::Array::is_array(&data) {
+            state.send(decode::<_, C>(data).map_err(WorkerError::Decode));
+        } else if let Ok(control) = serde_wasm_bindgen::from_value(data) {
+            match control {
+                Control::Panic(msg) => state.fail(WorkerError::Panicked(msg)),
+                Control::CloseAck => state.close(),
+                Control::Progress(p) => if let Some(sender) = &state.progress {
+                    let _ = sender.send(p);
+                }
+                Control::Pong(id) => if let Some(pong) = state.pings.remove(&id) {
+                    let _ = pong.resolve(());
+                }
+                _ => {}
+            }
+        }
+    });
+
+    let st = state.clone();
+    let on_error = worker.add_event_listener(move |e: event::Error| {
+        let msg = e.dyn_ref::<web_sys::ErrorEvent>()
+            .map(|e| e.message())
+            .unwrap_or_default();
+        st.borrow_mut().fail(WorkerError::Error(msg));
+    });
+
+    let st = state.clone();
+    let on_message_error = worker.add_event_listener(move |_: event::MessageError| {
+        st.borrow().send(Err(WorkerError::MessageError));
+    });
+
+    vec![on_message, on_error, on_message_error]
+}
+
+/// A worker entry point declared with [`#[webutil::worker]`](crate::worker), to be spawned with
+/// [`Worker::new`].
+pub trait Entry: 'static {
+    /// Name under which the entry point is exported to JS.
+    const ID: &'static str;
+    type Args: Serialize + DeserializeOwned + 'static;
+    type Incoming: Serialize + DeserializeOwned + 'static;
+    type Outgoing: Serialize + DeserializeOwned + 'static;
+    type Codec: Codec;
+    /// Bumped with `#[webutil::worker(version = N)]` when the message types change in ways
+    /// their names don't show.
+    const VERSION: u32 = 0;
+
+    /// Checked when the worker starts, so that a worker running a stale build fails to spawn
+    /// instead of misinterpreting messages. Derived from the names of the types and
+    /// [`VERSION`](Self::VERSION).
+    fn fingerprint() -> u32 {
+        fingerprint(&[
+            std::any::type_name::<Self::Args>(),
+            std::any::type_name::<Self::Incoming>(),
+            std::any::type_name::<Self::Outgoing>(),
+            std::any::type_name::<Self::Codec>()
+        ], Self::VERSION)
+    }
+
+    /// Runs the entry point, returning the future to drive if it is `async`.
+    fn run(
+        args: Self::Args,
+        incoming: Receiver<Self::Incoming>,
+        outgoing: WorkerSender<Self::Outgoing, Self::Codec>
+    ) -> Option<WorkerFuture>;
+}
+
+/// Future run by an `async` worker entry point.
+pub type WorkerFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+#[doc(hidden)]
+pub trait __EntryReceiver {
+    type Message;
+}
+
+impl<O> __EntryReceiver for Receiver<O> {
+    type Message = O;
+}
+
+#[doc(hidden)]
+pub trait __EntrySender {
+    type Message;
+    type Codec;
+}
+
+impl<I, C> __EntrySender for WorkerSender<I, C> {
+    type Message = I;
+    type Codec = C;
+}
+
+/// Must be called from `worker.js` once the wasm module is initialized, passing the global
+/// scope and the object holding the wasm-bindgen exports.
 #[wasm_bindgen]
-pub fn _web_worker_entry_point(scope: web_sys::DedicatedWorkerGlobalScope) {
+pub fn _web_worker_entry_point(scope: web_sys::DedicatedWorkerGlobalScope, bindings: JsValue) {
+    report_panics();
+
     let scop = scope.clone();
-    scope.add_event_listener_once(|e: event::Message| {
-        // receive and run bootstrapper
-        let (bootstrapper, userfun, userdata) = bincode::deserialize(
-            &e.data()
-                .dyn_into::<js_sys::Uint8Array>()
-                .unwrap()
-                .to_vec()
+    scope.add_event_listener_once(move |e: event::Message| {
+        // look up the entry point's export by its ID and run it
+        let msg: js_sys::Array = e.data().unchecked_into();
+        let (id, userdata): (String, Vec<u8>) = bincode::deserialize(
+            &msg.get(0).unchecked_into::<js_sys::Uint8Array>().to_vec()
         ).unwrap();
-        let bootstrapper = unsafe { std::mem::transmute::<
-            usize, fn(web_sys::DedicatedWorkerGlobalScope, usize, Vec<u8>)
-        >(bootstrapper) };
-        bootstrapper(scop, userfun, userdata);
+        let entry = js_sys::Reflect::get(&bindings, &JsValue::from_str(&id))
+            .ok()
+            .and_then(|f| f.dyn_into::<js_sys::Function>().ok())
+            .unwrap_or_else(|| panic!("unknown worker entry point {}", id));
+
+        // transferred objects in the arguments are picked up when the entry point deserializes
+        INCOMING.with(|i| *i.borrow_mut() = msg.iter().skip(1).map(Some).collect());
+        entry.call2(&JsValue::UNDEFINED, &scop, &js_sys::Uint8Array::from(&*userdata)).unwrap();
+        INCOMING.with(|i| i.borrow_mut().clear());
     }).forget();
 
     // notify main thread that we're ready to receive messages
     scope.post_message(&JsValue::UNDEFINED).unwrap();
 }
 
-fn bootstrapper<T, I, O>(
-    scope: web_sys::DedicatedWorkerGlobalScope, userfun: usize, userdata: Vec<u8>
-) where
-    T: DeserializeOwned,
-    I: Serialize + 'static,
-    O: DeserializeOwned + 'static
-{
-    // extract userfun and userdata
-    let userfun: fn(T, Receiver<O>, WorkerSender<I>) = unsafe { std::mem::transmute(userfun) };
-    let userdata: T = bincode::deserialize(&userdata).unwrap();
+/// FNV-1a hash of the type names, which is stable across builds unlike `std`'s hashers.
+fn fingerprint(names: &[&str], version: u32) -> u32 {
+    let mut hash = 0x811c9dc5u32;
+    for byte in names.join(",").bytes().chain(version.to_le_bytes()) {
+        hash = (hash ^ byte as u32).wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+/// Checks the fingerprint the main thread expects against the worker's own, returning the
+/// entry point arguments if they match.
+fn handshake<A: DeserializeOwned>(
+    scope: &web_sys::DedicatedWorkerGlobalScope, mut userdata: &[u8], fingerprint: u32
+) -> Option<A> {
+    let expected: u32 = bincode::deserialize_from(&mut userdata).unwrap();
+    if expected != fingerprint {
+        post_control(scope, &Control::VersionMismatch(expected, fingerprint));
+        return None;
+    }
+    let args = bincode::deserialize(userdata).unwrap();
+    post_control(scope, &Control::Started);
+    Some(args)
+}
+
+/// Reports panics to the main thread before the worker dies.
+fn report_panics() {
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        post_control(&js_sys::global().unchecked_into(), &Control::Panic(info.to_string()));
+        previous_hook(info);
+    }));
+}
+
+#[doc(hidden)]
+pub fn __run_entry<E: Entry>(scope: web_sys::DedicatedWorkerGlobalScope, userdata: Vec<u8>) {
+    let args = match handshake(&scope, &userdata, E::fingerprint()) {
+        Some(args) => args,
+        None => return
+    };
 
     // setup incoming message receiver
     let (sender, receiver) = channel();
-    let scop = scope.clone();
-    spawn_local(async move {
-        let incoming = scop.on::<event::Message>();
-        loop {
-            let msg = bincode::deserialize(
-                &incoming.next()
-                    .await
-                    .data()
-                    .dyn_into::<js_sys::Uint8Array>()
-                    .unwrap()
-                    .to_vec()
-            ).unwrap();
-            if sender.send(msg).is_err() {
-                break
-            }
-        }
-    });
+    let (done, finished) = oneshot();
+    forward_messages::<_, E::Codec>(scope.clone(), sender, finished);
 
-    userfun(userdata, receiver, WorkerSender(scope, PhantomData));
+    run_to_completion(E::run(args, receiver, WorkerSender(scope, PhantomData)), done);
+}
+
+/// Drives the entry point's future, if any, and signals `done` once it's complete.
+fn run_to_completion(future: Option<WorkerFuture>, done: Oneshot<()>) {
+    match future {
+        Some(future) => spawn_local(async move {
+            future.await;
+            let _ = done.resolve(());
+        }),
+        None => {
+            let _ = done.resolve(());
+        }
+    }
 }
 
 #[derive(Clone)]
-pub struct WorkerSender<I>(web_sys::DedicatedWorkerGlobalScope, PhantomData<fn(&I)>);
+pub struct WorkerSender<I, C = Bincode>(
+    web_sys::DedicatedWorkerGlobalScope, PhantomData<fn(&I, C)>
+);
 
-impl<I: Serialize> WorkerSender<I> {
+impl<I: Serialize, C: Codec> WorkerSender<I, C> {
+    /// Sends a message to the main thread.
+    ///
+    /// Any [`Transfer`] values in the message are structured-cloned.
     pub fn send(&self, v: &I) {
-        let data = bincode::serialize(v).unwrap();
-        let buf = js_sys::Uint8Array::from(&*data);
-        self.0.post_message_with_transfer(&buf, &js_sys::Array::of1(&buf.buffer())).unwrap();
+        let (msg, transfer) = encode::<_, C>(v, false).unwrap();
+        self.0.post_message_with_transfer(&msg, &transfer).unwrap();
+    }
+
+    /// Sends a message to the main thread, transferring ownership of any [`Transfer`] values
+    /// in it.
+    pub fn send_transfer(&self, v: &I) {
+        let (msg, transfer) = encode::<_, C>(v, true).unwrap();
+        self.0.post_message_with_transfer(&msg, &transfer).unwrap();
+    }
+
+    /// Reports progress to the main thread, where it is received through
+    /// [`Worker::progress`] rather than as a message.
+    pub fn progress(&self, done: u64, total: u64, stage: Option<&str>) {
+        post_control(&self.0, &Control::Progress(Progress {
+            done, total,
+            stage: stage.map(str::to_owned)
+        }));
+    }
+}
+
+/// A progress update for a long-running job, reported separately from the worker's messages.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Progress {
+    pub done: u64,
+    pub total: u64,
+    pub stage: Option<String>
+}
+
+/// JS objects which can be placed in a [`Transfer`].
+pub trait Transferable: JsCast {}
+
+impl Transferable for js_sys::ArrayBuffer {}
+impl Transferable for web_sys::MessagePort {}
+impl Transferable for web_sys::ImageBitmap {}
+impl Transferable for web_sys::OffscreenCanvas {}
+/// Shared rather than transferred; send it with `send`, since transferring it is an error.
+impl Transferable for js_sys::SharedArrayBuffer {}
+
+/// A JS object embedded in a worker message.
+///
+/// The object is passed alongside the serialized message rather than through serde. Sending
+/// with `send_transfer` moves the object to the other side without copying it; sending with
+/// `send` structured-clones it instead, which fails for objects that can only be transferred,
+/// such as `MessagePort`.
+///
+/// Serializing a `Transfer` outside of a worker message is an error.
+pub struct Transfer<T>(pub T);
+
+thread_local! {
+    static OUTGOING: RefCell<Option<Vec<JsValue>>> = const { RefCell::new(None) };
+    static INCOMING: RefCell<Vec<Option<JsValue>>> = const { RefCell::new(vec![]) };
+}
+
+impl<T: Transferable> Serialize for Transfer<T> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let index = OUTGOING.with(|o| o.borrow_mut().as_mut().map(|o| {
+            o.push(self.0.as_ref().clone());
+            o.len() as u32 - 1
+        }));
+        match index {
+            Some(index) => index.serialize(s),
+            None => Err(serde::ser::Error::custom("Transfer used outside of a worker message"))
+        }
+    }
+}
+
+impl<'de, T: Transferable> Deserialize<'de> for Transfer<T> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let index = u32::deserialize(d)? as usize;
+        INCOMING.with(|i| i.borrow_mut().get_mut(index).and_then(Option::take))
+            .map(|v| Transfer(v.unchecked_into()))
+            .ok_or_else(|| D::Error::custom("missing or duplicate transferred object"))
+    }
+}
+
+impl<T> std::ops::Deref for Transfer<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Serializes a message into the `[payload, ...objects]` wire format, returning the message and
+/// its transfer list.
+fn encode<T: Serialize, C: Codec>(
+    v: &T, transfer_objects: bool
+) -> Result<(js_sys::Array, js_sys::Array), GeneralError> {
+    let (encoded, objects) = capture_objects(|| C::encode(v));
+    let (payload, payload_transfer) = encoded?;
+
+    let msg = js_sys::Array::of1(&payload);
+    let transfer = js_sys::Array::new();
+    if let Some(t) = payload_transfer {
+        transfer.push(&t);
+    }
+    for object in objects {
+        msg.push(&object);
+        if transfer_objects {
+            transfer.push(&object);
+        }
+    }
+    Ok((msg, transfer))
+}
+
+/// Serializes entry point arguments. Any [`Transfer`] values in them are always transferred.
+fn serialize_args<T: Serialize>(args: &T) -> Result<(Vec<u8>, Vec<JsValue>), GeneralError> {
+    let (data, objects) = capture_objects(|| bincode::serialize(args));
+    Ok((data?, objects))
+}
+
+/// Runs a serializer, collecting the objects of the [`Transfer`] values it serializes.
+fn capture_objects<R>(f: impl FnOnce() -> R) -> (R, Vec<JsValue>) {
+    OUTGOING.with(|o| o.replace(Some(vec![])));
+    let result = f();
+    let objects = OUTGOING.with(|o| o.replace(None)).unwrap();
+    (result, objects)
+}
+
+/// The number of [`Transfer`] objects captured so far by the serializer running in
+/// [`capture_objects`].
+pub(crate) fn capture_mark() -> usize {
+    OUTGOING.with(|o| o.borrow().as_ref().map_or(0, Vec::len))
+}
+
+/// Runs the serializer `f` in place of one which already captured the objects after `mark`.
+/// Its objects replace theirs if it succeeds, and theirs are kept if it fails, so each object
+/// is only in the transfer list once.
+pub(crate) fn reserialize<R, E>(mark: usize, f: impl FnOnce() -> Result<R, E>) -> Result<R, E> {
+    let previous = OUTGOING.with(|o| o.borrow_mut().as_mut().map(|o| o.split_off(mark)));
+    let result = f();
+    if result.is_err() {
+        OUTGOING.with(|o| if let (Some(o), Some(previous)) = (&mut *o.borrow_mut(), previous) {
+            o.truncate(mark);
+            o.extend(previous);
+        });
     }
+    result
+}
+
+fn decode<T: DeserializeOwned, C: Codec>(msg: JsValue) -> Result<T, GeneralError> {
+    let msg: js_sys::Array = msg.unchecked_into();
+    INCOMING.with(|i| *i.borrow_mut() = msg.iter().skip(1).map(Some).collect());
+    let v = C::decode(msg.get(0));
+    INCOMING.with(|i| i.borrow_mut().clear());
+    v
+}
+
+/// Decodes messages arriving at the worker scope and forwards them into `sender` until the main
+/// thread asks the worker to shut down.
+///
+/// Shutdown is acknowledged once the receiver is dropped and `finished` resolves.
+fn forward_messages<T: DeserializeOwned + 'static, C: Codec>(
+    scope: web_sys::DedicatedWorkerGlobalScope, sender: Sender<T>, finished: Once<()>
+) {
+    spawn_local(async move {
+        let incoming = scope.on::<event::Message>();
+        loop {
+            let data = incoming.next().await.data();
+            if !js_sys::Array::is_array(&data) {
+                match serde_wasm_bindgen::from_value(data) {
+                    Ok(Control::Ping(id)) => post_control(&scope, &Control::Pong(id)),
+                    Ok(Control::Close) => {
+                        // acknowledge once the worker function is done with its receiver
+                        drop(incoming);
+                        sender.close().await;
+                        finished.await;
+                        post_control(&scope, &Control::CloseAck);
+                        break
+                    }
+                    _ => {}
+                }
+                continue
+            }
+            // keep listening after the receiver is dropped so shutdown requests get answered
+            let _ = sender.send(decode::<_, C>(data).unwrap());
+        }
+    });
 }
\ No newline at end of file
@@ -0,0 +1,36 @@
+use super::{ Entry, Script, Transfer, Worker };
+use crate::prelude::*;
+use serde::{ Serialize, de::DeserializeOwned };
+
+/// Hands rendering of `canvas` over to a new worker running the entry point `E`.
+///
+/// The entry point receives the canvas as an `OffscreenCanvas` along with `args`, and can pace
+/// its rendering with [`global::animation_frame`](crate::global::animation_frame), which works
+/// in dedicated workers too:
+/// ```ignore
+/// #[webutil::worker]
+/// async fn renderer(
+///     (canvas, _): (Transfer<OffscreenCanvas>, ()), _: Receiver<()>, _: WorkerSender<()>
+/// ) {
+///     let ctx: OffscreenCanvasRenderingContext2d = canvas.0.get_context("2d")...;
+///     loop {
+///         let now = global::animation_frame().await;
+///         // draw
+///     }
+/// }
+///
+/// let worker = worker::offscreen_render::<renderer, _>("./worker.js", &canvas, ()).await?;
+/// ```
+///
+/// Control of a canvas can only be transferred once, and the canvas can't have a rendering
+/// context of its own.
+pub async fn offscreen_render<E, T>(
+    script: impl Into<Script>, canvas: &web_sys::HtmlCanvasElement, args: T
+) -> Result<Worker<E::Incoming, E::Outgoing, E::Codec>, GeneralError>
+where
+    E: Entry<Args = (Transfer<web_sys::OffscreenCanvas>, T)>,
+    T: Serialize + DeserializeOwned + 'static
+{
+    let offscreen = canvas.transfer_control_to_offscreen()?;
+    Worker::new::<E>(script, &(Transfer(offscreen), args)).await
+}
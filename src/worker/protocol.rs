@@ -0,0 +1,259 @@
+use super::{
+    DEFAULT_SPAWN_TIMEOUT, Script, Worker, WorkerError, WorkerFuture, encode, fingerprint,
+    forward_messages, handshake, run_to_completion, serialize_args
+};
+use crate::prelude::*;
+use crate::global::IntoDelay;
+use crate::channel::{ Receiver, Oneshot, TryRecvError, channel, oneshot };
+use crate::codec::{ Codec, Bincode };
+use serde::{ Serialize, Deserialize, de::DeserializeOwned };
+use std::cell::{ Cell, RefCell };
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// Declares the messages exchanged with a [`ProtocolWorker`].
+///
+/// Requests flow from the main thread to the worker, and each request made with
+/// [`ProtocolWorker::call`] gets exactly one response. Events flow from the worker to the main
+/// thread whenever the worker wants.
+pub trait Protocol: 'static {
+    type Request: Serialize + DeserializeOwned + 'static;
+    type Response: Serialize + DeserializeOwned + 'static;
+    type Event: Serialize + DeserializeOwned + 'static;
+}
+
+#[derive(Serialize, Deserialize)]
+enum ToWorker<R> {
+    Call(u32, R),
+    Notify(R)
+}
+
+#[derive(Serialize, Deserialize)]
+enum FromWorker<R, E> {
+    Reply(u32, R),
+    Event(E)
+}
+
+type Inner<P, C> = Worker<
+    ToWorker<<P as Protocol>::Request>,
+    FromWorker<<P as Protocol>::Response, <P as Protocol>::Event>,
+    C
+>;
+type Pending<P> = Rc<RefCell<HashMap<u32, Oneshot<<P as Protocol>::Response>>>>;
+
+/// A worker speaking the protocol `P`.
+///
+/// Dropping it terminates the worker, like [`Worker`].
+pub struct ProtocolWorker<P: Protocol, C = Bincode> {
+    worker: Rc<Inner<P, C>>,
+    pending: Pending<P>,
+    next_id: Cell<u32>,
+    events: Receiver<Result<P::Event, WorkerError>>
+}
+
+impl<P: Protocol, C: Codec> ProtocolWorker<P, C> {
+    /// Spawns a new worker running the entry point `E`, declared with
+    /// [`#[webutil::worker]`](crate::worker).
+    pub async fn new<E>(script: impl Into<Script>, args: &E::Args) -> Result<Self, GeneralError>
+    where
+        E: ProtocolEntry<Protocol = P, Codec = C>
+    {
+        Self::new_with_timeout::<E>(script, args, DEFAULT_SPAWN_TIMEOUT).await
+    }
+
+    /// Like [`new`](Self::new), but fails if the worker takes longer than `timeout` to load
+    /// and initialize.
+    pub async fn new_with_timeout<E>(
+        script: impl Into<Script>, args: &E::Args, timeout: impl IntoDelay
+    ) -> Result<Self, GeneralError>
+    where
+        E: ProtocolEntry<Protocol = P, Codec = C>
+    {
+        let userdata = serialize_args(&(E::fingerprint(), args))?;
+        let script = script.into();
+        let worker = Rc::new(Worker::spawn(&script, E::ID, userdata, timeout.into_millis()).await?);
+
+        // route replies to their callers and everything else to the event stream
+        let pending: Pending<P> = Rc::new(RefCell::new(HashMap::new()));
+        let (sender, events) = channel();
+        let wrker = worker.clone();
+        let pend = pending.clone();
+        spawn_local(async move {
+            loop {
+                match wrker.recv().await {
+                    Ok(FromWorker::Reply(id, r)) => {
+                        if let Some(s) = pend.borrow_mut().remove(&id) {
+                            let _ = s.resolve(r);
+                        }
+                    }
+                    Ok(FromWorker::Event(e)) => { let _ = sender.send(Ok(e)); }
+                    Err(WorkerError::Closed) => break,
+                    Err(e) => { let _ = sender.send(Err(e)); }
+                }
+            }
+            // fail the outstanding calls
+            pend.borrow_mut().clear();
+        });
+
+        Ok(ProtocolWorker {
+            worker, pending, events,
+            next_id: Cell::new(0)
+        })
+    }
+
+    /// Sends a request and waits for the worker's response to it.
+    pub async fn call(&self, request: &P::Request) -> Result<P::Response, WorkerError> {
+        if self.worker.is_closed() {
+            return Err(WorkerError::Closed);
+        }
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+        let (s, r) = oneshot();
+        self.pending.borrow_mut().insert(id, s);
+        if let Err(e) = self.post(&ToWorker::Call(id, request)) {
+            self.pending.borrow_mut().remove(&id);
+            return Err(WorkerError::Send(e));
+        }
+        r.await.ok_or(WorkerError::Closed)
+    }
+
+    /// Sends a request that the worker doesn't respond to.
+    pub fn notify(&self, request: &P::Request) -> Result<(), GeneralError> {
+        self.post(&ToWorker::Notify(request))
+    }
+
+    /// Receives an event if one is available. Worker failures are reported here too.
+    pub fn try_recv_event(&self) -> Option<Result<P::Event, WorkerError>> {
+        match self.events.try_recv() {
+            Ok(v) => Some(v),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Closed) => Some(Err(WorkerError::Closed))
+        }
+    }
+
+    /// Receives the next event. Worker failures are reported here too.
+    pub async fn recv_event(&self) -> Result<P::Event, WorkerError> {
+        self.events.recv().await.unwrap_or(Err(WorkerError::Closed))
+    }
+
+    fn post(&self, msg: &ToWorker<&P::Request>) -> Result<(), GeneralError> {
+        let (msg, transfer) = encode::<_, C>(msg, false)?;
+        self.worker.worker.post_message_with_transfer(&msg, &transfer)?;
+        Ok(())
+    }
+}
+
+impl<P: Protocol, C> Drop for ProtocolWorker<P, C> {
+    fn drop(&mut self) {
+        self.worker.terminate();
+    }
+}
+
+/// The worker side of a [`ProtocolWorker`].
+pub struct Requests<P: Protocol, C = Bincode> {
+    receiver: Receiver<ToWorker<P::Request>>,
+    scope: web_sys::DedicatedWorkerGlobalScope,
+    _phantom: PhantomData<fn(C)>
+}
+
+impl<P: Protocol, C: Codec> Requests<P, C> {
+    /// Receives the next request, or `None` once the worker is shutting down.
+    pub async fn next(&self) -> Option<Request<P, C>> {
+        Some(match self.receiver.recv().await? {
+            ToWorker::Call(id, body) => Request {
+                body, reply: Some((id, self.scope.clone())), _phantom: PhantomData
+            },
+            ToWorker::Notify(body) => Request { body, reply: None, _phantom: PhantomData }
+        })
+    }
+
+    /// Sends an event to the main thread.
+    pub fn emit(&self, event: &P::Event) {
+        post::<_, C>(&self.scope, &FromWorker::<(), _>::Event(event));
+    }
+}
+
+/// A request received by a protocol worker.
+pub struct Request<P: Protocol, C = Bincode> {
+    pub body: P::Request,
+    reply: Option<(u32, web_sys::DedicatedWorkerGlobalScope)>,
+    _phantom: PhantomData<fn(C)>
+}
+
+impl<P: Protocol, C: Codec> Request<P, C> {
+    /// Whether the caller is waiting for a response.
+    pub fn expects_response(&self) -> bool {
+        self.reply.is_some()
+    }
+
+    /// Responds to the request. Does nothing if the request was a notification.
+    pub fn respond(self, response: &P::Response) {
+        if let Some((id, scope)) = self.reply {
+            post::<_, C>(&scope, &FromWorker::<_, ()>::Reply(id, response));
+        }
+    }
+}
+
+fn post<T: Serialize, C: Codec>(scope: &web_sys::DedicatedWorkerGlobalScope, v: &T) {
+    let (msg, transfer) = encode::<_, C>(v, false).unwrap();
+    scope.post_message_with_transfer(&msg, &transfer).unwrap();
+}
+
+/// A worker entry point declared with [`#[webutil::worker]`](crate::worker), to be spawned with
+/// [`ProtocolWorker::new`].
+pub trait ProtocolEntry: 'static {
+    /// Name under which the entry point is exported to JS.
+    const ID: &'static str;
+    type Args: Serialize + DeserializeOwned + 'static;
+    type Protocol: Protocol;
+    type Codec: Codec;
+    /// Bumped with `#[webutil::worker(version = N)]` when the message types change in ways
+    /// their names don't show.
+    const VERSION: u32 = 0;
+
+    /// Checked when the worker starts, like [`Entry::fingerprint`](super::Entry::fingerprint).
+    fn fingerprint() -> u32 {
+        fingerprint(&[
+            std::any::type_name::<Self::Args>(),
+            std::any::type_name::<<Self::Protocol as Protocol>::Request>(),
+            std::any::type_name::<<Self::Protocol as Protocol>::Response>(),
+            std::any::type_name::<<Self::Protocol as Protocol>::Event>(),
+            std::any::type_name::<Self::Codec>()
+        ], Self::VERSION)
+    }
+
+    /// Runs the entry point, returning the future to drive if it is `async`.
+    fn run(
+        args: Self::Args, requests: Requests<Self::Protocol, Self::Codec>
+    ) -> Option<WorkerFuture>;
+}
+
+#[doc(hidden)]
+pub trait __EntryRequests {
+    type Protocol;
+    type Codec;
+}
+
+impl<P: Protocol, C> __EntryRequests for Requests<P, C> {
+    type Protocol = P;
+    type Codec = C;
+}
+
+#[doc(hidden)]
+pub fn __run_protocol_entry<E: ProtocolEntry>(
+    scope: web_sys::DedicatedWorkerGlobalScope, userdata: Vec<u8>
+) {
+    let args = match handshake(&scope, &userdata, E::fingerprint()) {
+        Some(args) => args,
+        None => return
+    };
+
+    // setup incoming message receiver
+    let (sender, receiver) = channel();
+    let (done, finished) = oneshot();
+    forward_messages::<_, E::Codec>(scope.clone(), sender, finished);
+
+    let requests = Requests { receiver, scope, _phantom: PhantomData };
+    run_to_completion(E::run(args, requests), done);
+}
@@ -0,0 +1,143 @@
+use crate::prelude::*;
+use crate::global::GlobalScope;
+use crate::url::ObjectUrl;
+
+/// The wasm-bindgen target the application is built with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScriptTarget {
+    /// `--target no-modules`, loaded into classic workers with `importScripts`.
+    NoModules,
+    /// `--target web`, loaded into module workers with `import`.
+    Module
+}
+
+/// The script a worker is started from.
+///
+/// Plain strings convert into classic worker scripts.
+#[derive(Clone, Debug)]
+pub enum Script {
+    /// URL of a classic worker script.
+    Classic(String),
+    /// URL of a module worker script.
+    Module(String),
+    /// Loader generated at runtime by [`init_script`] and served from a Blob URL, so no
+    /// separate loader file is needed. `app` is resolved relative to the current page.
+    Inline { app: String, target: ScriptTarget }
+}
+
+impl Script {
+    pub(super) fn spawn(&self) -> Result<web_sys::Worker, JsValue> {
+        match self {
+            Script::Classic(uri) => web_sys::Worker::new(uri),
+            Script::Module(uri) => {
+                let options = web_sys::WorkerOptions::new();
+                options.set_type(web_sys::WorkerType::Module);
+                web_sys::Worker::new_with_options(uri, &options)
+            }
+            Script::Inline { app, target } => spawn_inline(app, *target, init_script)
+        }
+    }
+}
+
+/// Spawns a worker from a loader generated by `loader` and served from a Blob URL.
+pub(super) fn spawn_inline(
+    app: &str, target: ScriptTarget, loader: fn(&str, ScriptTarget) -> String
+) -> Result<web_sys::Worker, JsValue> {
+    let url = blob_url(&loader(&absolute_url(app)?, target))?;
+    let script = match target {
+        ScriptTarget::NoModules => Script::Classic(url.to_string()),
+        ScriptTarget::Module => Script::Module(url.to_string())
+    };
+    // the URL is resolved when the worker is constructed, so it is revoked right after
+    script.spawn()
+}
+
+impl From<&str> for Script {
+    fn from(uri: &str) -> Self {
+        Script::Classic(uri.to_owned())
+    }
+}
+
+impl From<String> for Script {
+    fn from(uri: String) -> Self {
+        Script::Classic(uri)
+    }
+}
+
+/// Generates the loader script workers are started from.
+///
+/// `app` is the path to the wasm-bindgen output without extension, relative to where the
+/// script is served from, e.g. `"./pkg/my_app"` for `./pkg/my_app.js` and
+/// `./pkg/my_app_bg.wasm`. Serve the result as a classic script for
+/// [`ScriptTarget::NoModules`] and as a module script for [`ScriptTarget::Module`].
+pub fn init_script(app: &str, target: ScriptTarget) -> String {
+    match target {
+        ScriptTarget::NoModules => format!(
+            "importScripts({js});\n\
+             wasm_bindgen({wasm}).then(() => {{\n\
+             \x20   wasm_bindgen._web_worker_entry_point(self, wasm_bindgen);\n\
+             }});\n",
+            js = js_string(&format!("{}.js", app)),
+            wasm = js_string(&format!("{}_bg.wasm", app))
+        ),
+        ScriptTarget::Module => format!(
+            "import init, * as bindings from {js};\n\
+             await init();\n\
+             bindings._web_worker_entry_point(self, bindings);\n",
+            js = js_string(&format!("{}.js", app))
+        )
+    }
+}
+
+/// Resolves `url` against the current page, since blob URLs have no useful base URL.
+pub(super) fn absolute_url(url: &str) -> Result<String, JsValue> {
+    let base = GlobalScope::current().location_href().unwrap_or_default();
+    Ok(web_sys::Url::new_with_base(url, &base)?.href())
+}
+
+/// Creates an object URL serving `source` as JavaScript.
+pub(super) fn blob_url(source: &str) -> Result<ObjectUrl, JsValue> {
+    let parts = js_sys::Array::of1(&source.into());
+    let properties = web_sys::BlobPropertyBag::new();
+    properties.set_type("text/javascript");
+    let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &properties)?;
+    ObjectUrl::create(&blob)
+}
+
+/// Generates the loader for [`spawn_thread`](super::spawn_thread) workers, which instantiate
+/// the module they are sent with the shared memory they are sent.
+#[cfg_attr(not(target_feature = "atomics"), allow(dead_code))]
+pub(super) fn thread_script(app: &str, target: ScriptTarget) -> String {
+    match target {
+        ScriptTarget::NoModules => format!(
+            "importScripts({js});\n\
+             self.onmessage = e => {{\n\
+             \x20   const [module, memory, ptr] = e.data;\n\
+             \x20   wasm_bindgen({{ module_or_path: module, memory }}).then(() => {{\n\
+             \x20       wasm_bindgen._web_worker_thread_entry_point(self, ptr);\n\
+             \x20   }});\n\
+             }};\n",
+            js = js_string(&format!("{}.js", app))
+        ),
+        ScriptTarget::Module => format!(
+            "import init, * as bindings from {js};\n\
+             self.onmessage = async e => {{\n\
+             \x20   const [module, memory, ptr] = e.data;\n\
+             \x20   await init({{ module_or_path: module, memory }});\n\
+             \x20   bindings._web_worker_thread_entry_point(self, ptr);\n\
+             }};\n",
+            js = js_string(&format!("{}.js", app))
+        )
+    }
+}
+
+pub(super) fn js_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}
+
+/// Writes the loader script generated by [`init_script`] to `path`, for use from build scripts.
+pub fn write_init_script(
+    path: impl AsRef<std::path::Path>, app: &str, target: ScriptTarget
+) -> std::io::Result<()> {
+    std::fs::write(path, init_script(app, target))
+}
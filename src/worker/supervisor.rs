@@ -0,0 +1,204 @@
+use super::{ Entry, Script, Worker, WorkerError };
+use crate::channel::{ Oneshot, oneshot };
+use crate::global;
+use std::cell::{ Cell, RefCell };
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// When a [`Supervisor`] restarts its worker after it fails.
+#[derive(Clone, Copy, Debug)]
+pub enum RestartPolicy {
+    /// Restart immediately, every time.
+    Always,
+    /// Restart after a delay starting at `initial` milliseconds and doubling with each
+    /// consecutive failure, up to `max` milliseconds.
+    Backoff { initial: u32, max: u32 },
+    /// Restart immediately, giving up after this many consecutive failures.
+    MaxRetries(u32)
+}
+
+/// A [`Worker`] which is respawned with the same entry point and arguments when it fails.
+///
+/// Failures are detected when receiving from or sending to the worker. Messages the failed
+/// worker sent before failing are still received; messages sent to it that it didn't get to
+/// are lost. A failure counts as consecutive unless a message was received since the previous
+/// one.
+///
+/// The worker can also be shut down while idle and respawned when it is next sent a message;
+/// see [`set_idle_timeout`](Self::set_idle_timeout).
+pub struct Supervisor<E: Entry> {
+    script: Script,
+    args: E::Args,
+    policy: RestartPolicy,
+    worker: RefCell<Rc<EntryWorker<E>>>,
+    backlog: RefCell<VecDeque<E::Outgoing>>,
+    failures: Cell<u32>,
+    restarts: Cell<u32>,
+    idle_timeout: Cell<Option<u32>>,
+    idle_timer: RefCell<Option<global::IntervalHandle>>,
+    idle: Rc<Cell<bool>>,
+    waiting: RefCell<Vec<Oneshot<()>>>
+}
+
+type EntryWorker<E> = Worker<<E as Entry>::Incoming, <E as Entry>::Outgoing, <E as Entry>::Codec>;
+
+impl<E: Entry> Supervisor<E> {
+    /// Spawns a supervised worker running the entry point `E`.
+    pub async fn new(
+        script: impl Into<Script>, args: E::Args, policy: RestartPolicy
+    ) -> Result<Self, WorkerError> {
+        let script = script.into();
+        let worker = Worker::new::<E>(script.clone(), &args).await.map_err(WorkerError::Spawn)?;
+        Ok(Supervisor {
+            script,
+            args, policy,
+            worker: RefCell::new(Rc::new(worker)),
+            backlog: RefCell::new(VecDeque::new()),
+            failures: Cell::new(0),
+            restarts: Cell::new(0),
+            idle_timeout: Cell::new(None),
+            idle_timer: RefCell::new(None),
+            idle: Rc::new(Cell::new(false)),
+            waiting: RefCell::new(vec![])
+        })
+    }
+
+    /// Terminates the worker once no messages have been sent to or received from it for
+    /// `timeout` milliseconds, or never if `None`. The worker is respawned when it is next
+    /// sent a message.
+    ///
+    /// This bounds memory use when a worker is only needed in bursts. Only use it for workers
+    /// which keep no state between messages and don't work for long without sending any.
+    pub fn set_idle_timeout(&self, timeout: Option<u32>) {
+        self.idle_timeout.set(timeout);
+        self.touch();
+    }
+
+    /// Whether the worker is currently shut down for being idle.
+    pub fn is_idle(&self) -> bool {
+        self.idle.get()
+    }
+
+    /// The currently running worker, or the last one if it is shut down for being idle.
+    pub fn worker(&self) -> Rc<EntryWorker<E>> {
+        self.worker.borrow().clone()
+    }
+
+    /// The total number of times the worker has been restarted.
+    pub fn restarts(&self) -> u32 {
+        self.restarts.get()
+    }
+
+    /// Sends a message to the worker, restarting it first if it has failed or respawning it if
+    /// it is idle.
+    pub async fn send(&self, v: &E::Incoming) -> Result<(), WorkerError> {
+        let worker = self.worker();
+        if self.idle.get() {
+            self.respawn(&worker).await?;
+        } else if worker.is_closed() {
+            self.restart(&worker, WorkerError::Closed).await?;
+        }
+        self.touch();
+        self.worker().send(v).map_err(WorkerError::Send)
+    }
+
+    /// Receives a message from the worker, restarting it if it fails.
+    ///
+    /// Returns the failure that caused the supervisor to give up when the restart policy is
+    /// exhausted.
+    pub async fn recv(&self) -> Result<E::Outgoing, WorkerError> {
+        loop {
+            if let Some(v) = self.backlog.borrow_mut().pop_front() {
+                return Ok(v);
+            }
+            let worker = self.worker();
+            match worker.recv().await {
+                Ok(v) => {
+                    self.failures.set(0);
+                    self.touch();
+                    return Ok(v);
+                }
+                Err(e @ WorkerError::Decode(_)) | Err(e @ WorkerError::MessageError) => {
+                    return Err(e)
+                }
+                // nothing more will be received until the worker is respawned by a send
+                Err(_) if self.idle.get() => {
+                    let (s, r) = oneshot();
+                    self.waiting.borrow_mut().push(s);
+                    r.await;
+                }
+                Err(e) => self.restart(&worker, e).await?
+            }
+        }
+    }
+
+    async fn restart(
+        &self, failed: &Rc<EntryWorker<E>>, cause: WorkerError
+    ) -> Result<(), WorkerError> {
+        let failures = self.failures.get();
+        let delay = match self.policy {
+            RestartPolicy::Always => 0,
+            RestartPolicy::MaxRetries(max) if failures >= max => return Err(cause),
+            RestartPolicy::MaxRetries(_) => 0,
+            RestartPolicy::Backoff { initial, max } => {
+                initial.saturating_mul(1 << failures.min(31)).min(max)
+            }
+        };
+        self.failures.set(failures + 1);
+
+        // keep whatever the failed worker managed to send
+        loop {
+            match failed.try_recv() {
+                Some(Ok(v)) => self.backlog.borrow_mut().push_back(v),
+                Some(Err(WorkerError::Closed)) | None => break,
+                Some(Err(_)) => {}
+            }
+        }
+
+        if delay > 0 {
+            global::later(delay).await;
+        }
+        // someone else may have restarted the worker while we were waiting
+        if !Rc::ptr_eq(&self.worker(), failed) {
+            return Ok(());
+        }
+        let worker = Worker::new::<E>(self.script.clone(), &self.args).await
+            .map_err(WorkerError::Spawn)?;
+        if Rc::ptr_eq(&self.worker(), failed) {
+            self.worker.replace(Rc::new(worker));
+            self.restarts.set(self.restarts.get() + 1);
+            self.touch();
+        }
+        Ok(())
+    }
+
+    /// Replaces a worker shut down for being idle. Doesn't count as a restart.
+    async fn respawn(&self, idle: &Rc<EntryWorker<E>>) -> Result<(), WorkerError> {
+        let worker = Worker::new::<E>(self.script.clone(), &self.args).await
+            .map_err(WorkerError::Spawn)?;
+        // someone else may have respawned the worker while we were waiting
+        if Rc::ptr_eq(&self.worker(), idle) {
+            self.worker.replace(Rc::new(worker));
+            self.idle.set(false);
+            for waiting in self.waiting.take() {
+                let _ = waiting.resolve(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Restarts the idle timer.
+    fn touch(&self) {
+        let timer = self.idle_timeout.get().map(|timeout| {
+            let idle = self.idle.clone();
+            let worker = Rc::downgrade(&self.worker());
+            global::set_timeout(timeout, move || {
+                if let Some(worker) = worker.upgrade().filter(|w| !w.is_closed()) {
+                    idle.set(true);
+                    worker.terminate();
+                }
+            })
+        });
+        self.idle_timer.replace(timer);
+    }
+}
@@ -0,0 +1,110 @@
+use super::{ Control, ScriptTarget, WorkerError, post_control, report_panics, script };
+use crate::prelude::*;
+use crate::channel::{ Once, oneshot };
+use crate::event::{ self, ListenerHandle };
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+
+type ThreadFn = Box<dyn FnOnce() + Send>;
+
+/// A worker sharing this module's wasm memory, started with [`spawn_thread`].
+///
+/// Dropping the handle detaches the thread rather than terminating it. Its worker is still
+/// terminated once the closure returns.
+pub struct Thread {
+    worker: web_sys::Worker,
+    finished: Option<Once<Result<(), WorkerError>>>,
+    listeners: Vec<ListenerHandle>
+}
+
+/// Runs `f` on a new worker which shares this module's wasm memory.
+///
+/// Only available when building with the `atomics` target feature and shared memory. `app`
+/// and `target` describe the wasm-bindgen output like [`Script::Inline`](super::Script). Data
+/// can be shared with the closure through the usual `Arc`s, atomics and mutexes, but keep in
+/// mind that the main thread is not allowed to block.
+pub fn spawn_thread(
+    app: &str, target: ScriptTarget, f: impl FnOnce() + Send + 'static
+) -> Result<Thread, GeneralError> {
+    let worker = script::spawn_inline(app, target, script::thread_script)?;
+
+    let (done, finished) = oneshot();
+    let done = Rc::new(RefCell::new(Some(done)));
+    let dne = done.clone();
+    let on_message = worker.add_event_listener(move |e: event::Message| {
+        let result = match serde_wasm_bindgen::from_value(e.data()) {
+            Ok(Control::Panic(msg)) => Err(WorkerError::Panicked(msg)),
+            Ok(Control::CloseAck) => Ok(()),
+            _ => return
+        };
+        if let Some(done) = dne.borrow_mut().take() {
+            let _ = done.resolve(result);
+        }
+    });
+    let on_error = worker.add_event_listener(move |e: event::Error| {
+        let msg = e.dyn_ref::<web_sys::ErrorEvent>()
+            .map(|e| e.message())
+            .unwrap_or_default();
+        if let Some(done) = done.borrow_mut().take() {
+            let _ = done.resolve(Err(WorkerError::Error(msg)));
+        }
+    });
+
+    // the worker takes ownership of the closure, which is freed when it runs
+    let ptr = Box::into_raw(Box::new(Box::new(f) as ThreadFn));
+    let msg = js_sys::Array::of3(
+        &wasm_bindgen::module(), &wasm_bindgen::memory(), &JsValue::from(ptr as u32)
+    );
+    if let Err(e) = worker.post_message(&msg) {
+        drop(unsafe { Box::from_raw(ptr) });
+        worker.terminate();
+        return Err(e.into());
+    }
+
+    Ok(Thread {
+        worker,
+        finished: Some(finished),
+        listeners: vec![on_message, on_error]
+    })
+}
+
+impl Thread {
+    /// Waits for the closure to return.
+    pub async fn join(mut self) -> Result<(), WorkerError> {
+        let finished = self.finished.take().unwrap();
+        let result = finished.await.unwrap_or(Err(WorkerError::Closed));
+        self.worker.terminate();
+        result
+    }
+
+    /// Stops the thread immediately. Whatever it was holding is leaked.
+    pub fn terminate(mut self) {
+        self.finished = None;
+        self.worker.terminate();
+    }
+}
+
+impl Drop for Thread {
+    fn drop(&mut self) {
+        // the closure can't be stopped safely, so wait for it before terminating the worker
+        if let Some(finished) = self.finished.take() {
+            let worker = self.worker.clone();
+            let listeners = std::mem::take(&mut self.listeners);
+            spawn_local(async move {
+                let _ = finished.await;
+                worker.terminate();
+                drop(listeners);
+            });
+        }
+    }
+}
+
+#[doc(hidden)]
+#[wasm_bindgen]
+pub fn _web_worker_thread_entry_point(scope: web_sys::DedicatedWorkerGlobalScope, ptr: u32) {
+    report_panics();
+    let f = unsafe { Box::from_raw(ptr as *mut ThreadFn) };
+    f();
+    post_control(&scope, &Control::CloseAck);
+}
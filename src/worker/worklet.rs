@@ -0,0 +1,253 @@
+//! Rust-backed audio worklet processors.
+//!
+//! Processors run on the audio rendering thread in their own instance of the wasm module,
+//! which is compiled once on the main thread and instantiated synchronously in the worklet.
+//! Worklets only load ES modules, so the application must be built with `--target web`.
+//!
+//! ```ignore
+//! #[webutil::audio_processor]
+//! struct Gain { port: AudioPort<Gain>, gain: f32 }
+//!
+//! impl AudioProcessor for Gain {
+//!     type Incoming = f32;
+//!     type Outgoing = ();
+//!     type Codec = Bincode;
+//!
+//!     fn new(port: AudioPort<Self>) -> Self {
+//!         Gain { port, gain: 1.0 }
+//!     }
+//!
+//!     fn process(&mut self, inputs: &[Buses], outputs: &mut [Buses]) -> bool { ... }
+//! }
+//!
+//! worklet::register_audio::<Gain>(&context, "./pkg/my_app", "gain").await?;
+//! let node = AudioProcessorNode::<Gain>::new(&context, "gain")?;
+//! node.send(&0.5)?;
+//! ```
+
+use super::{ decode, encode, script };
+use crate::prelude::*;
+use crate::channel::{ Receiver, TryRecvError, channel };
+use crate::codec::Codec;
+use crate::event::{ self, ListenerHandle };
+use serde::{ Serialize, de::DeserializeOwned };
+use std::marker::PhantomData;
+use wasm_bindgen::JsCast;
+
+/// The channels of one input or output, each holding one render quantum of samples.
+pub type Buses = Vec<Vec<f32>>;
+
+/// An audio processor running in an audio worklet. Declare it with
+/// [`#[webutil::audio_processor]`](crate::audio_processor).
+pub trait AudioProcessor: Sized + 'static {
+    /// Messages sent to the processor by its [`AudioProcessorNode`].
+    type Incoming: Serialize + DeserializeOwned + 'static;
+    /// Messages sent by the processor to its [`AudioProcessorNode`].
+    type Outgoing: Serialize + DeserializeOwned + 'static;
+    type Codec: Codec;
+
+    /// Creates the processor when its node is created on the main thread.
+    fn new(port: AudioPort<Self>) -> Self;
+
+    /// Processes one render quantum. `outputs` are zeroed beforehand. Returning `false` lets
+    /// the browser stop calling this once the node has no inputs left.
+    fn process(&mut self, inputs: &[Buses], outputs: &mut [Buses]) -> bool;
+}
+
+#[doc(hidden)]
+pub trait __AudioExport {
+    const ID: &'static str;
+}
+
+/// Loads the processor `P` into `context`'s audio worklet under `processor_name`.
+///
+/// `app` is the path to the wasm-bindgen output without extension, like for
+/// [`Script::Inline`](super::Script), and is resolved relative to the current page.
+pub async fn register_audio<P: AudioProcessor + __AudioExport>(
+    context: &web_sys::BaseAudioContext, app: &str, processor_name: &str
+) -> Result<(), GeneralError> {
+    let source = audio_processor_script(&script::absolute_url(app)?, processor_name, P::ID);
+    let url = script::blob_url(&source)?;
+    let loaded = context.audio_worklet().and_then(|w| w.add_module(&url));
+    let loaded = match loaded {
+        Ok(promise) => wasm_bindgen_futures::JsFuture::from(promise).await,
+        Err(e) => Err(e)
+    };
+    drop(url);
+    loaded?;
+    Ok(())
+}
+
+fn audio_processor_script(app: &str, processor_name: &str, id: &str) -> String {
+    format!(
+        "import * as bindings from {js};\n\
+         let initialized = false;\n\
+         registerProcessor({name}, class extends AudioWorkletProcessor {{\n\
+         \x20   constructor(options) {{\n\
+         \x20       super();\n\
+         \x20       if (!initialized) {{\n\
+         \x20           bindings.initSync({{ module: options.processorOptions.module }});\n\
+         \x20           initialized = true;\n\
+         \x20       }}\n\
+         \x20       this.processor = bindings[{id}](this.port);\n\
+         \x20   }}\n\
+         \x20   process(inputs, outputs) {{\n\
+         \x20       return this.processor.process(inputs, outputs);\n\
+         \x20   }}\n\
+         }});\n",
+        js = script::js_string(&format!("{}.js", app)),
+        name = script::js_string(processor_name),
+        id = script::js_string(id)
+    )
+}
+
+/// The main thread side of an [`AudioProcessor`].
+pub struct AudioProcessorNode<P: AudioProcessor> {
+    node: web_sys::AudioWorkletNode,
+    port: web_sys::MessagePort,
+    incoming: Receiver<Result<P::Outgoing, GeneralError>>,
+    _listener: ListenerHandle
+}
+
+impl<P: AudioProcessor> AudioProcessorNode<P> {
+    /// Creates a node running the processor registered as `processor_name` with
+    /// [`register_audio`].
+    pub fn new(
+        context: &web_sys::BaseAudioContext, processor_name: &str
+    ) -> Result<Self, GeneralError> {
+        Self::new_with_options(context, processor_name, &web_sys::AudioWorkletNodeOptions::new())
+    }
+
+    /// Like [`new`](Self::new), with options such as the number of inputs and outputs. The
+    /// processor options are used to instantiate the module and are replaced.
+    pub fn new_with_options(
+        context: &web_sys::BaseAudioContext,
+        processor_name: &str,
+        options: &web_sys::AudioWorkletNodeOptions
+    ) -> Result<Self, GeneralError> {
+        let processor_options = js_sys::Object::new();
+        js_sys::Reflect::set(&processor_options, &"module".into(), &wasm_bindgen::module())?;
+        options.set_processor_options(Some(&processor_options));
+        let node = web_sys::AudioWorkletNode::new_with_options(context, processor_name, options)?;
+
+        let port = node.port()?;
+        let (sender, incoming) = channel();
+        let listener = port.add_event_listener(move |e: event::Message| {
+            let _ = sender.send(decode::<_, P::Codec>(e.data()));
+        });
+        port.start();
+
+        Ok(AudioProcessorNode { node, port, incoming, _listener: listener })
+    }
+
+    /// The underlying node, for connecting it to the audio graph.
+    pub fn node(&self) -> &web_sys::AudioWorkletNode {
+        &self.node
+    }
+
+    pub fn send(&self, v: &P::Incoming) -> Result<(), GeneralError> {
+        let (msg, transfer) = encode::<_, P::Codec>(v, false)?;
+        self.port.post_message_with_transferable(&msg, &transfer)?;
+        Ok(())
+    }
+
+    pub fn try_recv(&self) -> Option<Result<P::Outgoing, GeneralError>> {
+        self.incoming.try_recv().ok()
+    }
+
+    pub async fn recv(&self) -> Result<P::Outgoing, GeneralError> {
+        self.incoming.recv().await.unwrap()
+    }
+}
+
+/// The worklet side of the channel between an [`AudioProcessor`] and its node.
+pub struct AudioPort<P: AudioProcessor> {
+    port: web_sys::MessagePort,
+    incoming: Receiver<P::Incoming>,
+    _listener: ListenerHandle,
+    _phantom: PhantomData<fn(P)>
+}
+
+impl<P: AudioProcessor> AudioPort<P> {
+    /// Receives a message if one is available. Messages which can't be decoded are dropped.
+    ///
+    /// Messages arrive between render quanta, so this is meant to be polled from
+    /// [`AudioProcessor::process`].
+    pub fn try_recv(&self) -> Option<P::Incoming> {
+        match self.incoming.try_recv() {
+            Ok(v) => Some(v),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => None
+        }
+    }
+
+    pub fn send(&self, v: &P::Outgoing) {
+        if let Ok((msg, transfer)) = encode::<_, P::Codec>(v, false) {
+            let _ = self.port.post_message_with_transferable(&msg, &transfer);
+        }
+    }
+}
+
+/// A processor instance, called by the worklet's `AudioWorkletProcessor`.
+#[doc(hidden)]
+#[wasm_bindgen]
+pub struct AudioProcessorHandle {
+    process: Box<ProcessFn>
+}
+
+type ProcessFn = dyn FnMut(&js_sys::Array, &js_sys::Array) -> bool;
+
+#[wasm_bindgen]
+impl AudioProcessorHandle {
+    pub fn process(&mut self, inputs: js_sys::Array, outputs: js_sys::Array) -> bool {
+        (self.process)(&inputs, &outputs)
+    }
+}
+
+#[doc(hidden)]
+pub fn __new_audio_processor<P: AudioProcessor>(
+    port: web_sys::MessagePort
+) -> AudioProcessorHandle {
+    let (sender, incoming) = channel();
+    let listener = port.add_event_listener(move |e: event::Message| {
+        if let Ok(v) = decode::<_, P::Codec>(e.data()) {
+            let _ = sender.send(v);
+        }
+    });
+    port.start();
+    let mut processor = P::new(AudioPort {
+        port, incoming,
+        _listener: listener,
+        _phantom: PhantomData
+    });
+
+    // reuse the sample buffers across render quanta
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    AudioProcessorHandle {
+        process: Box::new(move |ins, outs| {
+            read_buses(ins, &mut inputs);
+            read_buses(outs, &mut outputs);
+            let keep_alive = processor.process(&inputs, &mut outputs);
+            for (bus, channels) in outs.iter().zip(&outputs) {
+                let bus: js_sys::Array = bus.unchecked_into();
+                for (channel, samples) in bus.iter().zip(channels) {
+                    channel.unchecked_into::<js_sys::Float32Array>().copy_from(samples);
+                }
+            }
+            keep_alive
+        })
+    }
+}
+
+fn read_buses(buses: &js_sys::Array, into: &mut Vec<Buses>) {
+    into.resize_with(buses.length() as usize, Vec::new);
+    for (bus, channels) in buses.iter().zip(into.iter_mut()) {
+        let bus: js_sys::Array = bus.unchecked_into();
+        channels.resize_with(bus.length() as usize, Vec::new);
+        for (channel, samples) in bus.iter().zip(channels.iter_mut()) {
+            let channel: js_sys::Float32Array = channel.unchecked_into();
+            samples.resize(channel.length() as usize, 0.0);
+            channel.copy_to(samples);
+        }
+    }
+}
@@ -0,0 +1,463 @@
+//! Typed WebSockets.
+//!
+//! ```ignore
+//! let socket = Socket::<ClientMsg, ServerMsg>::connect("wss://example.com/game").await?;
+//! socket.send(&ClientMsg::Join)?;
+//! while let Ok(msg) = socket.recv().await { ... }
+//! ```
+
+use crate::prelude::*;
+use crate::channel::{
+    Receiver, Sender, TryRecvError, WatchReceiver, WatchSender, channel, watch
+};
+use crate::codec::{ Codec, Json };
+use crate::event::{ self, ListenerHandle };
+use crate::global;
+use crate::task::{ JoinHandle, RetryPolicy, WebFutureExt };
+use serde::{ Serialize, de::DeserializeOwned };
+use std::cell::{ Cell, RefCell };
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+
+/// A WebSocket sending `O`s and receiving `I`s.
+///
+/// Messages are encoded using the codec `C`, which is [`Json`] by default. Codecs producing
+/// strings send text frames, and codecs producing byte arrays such as [`Bincode`] send binary
+/// frames; others can't be used.
+///
+/// Dropping the socket closes the connection.
+///
+/// [`Bincode`]: crate::codec::Bincode
+pub struct Socket<O, I, C = Json> {
+    socket: web_sys::WebSocket,
+    incoming: Receiver<Result<I, SocketError>>,
+    state: Rc<RefCell<State<I>>>,
+    _listeners: Vec<ListenerHandle>,
+    _phantom: PhantomData<fn(O, C)>
+}
+
+type State<I> = MessageState<I, SocketError, CloseInfo>;
+
+/// The state shared by a WebSocket-like wrapper and its listeners. `close` records why the
+/// connection closed, once it has.
+pub(crate) struct MessageState<I, E, X> {
+    pub(crate) incoming: Option<Sender<Result<I, E>>>,
+    pub(crate) close: Option<X>
+}
+
+impl<I, E, X> MessageState<I, E, X> {
+    pub(crate) fn new(incoming: Sender<Result<I, E>>) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(MessageState { incoming: Some(incoming), close: None }))
+    }
+}
+
+/// Errors which a WebSocket-like wrapper reports instead of a message.
+pub(crate) trait MessageError {
+    /// The connection failed, which browsers don't explain.
+    fn failed() -> Self;
+    fn decode(e: GeneralError) -> Self;
+}
+
+/// Why a socket was closed, from its `close` event.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CloseInfo {
+    pub code: u16,
+    pub reason: String,
+    /// Whether the closing handshake completed.
+    pub clean: bool
+}
+
+/// Errors reported by a [`Socket`] instead of a message.
+#[derive(Debug)]
+pub enum SocketError {
+    /// The connection failed. Browsers don't say why; a [`Closed`](Self::Closed) follows.
+    Error,
+    /// A message could not be decoded.
+    Decode(GeneralError),
+    /// The connection is closed and there are no more messages.
+    Closed(CloseInfo)
+}
+
+impl<O, I, C> Socket<O, I, C>
+where
+    O: Serialize,
+    I: DeserializeOwned + 'static,
+    C: Codec
+{
+    /// Connects to `url`, waiting until the connection is open.
+    pub async fn connect(url: &str) -> Result<Self, GeneralError> {
+        let socket = web_sys::WebSocket::new(url)?;
+        socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+
+        let (sender, incoming) = channel();
+        let state = State::new(sender);
+        let listeners = listen::<_, C, _, _>(&socket, &state, |e| {
+            CloseInfo { code: e.code(), reason: e.reason(), clean: e.was_clean() }
+        });
+
+        let opened = socket.once::<event::Open>();
+        let closed = socket.once::<event::Close>();
+        let open = async { opened.await; true }.race(async { closed.await; false }).await;
+        if !open {
+            let msg = format!("failed to connect to {}", url);
+            return Err(GeneralError::message(&msg));
+        }
+
+        Ok(Socket { socket, incoming, state, _listeners: listeners, _phantom: PhantomData })
+    }
+
+    /// Sends a message, failing if the connection is closing or closed, since the browser
+    /// would silently drop it.
+    pub fn send(&self, v: &O) -> Result<(), GeneralError> {
+        let (msg, _) = C::encode(v)?;
+        self.send_encoded(&msg)
+    }
+
+    fn is_open(&self) -> bool {
+        self.socket.ready_state() == web_sys::WebSocket::OPEN
+    }
+
+    fn send_encoded(&self, msg: &JsValue) -> Result<(), GeneralError> {
+        if !self.is_open() {
+            return Err(GeneralError::message("socket is not open"));
+        }
+        send_frame(
+            msg,
+            |text| self.socket.send_with_str(text),
+            |bytes| self.socket.send_with_js_u8_array(bytes)
+        )
+    }
+
+    /// Receives a message if one is available.
+    pub fn try_recv(&self) -> Option<Result<I, SocketError>> {
+        match self.incoming.try_recv() {
+            Ok(v) => Some(v),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Closed) => Some(Err(self.closed()))
+        }
+    }
+
+    pub async fn recv(&self) -> Result<I, SocketError> {
+        match self.incoming.recv().await {
+            Some(v) => v,
+            None => Err(self.closed())
+        }
+    }
+
+    fn closed(&self) -> SocketError {
+        SocketError::Closed(self.state.borrow().close.clone().unwrap())
+    }
+}
+
+impl<O, I, C> Socket<O, I, C> {
+    /// Whether the connection has closed. Messages received before that may still be waiting to
+    /// be received.
+    pub fn is_closed(&self) -> bool {
+        self.state.borrow().close.is_some()
+    }
+
+    /// Bytes queued by [`send`](Self::send) but not yet transmitted, for applying backpressure.
+    pub fn buffered_amount(&self) -> u32 {
+        self.socket.buffered_amount()
+    }
+
+    /// Starts the closing handshake with a normal closure code.
+    pub fn close(&self) {
+        let _ = self.socket.close();
+    }
+
+    /// Starts the closing handshake with `code`, which must be 1000 or in 3000-4999.
+    pub fn close_with(&self, code: u16, reason: &str) -> Result<(), GeneralError> {
+        self.socket.close_with_code_and_reason(code, reason)?;
+        Ok(())
+    }
+
+    /// The underlying socket.
+    pub fn raw(&self) -> &web_sys::WebSocket {
+        &self.socket
+    }
+}
+
+impl<O, I, C> Drop for Socket<O, I, C> {
+    fn drop(&mut self) {
+        let _ = self.socket.close();
+    }
+}
+
+impl MessageError for SocketError {
+    fn failed() -> Self {
+        SocketError::Error
+    }
+
+    fn decode(e: GeneralError) -> Self {
+        SocketError::Decode(e)
+    }
+}
+
+/// Forwards the messages and errors of a WebSocket or data channel into `state`'s sender,
+/// until it closes and `close` says why.
+pub(crate) fn listen<I, C, E, X>(
+    target: &web_sys::EventTarget,
+    state: &Rc<RefCell<MessageState<I, E, X>>>,
+    close: impl FnOnce(event::Close) -> X + 'static
+) -> Vec<ListenerHandle>
+where
+    I: DeserializeOwned + 'static,
+    C: Codec,
+    E: MessageError + 'static,
+    X: 'static
+{
+    let st = state.clone();
+    let on_message = target.add_event_listener(move |e: event::Message| {
+        // binary messages are received as array buffers
+        let data = e.data();
+        let data = match data.dyn_ref::<js_sys::ArrayBuffer>() {
+            Some(buffer) => js_sys::Uint8Array::new(buffer).into(),
+            None => data
+        };
+        if let Some(incoming) = &st.borrow().incoming {
+            let _ = incoming.send(C::decode(data).map_err(E::decode));
+        }
+    });
+    let st = state.clone();
+    let on_error = target.add_event_listener(move |_: event::Error| {
+        if let Some(incoming) = &st.borrow().incoming {
+            let _ = incoming.send(Err(E::failed()));
+        }
+    });
+    let st = state.clone();
+    let on_close = target.add_event_listener_once(move |e: event::Close| {
+        let mut st = st.borrow_mut();
+        st.close = Some(close(e));
+        // receivers see the close once the queued messages are received
+        st.incoming = None;
+    });
+    vec![on_message, on_error, on_close]
+}
+
+/// Sends a message encoded by a codec as a text frame if it is a string, or as a binary frame
+/// if it is bytes.
+pub(crate) fn send_frame(
+    msg: &JsValue,
+    send_text: impl FnOnce(&str) -> Result<(), JsValue>,
+    send_bytes: impl FnOnce(&js_sys::Uint8Array) -> Result<(), JsValue>
+) -> Result<(), GeneralError> {
+    if let Some(text) = msg.as_string() {
+        send_text(&text)?;
+    } else if let Some(bytes) = msg.dyn_ref::<js_sys::Uint8Array>() {
+        send_bytes(bytes)?;
+    } else {
+        let error = js_sys::TypeError::new("codec output is neither text nor bytes");
+        return Err(GeneralError::WebSys(error.into()));
+    }
+    Ok(())
+}
+
+/// Connection state of a [`ReconnectingSocket`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConnectionState {
+    /// Making the first connection.
+    Connecting,
+    Open,
+    /// Waiting for or making another connection after `failures` failed attempts in a row.
+    Reconnecting { failures: u32 },
+    /// Closed for good, either by [`ReconnectingSocket::close`] or because reconnecting
+    /// failed as many times as the retry policy allows.
+    Closed
+}
+
+/// What a [`ReconnectingSocket`] does with messages sent while it isn't connected.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OfflinePolicy {
+    /// Keep up to this many messages and send them once reconnected, dropping the oldest ones
+    /// beyond that.
+    Buffer(usize),
+    /// Drop them.
+    Drop
+}
+
+/// A [`Socket`] which reconnects whenever the connection is lost, waiting between attempts as
+/// set by a [`RetryPolicy`](crate::task::RetryPolicy). Its `max_attempts` limits how many
+/// attempts in a row may fail before giving up.
+///
+/// Received messages from all connections arrive on one queue. Dropping the socket closes it.
+pub struct ReconnectingSocket<O, I, C = Json> {
+    shared: Rc<Shared<O, I, C>>,
+    incoming: Receiver<Result<I, SocketError>>,
+    state: WatchReceiver<ConnectionState>,
+    driver: JoinHandle<()>
+}
+
+type OpenHook<O, I, C> = Box<dyn FnMut(&Socket<O, I, C>)>;
+
+struct Shared<O, I, C> {
+    socket: RefCell<Option<Rc<Socket<O, I, C>>>>,
+    offline: Cell<OfflinePolicy>,
+    buffer: RefCell<VecDeque<JsValue>>,
+    on_open: RefCell<Option<OpenHook<O, I, C>>>,
+    last_close: RefCell<Option<CloseInfo>>,
+    state: WatchSender<ConnectionState>
+}
+
+impl<O, I, C> ReconnectingSocket<O, I, C>
+where
+    O: Serialize + 'static,
+    I: DeserializeOwned + 'static,
+    C: Codec
+{
+    /// Starts connecting to `url` in the background. Messages are buffered up to 1024 until
+    /// connected.
+    pub fn connect(url: &str, policy: RetryPolicy) -> Self {
+        let (state_sender, state) = watch(ConnectionState::Connecting);
+        let shared = Rc::new(Shared {
+            socket: RefCell::new(None),
+            offline: Cell::new(OfflinePolicy::Buffer(1024)),
+            buffer: RefCell::new(VecDeque::new()),
+            on_open: RefCell::new(None),
+            last_close: RefCell::new(None),
+            state: state_sender
+        });
+        let (sender, incoming) = channel();
+        let driver = crate::spawn(drive(url.to_owned(), policy, shared.clone(), sender));
+        ReconnectingSocket { shared, incoming, state, driver }
+    }
+
+    /// Sends a message now if connected, and otherwise as set by the [`OfflinePolicy`]. A
+    /// connection which is closing counts as not connected.
+    pub fn send(&self, v: &O) -> Result<(), GeneralError> {
+        let (msg, _) = C::encode(v)?;
+        let socket = self.shared.socket.borrow().clone();
+        match socket {
+            Some(socket) if socket.is_open() => socket.send_encoded(&msg),
+            _ => {
+                if let OfflinePolicy::Buffer(limit) = self.shared.offline.get() {
+                    let mut buffer = self.shared.buffer.borrow_mut();
+                    buffer.push_back(msg);
+                    while buffer.len() > limit {
+                        buffer.pop_front();
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Receives a message if one is available.
+    pub fn try_recv(&self) -> Option<Result<I, SocketError>> {
+        match self.incoming.try_recv() {
+            Ok(v) => Some(v),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Closed) => Some(Err(self.closed()))
+        }
+    }
+
+    /// Receives the next message. Connection losses aren't reported here, only the final
+    /// [`SocketError::Closed`] once the socket gives up or is closed.
+    pub async fn recv(&self) -> Result<I, SocketError> {
+        match self.incoming.recv().await {
+            Some(v) => v,
+            None => Err(self.closed())
+        }
+    }
+
+    fn closed(&self) -> SocketError {
+        SocketError::Closed(self.shared.last_close.borrow().clone().unwrap_or(CloseInfo {
+            code: 1006,
+            reason: String::new(),
+            clean: false
+        }))
+    }
+}
+
+impl<O, I, C> ReconnectingSocket<O, I, C> {
+    /// Returns a receiver for the connection state.
+    pub fn state(&self) -> WatchReceiver<ConnectionState> {
+        self.state.clone()
+    }
+
+    pub fn set_offline_policy(&self, policy: OfflinePolicy) {
+        self.shared.offline.set(policy);
+        if policy == OfflinePolicy::Drop {
+            self.shared.buffer.borrow_mut().clear();
+        }
+    }
+
+    /// Calls `f` with each new connection before the buffered messages are sent, for
+    /// re-sending subscriptions and other per-connection setup.
+    pub fn on_open(&self, f: impl FnMut(&Socket<O, I, C>) + 'static) {
+        *self.shared.on_open.borrow_mut() = Some(Box::new(f));
+    }
+
+    /// Closes the connection and stops reconnecting.
+    pub fn close(&self) {
+        self.driver.abort();
+        self.shared.socket.borrow_mut().take();
+        self.shared.buffer.borrow_mut().clear();
+        self.shared.state.set(ConnectionState::Closed);
+    }
+}
+
+impl<O, I, C> Drop for ReconnectingSocket<O, I, C> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+async fn drive<O, I, C>(
+    url: String,
+    policy: RetryPolicy,
+    shared: Rc<Shared<O, I, C>>,
+    incoming: Sender<Result<I, SocketError>>
+)
+where
+    O: Serialize + 'static,
+    I: DeserializeOwned + 'static,
+    C: Codec
+{
+    let mut failures = 0;
+    loop {
+        let socket = match Socket::<O, I, C>::connect(&url).await {
+            Ok(socket) => Rc::new(socket),
+            Err(_) => {
+                failures += 1;
+                if failures >= policy.attempts() {
+                    break;
+                }
+                shared.state.set(ConnectionState::Reconnecting { failures });
+                global::sleep(policy.delay(failures - 1)).await;
+                continue;
+            }
+        };
+        failures = 0;
+        *shared.socket.borrow_mut() = Some(socket.clone());
+        shared.state.set(ConnectionState::Open);
+        if let Some(on_open) = &mut *shared.on_open.borrow_mut() {
+            on_open(&socket);
+        }
+        let buffered = std::mem::take(&mut *shared.buffer.borrow_mut());
+        for msg in buffered {
+            let _ = socket.send_encoded(&msg);
+        }
+
+        loop {
+            match socket.recv().await {
+                Ok(v) => { let _ = incoming.send(Ok(v)); }
+                Err(SocketError::Decode(e)) => {
+                    let _ = incoming.send(Err(SocketError::Decode(e)));
+                }
+                // a close always follows
+                Err(SocketError::Error) => {}
+                Err(SocketError::Closed(info)) => {
+                    *shared.last_close.borrow_mut() = Some(info);
+                    break;
+                }
+            }
+        }
+        shared.socket.borrow_mut().take();
+        shared.state.set(ConnectionState::Reconnecting { failures: 0 });
+        global::sleep(policy.delay(0)).await;
+    }
+    shared.state.set(ConnectionState::Closed);
+}